@@ -67,6 +67,8 @@ impl Application for KmsApplication {
     fn tracing_config(&self, command: &KmsCommand) -> trace::Config {
         if command.verbose() {
             trace::Config::verbose()
+        } else if command.quiet() {
+            trace::Config::from("warn".to_owned())
         } else {
             trace::Config::default()
         }
@@ -0,0 +1,161 @@
+//! Exponential backoff with jitter between retry attempts
+//!
+//! Used when reconnecting to validators and retrying signing provider
+//! lookups. Randomizing the delays keeps many KMS instances (or clients) which
+//! failed at the same moment, e.g. when a backend recovers, from retrying in
+//! lockstep.
+
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How delays between attempts are randomized
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Jitter {
+    /// Exactly the exponential delay: `min * 2^n`, capped at `max`
+    None,
+
+    /// Uniformly random between zero and the exponential delay
+    #[default]
+    Full,
+
+    /// Half the exponential delay, plus a uniformly random amount up to the
+    /// other half
+    Equal,
+
+    /// Uniformly random between `min` and three times the previous delay,
+    /// capped at `max`
+    Decorrelated,
+}
+
+/// Delays between consecutive attempts of a retried operation
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// Delay before the first retry
+    min: Duration,
+
+    /// Upper bound on any delay
+    max: Duration,
+
+    /// Jitter strategy
+    jitter: Jitter,
+
+    /// Exponential delay for the next attempt (before jitter)
+    current: Duration,
+
+    /// Previous delay (for decorrelated jitter)
+    previous: Duration,
+}
+
+impl Backoff {
+    /// Create a new backoff doubling from `min` up to `max`
+    pub fn new(min: Duration, max: Duration, jitter: Jitter) -> Self {
+        let max = max.max(min);
+
+        Self {
+            min,
+            max,
+            jitter,
+            current: min,
+            previous: min,
+        }
+    }
+
+    /// Get the delay before the next attempt
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = match self.jitter {
+            Jitter::None => self.current,
+            Jitter::Full => random_between(Duration::ZERO, self.current),
+            Jitter::Equal => {
+                let half = self.current / 2;
+                half + random_between(Duration::ZERO, self.current - half)
+            }
+            Jitter::Decorrelated => {
+                random_between(self.min, self.previous.saturating_mul(3)).min(self.max)
+            }
+        };
+
+        self.current = self.current.saturating_mul(2).min(self.max);
+        self.previous = delay.max(self.min);
+        delay
+    }
+
+    /// Start over from the minimum delay, e.g. after a successful attempt
+    pub fn reset(&mut self) {
+        self.current = self.min;
+        self.previous = self.min;
+    }
+}
+
+/// Pick a uniformly random duration in `[low, high]`
+fn random_between(low: Duration, high: Duration) -> Duration {
+    if high <= low {
+        return low;
+    }
+
+    let range = (high - low).as_nanos().min(u64::MAX as u128) as u64;
+    low + Duration::from_nanos(OsRng.next_u64() % range.saturating_add(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIN: Duration = Duration::from_millis(100);
+    const MAX: Duration = Duration::from_millis(1000);
+
+    /// Exponential delays (before jitter) for the first attempts
+    const CEILINGS: [u64; 6] = [100, 200, 400, 800, 1000, 1000];
+
+    #[test]
+    fn no_jitter() {
+        let mut backoff = Backoff::new(MIN, MAX, Jitter::None);
+
+        for ceiling in CEILINGS {
+            assert_eq!(backoff.next_delay(), Duration::from_millis(ceiling));
+        }
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), MIN);
+    }
+
+    #[test]
+    fn full_jitter_bounds() {
+        for _ in 0..100 {
+            let mut backoff = Backoff::new(MIN, MAX, Jitter::Full);
+
+            for ceiling in CEILINGS {
+                assert!(backoff.next_delay() <= Duration::from_millis(ceiling));
+            }
+        }
+    }
+
+    #[test]
+    fn equal_jitter_bounds() {
+        for _ in 0..100 {
+            let mut backoff = Backoff::new(MIN, MAX, Jitter::Equal);
+
+            for ceiling in CEILINGS {
+                let ceiling = Duration::from_millis(ceiling);
+                let delay = backoff.next_delay();
+                assert!(delay >= ceiling / 2 && delay <= ceiling);
+            }
+        }
+    }
+
+    #[test]
+    fn decorrelated_jitter_bounds() {
+        for _ in 0..100 {
+            let mut backoff = Backoff::new(MIN, MAX, Jitter::Decorrelated);
+            let mut previous = MIN;
+
+            for _ in CEILINGS {
+                let delay = backoff.next_delay();
+                assert!(delay >= MIN && delay <= MAX);
+                assert!(delay <= previous * 3);
+                previous = delay;
+            }
+        }
+    }
+}
@@ -1,22 +1,59 @@
 //! Information about particular Tendermint blockchain networks
 
+pub mod approval;
+pub mod bounds;
+pub mod checkpoint;
+pub mod duplicate;
 mod guard;
+pub mod halt;
+pub mod lock;
+pub mod monitor;
+pub mod node;
+pub mod quiet;
 mod registry;
+pub mod rejections;
 pub mod state;
 
 pub use self::{
+    approval::ApprovalHook,
+    bounds::HeightBounds,
+    checkpoint::Checkpointer,
+    duplicate::{DuplicateRequestPolicy, LastSigned},
     guard::Guard,
+    halt::HaltDetector,
+    lock::StandbyLock,
+    quiet::SignWatchdog,
     registry::{GlobalRegistry, Registry, REGISTRY},
+    rejections::RejectionTracker,
     state::State,
 };
 use crate::{
-    config::{chain::ChainConfig, KmsConfig},
-    error::Error,
+    clock::{Clock, SystemClock},
+    config::{
+        chain::{ChainConfig, FsyncPolicy, MissingStatePolicy, ReplyEncoding, SignTimeoutConfig},
+        KmsConfig, ProtocolVersion,
+    },
+    error::{Error, ErrorKind::*},
     keyring::{self, KeyRing},
     prelude::*,
+    privval::SignedMsgType,
+};
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
 };
-use std::{path::PathBuf, sync::Mutex};
 pub use tendermint::chain::Id;
+use tendermint::TendermintKey;
+
+/// Interval between attempts to load signing providers while waiting for them
+const LOAD_RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Message types chains are allowed to sign unless configured otherwise
+const DEFAULT_ALLOWED_MESSAGE_TYPES: &[SignedMsgType] = &[
+    SignedMsgType::Proposal,
+    SignedMsgType::Prevote,
+    SignedMsgType::Precommit,
+];
 
 /// Information about a particular Tendermint blockchain network
 pub struct Chain {
@@ -31,17 +68,112 @@ pub struct Chain {
 
     /// State from the last block signed for this chain
     pub state: Mutex<State>,
+
+    /// Warm-standby coordination lock (if configured)
+    pub standby_lock: Option<StandbyLock>,
+
+    /// Chain halt detector (if configured)
+    pub halt_detector: Option<HaltDetector>,
+
+    /// Sign cadence watchdog (if configured)
+    pub sign_watchdog: Option<SignWatchdog>,
+
+    /// Consecutive double-signing guard rejections
+    pub rejections: Mutex<RejectionTracker>,
+
+    /// How long new connections wait following a guard rejection
+    pub rejection_grace_period: Duration,
+
+    /// Message types this chain is allowed to sign
+    pub allowed_message_types: Vec<SignedMsgType>,
+
+    /// Protocol version this chain runs (if configured)
+    pub protocol_version: Option<ProtocolVersion>,
+
+    /// Per-message-type signing provider time budgets
+    pub sign_timeout: SignTimeoutConfig,
+
+    /// Wire encoding of replies to validators
+    pub reply_encoding: ReplyEncoding,
+
+    /// Validate sign requests without ever signing them
+    pub observe_only: bool,
+
+    /// Range of heights which may be signed (if configured)
+    pub height_bounds: Option<HeightBounds>,
+
+    /// External approval of sign requests (if configured)
+    pub approval_hook: Option<ApprovalHook>,
+
+    /// How sign requests identical to the last one signed are handled
+    pub on_duplicate_request: DuplicateRequestPolicy,
+
+    /// Most recently signed request, locked while a sign request is handled
+    /// so they're handled one at a time
+    pub last_signed: Mutex<Option<LastSigned>>,
+
+    /// When a signature for this chain was last sent to a validator (if one
+    /// has been since the KMS started)
+    pub last_released_at: Mutex<Option<SystemTime>>,
+
+    /// Signed state checkpoint writer (if configured)
+    pub checkpointer: Option<Checkpointer>,
 }
 
 impl Chain {
     /// Attempt to create a `Chain` state from the given configuration
     pub fn from_config(config: &ChainConfig) -> Result<Chain, Error> {
-        let state_file = match config.state_file {
-            Some(ref path) => path.to_owned(),
-            None => PathBuf::from(&format!("{}_priv_validator_state.json", config.id)),
+        config.check_protocol_version()?;
+        let height_bounds = HeightBounds::from_config(config)?;
+        let approval_hook = config
+            .approval_hook
+            .as_ref()
+            .map(|hook| ApprovalHook::new(&config.id, hook))
+            .transpose()?;
+        let checkpointer = config
+            .state_checkpoint
+            .as_ref()
+            .map(|checkpoint| Checkpointer::new(&config.id, checkpoint))
+            .transpose()?;
+
+        let state_file_path = config.state_file_path();
+
+        let mut state = match config.on_missing_state.unwrap_or_default() {
+            MissingStatePolicy::InitZero => State::load_state(&state_file_path)?,
+            MissingStatePolicy::Refuse => State::load_existing_state(&state_file_path)?
+                .ok_or_else(|| {
+                    format_err!(
+                        ConfigError,
+                        "[{}] no state file at {}: refusing to start from height 0, which \
+                         could DOUBLE SIGN if this key has signed before. Restore the state \
+                         file, import the last signed height with `tmkms state import \
+                         --chain-id {} --height <HEIGHT>`, or if this is a brand new \
+                         validator set `on_missing_state = \"init_zero\"`",
+                        config.id,
+                        state_file_path.display(),
+                        config.id
+                    )
+                })?,
         };
 
-        let mut state = State::load_state(state_file)?;
+        let fsync = config.state_fsync.unwrap_or_default();
+        state.set_fsync_policy(fsync);
+
+        match fsync {
+            FsyncPolicy::Os => warn!(
+                "[{}] state_fsync = \"os\": state updates may be lost on crash or power \
+                 loss, which can lead to DOUBLE SIGNING after a restart",
+                config.id
+            ),
+            _ => info!("[{}] state file fsync policy: {}", config.id, fsync),
+        }
+
+        if config.observe_only {
+            warn!(
+                "[{}] observe_only = true: sign requests will be validated but NEVER signed",
+                config.id
+            );
+        }
 
         if let Some(ref hook) = config.state_hook {
             match state::hook::run(hook) {
@@ -57,21 +189,269 @@ impl Chain {
             }
         }
 
-        Ok(Self {
+        if let Some(height_bounds) = &height_bounds {
+            let height = state.consensus_state().height.value();
+
+            if height < height_bounds.min() {
+                warn!(
+                    "[{}] state file {} is at height {}, below min_height {}: was it reset? \
+                     Refusing to sign below min_height",
+                    config.id,
+                    state_file_path.display(),
+                    height,
+                    height_bounds.min()
+                );
+            }
+        }
+
+        let mut chain = Self::new(config, state);
+        chain.height_bounds = height_bounds;
+        chain.approval_hook = approval_hook;
+        chain.checkpointer = checkpointer;
+        Ok(chain)
+    }
+
+    /// Create a `Chain` for tools which use a chain's keyring without
+    /// signing consensus messages: the state file is read if it exists, but
+    /// is never created and state hooks aren't run.
+    pub fn from_config_readonly(config: &ChainConfig) -> Result<Chain, Error> {
+        let state = State::load_state_readonly(&config.state_file_path())?;
+        Ok(Self::new(config, state))
+    }
+
+    /// Create a `Chain` from the given configuration and state
+    fn new(config: &ChainConfig, state: State) -> Self {
+        Self {
             id: config.id.clone(),
             sign_extensions: config.sign_extensions,
-            keyring: KeyRing::new(config.key_format.clone()),
+            keyring: KeyRing::new(config.key_format.clone(), config.signature_post_process),
             state: Mutex::new(state),
-        })
+            standby_lock: config.standby_lock.as_ref().map(StandbyLock::new),
+            halt_detector: config.halt_detection.as_ref().map(HaltDetector::new),
+            sign_watchdog: config.sign_watchdog.as_ref().map(SignWatchdog::new),
+            rejections: Mutex::new(RejectionTracker::default()),
+            rejection_grace_period: config.rejection_grace_period(),
+            allowed_message_types: config
+                .allowed_message_types
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ALLOWED_MESSAGE_TYPES.to_vec()),
+            protocol_version: config.protocol_version,
+            sign_timeout: config.sign_timeout.clone().unwrap_or_default(),
+            reply_encoding: config.reply_encoding(),
+            observe_only: config.observe_only,
+            height_bounds: None,
+            approval_hook: None,
+            on_duplicate_request: config.on_duplicate_request.unwrap_or_default(),
+            last_signed: Mutex::new(None),
+            last_released_at: Mutex::new(None),
+            checkpointer: None,
+        }
+    }
+
+    /// Does this chain appear to be halted? (always `false` unless halt
+    /// detection is configured)
+    pub fn is_halted(&self) -> bool {
+        self.halt_detector
+            .as_ref()
+            .map(HaltDetector::is_halted)
+            .unwrap_or(false)
     }
 }
 
 /// Initialize the chain registry from the configuration file
 pub fn load_config(config: &KmsConfig) -> Result<(), Error> {
+    let mut registry = Registry::default();
+
     for config in &config.chain {
-        REGISTRY.register(Chain::from_config(config)?)?;
+        registry.register_chain(Chain::from_config(config)?)?;
+    }
+
+    keyring::load_config(&mut registry, &config.providers)?;
+    check_checkpoint_keys(&registry)?;
+    warn_on_shared_keys(&registry);
+    *REGISTRY.0.write().unwrap() = registry;
+    Ok(())
+}
+
+/// Load a standalone chain registry (i.e. not the global one) from the given
+/// configuration, for tools which use keyrings without signing consensus
+/// messages. No state files are created or modified.
+pub fn load_config_readonly(config: &KmsConfig) -> Result<Registry, Error> {
+    let mut registry = Registry::default();
+
+    for chain_config in &config.chain {
+        registry.register_chain(Chain::from_config_readonly(chain_config)?)?;
     }
 
-    let mut registry = REGISTRY.0.write().unwrap();
-    keyring::load_config(&mut registry, &config.providers)
+    keyring::load_config(&mut registry, &config.providers)?;
+    Ok(registry)
+}
+
+/// Ensure no chain's state checkpoints are signed by its own consensus key,
+/// which would let anyone with the consensus key forge them
+fn check_checkpoint_keys(registry: &Registry) -> Result<(), Error> {
+    for chain in registry.chains() {
+        let Some(checkpointer) = &chain.checkpointer else {
+            continue;
+        };
+
+        if let Ok(public_key) = chain.keyring.default_pubkey() {
+            ensure!(
+                *public_key.public_key() != checkpointer.verifying_key().into(),
+                ConfigError,
+                "[{}] state_checkpoint key must not be the chain's consensus key",
+                chain.id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Warn about consensus keys configured for more than one chain.
+///
+/// Signing several chains with one key is only safe because every canonical
+/// vote and proposal embeds the chain ID, which is checked against the chain
+/// the request was received for before signing (see [`rpc::Request::decode`]).
+/// A signature for one chain can therefore never be replayed on another, but
+/// compromise of the key affects all of them at once, and operators should opt
+/// into this deliberately.
+///
+/// [`rpc::Request::decode`]: crate::rpc::Request::decode
+fn warn_on_shared_keys(registry: &Registry) {
+    let mut chains_by_key = Vec::<(TendermintKey, Vec<&Id>)>::new();
+
+    for chain in registry.chains() {
+        if let Ok(public_key) = chain.keyring.default_pubkey() {
+            match chains_by_key.iter_mut().find(|(key, _)| *key == public_key) {
+                Some((_, chain_ids)) => chain_ids.push(&chain.id),
+                None => chains_by_key.push((public_key, vec![&chain.id])),
+            }
+        }
+    }
+
+    for (public_key, chain_ids) in chains_by_key {
+        if chain_ids.len() > 1 {
+            let chain_ids = chain_ids
+                .iter()
+                .map(|id| id.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            warn!(
+                "*** KEY SHARED ACROSS CHAINS: {:?} is configured for chains {}. \
+                 Signatures are domain-separated by chain ID, but a compromise of \
+                 this key affects every one of these chains ***",
+                public_key, chain_ids
+            );
+        }
+    }
+}
+
+/// Initialize the chain registry from the configuration file, retrying until
+/// the signing providers become available or the timeout elapses
+pub fn load_config_with_retry(config: &KmsConfig, timeout: Duration) -> Result<(), Error> {
+    retry_until_timeout(&SystemClock, timeout, || load_config(config))
+}
+
+/// Retry loading the chain registry with the given function until it
+/// succeeds, or the timeout has elapsed on the given clock
+fn retry_until_timeout(
+    clock: &dyn Clock,
+    timeout: Duration,
+    mut load: impl FnMut() -> Result<(), Error>,
+) -> Result<(), Error> {
+    let started_at = clock.now();
+
+    loop {
+        let elapsed = clock.now() - started_at;
+
+        match load() {
+            Ok(()) => return Ok(()),
+            Err(e) if elapsed < timeout => {
+                info!(
+                    "waiting for signing providers ({}s of {}s elapsed): {}",
+                    elapsed.as_secs(),
+                    timeout.as_secs(),
+                    e
+                );
+
+                clock.sleep(LOAD_RETRY_INTERVAL);
+            }
+            Err(e) => {
+                error!(
+                    "signing providers still unavailable after {}s, giving up",
+                    timeout.as_secs()
+                );
+
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Spawn background halt detectors for all chains which have them configured
+pub fn spawn_halt_detectors() {
+    for chain in REGISTRY.get().chains() {
+        if let Some(halt_detector) = &chain.halt_detector {
+            halt_detector.spawn(&chain.id);
+        }
+    }
+}
+
+/// Spawn background state checkpoint writers for all chains which have them
+/// configured
+pub fn spawn_checkpointers() {
+    for chain in REGISTRY.get().chains() {
+        if let Some(checkpointer) = &chain.checkpointer {
+            checkpointer.spawn(&chain.id);
+        }
+    }
+}
+
+/// Spawn background sign cadence watchdogs for all chains which have them
+/// configured
+pub fn spawn_sign_watchdogs() {
+    for chain in REGISTRY.get().chains() {
+        if let Some(sign_watchdog) = &chain.sign_watchdog {
+            sign_watchdog.spawn(&chain.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn retry_until_providers_available() {
+        let clock = MockClock::new();
+        let mut attempts = 0;
+
+        retry_until_timeout(&clock, Duration::from_secs(10), || {
+            attempts += 1;
+            ensure!(attempts == 3, SigningError, "provider unavailable");
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(attempts, 3);
+        assert_eq!(clock.elapsed(), LOAD_RETRY_INTERVAL * 2);
+    }
+
+    #[test]
+    fn give_up_after_timeout() {
+        let clock = MockClock::new();
+        let mut attempts = 0;
+
+        let result = retry_until_timeout(&clock, Duration::from_secs(5), || {
+            attempts += 1;
+            fail!(SigningError, "provider unavailable")
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, 4);
+        assert_eq!(clock.elapsed(), LOAD_RETRY_INTERVAL * 3);
+    }
 }
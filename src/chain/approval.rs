@@ -0,0 +1,286 @@
+//! External approval of sign requests
+//!
+//! A break-glass control for high-value signing: a chain with an
+//! `approval_hook` runs the configured command before signing the message
+//! types (and heights) it covers, and only signs if the command approves
+//! within its deadline. Everything else fails closed: a command which can't
+//! be run, exits with an error, or misses the deadline refuses the request.
+//! Outcomes are counted per chain and exported as
+//! `tmkms_approval_hook_total` (see [`crate::metrics`]).
+
+use super::Id;
+use crate::{
+    config::chain::ApprovalHookConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privval::SignedMsgType,
+    Map,
+};
+use serde::Serialize;
+use std::{
+    ffi::OsString,
+    fmt,
+    io::{self, Write},
+    process::{Command, Stdio},
+    sync::Mutex,
+    time::Duration,
+};
+use tendermint::{block, consensus};
+use wait_timeout::ChildExt;
+
+/// Default deadline for the command to approve (in milliseconds)
+const DEFAULT_TIMEOUT_MS: u64 = 500;
+
+/// Number of approval hook runs with each outcome for each chain
+static OUTCOMES: Mutex<Map<(String, Outcome), u64>> = Mutex::new(Map::new());
+
+/// Approval hook for a chain
+#[derive(Debug)]
+pub struct ApprovalHook {
+    /// Command (with arguments) to invoke
+    cmd: Vec<OsString>,
+
+    /// Deadline for the command to approve
+    timeout: Duration,
+
+    /// Message types which require approval
+    message_types: Vec<SignedMsgType>,
+
+    /// Lowest height which requires approval
+    min_height: block::Height,
+}
+
+impl ApprovalHook {
+    /// Create the approval hook of the given chain
+    pub fn new(chain_id: &Id, config: &ApprovalHookConfig) -> Result<Self, Error> {
+        ensure!(
+            !config.cmd.is_empty(),
+            ConfigError,
+            "[{}] approval_hook `cmd` must not be empty",
+            chain_id
+        );
+
+        Ok(Self {
+            cmd: config.cmd.clone(),
+            timeout: Duration::from_millis(config.timeout_ms.unwrap_or(DEFAULT_TIMEOUT_MS)),
+            message_types: config
+                .message_types
+                .clone()
+                .unwrap_or_else(|| vec![SignedMsgType::Proposal]),
+            min_height: config.min_height.unwrap_or_default(),
+        })
+    }
+
+    /// Does a request of the given type at the given height need approval?
+    pub fn applies_to(&self, msg_type: SignedMsgType, height: block::Height) -> bool {
+        self.message_types.contains(&msg_type) && height >= self.min_height
+    }
+
+    /// Ask the command to approve signing the given request, returning an
+    /// error unless it did
+    pub fn approve(
+        &self,
+        chain_id: &Id,
+        msg_type: SignedMsgType,
+        request_state: &consensus::State,
+    ) -> Result<(), Error> {
+        let request = Request {
+            chain_id,
+            msg_type,
+            height: request_state.height,
+            round: request_state.round,
+            block_id: request_state.block_id.as_ref(),
+        };
+
+        let (outcome, result) = match self.run(&request) {
+            Ok(None) => (Outcome::Approved, Ok(())),
+            Ok(Some(outcome)) => (
+                outcome,
+                Err(format_err!(
+                    HookError,
+                    "approval hook {} {:?} at h/r/s {}",
+                    outcome.description(self.timeout),
+                    msg_type,
+                    request_state
+                )
+                .into()),
+            ),
+            Err(e) => (
+                Outcome::Failed,
+                Err(format_err!(
+                    HookError,
+                    "approval hook failed for {:?} at h/r/s {}: {}",
+                    msg_type,
+                    request_state,
+                    e
+                )
+                .into()),
+            ),
+        };
+
+        *OUTCOMES
+            .lock()
+            .unwrap()
+            .entry((chain_id.to_string(), outcome))
+            .or_default() += 1;
+
+        result
+    }
+
+    /// Run the command, returning the outcome unless it approved
+    fn run(&self, request: &Request<'_>) -> Result<Option<Outcome>, Error> {
+        let mut child = Command::new(&self.cmd[0])
+            .args(&self.cmd[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()?;
+
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+
+        // A command which decides without reading the request may exit
+        // before it's written
+        match stdin.write_all(&serde_json::to_vec(request)?) {
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => {
+                child.kill()?;
+                child.wait()?;
+                return Err(e.into());
+            }
+            _ => drop(stdin),
+        }
+
+        match child.wait_timeout(self.timeout)? {
+            Some(status) if status.success() => Ok(None),
+            Some(_) => Ok(Some(Outcome::Denied)),
+            None => {
+                child.kill()?;
+                child.wait()?;
+                Ok(Some(Outcome::TimedOut))
+            }
+        }
+    }
+}
+
+/// Sign request, as written to the command's stdin
+#[derive(Serialize)]
+struct Request<'a> {
+    chain_id: &'a Id,
+    msg_type: SignedMsgType,
+    height: block::Height,
+    round: block::Round,
+    block_id: Option<&'a block::Id>,
+}
+
+/// Outcome of running an approval hook
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Outcome {
+    /// The command exited successfully
+    Approved,
+
+    /// The command exited with an error
+    Denied,
+
+    /// The command didn't exit before the deadline
+    TimedOut,
+
+    /// The command couldn't be run
+    Failed,
+}
+
+impl Outcome {
+    /// Description of a refusal with this outcome
+    fn description(self, timeout: Duration) -> String {
+        match self {
+            Outcome::TimedOut => format!("didn't approve within {} ms", timeout.as_millis()),
+            _ => "denied".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Approved => "approved",
+            Outcome::Denied => "denied",
+            Outcome::TimedOut => "timed_out",
+            Outcome::Failed => "failed",
+        })
+    }
+}
+
+/// Number of approval hook runs with each outcome for each chain so far
+pub fn outcomes() -> Vec<(String, Outcome, u64)> {
+    OUTCOMES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((chain_id, outcome), count)| (chain_id.clone(), *outcome, *count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hook(script: &str, timeout_ms: Option<u64>) -> ApprovalHook {
+        ApprovalHook::new(
+            &"approval-chain".parse().unwrap(),
+            &ApprovalHookConfig {
+                cmd: vec!["sh".into(), "-c".into(), script.into()],
+                timeout_ms,
+                message_types: None,
+                min_height: Some(10u32.into()),
+            },
+        )
+        .unwrap()
+    }
+
+    fn approve(hook: &ApprovalHook) -> Result<(), Error> {
+        let request_state = consensus::State {
+            height: 42u32.into(),
+            round: 0u16.into(),
+            step: 0,
+            block_id: None,
+        };
+
+        hook.approve(
+            &"approval-chain".parse().unwrap(),
+            SignedMsgType::Proposal,
+            &request_state,
+        )
+    }
+
+    #[test]
+    fn applies_to_configured_requests() {
+        let hook = hook("true", None);
+
+        assert!(hook.applies_to(SignedMsgType::Proposal, 10u32.into()));
+        assert!(!hook.applies_to(SignedMsgType::Proposal, 9u32.into()));
+        assert!(!hook.applies_to(SignedMsgType::Prevote, 10u32.into()));
+    }
+
+    #[test]
+    fn fails_closed() {
+        // The request is passed on stdin
+        assert!(approve(&hook(r#"grep -q '"height":"42"'"#, None)).is_ok());
+        assert!(approve(&hook("true", None)).is_ok());
+
+        let err = approve(&hook("exit 1", None)).unwrap_err();
+        assert!(err.to_string().contains("approval hook denied Proposal"));
+
+        let err = approve(&hook("sleep 5", Some(50))).unwrap_err();
+        assert!(err.to_string().contains("didn't approve within 50 ms"));
+
+        let err = ApprovalHook::new(
+            &"approval-chain".parse().unwrap(),
+            &ApprovalHookConfig {
+                cmd: vec!["/nonexistent/approval-hook".into()],
+                timeout_ms: None,
+                message_types: None,
+                min_height: None,
+            },
+        )
+        .and_then(|hook| approve(&hook))
+        .unwrap_err();
+        assert!(err.to_string().contains("approval hook failed"));
+    }
+}
@@ -0,0 +1,202 @@
+//! Absolute height bounds for signing
+//!
+//! A blunt safety net on top of the double-signing guard: a chain configured
+//! with `min_height` never signs below it, even if its state file is reset,
+//! and one with `max_height` never signs above it, e.g. for a wildly wrong
+//! request height. The minimum only ever moves forward, following the
+//! heights the double-signing guard accepts. Refused requests are counted
+//! per chain and bound, and exported as `tmkms_height_bound_rejections_total`
+//! (see [`crate::metrics`]).
+
+use super::Id;
+use crate::{
+    config::chain::ChainConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    Map,
+};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tendermint::block;
+
+/// Number of requests refused by each bound for each chain
+static REJECTIONS: Mutex<Map<(String, Bound), u64>> = Mutex::new(Map::new());
+
+/// Range of heights a chain may sign at
+#[derive(Debug)]
+pub struct HeightBounds {
+    /// Lowest height which may be signed, raised as heights are signed
+    min: AtomicU64,
+
+    /// Highest height which may be signed
+    max: Option<u64>,
+}
+
+impl HeightBounds {
+    /// Create the height bounds of the given chain, if it has any configured
+    pub fn from_config(config: &ChainConfig) -> Result<Option<Self>, Error> {
+        if config.min_height.is_none() && config.max_height.is_none() {
+            return Ok(None);
+        }
+
+        let min = config.min_height.map(|height| height.value()).unwrap_or(0);
+        let max = config.max_height.map(|height| height.value());
+
+        if let Some(max) = max {
+            ensure!(
+                min < max,
+                ConfigError,
+                "[{}] min_height ({}) must be less than max_height ({})",
+                config.id,
+                min,
+                max
+            );
+        }
+
+        Ok(Some(Self {
+            min: AtomicU64::new(min),
+            max,
+        }))
+    }
+
+    /// Lowest height which may presently be signed
+    pub fn min(&self) -> u64 {
+        self.min.load(Ordering::SeqCst)
+    }
+
+    /// Check whether the given height may be signed for the given chain
+    pub fn check(&self, chain_id: &Id, height: block::Height) -> Result<(), Error> {
+        let height = height.value();
+        let min = self.min();
+
+        let (bound, description) = if height < min {
+            (Bound::Min, format!("below the minimum height {}", min))
+        } else if let Some(max) = self.max.filter(|&max| height > max) {
+            (Bound::Max, format!("above max_height {}", max))
+        } else {
+            return Ok(());
+        };
+
+        *REJECTIONS
+            .lock()
+            .unwrap()
+            .entry((chain_id.to_string(), bound))
+            .or_default() += 1;
+
+        fail!(
+            InvalidMessageError,
+            "refusing to sign at height {} for chain {}: {}",
+            height,
+            chain_id,
+            description
+        )
+    }
+
+    /// Raise the minimum to a height the double-signing guard has accepted
+    pub fn advance(&self, height: block::Height) {
+        self.min.fetch_max(height.value(), Ordering::SeqCst);
+    }
+}
+
+/// Bound which refused a request
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Bound {
+    /// Below `min_height` (or a height since signed)
+    Min,
+
+    /// Above `max_height`
+    Max,
+}
+
+impl fmt::Display for Bound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Bound::Min => "min",
+            Bound::Max => "max",
+        })
+    }
+}
+
+/// Number of requests refused by each bound for each chain so far
+pub fn rejections() -> Vec<(String, Bound, u64)> {
+    REJECTIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((chain_id, bound), count)| (chain_id.clone(), *bound, *count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(chain_id: &str, min_height: Option<u32>, max_height: Option<u32>) -> ChainConfig {
+        toml::from_str(&format!(
+            "id = \"{}\"\nkey_format = {{ type = \"hex\" }}\n{}{}",
+            chain_id,
+            min_height
+                .map(|height| format!("min_height = \"{}\"\n", height))
+                .unwrap_or_default(),
+            max_height
+                .map(|height| format!("max_height = \"{}\"\n", height))
+                .unwrap_or_default(),
+        ))
+        .unwrap()
+    }
+
+    fn rejection_count(chain: &str, bound: Bound) -> u64 {
+        rejections()
+            .into_iter()
+            .find(|(id, b, _)| id == chain && *b == bound)
+            .map(|(_, _, count)| count)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn validate_config() {
+        assert!(HeightBounds::from_config(&config("no-bounds", None, None))
+            .unwrap()
+            .is_none());
+        assert!(HeightBounds::from_config(&config("bounds", Some(10), Some(20))).is_ok());
+
+        let err = HeightBounds::from_config(&config("bounds", Some(20), Some(20))).unwrap_err();
+        assert_eq!(*err.kind(), ConfigError);
+    }
+
+    #[test]
+    fn enforce_bounds() {
+        let chain_id: Id = "bounded-chain".parse().unwrap();
+        let bounds = HeightBounds::from_config(&config("bounded-chain", Some(100), Some(1000)))
+            .unwrap()
+            .unwrap();
+
+        let check = |height: u32| bounds.check(&chain_id, height.into());
+
+        assert!(check(100).is_ok());
+        assert!(check(1000).is_ok());
+        assert!(check(99)
+            .unwrap_err()
+            .to_string()
+            .contains("minimum height 100"));
+        assert!(check(1001)
+            .unwrap_err()
+            .to_string()
+            .contains("max_height 1000"));
+
+        // The minimum only moves forward
+        bounds.advance(500u32.into());
+        bounds.advance(200u32.into());
+        assert_eq!(bounds.min(), 500);
+        assert!(check(499).is_err());
+        assert!(check(500).is_ok());
+
+        assert_eq!(rejection_count("bounded-chain", Bound::Min), 2);
+        assert_eq!(rejection_count("bounded-chain", Bound::Max), 1);
+    }
+}
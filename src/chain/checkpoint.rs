@@ -0,0 +1,396 @@
+//! Signed state checkpoints
+//!
+//! When `state_checkpoint` is configured for a chain, the KMS appends a
+//! checkpoint of the chain's double-signing state to a file at startup and
+//! every `interval_secs` thereafter, one JSON object per line:
+//!
+//! ```json
+//! {"chain_id":"cosmoshub-4","height":123,"round":0,"step":2,
+//!  "timestamp":"2024-01-01T00:00:00.000000Z","prev":"...","signature":"..."}
+//! ```
+//!
+//! Each checkpoint is signed by a dedicated Ed25519 auditing key, distinct
+//! from the consensus key, over the bytes described in
+//! [`Checkpoint::signed_bytes`]. `prev` is the signature of the chain's
+//! previous checkpoint in the file (empty for the first), so checkpoints
+//! can't be removed, reordered, or altered without detection. Together with
+//! append-only storage, this lets operators later prove how the signer's
+//! state progressed. `tmkms state verify-checkpoints` checks a file (see
+//! [`verify`]).
+
+use super::{Id, REGISTRY};
+use crate::{
+    clock::{Clock, SystemClock},
+    config::chain::StateCheckpointConfig,
+    error::{Error, ErrorKind::*},
+    key_utils,
+    keyring::ed25519,
+    prelude::*,
+    Map,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use signature::{Signer, Verifier};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+use subtle_encoding::{base64, hex};
+use tendermint::consensus;
+
+/// Domain separator at the start of the bytes signed for each checkpoint
+const DOMAIN: &str = "tmkms-state-checkpoint/v1";
+
+/// Signed checkpoint of a chain's double-signing state
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Checkpoint {
+    /// Chain whose state this is
+    pub chain_id: String,
+
+    /// Last signed height
+    pub height: u64,
+
+    /// Last signed round
+    pub round: u32,
+
+    /// Last signed step
+    pub step: i8,
+
+    /// When the checkpoint was taken (RFC 3339)
+    pub timestamp: String,
+
+    /// Signature of the chain's previous checkpoint (empty for the first)
+    pub prev: String,
+
+    /// Base64-encoded Ed25519 signature of [`Checkpoint::signed_bytes`]
+    pub signature: String,
+}
+
+impl Checkpoint {
+    /// Bytes signed by the auditing key: the domain separator followed by
+    /// the chain ID, height, round, step, timestamp, and previous signature,
+    /// each on its own line, e.g.
+    /// `tmkms-state-checkpoint/v1\ncosmoshub-4\n123\n0\n2\n2024-...Z\n<prev>`
+    pub fn signed_bytes(&self) -> Vec<u8> {
+        format!(
+            "{}\n{}\n{}\n{}\n{}\n{}\n{}",
+            DOMAIN, self.chain_id, self.height, self.round, self.step, self.timestamp, self.prev
+        )
+        .into_bytes()
+    }
+
+    /// Is this checkpoint signed by the given key?
+    fn is_signed_by(&self, key: &ed25519::VerifyingKey) -> bool {
+        base64::decode(&self.signature)
+            .ok()
+            .and_then(|bytes| ed25519::Signature::from_slice(&bytes).ok())
+            .map(|signature| key.verify(&self.signed_bytes(), &signature).is_ok())
+            .unwrap_or(false)
+    }
+
+    /// Height/round/step of this checkpoint, for comparisons
+    fn hrs(&self) -> (u64, u32, i8) {
+        (self.height, self.round, self.step)
+    }
+}
+
+/// Writer of a chain's signed state checkpoints
+#[derive(Clone)]
+pub struct Checkpointer {
+    /// File checkpoints are appended to
+    path: PathBuf,
+
+    /// Auditing key which signs checkpoints
+    key: ed25519::SigningKey,
+
+    /// Time between checkpoints
+    interval: Duration,
+
+    /// Signature of the chain's last checkpoint in the file
+    prev: String,
+
+    /// Clock checkpoints are timestamped and spaced out with
+    clock: Arc<dyn Clock>,
+}
+
+impl Checkpointer {
+    /// Load the auditing key and the chain's last checkpoint in the file (if
+    /// any) for the given chain
+    pub fn new(chain_id: &Id, config: &StateCheckpointConfig) -> Result<Self, Error> {
+        Self::with_clock(chain_id, config, Arc::new(SystemClock))
+    }
+
+    /// Create a checkpointer like [`Checkpointer::new`] which timestamps and
+    /// spaces out checkpoints with the given clock
+    pub fn with_clock(
+        chain_id: &Id,
+        config: &StateCheckpointConfig,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self, Error> {
+        let key = key_utils::load_base64_ed25519_key(&config.key).map_err(|e| {
+            format_err!(
+                ConfigError,
+                "[{}] couldn't load state checkpoint key from {}: {}",
+                chain_id,
+                config.key.display(),
+                e
+            )
+        })?;
+
+        let prev = read_checkpoints(&config.path)?
+            .into_iter()
+            .rev()
+            .find(|(_, checkpoint)| checkpoint.chain_id == chain_id.as_str())
+            .map(|(_, checkpoint)| checkpoint.signature)
+            .unwrap_or_default();
+
+        Ok(Self {
+            path: config.path.clone(),
+            key,
+            interval: config.interval(),
+            prev,
+            clock,
+        })
+    }
+
+    /// Public key of the auditing key, to verify checkpoints with
+    pub fn verifying_key(&self) -> ed25519::VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    /// Spawn a background thread checkpointing the given chain
+    pub fn spawn(&self, chain_id: &Id) {
+        let checkpointer = self.clone();
+        let chain_id = chain_id.clone();
+
+        info!(
+            "[{}] appending signed state checkpoints to {} every {}s (auditing key {})",
+            chain_id,
+            self.path.display(),
+            self.interval.as_secs(),
+            String::from_utf8(hex::encode_upper(self.verifying_key().as_bytes())).unwrap()
+        );
+
+        thread::Builder::new()
+            .name(format!("{}@state-checkpoint", chain_id))
+            .spawn(move || checkpointer.checkpoint_loop(&chain_id))
+            .unwrap_or_else(|e| {
+                status_err!("error spawning thread: {}", e);
+                process::exit(1);
+            });
+    }
+
+    /// Checkpoint the chain's current state forever
+    fn checkpoint_loop(mut self, chain_id: &Id) {
+        loop {
+            let state = REGISTRY.get().get_chain(chain_id).and_then(|chain| {
+                chain
+                    .state
+                    .lock()
+                    .ok()
+                    .map(|state| state.consensus_state().clone())
+            });
+
+            if let Some(state) = state {
+                if let Err(e) = self.append(chain_id, &state) {
+                    error!("[{}] couldn't write state checkpoint: {}", chain_id, e);
+                }
+            }
+
+            self.clock.sleep(self.interval);
+        }
+    }
+
+    /// Sign a checkpoint of the given state and append it to the file
+    pub fn append(&mut self, chain_id: &Id, state: &consensus::State) -> Result<Checkpoint, Error> {
+        let mut checkpoint = Checkpoint {
+            chain_id: chain_id.to_string(),
+            height: state.height.value(),
+            round: state.round.value(),
+            step: state.step,
+            timestamp: DateTime::<Utc>::from(self.clock.system_time())
+                .to_rfc3339_opts(SecondsFormat::Micros, true),
+            prev: self.prev.clone(),
+            signature: String::new(),
+        };
+
+        let signature = self
+            .key
+            .try_sign(&checkpoint.signed_bytes())
+            .map_err(|e| format_err!(SigningError, "couldn't sign state checkpoint: {}", e))?;
+
+        checkpoint.signature = String::from_utf8(base64::encode(signature.to_bytes())).unwrap();
+
+        let mut line = serde_json::to_string(&checkpoint)?;
+        line.push('\n');
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| {
+                file.write_all(line.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|e| format_err!(IoError, "{}: {}", self.path.display(), e))?;
+
+        self.prev.clone_from(&checkpoint.signature);
+        Ok(checkpoint)
+    }
+}
+
+/// Verify every checkpoint in the given file against the given auditing key,
+/// returning how many there are.
+///
+/// Each checkpoint must be signed by the key, name the previous checkpoint
+/// for its chain in the file as `prev` (so none were removed or reordered),
+/// and not move its chain's height/round/step backwards.
+pub fn verify(path: &Path, key: &ed25519::VerifyingKey) -> Result<usize, Error> {
+    let checkpoints = read_checkpoints(path)?;
+    let mut last = Map::<String, Checkpoint>::new();
+
+    for (line, checkpoint) in &checkpoints {
+        ensure!(
+            checkpoint.is_signed_by(key),
+            VerificationError,
+            "{}:{}: invalid signature",
+            path.display(),
+            line
+        );
+
+        let prev = last.get(&checkpoint.chain_id);
+
+        ensure!(
+            checkpoint.prev == prev.map(|prev| prev.signature.as_str()).unwrap_or_default(),
+            VerificationError,
+            "{}:{}: doesn't follow the previous checkpoint for chain {} (removed or reordered?)",
+            path.display(),
+            line,
+            checkpoint.chain_id
+        );
+
+        if let Some(prev) = prev {
+            ensure!(
+                checkpoint.hrs() >= prev.hrs(),
+                VerificationError,
+                "{}:{}: chain {} moved backwards from {}/{}/{} to {}/{}/{}",
+                path.display(),
+                line,
+                checkpoint.chain_id,
+                prev.height,
+                prev.round,
+                prev.step,
+                checkpoint.height,
+                checkpoint.round,
+                checkpoint.step
+            );
+        }
+
+        last.insert(checkpoint.chain_id.clone(), checkpoint.clone());
+    }
+
+    Ok(checkpoints.len())
+}
+
+/// Read the checkpoints in the given file (if it exists) along with their
+/// line numbers
+fn read_checkpoints(path: &Path) -> Result<Vec<(usize, Checkpoint)>, Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => fail!(IoError, "{}: {}", path.display(), e),
+    };
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .map(|checkpoint| (i + 1, checkpoint))
+                .map_err(|e| {
+                    format_err!(
+                        ParseError,
+                        "{}:{}: malformed checkpoint: {}",
+                        path.display(),
+                        i + 1,
+                        e
+                    )
+                    .into()
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    fn state(height: u32, step: i8) -> consensus::State {
+        consensus::State {
+            height: height.into(),
+            round: 0u16.into(),
+            step,
+            block_id: None,
+        }
+    }
+
+    #[test]
+    fn append_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoints.jsonl");
+        let key_path = dir.path().join("audit.key");
+        key_utils::write_base64_secret(&key_path, &[7; 32]).unwrap();
+
+        let config = StateCheckpointConfig {
+            path: path.clone(),
+            key: key_path,
+            interval_secs: None,
+        };
+
+        let chain_id: Id = "checkpoint-chain".parse().unwrap();
+        let clock = Arc::new(MockClock::new());
+        let mut checkpointer = Checkpointer::with_clock(&chain_id, &config, clock.clone()).unwrap();
+        let key = checkpointer.verifying_key();
+
+        let first = checkpointer.append(&chain_id, &state(10, 2)).unwrap();
+        clock.advance(Duration::from_secs(300));
+        let second = checkpointer.append(&chain_id, &state(11, 1)).unwrap();
+
+        // Checkpoints are timestamped with the checkpointer's clock
+        let timestamp =
+            |checkpoint: &Checkpoint| DateTime::parse_from_rfc3339(&checkpoint.timestamp).unwrap();
+        assert_eq!(
+            timestamp(&second) - timestamp(&first),
+            chrono::Duration::seconds(300)
+        );
+
+        // Checkpoints continue the chain after a restart
+        let mut checkpointer = Checkpointer::new(&chain_id, &config).unwrap();
+        checkpointer.append(&chain_id, &state(11, 2)).unwrap();
+        assert_eq!(verify(&path, &key).unwrap(), 3);
+
+        let lines = fs::read_to_string(&path).unwrap();
+        let lines = lines.lines().collect::<Vec<_>>();
+
+        let tampered = lines.join("\n").replace("\"height\":11", "\"height\":12");
+        fs::write(&path, tampered).unwrap();
+        let err = verify(&path, &key).unwrap_err().to_string();
+        assert!(err.contains(":2: invalid signature"), "{}", err);
+
+        fs::write(&path, [lines[0], lines[2]].join("\n")).unwrap();
+        let err = verify(&path, &key).unwrap_err().to_string();
+        assert!(err.contains(":2: doesn't follow"), "{}", err);
+
+        let other_key = ed25519::SigningKey::from([8; 32]).verifying_key();
+        fs::write(&path, lines.join("\n")).unwrap();
+        assert!(verify(&path, &other_key).is_err());
+    }
+}
@@ -0,0 +1,35 @@
+//! Detection of duplicate sign requests
+//!
+//! Sign requests for a chain are handled one at a time, with the most
+//! recently signed one kept in a [`LastSigned`]. When two connections send
+//! the same request concurrently, the second is only handled once the first
+//! has been signed, and is recognized as a duplicate of it: it's then handled
+//! according to the chain's `on_duplicate_request` policy (see
+//! [`DuplicateRequestPolicy`]) rather than racing it through the
+//! double-signing guard.
+
+use bytes::Bytes;
+
+pub use crate::config::chain::DuplicateRequestPolicy;
+
+/// Most recently signed request for a chain, and its signatures
+pub struct LastSigned {
+    /// Canonical bytes which were signed
+    pub sign_bytes: Bytes,
+
+    /// Canonical vote extension bytes which were signed (if any)
+    pub extension_bytes: Option<Bytes>,
+
+    /// Signature over `sign_bytes`
+    pub signature: tendermint::Signature,
+
+    /// Signature over `extension_bytes` (if any)
+    pub extension_signature: Option<tendermint::Signature>,
+}
+
+impl LastSigned {
+    /// Is a request with the given canonical bytes identical to this one?
+    pub fn is_duplicate(&self, sign_bytes: &[u8], extension_bytes: Option<&[u8]>) -> bool {
+        self.sign_bytes == sign_bytes && self.extension_bytes.as_deref() == extension_bytes
+    }
+}
@@ -0,0 +1,248 @@
+//! Chain halt detection
+//!
+//! Polls a node's RPC endpoint for the latest block height, and considers the
+//! chain halted if the height hasn't advanced within `halt_after_secs`. While
+//! a chain appears halted, validator clients back off reconnecting to reduce
+//! log noise and load. Detection resumes automatically once heights advance.
+//!
+//! This is purely an operational aid: it never affects the double-signing
+//! guard or whether a signature is produced.
+
+use super::{node, Id};
+use crate::{
+    clock::{Clock, SystemClock},
+    config::chain::HaltDetectionConfig,
+    prelude::*,
+};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tendermint::block;
+use tendermint_config::net;
+
+/// Default time without a new block after which a chain is considered halted
+const DEFAULT_HALT_AFTER_SECS: u64 = 60;
+
+/// Default interval at which to poll the node's RPC endpoint
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 10;
+
+/// Chain halt detector
+#[derive(Clone)]
+pub struct HaltDetector {
+    /// Address of the node's RPC endpoint
+    rpc_addr: net::Address,
+
+    /// Time without a new block after which the chain is considered halted
+    halt_after: Duration,
+
+    /// Interval at which to poll the RPC endpoint
+    poll_interval: Duration,
+
+    /// Does the chain currently appear to be halted?
+    halted: Arc<AtomicBool>,
+
+    /// Latest observed height (shared by all clones)
+    status: Arc<Mutex<Status>>,
+
+    /// Clock the time since the latest new block is measured with
+    clock: Arc<dyn Clock>,
+}
+
+impl HaltDetector {
+    /// Create a new halt detector from the given configuration
+    pub fn new(config: &HaltDetectionConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create a new halt detector which measures time with the given clock
+    pub fn with_clock(config: &HaltDetectionConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            rpc_addr: config.rpc_addr.clone(),
+            halt_after: Duration::from_secs(
+                config.halt_after_secs.unwrap_or(DEFAULT_HALT_AFTER_SECS),
+            ),
+            poll_interval: Duration::from_secs(
+                config
+                    .poll_interval_secs
+                    .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+            ),
+            halted: Arc::new(AtomicBool::new(false)),
+            status: Arc::new(Mutex::new(Status::default())),
+            clock,
+        }
+    }
+
+    /// Does the chain currently appear to be halted?
+    pub fn is_halted(&self) -> bool {
+        self.halted.load(Ordering::Relaxed)
+    }
+
+    /// Spawn a background thread which polls the RPC endpoint for the given chain
+    pub fn spawn(&self, chain_id: &Id) {
+        let detector = self.clone();
+        let chain_id = chain_id.clone();
+
+        thread::Builder::new()
+            .name(format!("{}@halt-detector", chain_id))
+            .spawn(move || detector.poll_loop(&chain_id))
+            .unwrap_or_else(|e| {
+                status_err!("error spawning thread: {}", e);
+                std::process::exit(1);
+            });
+    }
+
+    /// Poll the RPC endpoint forever, updating the halted status
+    fn poll_loop(&self, chain_id: &Id) {
+        loop {
+            match node::latest_block_height(&self.rpc_addr) {
+                Ok(height) => self.observe(chain_id, height),
+                // An unreachable node tells us nothing about the chain itself
+                Err(e) => debug!("[{}] halt detection: {}", chain_id, e),
+            }
+
+            self.clock.sleep(self.poll_interval);
+        }
+    }
+
+    /// Record the latest height reported by the node, updating the halted
+    /// status
+    fn observe(&self, chain_id: &Id, height: block::Height) {
+        let transition =
+            self.status
+                .lock()
+                .unwrap()
+                .observe(height, self.clock.now(), self.halt_after);
+
+        match transition {
+            Some(Transition::Halted) => {
+                warn!(
+                    "[{}] chain appears halted: no new blocks via {} since height {} ({}s)",
+                    chain_id,
+                    self.rpc_addr,
+                    height,
+                    self.halt_after.as_secs()
+                );
+                self.halted.store(true, Ordering::Relaxed);
+            }
+            Some(Transition::Resumed) => {
+                info!("[{}] chain resumed at height {}", chain_id, height);
+                self.halted.store(false, Ordering::Relaxed);
+            }
+            None => (),
+        }
+    }
+}
+
+/// Change in whether the chain appears halted
+#[derive(Debug, Eq, PartialEq)]
+enum Transition {
+    /// Heights stopped advancing
+    Halted,
+
+    /// Heights advanced again after a halt
+    Resumed,
+}
+
+/// Latest observed height and when it was first seen
+#[derive(Default)]
+struct Status {
+    /// Latest height and the time at which it was first observed
+    latest: Option<(block::Height, Instant)>,
+
+    /// Is the chain currently considered halted?
+    halted: bool,
+}
+
+impl Status {
+    /// Record an observed height, returning a transition if one occurred
+    fn observe(
+        &mut self,
+        height: block::Height,
+        now: Instant,
+        halt_after: Duration,
+    ) -> Option<Transition> {
+        match self.latest {
+            Some((latest_height, since)) if height <= latest_height => {
+                if !self.halted && now.duration_since(since) >= halt_after {
+                    self.halted = true;
+                    return Some(Transition::Halted);
+                }
+
+                None
+            }
+            _ => {
+                self.latest = Some((height, now));
+
+                if self.halted {
+                    self.halted = false;
+                    Some(Transition::Resumed)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+
+    #[test]
+    fn detector_uses_clock() {
+        let config: HaltDetectionConfig =
+            toml::from_str("rpc_addr = \"tcp://127.0.0.1:26657\"").unwrap();
+        let clock = Arc::new(MockClock::new());
+        let detector = HaltDetector::with_clock(&config, clock.clone());
+        let chain_id: Id = "halt-chain".parse().unwrap();
+        let height = block::Height::from(7u32);
+
+        detector.observe(&chain_id, height);
+        clock.advance(Duration::from_secs(DEFAULT_HALT_AFTER_SECS - 1));
+        detector.observe(&chain_id, height);
+        assert!(!detector.is_halted());
+
+        clock.advance(Duration::from_secs(1));
+        detector.observe(&chain_id, height);
+        assert!(detector.is_halted());
+
+        detector.observe(&chain_id, height.increment());
+        assert!(!detector.is_halted());
+    }
+
+    #[test]
+    fn halt_and_resume() {
+        let halt_after = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut status = Status::default();
+
+        let height = block::Height::from(42u32);
+        assert_eq!(status.observe(height, start, halt_after), None);
+        assert_eq!(
+            status.observe(height, start + Duration::from_secs(59), halt_after),
+            None
+        );
+        assert_eq!(
+            status.observe(height, start + Duration::from_secs(60), halt_after),
+            Some(Transition::Halted)
+        );
+        assert_eq!(
+            status.observe(height, start + Duration::from_secs(120), halt_after),
+            None
+        );
+        assert_eq!(
+            status.observe(
+                height.increment(),
+                start + Duration::from_secs(121),
+                halt_after
+            ),
+            Some(Transition::Resumed)
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! Warm-standby coordination lock
+//!
+//! When an active/standby pair of KMS instances serve the same chain, the
+//! lock ensures only the instance holding the lease will sign. The lease is a
+//! small JSON file on storage shared by both instances:
+//!
+//! - A lease is acquired by creating the lease file if it's absent.
+//! - The holder renews its lease on every signature it produces.
+//! - A standby may only take over a lease once it has expired, i.e. the active
+//!   instance hasn't signed anything within `ttl_secs`.
+//! - The lease is checked before every signature: if the lease has expired or
+//!   is held by another instance, signing stops immediately.
+//!
+//! Reading the lease and writing it back happen while holding an exclusive
+//! `flock` on a sidecar `<path>.lock` file, so two instances can never both
+//! decide an expired lease is theirs to take: the shared storage must support
+//! file locks (e.g. a local filesystem, or NFSv4).
+//!
+//! Both instances must have reasonably synchronized clocks, and the TTL should
+//! comfortably exceed the chain's block time so an idle-but-healthy active
+//! instance doesn't lose its lease between blocks.
+
+use crate::{
+    clock::{Clock, SystemClock},
+    config::chain::StandbyLockConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use rustix::fs::{flock, FlockOperation};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+use tempfile::NamedTempFile;
+
+/// Default lease TTL when a user one is unspecified
+const DEFAULT_TTL_SECS: u64 = 30;
+
+/// Coordination lock ensuring only one KMS instance signs for a chain
+pub struct StandbyLock {
+    /// Path to the shared lease file
+    path: PathBuf,
+
+    /// Name of this KMS instance
+    holder: String,
+
+    /// How long a lease is valid for without being renewed
+    ttl: Duration,
+
+    /// Clock lease expiry is measured with
+    clock: Arc<dyn Clock>,
+}
+
+/// Lease stored in the lease file (serialized as JSON)
+#[derive(Debug, Deserialize, Serialize)]
+struct Lease {
+    /// Name of the KMS instance holding the lease
+    holder: String,
+
+    /// UNIX timestamp (in seconds) at which the lease expires
+    expires_at: u64,
+}
+
+impl StandbyLock {
+    /// Create a new standby lock from the given configuration
+    pub fn new(config: &StandbyLockConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Create a new standby lock measuring lease expiry with the given clock
+    pub fn with_clock(config: &StandbyLockConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            path: config.path.clone(),
+            holder: config.holder.clone(),
+            ttl: Duration::from_secs(config.ttl_secs.unwrap_or(DEFAULT_TTL_SECS)),
+            clock,
+        }
+    }
+
+    /// Ensure this instance holds the lease, acquiring or renewing it.
+    ///
+    /// Returns an error if the lease is held by another instance, in which
+    /// case no signature may be produced.
+    pub fn acquire(&self) -> Result<(), Error> {
+        let _guard = self.lock_file()?;
+        let now = self.unix_time();
+
+        match self.read_lease()? {
+            None => {
+                self.write_lease(now)?;
+                info!(
+                    "standby lock {}: acquired lease as `{}`",
+                    self.path.display(),
+                    self.holder
+                );
+                Ok(())
+            }
+            Some(lease) if lease.holder == self.holder && lease.expires_at > now => {
+                self.write_lease(now)
+            }
+            Some(lease) if lease.expires_at <= now => {
+                if lease.holder == self.holder {
+                    warn!(
+                        "standby lock {}: our lease expired at {}, reacquiring",
+                        self.path.display(),
+                        lease.expires_at
+                    );
+                } else {
+                    warn!(
+                        "standby lock {}: taking over expired lease from `{}`",
+                        self.path.display(),
+                        lease.holder
+                    );
+                }
+
+                self.write_lease(now)
+            }
+            Some(lease) => fail!(
+                AccessError,
+                "standby lock {} held by `{}` for another {}s; not signing",
+                self.path.display(),
+                lease.holder,
+                lease.expires_at - now
+            ),
+        }
+    }
+
+    /// Take an exclusive lock on the sidecar lock file, held until the
+    /// returned file is dropped
+    fn lock_file(&self) -> Result<File, Error> {
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(|e| {
+                format_err!(
+                    IoError,
+                    "couldn't open {}: {}",
+                    Path::new(&lock_path).display(),
+                    e
+                )
+            })?;
+
+        flock(&file, FlockOperation::LockExclusive).map_err(|e| {
+            format_err!(
+                IoError,
+                "couldn't lock {}: {}",
+                Path::new(&lock_path).display(),
+                e
+            )
+        })?;
+
+        Ok(file)
+    }
+
+    /// Read the current lease (if any)
+    fn read_lease(&self) -> Result<Option<Lease>, Error> {
+        match fs::read_to_string(&self.path) {
+            Ok(json) => Ok(Some(serde_json::from_str(&json).map_err(|e| {
+                format_err!(ParseError, "error parsing {}: {}", self.path.display(), e)
+            })?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Atomically write a lease held by this instance
+    fn write_lease(&self, now: u64) -> Result<(), Error> {
+        let json = self.lease_json(now)?;
+
+        let lease_dir = self.path.parent().unwrap_or_else(|| {
+            panic!("lease file cannot be root directory");
+        });
+
+        let mut lease_file = NamedTempFile::new_in(lease_dir)?;
+        lease_file.write_all(json.as_bytes())?;
+        lease_file.as_file().sync_all()?;
+        lease_file
+            .persist(&self.path)
+            .map_err(|e| Error::from(e.error))?;
+
+        Ok(())
+    }
+
+    /// Serialize a lease held by this instance starting at `now`
+    fn lease_json(&self, now: u64) -> Result<String, Error> {
+        Ok(serde_json::to_string(&Lease {
+            holder: self.holder.clone(),
+            expires_at: now + self.ttl.as_secs(),
+        })?)
+    }
+
+    /// Current UNIX time in seconds
+    fn unix_time(&self) -> u64 {
+        self.clock
+            .system_time()
+            .duration_since(UNIX_EPOCH)
+            .expect("system clock is before the UNIX epoch")
+            .as_secs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::{sync::Barrier, thread};
+
+    fn lock(path: &Path, holder: &str, ttl_secs: u64) -> StandbyLock {
+        StandbyLock::new(&StandbyLockConfig {
+            path: path.to_owned(),
+            holder: holder.to_owned(),
+            ttl_secs: Some(ttl_secs),
+        })
+    }
+
+    fn mock_lock(path: &Path, holder: &str, clock: &Arc<MockClock>) -> StandbyLock {
+        StandbyLock::with_clock(
+            &StandbyLockConfig {
+                path: path.to_owned(),
+                holder: holder.to_owned(),
+                ttl_secs: Some(60),
+            },
+            clock.clone(),
+        )
+    }
+
+    #[test]
+    fn acquire_and_renew() {
+        let dir = tempfile::tempdir().unwrap();
+        let active = lock(&dir.path().join("lease.json"), "kms-a", 60);
+
+        active.acquire().unwrap();
+        active.acquire().unwrap();
+    }
+
+    #[test]
+    fn standby_refused_while_lease_valid() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lease.json");
+
+        lock(&path, "kms-a", 60).acquire().unwrap();
+
+        let err = lock(&path, "kms-b", 60).acquire().unwrap_err();
+        assert_eq!(*err.kind(), AccessError);
+    }
+
+    #[test]
+    fn standby_takes_over_expired_lease() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lease.json");
+        let active = lock(&path, "kms-a", 0);
+        let standby = lock(&path, "kms-b", 60);
+
+        active.acquire().unwrap();
+        standby.acquire().unwrap();
+
+        // The former active instance must now refuse to sign
+        assert_eq!(*active.acquire().unwrap_err().kind(), AccessError);
+    }
+
+    #[test]
+    fn concurrent_takeover() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lease.json");
+        lock(&path, "kms-expired", 0).acquire().unwrap();
+
+        // Instances racing to take over the expired lease: exactly one wins
+        let barrier = Arc::new(Barrier::new(8));
+        let acquired = (0..8)
+            .map(|i| {
+                let path = path.clone();
+                let barrier = barrier.clone();
+
+                thread::spawn(move || {
+                    let lock = lock(&path, &format!("kms-{}", i), 60);
+                    barrier.wait();
+                    lock.acquire().is_ok()
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|&acquired| acquired)
+            .count();
+
+        assert_eq!(acquired, 1);
+    }
+
+    #[test]
+    fn renewal_extends_lease() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lease.json");
+        let clock = Arc::new(MockClock::new());
+        let active = mock_lock(&path, "kms-a", &clock);
+        let standby = mock_lock(&path, "kms-b", &clock);
+
+        active.acquire().unwrap();
+
+        // Renewed before expiry, the lease outlives its original TTL
+        clock.advance(Duration::from_secs(50));
+        active.acquire().unwrap();
+        clock.advance(Duration::from_secs(50));
+        assert_eq!(*standby.acquire().unwrap_err().kind(), AccessError);
+
+        // Once the active instance stops renewing, the standby takes over
+        clock.advance(Duration::from_secs(10));
+        standby.acquire().unwrap();
+        assert_eq!(*active.acquire().unwrap_err().kind(), AccessError);
+    }
+}
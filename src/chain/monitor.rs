@@ -0,0 +1,152 @@
+//! Cross-checking local signing state against the chain (`tmkms monitor`)
+//!
+//! Polls a chain's state file for heights the KMS has signed a precommit for,
+//! and a node's RPC endpoint for whether the block's canonical commit
+//! includes this validator's precommit. A missing precommit the KMS signed
+//! means the signature never made it on chain, e.g. because the connection
+//! between the KMS and its validator was lost.
+//!
+//! The state file is polled, so heights signed between two polls aren't
+//! checked: this is a best-effort operational aid, and never touches the
+//! state file or signing providers.
+
+use super::{node, state::State, Id};
+use crate::{error::Error, prelude::*, Map};
+use std::{collections::BTreeSet, fmt, path::PathBuf, sync::Mutex};
+use tendermint::block;
+use tendermint_config::net;
+
+/// Consensus step of precommits in state files
+const PRECOMMIT_STEP: i8 = 2;
+
+/// Maximum number of signed heights awaiting a canonical commit, so an
+/// unreachable node can't make the monitor's memory grow without bound
+const MAX_PENDING_HEIGHTS: usize = 1000;
+
+/// Number of checked heights with each outcome for each chain
+static OUTCOMES: Mutex<Map<(String, Outcome), u64>> = Mutex::new(Map::new());
+
+/// Monitor of a chain's signatures making it on chain
+pub struct Monitor {
+    /// Chain being monitored
+    chain_id: Id,
+
+    /// Path to the chain's state file
+    state_file: PathBuf,
+
+    /// Address of the node's RPC endpoint
+    rpc_addr: net::Address,
+
+    /// Hex address of the validator's consensus key
+    validator_address: String,
+
+    /// Signed heights whose commit hasn't been checked yet
+    pending: BTreeSet<block::Height>,
+
+    /// Highest signed height seen so far
+    last_signed: Option<block::Height>,
+}
+
+impl Monitor {
+    /// Create a monitor of the given chain's state file
+    pub fn new(
+        chain_id: Id,
+        state_file: PathBuf,
+        rpc_addr: net::Address,
+        validator_address: String,
+    ) -> Self {
+        Self {
+            chain_id,
+            state_file,
+            rpc_addr,
+            validator_address: validator_address.to_uppercase(),
+            pending: BTreeSet::new(),
+            last_signed: None,
+        }
+    }
+
+    /// Record the height last signed in the state file (if it's a new
+    /// precommit), then check every signed height the chain has committed
+    /// since, returning how many of them are missing our precommit
+    pub fn poll(&mut self) -> Result<usize, Error> {
+        if let Some(state) = State::read_consensus_state(&self.state_file)? {
+            if state.step == PRECOMMIT_STEP && self.last_signed < Some(state.height) {
+                self.last_signed = Some(state.height);
+                self.pending.insert(state.height);
+
+                while self.pending.len() > MAX_PENDING_HEIGHTS {
+                    self.pending.pop_first();
+                }
+            }
+        }
+
+        let latest_height = node::latest_block_height(&self.rpc_addr)?;
+        let mut missing = 0;
+
+        while let Some(&height) = self.pending.first() {
+            if height >= latest_height {
+                break;
+            }
+
+            let outcome =
+                match node::commit_includes(&self.rpc_addr, height, &self.validator_address)? {
+                    Some(true) => Outcome::Included,
+                    Some(false) => Outcome::Missing,
+                    None => break,
+                };
+
+            self.pending.remove(&height);
+            *OUTCOMES
+                .lock()
+                .unwrap()
+                .entry((self.chain_id.to_string(), outcome))
+                .or_default() += 1;
+
+            if outcome == Outcome::Missing {
+                missing += 1;
+
+                error!(
+                    "[{}] precommit signed at height {} is missing from the chain's commit \
+                     (validator {}): was the validator connected?",
+                    self.chain_id, height, self.validator_address
+                );
+            } else {
+                debug!(
+                    "[{}] precommit signed at height {} is included on chain",
+                    self.chain_id, height
+                );
+            }
+        }
+
+        Ok(missing)
+    }
+}
+
+/// Outcome of checking a signed height against the chain
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Outcome {
+    /// The commit includes our precommit
+    Included,
+
+    /// The commit doesn't include the precommit we signed
+    Missing,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Included => "included",
+            Outcome::Missing => "missing",
+        })
+    }
+}
+
+/// Number of checked heights with each outcome for each chain so far
+pub fn outcomes() -> Vec<(String, Outcome, u64)> {
+    OUTCOMES
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((chain_id, outcome), count)| (chain_id.clone(), *outcome, *count))
+        .collect()
+}
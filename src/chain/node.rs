@@ -0,0 +1,329 @@
+//! Minimal client for a node's CometBFT RPC endpoint
+//!
+//! This is only used for best-effort operational checks (it's never on the
+//! signing path), so it speaks plain HTTP/1.0 over TCP to avoid pulling in a
+//! full HTTP client.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::{
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+use tendermint::block;
+use tendermint_config::net;
+
+/// Timeout for RPC requests
+const RPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of an RPC response we're willing to read
+const MAX_RESPONSE_SIZE: u64 = 1024 * 1024;
+
+/// Maximum number of characters of an unexpected response body to include in
+/// error messages
+const BODY_PREVIEW_LEN: usize = 100;
+
+/// Perform a JSON-RPC `GET` request for the given path (e.g. `/status`),
+/// returning the `result` field of the response
+///
+/// A response cut short by an unexpected EOF (e.g. a network blip mid-body)
+/// is refetched once, provided that's still within `RPC_TIMEOUT` of the first
+/// attempt: requests are read-only, so repeating them is harmless.
+pub fn get(addr: &net::Address, path: &str) -> Result<serde_json::Value, Error> {
+    let (host, port) = match addr {
+        net::Address::Tcp { host, port, .. } => (host, *port),
+        net::Address::Unix { .. } => fail!(ConfigError, "RPC address must be tcp://: {}", addr),
+    };
+
+    let socket_addr = (host.as_str(), port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format_err!(IoError, "couldn't resolve RPC address: {}", addr))?;
+
+    let started_at = Instant::now();
+    let mut response = fetch(&socket_addr, host, port, path)?;
+
+    if is_truncated(&response) {
+        ensure!(
+            started_at.elapsed() < RPC_TIMEOUT,
+            ProtocolError,
+            "RPC request to {}{} failed: response truncated by unexpected EOF",
+            addr,
+            path
+        );
+
+        debug!(
+            "RPC response from {}{} truncated by unexpected EOF; retrying once",
+            addr, path
+        );
+
+        response = fetch(&socket_addr, host, port, path)?;
+
+        ensure!(
+            !is_truncated(&response),
+            ProtocolError,
+            "RPC request to {}{} failed: response truncated by unexpected EOF (after retrying)",
+            addr,
+            path
+        );
+    }
+
+    parse_response(&response).map_err(|e| {
+        format_err!(
+            ProtocolError,
+            "RPC request to {}{} failed: {}",
+            addr,
+            path,
+            e
+        )
+        .into()
+    })
+}
+
+/// Send a `GET` request for the given path, returning the raw HTTP response
+fn fetch(socket_addr: &SocketAddr, host: &str, port: u16, path: &str) -> Result<Vec<u8>, Error> {
+    let mut socket = TcpStream::connect_timeout(socket_addr, RPC_TIMEOUT)?;
+    socket.set_read_timeout(Some(RPC_TIMEOUT))?;
+    socket.set_write_timeout(Some(RPC_TIMEOUT))?;
+
+    // HTTP/1.0 so the response body is never chunked
+    write!(socket, "GET {path} HTTP/1.0\r\nHost: {host}:{port}\r\n\r\n")?;
+
+    let mut response = vec![];
+    socket.take(MAX_RESPONSE_SIZE).read_to_end(&mut response)?;
+    Ok(response)
+}
+
+/// Was the given HTTP response cut short, i.e. did the connection reach EOF
+/// before the headers, the `Content-Length` worth of body, or the end of a
+/// JSON body (if no length was given)?
+fn is_truncated(response: &[u8]) -> bool {
+    // Oversized responses are cut off deliberately, and retrying won't help
+    if response.len() as u64 >= MAX_RESPONSE_SIZE {
+        return false;
+    }
+
+    let Some(head_len) = response.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return true;
+    };
+
+    let head = String::from_utf8_lossy(&response[..head_len]);
+    let body = &response[head_len + 4..];
+
+    let content_length = head.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-length")
+            .then(|| value.trim().parse::<usize>().ok())?
+    });
+
+    match content_length {
+        Some(content_length) => body.len() < content_length,
+        None => serde_json::from_slice::<serde_json::Value>(body).is_err_and(|e| e.is_eof()),
+    }
+}
+
+/// Get the latest block height known to the node
+pub fn latest_block_height(addr: &net::Address) -> Result<block::Height, Error> {
+    let status = get(addr, "/status")?;
+
+    status["sync_info"]["latest_block_height"]
+        .as_str()
+        .and_then(|height| height.parse().ok())
+        .ok_or_else(|| {
+            format_err!(
+                ProtocolError,
+                "malformed /status response from {}: missing latest_block_height",
+                addr
+            )
+            .into()
+        })
+}
+
+/// Get the address of the node's own validator key (uppercase hex), i.e. the
+/// key its remote signer provides
+pub fn validator_address(addr: &net::Address) -> Result<String, Error> {
+    let status = get(addr, "/status")?;
+
+    status["validator_info"]["address"]
+        .as_str()
+        .map(str::to_uppercase)
+        .ok_or_else(|| {
+            format_err!(
+                ProtocolError,
+                "malformed /status response from {}: missing validator_info.address",
+                addr
+            )
+            .into()
+        })
+}
+
+/// Check whether the canonical commit of the block at the given height
+/// includes a precommit from the validator with the given (hex) address.
+///
+/// Returns `None` if the block's commit isn't canonical yet, i.e. the next
+/// block hasn't been committed.
+pub fn commit_includes(
+    addr: &net::Address,
+    height: block::Height,
+    validator_address: &str,
+) -> Result<Option<bool>, Error> {
+    let commit = get(addr, &format!("/commit?height={}", height))?;
+
+    if commit["canonical"].as_bool() != Some(true) {
+        return Ok(None);
+    }
+
+    let signatures = commit["signed_header"]["commit"]["signatures"]
+        .as_array()
+        .ok_or_else(|| {
+            format_err!(
+                ProtocolError,
+                "malformed /commit response from {}: missing signatures",
+                addr
+            )
+        })?;
+
+    Ok(Some(signatures.iter().any(|signature| {
+        signature["validator_address"]
+            .as_str()
+            .is_some_and(|address| address.eq_ignore_ascii_case(validator_address))
+            && signature["signature"]
+                .as_str()
+                .is_some_and(|sig| !sig.is_empty())
+    })))
+}
+
+/// Parse an HTTP response containing a JSON-RPC response body, returning its `result`
+fn parse_response(response: &[u8]) -> Result<serde_json::Value, String> {
+    let response = std::str::from_utf8(response).map_err(|e| e.to_string())?;
+
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| "malformed HTTP response".to_owned())?;
+
+    let status_line = head.lines().next().unwrap_or_default();
+
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("unexpected HTTP status: {status_line}"));
+    }
+
+    // e.g. a misconfigured proxy answering with an HTML error page
+    let content_type = head.lines().skip(1).find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("content-type")
+            .then(|| value.trim())
+    });
+
+    if let Some(content_type) = content_type {
+        if !is_json(content_type) {
+            return Err(format!(
+                "expected JSON, got `{}`: {}",
+                content_type,
+                body_preview(body)
+            ));
+        }
+    }
+
+    let mut json: serde_json::Value = serde_json::from_str(body).map_err(|e| e.to_string())?;
+
+    if let Some(error) = json.get("error") {
+        return Err(format!("RPC error: {error}"));
+    }
+
+    match json.get_mut("result") {
+        Some(result) => Ok(result.take()),
+        None => Err("missing `result` in JSON-RPC response".to_owned()),
+    }
+}
+
+/// Is the given `Content-Type` JSON? (e.g. `application/json; charset=utf-8`)
+fn is_json(content_type: &str) -> bool {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim();
+    media_type.eq_ignore_ascii_case("application/json")
+        || media_type.to_ascii_lowercase().ends_with("+json")
+}
+
+/// Abbreviate a response body for inclusion in an error message
+fn body_preview(body: &str) -> String {
+    let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if body.chars().count() > BODY_PREVIEW_LEN {
+        format!(
+            "{}...",
+            body.chars().take(BODY_PREVIEW_LEN).collect::<String>()
+        )
+    } else {
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_truncated, parse_response};
+
+    #[test]
+    fn parse_status_response() {
+        let response = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n\
+            {\"jsonrpc\":\"2.0\",\"id\":-1,\"result\":{\"sync_info\":{\"latest_block_height\":\"347290\"}}}";
+
+        let result = parse_response(response).unwrap();
+        assert_eq!(result["sync_info"]["latest_block_height"], "347290");
+    }
+
+    #[test]
+    fn reject_error_responses() {
+        assert!(parse_response(b"HTTP/1.0 500 Internal Server Error\r\n\r\n{}").is_err());
+        assert!(parse_response(b"HTTP/1.0 200 OK\r\n\r\n<html></html>").is_err());
+        assert!(parse_response(
+            b"HTTP/1.0 200 OK\r\n\r\n{\"jsonrpc\":\"2.0\",\"id\":-1,\"error\":{\"code\":-32603}}"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn reject_non_json_content_type() {
+        let err = parse_response(
+            b"HTTP/1.0 200 OK\r\nContent-Type: text/html\r\n\r\n\
+            <html>\n  <body>502 Bad Gateway</body>\n</html>",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err,
+            "expected JSON, got `text/html`: <html> <body>502 Bad Gateway</body> </html>"
+        );
+
+        assert!(parse_response(
+            b"HTTP/1.0 200 OK\r\ncontent-type: application/json; charset=utf-8\r\n\r\n\
+            {\"jsonrpc\":\"2.0\",\"id\":-1,\"result\":{}}"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn detect_truncated_responses() {
+        let complete = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n\
+            {\"jsonrpc\":\"2.0\",\"id\":-1,\"result\":{}}";
+
+        assert!(!is_truncated(complete));
+        assert!(is_truncated(&complete[..complete.len() - 3]));
+        assert!(is_truncated(b"HTTP/1.0 200 OK\r\nContent-Ty"));
+        assert!(is_truncated(b""));
+
+        // Truncation is judged by `Content-Length`, if present
+        assert!(!is_truncated(
+            b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\n{}"
+        ));
+        assert!(is_truncated(
+            b"HTTP/1.0 200 OK\r\nContent-Length: 40\r\n\r\n{}"
+        ));
+
+        // Complete but malformed bodies aren't truncated
+        assert!(!is_truncated(b"HTTP/1.0 200 OK\r\n\r\n<html></html>"));
+    }
+}
@@ -0,0 +1,196 @@
+//! Sign cadence watchdog
+//!
+//! An active validator's signer sends signatures at roughly the chain's block
+//! cadence, so going quiet for much longer than that is a strong failure
+//! signal. The watchdog checks how long it's been since a signature for the
+//! chain was last sent to a validator (or since startup, if none has been),
+//! and once that exceeds `quiet_after_secs` logs
+//! a warning, reports it via the `tmkms_signer_quiet` metric, and optionally
+//! exits so an orchestrator can restart the KMS.
+//!
+//! If halt detection is configured for the chain, a quiet signer on a halted
+//! chain (i.e. one where all validators are quiet) is only logged: restarting
+//! the KMS wouldn't help.
+
+use super::{Id, REGISTRY};
+use crate::{
+    config::chain::{QuietAction, SignWatchdogConfig},
+    prelude::*,
+};
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Interval at which the time since the last signature sent is checked
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Exit code used when exiting because this signer went quiet
+pub const EXIT_CODE: i32 = 4;
+
+/// Sign cadence watchdog for a chain
+#[derive(Clone)]
+pub struct SignWatchdog {
+    /// Time without a signature sent after which we're quiet
+    quiet_after: Duration,
+
+    /// What to do once quiet
+    action: QuietAction,
+
+    /// Is this signer currently quiet (on a chain which isn't halted)?
+    quiet: Arc<AtomicBool>,
+}
+
+impl SignWatchdog {
+    /// Create a new watchdog from the given configuration
+    pub fn new(config: &SignWatchdogConfig) -> Self {
+        Self {
+            quiet_after: Duration::from_secs(config.quiet_after_secs),
+            action: config.action.unwrap_or_default(),
+            quiet: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Is this signer currently quiet (on a chain which isn't halted)?
+    pub fn is_quiet(&self) -> bool {
+        self.quiet.load(Ordering::Relaxed)
+    }
+
+    /// Spawn a background thread watching the given chain
+    pub fn spawn(&self, chain_id: &Id) {
+        let watchdog = self.clone();
+        let chain_id = chain_id.clone();
+
+        thread::Builder::new()
+            .name(format!("{}@sign-watchdog", chain_id))
+            .spawn(move || watchdog.watch_loop(&chain_id))
+            .unwrap_or_else(|e| {
+                status_err!("error spawning thread: {}", e);
+                process::exit(1);
+            });
+    }
+
+    /// Check the chain's cadence forever
+    fn watch_loop(&self, chain_id: &Id) {
+        let started_at = SystemTime::now();
+        let mut condition = Condition::Signing;
+
+        loop {
+            thread::sleep(CHECK_INTERVAL);
+
+            let Some((released_at, halt_detection, halted)) = chain_status(chain_id) else {
+                continue;
+            };
+
+            let quiet_for = SystemTime::now()
+                .duration_since(released_at.unwrap_or(started_at))
+                .unwrap_or_default();
+
+            let new_condition = Condition::new(quiet_for, self.quiet_after, halted);
+            self.quiet
+                .store(new_condition == Condition::SignerQuiet, Ordering::Relaxed);
+
+            if new_condition == condition {
+                continue;
+            }
+
+            condition = new_condition;
+
+            match condition {
+                Condition::Signing => info!("[{}] sending signatures again", chain_id),
+                Condition::ChainHalted => info!(
+                    "[{}] no signatures sent for {}s, but the chain appears halted",
+                    chain_id,
+                    quiet_for.as_secs()
+                ),
+                Condition::SignerQuiet => {
+                    warn!(
+                        "[{}] no signatures sent for {}s: this signer appears quiet{}",
+                        chain_id,
+                        quiet_for.as_secs(),
+                        if halt_detection {
+                            " (the chain itself is making progress)"
+                        } else {
+                            " (configure halt_detection to tell whether the chain has halted)"
+                        }
+                    );
+
+                    if self.action == QuietAction::Exit {
+                        error!(
+                            "*** [{}] sign_watchdog action = \"exit\": shutting down ***",
+                            chain_id
+                        );
+                        process::exit(EXIT_CODE);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// When a signature for the chain was last sent, whether it has halt
+/// detection configured, and whether it appears halted
+fn chain_status(chain_id: &Id) -> Option<(Option<SystemTime>, bool, bool)> {
+    let registry = REGISTRY.get();
+    let chain = registry.get_chain(chain_id)?;
+    let released_at = *chain.last_released_at.lock().ok()?;
+    Some((
+        released_at,
+        chain.halt_detector.is_some(),
+        chain.is_halted(),
+    ))
+}
+
+/// Signing condition of a chain
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Condition {
+    /// Signatures are being sent within the expected cadence
+    Signing,
+
+    /// No signatures sent, but the chain appears halted
+    ChainHalted,
+
+    /// No signatures sent, although the chain isn't known to be halted
+    SignerQuiet,
+}
+
+impl Condition {
+    /// Determine the condition from the time since the last signature sent
+    fn new(quiet_for: Duration, quiet_after: Duration, halted: bool) -> Self {
+        if quiet_for < quiet_after {
+            Condition::Signing
+        } else if halted {
+            Condition::ChainHalted
+        } else {
+            Condition::SignerQuiet
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditions() {
+        let quiet_after = Duration::from_secs(30);
+
+        assert_eq!(
+            Condition::new(Duration::from_secs(29), quiet_after, true),
+            Condition::Signing
+        );
+        assert_eq!(
+            Condition::new(quiet_after, quiet_after, false),
+            Condition::SignerQuiet
+        );
+        assert_eq!(
+            Condition::new(quiet_after, quiet_after, true),
+            Condition::ChainHalted
+        );
+    }
+}
@@ -70,6 +70,21 @@ impl Registry {
     pub fn get_chain(&self, chain_id: &Id) -> Option<&Chain> {
         self.0.get(chain_id)
     }
+
+    /// Iterate over all registered chains
+    pub fn chains(&self) -> impl Iterator<Item = &Chain> {
+        self.0.values()
+    }
+
+    /// Ensure each chain's signing providers agree on its consensus key (see
+    /// [`keyring::KeyRing::reconcile`])
+    pub fn reconcile_keys(&mut self, expect_different: bool) -> Result<(), Error> {
+        for chain in self.0.values_mut() {
+            chain.keyring.reconcile(&chain.id, expect_different)?;
+        }
+
+        Ok(())
+    }
 }
 
 /// Global registry of blockchain networks known to the KMS
@@ -96,4 +111,9 @@ impl GlobalRegistry {
         let mut registry = self.0.write().unwrap();
         registry.register_chain(chain)
     }
+
+    /// Ensure each chain's signing providers agree on its consensus key
+    pub fn reconcile_keys(&self, expect_different: bool) -> Result<(), Error> {
+        self.0.write().unwrap().reconcile_keys(expect_different)
+    }
 }
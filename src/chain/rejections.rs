@@ -0,0 +1,165 @@
+//! Tracking of repeated double-signing guard rejections
+//!
+//! A flood of requests for the same height/round/step which the guard keeps
+//! rejecting usually means a stuck or looping node, or an active/standby pair
+//! of validators flapping. Counting consecutive rejections of the same
+//! height/round/step makes this visible.
+//!
+//! Chains can also be configured with a `rejection_grace_period`: after a
+//! rejection, new validator connections wait out the remainder of it before
+//! their requests are processed, giving the topology time to settle rather
+//! than racing a connection from the instance which was just rejected.
+
+use std::time::{Duration, Instant};
+use tendermint::consensus;
+
+/// Number of consecutive same-HRS rejections after which we start warning
+pub const WARNING_THRESHOLD: u64 = 5;
+
+/// Minimum interval between warnings about consecutive rejections
+pub const WARNING_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Tracks consecutive guard rejections of the same height/round/step
+#[derive(Debug, Default)]
+pub struct RejectionTracker {
+    /// Height/round/step of the most recently rejected request
+    last_rejected: Option<consensus::State>,
+
+    /// Number of consecutive rejections of `last_rejected`
+    consecutive: u64,
+
+    /// When we last warned about consecutive rejections
+    last_warning: Option<Instant>,
+
+    /// When the most recent rejection happened (not reset by successes)
+    last_rejected_at: Option<Instant>,
+}
+
+impl RejectionTracker {
+    /// Number of consecutive rejections of the same height/round/step
+    pub fn consecutive(&self) -> u64 {
+        self.consecutive
+    }
+
+    /// Record a rejected request, returning the number of consecutive
+    /// rejections if a (throttled) warning should be logged
+    pub fn record_rejection(
+        &mut self,
+        request_state: &consensus::State,
+        now: Instant,
+    ) -> Option<u64> {
+        let same_hrs = self.last_rejected.as_ref().is_some_and(|last| {
+            last.height == request_state.height
+                && last.round == request_state.round
+                && last.step == request_state.step
+        });
+
+        self.last_rejected_at = Some(now);
+
+        if same_hrs {
+            self.consecutive = self.consecutive.saturating_add(1);
+        } else {
+            self.last_rejected = Some(request_state.clone());
+            self.consecutive = 1;
+        }
+
+        if self.consecutive < WARNING_THRESHOLD {
+            return None;
+        }
+
+        match self.last_warning {
+            Some(last_warning) if now.duration_since(last_warning) < WARNING_INTERVAL => None,
+            _ => {
+                self.last_warning = Some(now);
+                Some(self.consecutive)
+            }
+        }
+    }
+
+    /// Record a successfully signed request, resetting the count
+    pub fn record_success(&mut self) {
+        self.last_rejected = None;
+        self.consecutive = 0;
+    }
+
+    /// Time remaining in the given grace period following the most recent
+    /// rejection, if it hasn't elapsed yet
+    pub fn grace_period_remaining(&self, grace_period: Duration, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.last_rejected_at?);
+        grace_period.checked_sub(elapsed).filter(|d| !d.is_zero())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tendermint::block;
+
+    fn hrs(height: u32, round: u16, step: i8) -> consensus::State {
+        consensus::State {
+            height: block::Height::from(height),
+            round: block::Round::from(round),
+            step,
+            block_id: None,
+        }
+    }
+
+    #[test]
+    fn warns_past_threshold_with_throttling() {
+        let mut tracker = RejectionTracker::default();
+        let now = Instant::now();
+
+        for _ in 1..WARNING_THRESHOLD {
+            assert_eq!(tracker.record_rejection(&hrs(10, 0, 1), now), None);
+        }
+
+        assert_eq!(
+            tracker.record_rejection(&hrs(10, 0, 1), now),
+            Some(WARNING_THRESHOLD)
+        );
+
+        // Throttled until the warning interval elapses
+        assert_eq!(tracker.record_rejection(&hrs(10, 0, 1), now), None);
+        assert_eq!(
+            tracker.record_rejection(&hrs(10, 0, 1), now + WARNING_INTERVAL),
+            Some(WARNING_THRESHOLD + 2)
+        );
+    }
+
+    #[test]
+    fn resets_on_new_hrs_or_success() {
+        let mut tracker = RejectionTracker::default();
+        let now = Instant::now();
+
+        tracker.record_rejection(&hrs(10, 0, 1), now);
+        tracker.record_rejection(&hrs(10, 0, 1), now);
+        assert_eq!(tracker.consecutive(), 2);
+
+        tracker.record_rejection(&hrs(10, 1, 1), now);
+        assert_eq!(tracker.consecutive(), 1);
+
+        tracker.record_success();
+        assert_eq!(tracker.consecutive(), 0);
+    }
+
+    #[test]
+    fn grace_period_follows_latest_rejection() {
+        let mut tracker = RejectionTracker::default();
+        let grace_period = Duration::from_secs(5);
+        let now = Instant::now();
+
+        assert_eq!(tracker.grace_period_remaining(grace_period, now), None);
+
+        tracker.record_rejection(&hrs(10, 0, 1), now);
+        tracker.record_success();
+        assert_eq!(
+            tracker.grace_period_remaining(grace_period, now + Duration::from_secs(2)),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(
+            tracker.grace_period_remaining(grace_period, now + grace_period),
+            None
+        );
+        assert_eq!(tracker.grace_period_remaining(Duration::ZERO, now), None);
+    }
+}
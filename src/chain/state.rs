@@ -4,17 +4,20 @@
 //! Double-signing protection is the primary purpose of this code (for now).
 
 mod error;
+mod fsync;
 pub mod hook;
 
 pub use self::error::{StateError, StateErrorKind};
 
 use crate::{
+    config::chain::FsyncPolicy,
     error::{Error, ErrorKind::*},
     prelude::*,
 };
 use std::{
-    fs,
+    fs::{self, File},
     io::{self, prelude::*},
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
 };
 use tempfile::NamedTempFile;
@@ -24,43 +27,109 @@ use tendermint::consensus;
 pub struct State {
     consensus_state: consensus::State,
     state_file_path: PathBuf,
+    fsync: FsyncPolicy,
 }
 
 impl State {
-    /// Load the state from the given path
+    /// Load the state from the given path, creating an initial state file at
+    /// height 0 if it doesn't exist
     pub fn load_state<P>(path: P) -> Result<Self, Error>
     where
         P: AsRef<Path>,
     {
-        match fs::read_to_string(path.as_ref()) {
-            Ok(state_json) => {
-                let consensus_state = serde_json::from_str(&state_json).map_err(|e| {
-                    format_err!(
-                        ParseError,
-                        "error parsing {}: {}",
-                        path.as_ref().display(),
-                        e
-                    )
-                })?;
-
-                Ok(Self {
-                    consensus_state,
-                    state_file_path: path.as_ref().to_owned(),
-                })
-            }
-            Err(e) if e.kind() == io::ErrorKind::NotFound => {
-                Self::write_initial_state(path.as_ref())
-            }
-            Err(e) => Err(Error::from(e)),
+        match Self::load_existing_state(path.as_ref())? {
+            Some(state) => Ok(state),
+            None => Self::write_initial_state(path.as_ref()),
         }
     }
 
+    /// Load the state from the given path, returning `None` if the state file
+    /// doesn't exist
+    pub fn load_existing_state(path: &Path) -> Result<Option<Self>, Error> {
+        Ok(
+            Self::read_consensus_state(path)?.map(|consensus_state| Self {
+                consensus_state,
+                state_file_path: path.to_owned(),
+                fsync: FsyncPolicy::default(),
+            }),
+        )
+    }
+
+    /// Load the state from the given path without creating the state file if
+    /// it doesn't exist (the initial state is used instead).
+    pub fn load_state_readonly(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            consensus_state: Self::read_consensus_state(path)?.unwrap_or_default(),
+            state_file_path: path.to_owned(),
+            fsync: FsyncPolicy::default(),
+        })
+    }
+
+    /// Read and validate the consensus state stored at the given path without
+    /// modifying it, returning `None` if the file does not exist.
+    ///
+    /// Refuses to load a state file which is world-writable or contains an
+    /// invalid height/round/step, as either could silently undermine the
+    /// double-signing guard.
+    pub fn read_consensus_state(path: &Path) -> Result<Option<consensus::State>, Error> {
+        let state_json = match fs::read_to_string(path) {
+            Ok(state_json) => state_json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(Error::from(e)),
+        };
+
+        let mode = fs::metadata(path)?.permissions().mode();
+
+        ensure!(
+            mode & 0o002 == 0,
+            AccessError,
+            "state file {} is world-writable (mode {:o}); refusing to load it",
+            path.display(),
+            mode & 0o777
+        );
+
+        let consensus_state: consensus::State = serde_json::from_str(&state_json)
+            .map_err(|e| format_err!(ParseError, "error parsing {}: {}", path.display(), e))?;
+
+        // Steps are 0 (proposal), 1 (prevote), or 2 (precommit)
+        ensure!(
+            (0..=2).contains(&consensus_state.step),
+            ParseError,
+            "corrupt state file {}: invalid step {}",
+            path.display(),
+            consensus_state.step
+        );
+
+        // The initial state can't have progressed past round/step zero
+        ensure!(
+            consensus_state.height.value() > 0
+                || (consensus_state.round.value() == 0
+                    && consensus_state.step == 0
+                    && consensus_state.block_id.is_none()),
+            ParseError,
+            "corrupt state file {}: progress recorded at height 0",
+            path.display()
+        );
+
+        Ok(Some(consensus_state))
+    }
+
+    /// Set when subsequent state updates are flushed to stable storage
+    pub fn set_fsync_policy(&mut self, fsync: FsyncPolicy) {
+        self.fsync = fsync;
+    }
+
     /// Borrow the current consensus state
     pub fn consensus_state(&self) -> &consensus::State {
         &self.consensus_state
     }
 
     /// Check and update the chain's height, round, and step
+    ///
+    /// The new state is persisted to disk before this function returns. If
+    /// persisting fails a `StateErrorKind::SyncError` is returned, and callers
+    /// MUST NOT release a signature for the request: the double-sign guard
+    /// can only be relied upon across restarts if the state hit the disk.
     // TODO(tarcieri): rewrite this logic to follow Tendermint spec and be clippy-friendly
     #[allow(clippy::comparison_chain)]
     pub fn update_consensus_state(
@@ -125,6 +194,7 @@ impl State {
                 e
             )
         })?;
+
         Ok(())
     }
 
@@ -169,14 +239,21 @@ impl State {
             ..Default::default()
         };
 
-        let initial_state = Self {
+        Self::write_state(path, consensus_state)
+    }
+
+    /// Write the given consensus state to the given path on disk, replacing
+    /// any existing state file
+    pub fn write_state(path: &Path, consensus_state: consensus::State) -> Result<Self, Error> {
+        let state = Self {
             consensus_state,
             state_file_path: path.to_owned(),
+            fsync: FsyncPolicy::default(),
         };
 
-        initial_state.sync_to_disk()?;
+        state.sync_to_disk()?;
 
-        Ok(initial_state)
+        Ok(state)
     }
 
     /// Sync the current state to disk
@@ -189,14 +266,28 @@ impl State {
 
         let json = serde_json::to_string(&self.consensus_state)?;
 
-        let state_file_dir = self.state_file_path.parent().unwrap_or_else(|| {
-            panic!("state file cannot be root directory");
-        });
+        let state_file_dir = match self.state_file_path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+            Some(dir) => dir,
+            None => panic!("state file cannot be root directory"),
+        };
 
         let mut state_file = NamedTempFile::new_in(state_file_dir)?;
         state_file.write_all(json.as_bytes())?;
+
+        if self.fsync != FsyncPolicy::Os {
+            state_file.as_file().sync_all()?;
+        }
+
         state_file.persist(&self.state_file_path)?;
 
+        // Make the rename itself durable
+        match self.fsync {
+            FsyncPolicy::Always => File::open(state_file_dir)?.sync_all()?,
+            FsyncPolicy::Batched => fsync::sync_dir(state_file_dir)?,
+            FsyncPolicy::Os => (),
+        }
+
         debug!(
             "successfully wrote new consensus state to {}",
             self.state_file_path.display(),
@@ -246,6 +337,7 @@ mod tests {
                 State {
                     consensus_state: $old_state,
                     state_file_path: EXAMPLE_PATH.into(),
+                    fsync: FsyncPolicy::Always,
                 }
                 .update_consensus_state($new_state)
                 .unwrap();
@@ -261,6 +353,7 @@ mod tests {
                 let err = State {
                     consensus_state: $old_state,
                     state_file_path: EXAMPLE_PATH.into(),
+                    fsync: FsyncPolicy::Always,
                 }
                 .update_consensus_state($new_state)
                 .expect_err("expected StateErrorKind::DoubleSign but succeeded");
@@ -311,4 +404,42 @@ mod tests {
         state!(1, 1, 2, None),
         state!(1, 1, 2, block_id!(EXAMPLE_BLOCK_ID))
     );
+
+    #[test]
+    fn reject_corrupt_state_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("priv_validator_state.json");
+
+        let invalid_step = state!(5, 0, 7, None);
+        fs::write(&path, serde_json::to_string(&invalid_step).unwrap()).unwrap();
+        assert_eq!(
+            *State::read_consensus_state(&path).unwrap_err().kind(),
+            ParseError
+        );
+
+        let valid = state!(5, 0, 1, None);
+        fs::write(&path, serde_json::to_string(&valid).unwrap()).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        assert_eq!(
+            *State::read_consensus_state(&path).unwrap_err().kind(),
+            AccessError
+        );
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(State::read_consensus_state(&path).unwrap(), Some(valid));
+    }
+
+    #[test]
+    fn sync_failure_fails_closed() {
+        let err = State {
+            consensus_state: state!(1, 1, 0, None),
+            state_file_path: "/nonexistent/tmkms/tmp_state.json".into(),
+            fsync: FsyncPolicy::Always,
+        }
+        .update_consensus_state(state!(2, 0, 0, None))
+        .expect_err("expected StateErrorKind::SyncError but succeeded");
+
+        assert_eq!(err.kind(), StateErrorKind::SyncError);
+        assert_eq!(*Error::from(err).kind(), StateSyncError);
+    }
 }
@@ -0,0 +1,127 @@
+//! Group commit of directory `fsync`s for the `batched` fsync policy
+//!
+//! Making a renamed state file durable requires an `fsync` of its directory.
+//! When several chains' state files live in the same directory and are
+//! updated concurrently, one directory `fsync` can cover all of them: an
+//! update waits for the first `fsync` which *starts* after its rename, either
+//! performing it itself or waiting for another thread's. No update returns
+//! before a directory `fsync` covering it has completed.
+
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+};
+
+/// Directory sync coordinators, by directory
+static DIRECTORIES: Lazy<Mutex<HashMap<PathBuf, Arc<DirSync>>>> = Lazy::new(Default::default);
+
+/// Flush the given directory to stable storage, sharing the `fsync` with
+/// concurrent callers where possible
+pub fn sync_dir(dir: &Path) -> io::Result<()> {
+    let dir_sync = DIRECTORIES
+        .lock()
+        .unwrap()
+        .entry(dir.to_owned())
+        .or_default()
+        .clone();
+
+    dir_sync.sync(dir)
+}
+
+/// Coordinates `fsync`s of a single directory
+#[derive(Default)]
+struct DirSync {
+    /// Sync progress
+    progress: Mutex<Progress>,
+
+    /// Signalled whenever an `fsync` finishes
+    finished: Condvar,
+}
+
+/// Progress of syncing a directory
+#[derive(Default)]
+struct Progress {
+    /// Number of sync requests so far
+    requested: u64,
+
+    /// All requests up to and including this one are durable
+    synced: u64,
+
+    /// Is an `fsync` currently in progress?
+    syncing: bool,
+}
+
+impl DirSync {
+    /// Wait until a directory `fsync` started after this call completes
+    fn sync(&self, dir: &Path) -> io::Result<()> {
+        let mut progress = self.progress.lock().unwrap();
+        progress.requested += 1;
+        let ticket = progress.requested;
+
+        loop {
+            if progress.synced >= ticket {
+                return Ok(());
+            }
+
+            if progress.syncing {
+                progress = self.finished.wait(progress).unwrap();
+                continue;
+            }
+
+            // Become the leader: this `fsync` covers every request so far
+            let covered = progress.requested;
+            progress.syncing = true;
+            drop(progress);
+
+            let result = File::open(dir).and_then(|d| d.sync_all());
+
+            progress = self.progress.lock().unwrap();
+            progress.syncing = false;
+
+            if result.is_ok() {
+                progress.synced = progress.synced.max(covered);
+            }
+
+            self.finished.notify_all();
+
+            // On failure, waiting requests retry (and may fail) themselves
+            result?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn concurrent_syncs_complete() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let threads = (0..8)
+            .map(|_| {
+                let path = dir.path().to_owned();
+                thread::spawn(move || sync_dir(&path))
+            })
+            .collect::<Vec<_>>();
+
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+
+        let dir_sync = DIRECTORIES.lock().unwrap()[dir.path()].clone();
+        let progress = dir_sync.progress.lock().unwrap();
+        assert_eq!(progress.requested, 8);
+        assert_eq!(progress.synced, 8);
+    }
+
+    #[test]
+    fn failed_sync_is_reported() {
+        assert!(sync_dir(Path::new("/nonexistent/tmkms")).is_err());
+    }
+}
@@ -6,13 +6,25 @@
 //! as a "Key Management System".
 
 use crate::{
+    backoff::Backoff,
     chain,
-    config::ValidatorConfig,
+    clock::{Clock, SystemClock},
+    config::{KmsConfig, ValidatorConfig},
+    control,
     error::{Error, ErrorKind},
+    events, keyring, maintenance, metrics,
     prelude::*,
+    rpc::Response,
     session::Session,
+    status::{self, ConnectionState},
+    watchdog,
+};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    process::exit,
+    thread,
+    time::Duration,
 };
-use std::{panic, process::exit, thread, time::Duration};
 
 /// Join handle type used by our clients
 type JoinHandle = thread::JoinHandle<Result<(), Error>>;
@@ -20,6 +32,10 @@ type JoinHandle = thread::JoinHandle<Result<(), Error>>;
 /// How long to wait after a crash before respawning (in seconds)
 pub const RESPAWN_DELAY: u64 = 1;
 
+/// Default minimum delay before reconnecting while the chain appears halted
+/// (in seconds)
+pub const HALTED_RECONNECT_DELAY: u64 = 30;
+
 /// Client connections: wraps a thread which makes a connection to a particular
 /// validator node and then receives RPCs.
 ///
@@ -43,7 +59,7 @@ impl Client {
 
         let handle = thread::Builder::new()
             .name(name.clone())
-            .spawn(move || main_loop(config))
+            .spawn(move || main_loop(config, &SystemClock))
             .unwrap_or_else(|e| {
                 status_err!("error spawning thread: {}", e);
                 exit(1);
@@ -63,26 +79,159 @@ impl Client {
     }
 }
 
-/// Main loop for all clients. Handles reconnecting in the event of an error
-fn main_loop(config: ValidatorConfig) -> Result<(), Error> {
-    while let Err(e) = run_client(config.clone()) {
+/// Load chains and signing keys from the given configuration into the global
+/// chain registry, then spawn a client for each configured validator.
+///
+/// This is the entry point used by `tmkms start`, and by applications which
+/// embed the KMS. It must only be called once per process.
+pub fn start(config: &KmsConfig) -> Result<Vec<Client>, Error> {
+    chain::load_config(config)?;
+    spawn_all(config)
+}
+
+/// Spawn a client for each configured validator, using the chains previously
+/// loaded into the global chain registry with [`chain::load_config`]
+pub fn spawn_all(config: &KmsConfig) -> Result<Vec<Client>, Error> {
+    for validator in &config.validator {
+        let registry = chain::REGISTRY.get();
+
+        let chain = registry.get_chain(&validator.chain_id).ok_or_else(|| {
+            format_err!(
+                ErrorKind::ConfigError,
+                "unregistered chain: {} (add it to tmkms.toml's [[chain]] section)",
+                validator.chain_id
+            )
+        })?;
+
+        if let Some(protocol_version) = chain.protocol_version {
+            ensure!(
+                validator.protocol_version == protocol_version,
+                ErrorKind::ConfigError,
+                "validator {} uses protocol_version {:?} but chain {} is configured for {:?}",
+                validator.addr,
+                validator.protocol_version,
+                chain.id,
+                protocol_version
+            );
+        }
+    }
+
+    if let Some(event_socket) = &config.event_socket {
+        events::init(event_socket)?;
+    }
+
+    maintenance::init(config.chain.iter().map(|chain| chain.state_file_path()))?;
+
+    if let Some(control_socket) = &config.control_socket {
+        control::init(control_socket)?;
+    }
+
+    if let Some(metrics_addr) = &config.metrics_addr {
+        metrics::init(metrics_addr)?;
+    }
+
+    if let Some(max_rss_mb) = config.max_rss_mb {
+        watchdog::spawn(max_rss_mb)?;
+    }
+
+    chain::spawn_halt_detectors();
+    chain::spawn_sign_watchdogs();
+    chain::spawn_checkpointers();
+    keyring::spawn_key_checks();
+
+    Ok(config
+        .validator
+        .iter()
+        .cloned()
+        .map(Client::spawn)
+        .collect())
+}
+
+/// Main loop for all clients. Handles reconnecting in the event of an error,
+/// waiting between attempts on the given clock
+fn main_loop(config: ValidatorConfig, clock: &dyn Clock) -> Result<(), Error> {
+    let min_delay = config.reconnect_delay_secs.unwrap_or(RESPAWN_DELAY);
+    let max_delay = config.max_reconnect_delay_secs.unwrap_or(min_delay);
+
+    let mut backoff = Backoff::new(
+        Duration::from_secs(min_delay),
+        Duration::from_secs(max_delay),
+        config.reconnect_jitter(),
+    );
+
+    loop {
+        let mut connected = false;
+        status::set_connection_state(&config, ConnectionState::Connecting);
+
+        let e = match run_session(config.clone(), &mut connected) {
+            Ok(()) => {
+                status::set_connection_state(&config, ConnectionState::Stopped);
+                return Ok(());
+            }
+            Err(e) => e,
+        };
+
+        status::record_connection_error(&config, &e);
+
         // `PoisonError` is unrecoverable
         if *e.kind() == ErrorKind::PoisonError {
-            error!("[{}@{}] FATAL -- {}", &config.chain_id, &config.addr, e);
+            error!(
+                class = %e.class(),
+                "[{}@{}] FATAL -- {}",
+                &config.chain_id,
+                &config.addr,
+                e
+            );
+            status::set_connection_state(&config, ConnectionState::Stopped);
             return Err(e);
         } else {
-            error!("[{}@{}] {}", &config.chain_id, &config.addr, e);
+            error!(
+                class = %e.class(),
+                "[{}@{}] {}",
+                &config.chain_id,
+                &config.addr,
+                e
+            );
         }
 
-        if config.reconnect {
-            // TODO: configurable respawn delay
-            thread::sleep(Duration::from_secs(RESPAWN_DELAY));
-        } else {
+        if !config.reconnect {
+            status::set_connection_state(&config, ConnectionState::Stopped);
             return Err(e);
         }
-    }
 
-    Ok(())
+        // Back off exponentially while we're unable to connect
+        if connected {
+            backoff.reset();
+        }
+
+        let delay = backoff.next_delay();
+
+        // Don't hammer a validator which is stuck on a halted chain
+        let delay = if chain_is_halted(&config.chain_id) {
+            let delay = delay.max(Duration::from_secs(
+                config
+                    .halted_reconnect_delay_secs
+                    .unwrap_or(HALTED_RECONNECT_DELAY),
+            ));
+            info!(
+                "[{}@{}] chain appears halted; reconnecting in {} ms",
+                &config.chain_id,
+                &config.addr,
+                delay.as_millis()
+            );
+            delay
+        } else {
+            debug!(
+                "[{}@{}] reconnecting in {} ms",
+                &config.chain_id,
+                &config.addr,
+                delay.as_millis()
+            );
+            delay
+        };
+
+        clock.sleep(delay);
+    }
 }
 
 /// Ensure chain with given ID is properly registered
@@ -99,8 +248,37 @@ pub fn register_chain(chain_id: &chain::Id) {
     });
 }
 
+/// Does the chain with the given ID appear to be halted?
+fn chain_is_halted(chain_id: &chain::Id) -> bool {
+    chain::REGISTRY
+        .get()
+        .get_chain(chain_id)
+        .map(chain::Chain::is_halted)
+        .unwrap_or(false)
+}
+
 /// Open a new session and run the session loop
 pub fn run_client(config: ValidatorConfig) -> Result<(), Error> {
-    panic::catch_unwind(move || Session::open(config)?.request_loop())
-        .unwrap_or_else(|e| Err(Error::from_panic(e)))
+    run_session(config, &mut false)
+}
+
+/// Open a new session and handle requests until a single sign request has
+/// been answered (for `tmkms start --once`)
+pub fn sign_once(config: ValidatorConfig) -> Result<Response, Error> {
+    panic::catch_unwind(AssertUnwindSafe(move || {
+        Session::open(config)?.handle_sign_request()
+    }))
+    .unwrap_or_else(|e| Err(Error::from_panic(e)))
+}
+
+/// Open a new session and run the session loop, noting whether we were able
+/// to connect to the validator
+fn run_session(config: ValidatorConfig, connected: &mut bool) -> Result<(), Error> {
+    panic::catch_unwind(AssertUnwindSafe(move || {
+        let mut session = Session::open(config.clone())?;
+        *connected = true;
+        status::set_connection_state(&config, ConnectionState::Connected);
+        session.request_loop()
+    }))
+    .unwrap_or_else(|e| Err(Error::from_panic(e)))
 }
@@ -0,0 +1,99 @@
+//! Sources of the current time
+//!
+//! Logic which depends on the passage of time takes a [`Clock`] rather than
+//! reading the system clock directly, so its tests can advance time
+//! deterministically with a [`MockClock`] instead of sleeping: standby lock
+//! lease expiry, waiting for a deadline, reconnect and Fortanix DSM lookup
+//! backoff, chain halt detection, and state checkpoints. The sign and RSS
+//! watchdogs still use the system clock.
+
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    /// Current monotonic time
+    fn now(&self) -> Instant;
+
+    /// Current wall-clock time
+    fn system_time(&self) -> SystemTime;
+
+    /// Wait for the given amount of time to pass
+    fn sleep(&self, duration: Duration);
+}
+
+/// The system's clocks
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration)
+    }
+}
+
+/// Clock which only advances when told to (or slept on), for tests
+#[derive(Debug)]
+pub struct MockClock {
+    /// Monotonic time the clock was created at
+    started_at: Instant,
+
+    /// Wall-clock time the clock was created at
+    system_started_at: SystemTime,
+
+    /// Time elapsed since the clock was created
+    elapsed: Mutex<Duration>,
+}
+
+impl MockClock {
+    /// Create a clock starting at the current time
+    pub fn new() -> Self {
+        Self {
+            started_at: Instant::now(),
+            system_started_at: SystemTime::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// Advance the clock by the given amount of time
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    /// Time elapsed since the clock was created
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.started_at + self.elapsed()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.system_started_at + self.elapsed()
+    }
+
+    /// Advance the clock rather than waiting
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration)
+    }
+}
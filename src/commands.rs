@@ -1,11 +1,21 @@
 //! Subcommands of the `tmkms` command-line application
 
+pub mod compare_signers;
+pub mod config;
+pub mod decode_request;
+pub mod doctor;
 pub mod init;
 #[cfg(feature = "ledger")]
 pub mod ledger;
+pub mod maintenance;
+pub mod monitor;
+pub mod pubkey;
 #[cfg(feature = "softsign")]
 pub mod softsign;
 pub mod start;
+pub mod state;
+pub mod status;
+pub mod verify_signature;
 pub mod version;
 #[cfg(feature = "yubihsm")]
 pub mod yubihsm;
@@ -17,16 +27,35 @@ pub use self::softsign::SoftsignCommand;
 #[cfg(feature = "yubihsm")]
 pub use self::yubihsm::YubihsmCommand;
 
-pub use self::{init::InitCommand, start::StartCommand, version::VersionCommand};
+pub use self::{
+    compare_signers::CompareSignersCommand, config::ConfigCommand,
+    decode_request::DecodeRequestCommand, doctor::DoctorCommand, init::InitCommand,
+    maintenance::MaintenanceCommand, monitor::MonitorCommand, pubkey::PubkeyCommand,
+    start::StartCommand, state::StateCommand, status::StatusCommand,
+    verify_signature::VerifySignatureCommand, version::VersionCommand,
+};
 
 use crate::config::{KmsConfig, CONFIG_ENV_VAR, CONFIG_FILE_NAME};
-use abscissa_core::{Command, Configurable, Runnable};
+use abscissa_core::{Command, Configurable, FrameworkError, FrameworkErrorKind, Runnable};
 use clap::Parser;
 use std::{env, path::PathBuf};
 
 /// Subcommands of the KMS command-line application
 #[derive(Command, Debug, Parser, Runnable)]
 pub enum KmsCommand {
+    /// ensure two signing providers produce identical signatures
+    CompareSigners(CompareSignersCommand),
+
+    /// inspect the effective configuration
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+
+    /// print the bytes a captured sign request would have signed
+    DecodeRequest(DecodeRequestCommand),
+
+    /// check the configuration for common problems
+    Doctor(DoctorCommand),
+
     /// initialize KMS configuration
     Init(InitCommand),
 
@@ -35,6 +64,15 @@ pub enum KmsCommand {
     #[clap(subcommand)]
     Ledger(LedgerCommand),
 
+    /// pause or resume signing on a running KMS
+    Maintenance(MaintenanceCommand),
+
+    /// check that signed precommits made it on chain
+    Monitor(MonitorCommand),
+
+    /// print a chain's public key in all commonly needed formats
+    Pubkey(PubkeyCommand),
+
     /// subcommands for software signer
     #[cfg(feature = "softsign")]
     #[clap(subcommand)]
@@ -43,6 +81,16 @@ pub enum KmsCommand {
     /// start the KMS application"
     Start(StartCommand),
 
+    /// inspect double-signing state files
+    #[clap(subcommand)]
+    State(StateCommand),
+
+    /// report the live state of a running KMS
+    Status(StatusCommand),
+
+    /// check which configured key produced a signature
+    VerifySignature(VerifySignatureCommand),
+
     /// display the version
     Version(VersionCommand),
 
@@ -62,6 +110,12 @@ impl KmsCommand {
             _ => false,
         }
     }
+
+    /// Should informational logging be suppressed, e.g. to keep stdout
+    /// machine-readable?
+    pub fn quiet(&self) -> bool {
+        matches!(self, KmsCommand::Pubkey(pubkey) if pubkey.is_machine_readable())
+    }
 }
 
 impl Configurable<KmsConfig> for KmsCommand {
@@ -69,7 +123,24 @@ impl Configurable<KmsConfig> for KmsCommand {
     /// or the default
     fn config_path(&self) -> Option<PathBuf> {
         let config = match self {
+            KmsCommand::CompareSigners(compare) => compare.config.as_ref(),
+            KmsCommand::Config(config) => config.config_path(),
+            KmsCommand::Doctor(doctor) => doctor.config.as_ref(),
+            KmsCommand::Maintenance(maintenance) => maintenance.config.as_ref(),
+            KmsCommand::Monitor(monitor) => monitor.config.as_ref(),
+            KmsCommand::Pubkey(pubkey) => pubkey.config.as_ref(),
             KmsCommand::Start(start) => start.config.as_ref(),
+            // Benchmarks a scratch state file: no configuration needed
+            KmsCommand::State(
+                StateCommand::BenchmarkGuard(_) | StateCommand::VerifyCheckpoints(_),
+            ) => return None,
+            // Only used to refuse configured state files, if there is one
+            KmsCommand::State(StateCommand::SimulateGuard(simulate)) => {
+                return simulate.config_path()
+            }
+            KmsCommand::State(state) => state.config_path(),
+            KmsCommand::Status(status) => status.config.as_ref(),
+            KmsCommand::VerifySignature(verify) => verify.config.as_ref(),
             #[cfg(feature = "yubihsm")]
             KmsCommand::Yubihsm(yubihsm) => yubihsm.config_path(),
             #[cfg(feature = "ledger")]
@@ -84,4 +155,41 @@ impl Configurable<KmsConfig> for KmsCommand {
 
         Some(path)
     }
+
+    /// Apply command-line overrides to the loaded configuration
+    fn process_config(&self, mut config: KmsConfig) -> Result<KmsConfig, FrameworkError> {
+        let overrides = match self {
+            KmsCommand::Config(config) => config.overrides(),
+            KmsCommand::Start(start) => &start.overrides,
+            _ => &[],
+        };
+
+        if !overrides.is_empty() || config.include_dir.is_some() {
+            let path = self.config_path().expect("no config path");
+
+            config = KmsConfig::load_with_overrides(&path, overrides)
+                .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+        }
+
+        let state_dir = match self {
+            KmsCommand::Doctor(doctor) => doctor.state_dir.as_ref(),
+            KmsCommand::Start(start) => start.state_dir.as_ref(),
+            KmsCommand::State(state) => state.state_dir(),
+            _ => None,
+        };
+
+        if let Some(state_dir) = state_dir {
+            config.override_state_dir(state_dir);
+        }
+
+        if let KmsCommand::Start(start) = self {
+            if !start.chain_ids.is_empty() {
+                config
+                    .select_chains(&start.chain_ids)
+                    .map_err(|e| FrameworkErrorKind::ConfigError.context(e))?;
+            }
+        }
+
+        Ok(config)
+    }
 }
@@ -0,0 +1,176 @@
+//! `tmkms compare-signers`: ensure two signing providers produce identical
+//! Ed25519 signatures, e.g. before migrating a consensus key between them
+
+use crate::{
+    chain::{self, Chain},
+    config::KmsConfig,
+    error::{Error, ErrorKind::*},
+    keyring::ed25519,
+    prelude::*,
+};
+use abscissa_core::Command;
+use clap::Parser;
+use rand_core::{OsRng, RngCore};
+use std::{
+    path::{Path, PathBuf},
+    process,
+};
+use subtle_encoding::hex;
+
+/// Domain separator prepended to the test message, ensuring it can never be
+/// mistaken for a canonical consensus message
+const TEST_MESSAGE_PREFIX: &[u8] = b"tmkms compare-signers test message:";
+
+/// The `compare-signers` command
+#[derive(Command, Debug, Parser)]
+pub struct CompareSignersCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// chain ID whose consensus keys should be compared
+    #[clap(long = "chain-id")]
+    pub chain_id: chain::Id,
+
+    /// path to a tmkms.toml configuring the other signing provider (default:
+    /// compare the chain's two providers in the same configuration, e.g.
+    /// during a migration)
+    #[clap(long = "other")]
+    pub other: Option<PathBuf>,
+}
+
+impl Runnable for CompareSignersCommand {
+    /// Sign the same test message with both providers and compare the results
+    fn run(&self) {
+        if let Err(e) = self.compare() {
+            status_err!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+impl CompareSignersCommand {
+    /// Compare the signers for the configured chain
+    fn compare(&self) -> Result<(), Error> {
+        let config = APP.config();
+        let ours = chain::load_config_readonly(&config)?;
+        let ours = get_chain(&ours, &self.chain_id, "configuration")?;
+        let format = ours.keyring.format().clone();
+
+        let (ours, theirs) = match &self.other {
+            Some(other) => (
+                sole_signer(ours, "configuration")?,
+                sole_signer(
+                    get_chain(
+                        &self.load_other(other)?,
+                        &self.chain_id,
+                        &other.display().to_string(),
+                    )?,
+                    &other.display().to_string(),
+                )?,
+            ),
+            None => match ours.keyring.consensus_signers() {
+                [ours, theirs] => (
+                    (ours.clone(), ours.provider().to_string()),
+                    (theirs.clone(), theirs.provider().to_string()),
+                ),
+                signers => fail!(
+                    ConfigError,
+                    "chain {} has {} consensus key provider(s) configured: without `--other`, \
+                     exactly two are compared",
+                    self.chain_id,
+                    signers.len()
+                ),
+            },
+        };
+
+        let (ours, our_source) = ours;
+        let (theirs, their_source) = theirs;
+
+        ensure!(
+            ours.public_key() == theirs.public_key(),
+            InvalidKey,
+            "public keys differ:\n  {}: {}\n  {}: {}",
+            our_source,
+            format.serialize(ours.public_key()),
+            their_source,
+            format.serialize(theirs.public_key())
+        );
+
+        let mut nonce = [0u8; 32];
+        OsRng.fill_bytes(&mut nonce);
+        let msg = [TEST_MESSAGE_PREFIX, &nonce].concat();
+
+        let our_sig = ours.sign(&msg)?.to_vec();
+        let their_sig = theirs.sign(&msg)?.to_vec();
+
+        if our_sig == their_sig {
+            status_ok!(
+                "Identical",
+                "{} signers ({}, {}) produced the same Ed25519 signature",
+                self.chain_id,
+                our_source,
+                their_source
+            );
+            Ok(())
+        } else {
+            let first_diff = our_sig
+                .iter()
+                .zip(&their_sig)
+                .position(|(a, b)| a != b)
+                .unwrap_or_default();
+
+            fail!(
+                VerificationError,
+                "signatures differ starting at byte {}:\n  {}: {}\n  {}: {}",
+                first_diff,
+                our_source,
+                String::from_utf8(hex::encode_upper(&our_sig)).unwrap(),
+                their_source,
+                String::from_utf8(hex::encode_upper(&their_sig)).unwrap()
+            )
+        }
+    }
+
+    /// Load the chain registry of the `--other` configuration
+    fn load_other(&self, other: &Path) -> Result<chain::Registry, Error> {
+        let other_config = KmsConfig::load_with_overrides(other, &[])?;
+
+        chain::load_config_readonly(&other_config)
+    }
+}
+
+/// Get the given chain from a registry
+fn get_chain<'a>(
+    registry: &'a chain::Registry,
+    chain_id: &chain::Id,
+    source: &str,
+) -> Result<&'a Chain, Error> {
+    registry.get_chain(chain_id).ok_or_else(|| {
+        format_err!(ConfigError, "chain {} missing from {}", chain_id, source).into()
+    })
+}
+
+/// Get the chain's only Ed25519 consensus key signer, along with a
+/// description of where it came from
+fn sole_signer(chain: &Chain, source: &str) -> Result<(ed25519::Signer, String), Error> {
+    match chain.keyring.consensus_signers() {
+        [signer] => Ok((
+            signer.clone(),
+            format!("{} in {}", signer.provider(), source),
+        )),
+        [] => fail!(
+            InvalidKey,
+            "{}: no Ed25519 consensus key in {} (only those can be compared)",
+            chain.id,
+            source
+        ),
+        signers => fail!(
+            ConfigError,
+            "{}: expected one consensus key provider in {}, found {}",
+            chain.id,
+            source,
+            signers.len()
+        ),
+    }
+}
@@ -0,0 +1,78 @@
+//! `tmkms config` CLI (sub)commands
+
+use crate::{config::KmsConfig, prelude::*};
+use abscissa_core::{Command, Runnable};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::{path::PathBuf, process};
+
+/// `config` subcommand
+#[derive(Command, Debug, Runnable, Subcommand)]
+pub enum ConfigCommand {
+    /// print the effective configuration (with secrets redacted)
+    Dump(DumpCommand),
+}
+
+impl ConfigCommand {
+    pub(super) fn config_path(&self) -> Option<&PathBuf> {
+        match self {
+            ConfigCommand::Dump(dump) => dump.config.as_ref(),
+        }
+    }
+
+    pub(super) fn overrides(&self) -> &[String] {
+        match self {
+            ConfigCommand::Dump(dump) => &dump.overrides,
+        }
+    }
+}
+
+/// Output formats for `config dump`
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum DumpFormat {
+    /// TOML, i.e. the format of `tmkms.toml`
+    #[default]
+    Toml,
+
+    /// JSON
+    Json,
+}
+
+/// `config dump` subcommand
+#[derive(Command, Debug, Parser)]
+pub struct DumpCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// override a configuration setting, as with `tmkms start --set`
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+
+    /// output format
+    #[clap(long = "format", value_enum, default_value_t)]
+    pub format: DumpFormat,
+}
+
+impl Runnable for DumpCommand {
+    /// Print the fully-resolved configuration, including defaults and
+    /// overrides, to stdout
+    fn run(&self) {
+        let config = APP.config();
+
+        match dump(&config, self.format) {
+            Ok(output) => println!("{}", output.trim_end()),
+            Err(e) => {
+                status_err!("couldn't serialize configuration: {}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Serialize the given configuration in the given format
+fn dump(config: &KmsConfig, format: DumpFormat) -> Result<String, String> {
+    match format {
+        DumpFormat::Toml => toml::to_string_pretty(config).map_err(|e| e.to_string()),
+        DumpFormat::Json => serde_json::to_string_pretty(config).map_err(|e| e.to_string()),
+    }
+}
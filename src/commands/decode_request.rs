@@ -0,0 +1,114 @@
+//! `tmkms decode-request`: show what a captured sign request would sign
+//!
+//! This is a pure decoding aid: no configuration is loaded, and nothing is
+//! signed or written to a state file.
+
+use crate::{
+    config::chain::ReplyEncoding,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    rpc::Request,
+};
+use abscissa_core::Command;
+use clap::{Parser, ValueEnum};
+use std::process;
+use subtle_encoding::hex;
+
+/// Protocol versions requests can be decoded as
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum RequestVersion {
+    /// Tendermint v0.34
+    #[value(name = "v0.34")]
+    V0_34,
+
+    /// CometBFT v0.37
+    #[value(name = "v0.37")]
+    V0_37,
+
+    /// CometBFT v0.38 (vote extensions)
+    #[default]
+    #[value(name = "v0.38")]
+    V0_38,
+}
+
+impl From<RequestVersion> for ReplyEncoding {
+    fn from(version: RequestVersion) -> ReplyEncoding {
+        match version {
+            RequestVersion::V0_34 => ReplyEncoding::ProtobufV0_34,
+            RequestVersion::V0_37 => ReplyEncoding::ProtobufV0_37,
+            RequestVersion::V0_38 => ReplyEncoding::ProtobufV0_38,
+        }
+    }
+}
+
+/// The `decode-request` command
+#[derive(Command, Debug, Parser)]
+pub struct DecodeRequestCommand {
+    /// hex-encoded privval request, as read from the validator connection
+    /// (i.e. a length-delimited Protobuf `privval.Message`)
+    #[clap(long = "hex")]
+    pub hex: String,
+
+    /// protocol version whose Protobuf definitions the request is decoded
+    /// with: `v0.34`, `v0.37`, or `v0.38` (default: `v0.38`)
+    #[clap(long = "protocol-version", value_enum)]
+    pub protocol_version: Option<RequestVersion>,
+}
+
+impl Runnable for DecodeRequestCommand {
+    /// Print the decoded request and the bytes which would be signed
+    fn run(&self) {
+        if let Err(e) = self.decode() {
+            status_err!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+impl DecodeRequestCommand {
+    /// Decode the request, printing what it is and what would be signed
+    fn decode(&self) -> Result<(), Error> {
+        let version = self.protocol_version.unwrap_or_default();
+        let bytes = hex::decode(self.hex.trim().to_ascii_lowercase())
+            .map_err(|e| format_err!(ParseError, "invalid hex request: {}", e))?;
+
+        let (request, chain_id) = Request::decode_as(&bytes, version.into())?;
+
+        let (signable_msg, chain_id) = match (request, chain_id) {
+            (request @ (Request::SignProposal(_) | Request::SignVote(_)), Some(chain_id)) => {
+                (request.into_signable_msg()?, chain_id)
+            }
+            (request, _) => {
+                println!("request: {:?} (nothing to sign)", request);
+                return Ok(());
+            }
+        };
+
+        let state = signable_msg.consensus_state();
+        let canonical_bytes = signable_msg.canonical_bytes(chain_id.clone())?;
+
+        println!("type: {:?}", signable_msg.msg_type());
+        println!("chain_id: {}", chain_id);
+        println!(
+            "height/round/step: {}/{}/{}",
+            state.height, state.round, state.step
+        );
+        println!("block_id: {}", state.block_id_prefix());
+        println!(
+            "sign_bytes: {}",
+            String::from_utf8(hex::encode_upper(&canonical_bytes)).unwrap()
+        );
+
+        // Vote extensions are only signed by v0.38 and newer
+        if let RequestVersion::V0_38 = version {
+            if let Some(extension_bytes) = signable_msg.extension_bytes(chain_id)? {
+                println!(
+                    "extension_sign_bytes: {}",
+                    String::from_utf8(hex::encode_upper(&extension_bytes)).unwrap()
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+//! `tmkms doctor`: check the configuration for common problems
+//!
+//! By default only checks which don't touch the network are performed, so
+//! configurations can be validated in CI or on air-gapped hosts. Reachability
+//! checks are reported as SKIPPED unless `--online` is passed.
+
+use crate::{
+    chain::{node, State},
+    config::{chain::MissingStatePolicy, KmsConfig},
+    prelude::*,
+};
+use abscissa_core::Command;
+use clap::Parser;
+use std::{
+    fmt::Display,
+    fs,
+    net::{TcpStream, ToSocketAddrs},
+    os::unix::{fs::PermissionsExt, net::UnixStream},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+use tendermint_config::net;
+
+/// Timeout for reachability checks
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The `doctor` command
+#[derive(Command, Debug, Default, Parser)]
+pub struct DoctorCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// directory containing all chains' state files (overrides `state_file`)
+    #[clap(long = "state-dir")]
+    pub state_dir: Option<PathBuf>,
+
+    /// also check that validators and nodes are reachable over the network
+    #[clap(long = "online")]
+    pub online: bool,
+}
+
+impl Runnable for DoctorCommand {
+    /// Run all checks, exiting with an error status if any failed
+    fn run(&self) {
+        let config = APP.config();
+        let mut report = Report::default();
+
+        report.ok(format!(
+            "configuration parsed ({} chain(s), {} validator(s))",
+            config.chain.len(),
+            config.validator.len()
+        ));
+
+        check_state_files(&config, &mut report);
+        check_chain_files(&config, &mut report);
+        check_validators(&config, &mut report);
+
+        #[cfg(feature = "softsign")]
+        check_softsign_keys(&config, &mut report);
+
+        if self.online {
+            check_reachability(&config, &mut report);
+        } else {
+            report.skip("network reachability checks (pass --online to enable)");
+        }
+
+        if report.failures > 0 {
+            status_err!("{} check(s) failed", report.failures);
+            process::exit(1);
+        }
+    }
+}
+
+/// Results of the checks performed so far
+#[derive(Default)]
+struct Report {
+    /// Number of failed checks
+    failures: usize,
+}
+
+impl Report {
+    /// Record a successful check
+    fn ok(&mut self, msg: impl Display) {
+        status_ok!("OK", "{}", msg);
+    }
+
+    /// Record a skipped check
+    fn skip(&mut self, msg: impl Display) {
+        status_info!("SKIPPED", "{}", msg);
+    }
+
+    /// Record a failed check
+    fn fail(&mut self, msg: impl Display) {
+        status_err!("{}", msg);
+        self.failures += 1;
+    }
+}
+
+/// Ensure each chain's state file (if present) is sane
+fn check_state_files(config: &KmsConfig, report: &mut Report) {
+    for chain in &config.chain {
+        let path = chain.state_file_path();
+
+        match State::read_consensus_state(&path) {
+            Ok(Some(state)) => report.ok(format_args!(
+                "{}: state file {} (height {})",
+                chain.id,
+                path.display(),
+                state.height
+            )),
+            Ok(None) => match chain.on_missing_state.unwrap_or_default() {
+                MissingStatePolicy::InitZero => report.ok(format_args!(
+                    "{}: state file {} will be created on first start",
+                    chain.id,
+                    path.display()
+                )),
+                MissingStatePolicy::Refuse => report.fail(format_args!(
+                    "{}: no state file at {} (import one with `tmkms state import`, or set \
+                     `on_missing_state = \"init_zero\"` for a new validator)",
+                    chain.id,
+                    path.display()
+                )),
+            },
+            Err(e) => report.fail(format_args!("{}: {}", chain.id, e)),
+        }
+    }
+}
+
+/// Ensure no two chains share a state file or standby lock file
+fn check_chain_files(config: &KmsConfig, report: &mut Report) {
+    match config.check_chain_files() {
+        Ok(()) => report.ok("each chain has its own state and lock files"),
+        Err(e) => report.fail(e),
+    }
+}
+
+/// Ensure each validator references a configured chain and has a usable
+/// secret connection key
+fn check_validators(config: &KmsConfig, report: &mut Report) {
+    for validator in &config.validator {
+        if !config.chain.iter().any(|c| c.id == validator.chain_id) {
+            report.fail(format_args!(
+                "validator {}: chain {} missing from [[chain]] section",
+                validator.addr, validator.chain_id
+            ));
+        }
+
+        match (&validator.addr, &validator.secret_key) {
+            (net::Address::Tcp { .. }, None) => report.fail(format_args!(
+                "validator {}: tcp:// validators require a `secret_key`",
+                validator.addr
+            )),
+            (_, Some(path)) => check_secret_file(report, "secret connection key", path),
+            (net::Address::Unix { .. }, None) => (),
+        }
+    }
+}
+
+/// Ensure softsign keys load as the configured key type
+#[cfg(feature = "softsign")]
+fn check_softsign_keys(config: &KmsConfig, report: &mut Report) {
+    use crate::keyring::providers::softsign;
+
+    for softsign_config in &config.providers.softsign {
+        let path = softsign_config.path.as_ref();
+        check_secret_file(report, "softsign key", path);
+
+        if let Err(e) = softsign::check(softsign_config) {
+            report.fail(format_args!("softsign key {}: {}", path.display(), e));
+        } else {
+            report.ok(format_args!(
+                "softsign key {} is a valid {:?} key",
+                path.display(),
+                softsign_config.key_type
+            ));
+        }
+    }
+}
+
+/// Ensure a file containing secret key material exists and isn't world-readable
+fn check_secret_file(report: &mut Report, description: &str, path: &Path) {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.permissions().mode() & 0o004 != 0 => report.fail(format_args!(
+            "{} {} is world-readable (mode {:o})",
+            description,
+            path.display(),
+            metadata.permissions().mode() & 0o777
+        )),
+        Ok(_) => report.ok(format_args!("{} {}", description, path.display())),
+        Err(e) => report.fail(format_args!("{} {}: {}", description, path.display(), e)),
+    }
+}
+
+/// Ensure validators and nodes are reachable over the network
+fn check_reachability(config: &KmsConfig, report: &mut Report) {
+    for validator in &config.validator {
+        match connect(&validator.addr) {
+            Ok(()) => report.ok(format_args!("validator {} is reachable", validator.addr)),
+            Err(e) => report.fail(format_args!(
+                "validator {} is unreachable: {}",
+                validator.addr, e
+            )),
+        }
+    }
+
+    for chain in &config.chain {
+        if let Some(halt_detection) = &chain.halt_detection {
+            match node::latest_block_height(&halt_detection.rpc_addr) {
+                Ok(height) => report.ok(format_args!(
+                    "{}: node RPC {} is at height {}",
+                    chain.id, halt_detection.rpc_addr, height
+                )),
+                Err(e) => report.fail(format_args!("{}: {}", chain.id, e)),
+            }
+        }
+    }
+}
+
+/// Attempt to open (and immediately close) a connection to the given address
+fn connect(addr: &net::Address) -> std::io::Result<()> {
+    match addr {
+        net::Address::Tcp { host, port, .. } => {
+            for socket_addr in (host.as_str(), *port).to_socket_addrs()? {
+                if TcpStream::connect_timeout(&socket_addr, CONNECT_TIMEOUT).is_ok() {
+                    return Ok(());
+                }
+            }
+
+            Err(std::io::ErrorKind::ConnectionRefused.into())
+        }
+        net::Address::Unix { path } => UnixStream::connect(path).map(|_| ()),
+    }
+}
@@ -0,0 +1,74 @@
+//! `tmkms maintenance`: pause or resume signing on a running KMS
+
+use crate::{
+    control::{self, Command as ControlCommand},
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use abscissa_core::Command;
+use clap::{Parser, ValueEnum};
+use std::{path::PathBuf, process};
+
+/// Maintenance mode actions
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum MaintenanceAction {
+    /// stop signing until resumed, leaving connections and state intact
+    Pause,
+
+    /// resume signing
+    Resume,
+
+    /// report whether signing is paused
+    Status,
+}
+
+impl From<MaintenanceAction> for ControlCommand {
+    fn from(action: MaintenanceAction) -> ControlCommand {
+        match action {
+            MaintenanceAction::Pause => ControlCommand::Pause,
+            MaintenanceAction::Resume => ControlCommand::Resume,
+            MaintenanceAction::Status => ControlCommand::Maintenance,
+        }
+    }
+}
+
+/// The `maintenance` command
+#[derive(Command, Debug, Parser)]
+pub struct MaintenanceCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// action to perform
+    #[clap(value_enum)]
+    pub action: MaintenanceAction,
+}
+
+impl Runnable for MaintenanceCommand {
+    /// Send the action to the running KMS's control socket
+    fn run(&self) {
+        match self.send() {
+            Ok(status) => status_ok!("Maintenance", "{}", status),
+            Err(e) => {
+                status_err!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+impl MaintenanceCommand {
+    /// Send the action, returning the KMS's resulting status
+    fn send(&self) -> Result<String, Error> {
+        let config = APP.config();
+
+        let control_socket = config.control_socket.as_ref().ok_or_else(|| {
+            format_err!(
+                ConfigError,
+                "no `control_socket` configured: maintenance mode unavailable"
+            )
+        })?;
+
+        control::send(control_socket, self.action.into())
+    }
+}
@@ -0,0 +1,128 @@
+//! `tmkms monitor`: check that what the KMS signed made it on chain
+
+use crate::{
+    chain::{self, monitor::Monitor, node},
+    error::{Error, ErrorKind::*},
+    metrics,
+    prelude::*,
+};
+use abscissa_core::Command;
+use clap::Parser;
+use std::{net::SocketAddr, path::PathBuf, process, thread, time::Duration};
+use tendermint_config::net;
+
+/// Default number of seconds between polls
+const DEFAULT_INTERVAL_SECS: u64 = 10;
+
+/// The `monitor` command
+#[derive(Command, Debug, Parser)]
+pub struct MonitorCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// chain ID whose state file should be monitored
+    #[clap(long = "chain-id")]
+    pub chain_id: chain::Id,
+
+    /// address of a node's RPC endpoint, e.g. `tcp://127.0.0.1:26657`
+    #[clap(long = "rpc")]
+    pub rpc: net::Address,
+
+    /// hex address of the validator's consensus key (default: the node's own
+    /// validator, from its `/status`)
+    #[clap(long = "address")]
+    pub address: Option<String>,
+
+    /// seconds between polls (default: 10)
+    #[clap(long = "interval", value_name = "SECS")]
+    pub interval: Option<u64>,
+
+    /// serve this monitor's metrics on the given address (which must differ
+    /// from the running KMS's `metrics_addr`)
+    #[clap(long = "metrics-addr")]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// poll once and exit: with an error status if a signed precommit is
+    /// missing on chain or the check failed
+    #[clap(long = "once")]
+    pub once: bool,
+}
+
+impl Runnable for MonitorCommand {
+    /// Poll the state file and node until interrupted
+    fn run(&self) {
+        let mut monitor = self.monitor().unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        if let Some(addr) = &self.metrics_addr {
+            if let Err(e) = metrics::init(addr) {
+                status_err!("{}", e);
+                process::exit(1);
+            }
+        }
+
+        let interval = Duration::from_secs(self.interval.unwrap_or(DEFAULT_INTERVAL_SECS));
+
+        loop {
+            let result = monitor.poll();
+
+            if self.once {
+                match result {
+                    Ok(0) => status_ok!("OK", "no signed precommits missing on chain"),
+                    Ok(missing) => {
+                        status_err!("{} signed precommit(s) missing on chain", missing);
+                        process::exit(1);
+                    }
+                    Err(e) => {
+                        status_err!("{}", e);
+                        process::exit(1);
+                    }
+                }
+
+                return;
+            }
+
+            if let Err(e) = result {
+                warn!("[{}] monitor check failed: {}", self.chain_id, e);
+            }
+
+            thread::sleep(interval);
+        }
+    }
+}
+
+impl MonitorCommand {
+    /// Create the monitor for the configured chain
+    fn monitor(&self) -> Result<Monitor, Error> {
+        let config = APP.config();
+
+        let chain_config = config
+            .chain
+            .iter()
+            .find(|chain| chain.id == self.chain_id)
+            .ok_or_else(|| format_err!(ConfigError, "chain not configured: {}", self.chain_id))?;
+
+        let address = match &self.address {
+            Some(address) => address.clone(),
+            None => node::validator_address(&self.rpc)?,
+        };
+
+        info!(
+            "[{}] monitoring state file {} against {} (validator {})",
+            self.chain_id,
+            chain_config.state_file_path().display(),
+            self.rpc,
+            address
+        );
+
+        Ok(Monitor::new(
+            self.chain_id.clone(),
+            chain_config.state_file_path(),
+            self.rpc.clone(),
+            address,
+        ))
+    }
+}
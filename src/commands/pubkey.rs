@@ -0,0 +1,218 @@
+//! `tmkms pubkey`: print a chain's public key in every commonly needed format
+//!
+//! The key is fetched from the chain's signing provider once, and the other
+//! representations (hex, Bech32, and the Tendermint address) are derived
+//! from it locally. Consensus keys are shown with the chain's `valconspub`
+//! and `valcons` Bech32 prefixes, account keys with its `pub` and account
+//! prefixes.
+
+use crate::{
+    chain,
+    error::{Error, ErrorKind::*},
+    keyring,
+    prelude::*,
+};
+use abscissa_core::Command;
+use clap::{Parser, ValueEnum};
+use serde::Serialize;
+use std::{path::PathBuf, process};
+use subtle_encoding::{base64, bech32};
+use tendermint::{account, PublicKey, TendermintKey};
+
+/// Suffix of the human-readable part of Bech32 validator consensus addresses
+const VALCONS_SUFFIX: &str = "valcons";
+
+/// Suffix of the human-readable part of Bech32 validator consensus public keys
+const VALCONSPUB_SUFFIX: &str = "valconspub";
+
+/// Suffix of the human-readable part of Bech32 account public keys
+const ACCOUNT_PUB_SUFFIX: &str = "pub";
+
+/// Output formats for `pubkey`
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum PubkeyFormat {
+    /// Labeled text, one format per line
+    #[default]
+    Text,
+
+    /// JSON
+    Json,
+}
+
+/// The `pubkey` command
+#[derive(Command, Debug, Parser)]
+pub struct PubkeyCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// chain ID whose key should be printed
+    #[clap(long = "chain-id")]
+    pub chain_id: chain::Id,
+
+    /// Bech32 account prefix of the chain, e.g. `cosmos` (default: derived
+    /// from the chain's `key_format`, if it's `bech32`), which the `valcons`
+    /// or `pub` suffixes are added to
+    #[clap(long = "bech32-prefix")]
+    pub bech32_prefix: Option<String>,
+
+    /// output format
+    #[clap(long = "format", value_enum, default_value_t)]
+    pub format: PubkeyFormat,
+}
+
+impl Runnable for PubkeyCommand {
+    /// Print the chain's public key in all supported formats to stdout
+    fn run(&self) {
+        let formats = self.formats().unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        match self.format {
+            PubkeyFormat::Text => formats.print(),
+            PubkeyFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&formats).expect("JSON serialization failed")
+            ),
+        }
+    }
+}
+
+impl PubkeyCommand {
+    /// Is the output meant for scripts rather than people?
+    pub fn is_machine_readable(&self) -> bool {
+        matches!(self.format, PubkeyFormat::Json)
+    }
+
+    /// Fetch the chain's public key and derive all formats from it
+    fn formats(&self) -> Result<PubkeyFormats, Error> {
+        let registry = chain::load_config_readonly(&APP.config())?;
+        let chain = registry.get_chain(&self.chain_id).ok_or_else(|| {
+            format_err!(
+                ConfigError,
+                "chain {} missing from configuration",
+                self.chain_id
+            )
+        })?;
+
+        let key = chain.keyring.default_pubkey()?;
+
+        let prefix = self.bech32_prefix.clone().or_else(|| {
+            let config = APP.config();
+            let chain_config = config.chain.iter().find(|c| c.id == self.chain_id)?;
+            bech32_prefix(&chain_config.key_format, &key)
+        });
+
+        Ok(PubkeyFormats::new(&self.chain_id, key, prefix.as_deref()))
+    }
+}
+
+/// A public key in all supported formats
+#[derive(Debug, Serialize)]
+struct PubkeyFormats {
+    /// Chain ID
+    chain_id: String,
+
+    /// `account` or `consensus`
+    key_type: &'static str,
+
+    /// Algorithm, e.g. `ed25519`
+    algorithm: &'static str,
+
+    /// Raw key bytes, Base64-encoded
+    base64: String,
+
+    /// Raw key bytes, hex-encoded
+    hex: String,
+
+    /// Tendermint address (truncated SHA-256 of the key), hex-encoded
+    address: String,
+
+    /// Bech32 public key: `<prefix>valconspub` for consensus keys,
+    /// `<prefix>pub` for account keys (if a prefix is known)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bech32_pubkey: Option<String>,
+
+    /// Bech32 address: `<prefix>valcons` for consensus keys, `<prefix>` for
+    /// account keys (if a prefix is known)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bech32_address: Option<String>,
+}
+
+impl PubkeyFormats {
+    /// Derive all formats from the given public key
+    fn new(chain_id: &chain::Id, key: TendermintKey, bech32_prefix: Option<&str>) -> Self {
+        let (key_type, pubkey_suffix, address_suffix, public_key) = match key {
+            TendermintKey::AccountKey(pk) => ("account", ACCOUNT_PUB_SUFFIX, "", pk),
+            TendermintKey::ConsensusKey(pk) => ("consensus", VALCONSPUB_SUFFIX, VALCONS_SUFFIX, pk),
+        };
+
+        let bytes = public_key.to_bytes();
+        let address = account::Id::from(public_key);
+
+        let algorithm = match public_key {
+            PublicKey::Ed25519(_) => "ed25519",
+            PublicKey::Secp256k1(_) => "secp256k1",
+            _ => "unknown",
+        };
+
+        Self {
+            chain_id: chain_id.to_string(),
+            key_type,
+            algorithm,
+            base64: String::from_utf8(base64::encode(&bytes)).unwrap(),
+            hex: public_key.to_hex(),
+            address: address.to_string(),
+            bech32_pubkey: bech32_prefix
+                .map(|prefix| public_key.to_bech32(&format!("{prefix}{pubkey_suffix}"))),
+            bech32_address: bech32_prefix
+                .map(|prefix| bech32::encode(format!("{prefix}{address_suffix}"), address)),
+        }
+    }
+
+    /// Print the formats as labeled text
+    fn print(&self) {
+        println!("chain id:       {}", self.chain_id);
+        println!("key type:       {} ({})", self.key_type, self.algorithm);
+        println!("base64:         {}", self.base64);
+        println!("hex:            {}", self.hex);
+        println!("address:        {}", self.address);
+
+        let (pubkey_label, address_label) = match self.key_type {
+            "account" => ("account pubkey: ", "account addr:   "),
+            _ => ("valcons pubkey: ", "valcons addr:   "),
+        };
+
+        if let Some(bech32_pubkey) = &self.bech32_pubkey {
+            println!("{}{}", pubkey_label, bech32_pubkey);
+        }
+
+        if let Some(bech32_address) = &self.bech32_address {
+            println!("{}{}", address_label, bech32_address);
+        }
+    }
+}
+
+/// Derive the Bech32 account prefix from a chain's key format for the given
+/// kind of key, e.g. `cosmos` from a `consensus_key_prefix` of
+/// `cosmosvalconspub`, or from an `account_key_prefix` of `cosmospub`
+fn bech32_prefix(key_format: &keyring::Format, key: &TendermintKey) -> Option<String> {
+    match (key_format, key) {
+        (
+            keyring::Format::Bech32 {
+                consensus_key_prefix,
+                ..
+            },
+            TendermintKey::ConsensusKey(_),
+        ) => consensus_key_prefix.strip_suffix(VALCONSPUB_SUFFIX),
+        (
+            keyring::Format::Bech32 {
+                account_key_prefix, ..
+            },
+            TendermintKey::AccountKey(_),
+        ) => account_key_prefix.strip_suffix(ACCOUNT_PUB_SUFFIX),
+        _ => None,
+    }
+    .map(ToOwned::to_owned)
+}
@@ -1,9 +1,10 @@
 //! `tmkms softsign` CLI (sub)commands
 
+mod encrypt;
 mod import;
 mod keygen;
 
-use self::{import::ImportCommand, keygen::KeygenCommand};
+use self::{encrypt::EncryptCommand, import::ImportCommand, keygen::KeygenCommand};
 use abscissa_core::{Command, Runnable};
 use clap::Subcommand;
 
@@ -15,4 +16,7 @@ pub enum SoftsignCommand {
 
     /// convert existing private key to base64 format
     Import(ImportCommand),
+
+    /// encrypt a key file with age or GnuPG
+    Encrypt(EncryptCommand),
 }
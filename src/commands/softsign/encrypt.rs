@@ -0,0 +1,110 @@
+//! `tmkms softsign encrypt` command
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    key_utils::SECRET_FILE_PERMS,
+    keyring::providers::softsign::encryption,
+    prelude::*,
+};
+use abscissa_core::Command;
+use clap::Parser;
+use std::{
+    fs::{self, Permissions},
+    os::unix::fs::PermissionsExt,
+    path::PathBuf,
+    process,
+};
+
+/// `encrypt` command: encrypt a softsign key file with `age` or `gpg`, for
+/// use with the `encryption` option of `[[providers.softsign]]`
+#[derive(Command, Debug, Parser)]
+pub struct EncryptCommand {
+    /// age recipient (public key) to encrypt to
+    #[clap(long = "age", value_name = "RECIPIENT", conflicts_with_all = ["gpg", "gpg_passphrase_env"])]
+    age: Option<String>,
+
+    /// GnuPG recipient (key ID or email) to encrypt to
+    #[clap(
+        long = "gpg",
+        value_name = "RECIPIENT",
+        conflicts_with = "gpg_passphrase_env"
+    )]
+    gpg: Option<String>,
+
+    /// environment variable containing a passphrase to encrypt with
+    /// symmetrically using GnuPG
+    #[clap(long = "gpg-passphrase-env", value_name = "VAR")]
+    gpg_passphrase_env: Option<String>,
+
+    /// path to the plaintext key file
+    input: PathBuf,
+
+    /// path to write the encrypted key file to
+    output: PathBuf,
+}
+
+impl Runnable for EncryptCommand {
+    /// Encrypt a key file
+    fn run(&self) {
+        if let Err(e) = self.encrypt() {
+            status_err!("{}", e);
+            process::exit(1);
+        }
+
+        status_ok!(
+            "Encrypted",
+            "{} to {} (the plaintext file can now be removed)",
+            self.input.display(),
+            self.output.display()
+        );
+    }
+}
+
+impl EncryptCommand {
+    /// Run the configured encryption tool
+    fn encrypt(&self) -> Result<(), Error> {
+        let mut passphrase = None;
+
+        let mut command = if let Some(recipient) = &self.age {
+            let mut command = process::Command::new("age");
+            command.args(["--encrypt", "--recipient", recipient, "--output"]);
+            command
+        } else {
+            let mut command = process::Command::new("gpg");
+            command.args(["--batch", "--yes", "--quiet"]);
+
+            if let Some(recipient) = &self.gpg {
+                command.args(["--encrypt", "--recipient", recipient]);
+            } else if let Some(var) = &self.gpg_passphrase_env {
+                passphrase = Some(encryption::passphrase(var)?);
+                command.args(["--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+                command.arg("--no-symkey-cache");
+                command.arg("--symmetric");
+            } else {
+                fail!(
+                    ConfigError,
+                    "one of --age, --gpg, or --gpg-passphrase-env is required"
+                );
+            }
+
+            command.arg("--output");
+            command
+        };
+
+        command.arg(&self.output).arg(&self.input);
+        encryption::run(command, passphrase.as_ref().map(|p| p.as_bytes()))?;
+
+        fs::set_permissions(&self.output, Permissions::from_mode(SECRET_FILE_PERMS)).map_err(
+            |e| {
+                format_err!(
+                    IoError,
+                    "couldn't set permissions on {}: {}",
+                    self.output.display(),
+                    e
+                )
+            },
+        )?;
+
+        Ok(())
+    }
+}
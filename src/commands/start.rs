@@ -1,9 +1,21 @@
 //! Start the KMS
 
-use crate::{chain, client::Client, prelude::*};
+use crate::{
+    chain,
+    client::{self, Client},
+    config::{provider::PROVIDERS, KmsConfig},
+    connection::tls,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    resources,
+};
 use abscissa_core::Command;
 use clap::Parser;
-use std::{path::PathBuf, process};
+use std::{path::PathBuf, process, sync::mpsc, thread, time::Duration};
+use tendermint_config::net;
+
+/// Default number of seconds `--once` waits for a sign request
+const DEFAULT_ONCE_TIMEOUT: u64 = 30;
 
 /// The `start` command
 #[derive(Command, Debug, Default, Parser)]
@@ -12,6 +24,38 @@ pub struct StartCommand {
     #[clap(short = 'c', long = "config")]
     pub config: Option<PathBuf>,
 
+    /// directory containing all chains' state files (overrides `state_file`)
+    #[clap(long = "state-dir")]
+    pub state_dir: Option<PathBuf>,
+
+    /// wait up to this many seconds for signing providers to become available
+    #[clap(long = "wait-for-backend", value_name = "SECS")]
+    pub wait_for_backend: Option<u64>,
+
+    /// only serve the given chain (may be repeated); other configured chains
+    /// are loaded but no validators are connected for them
+    #[clap(long = "chain-id", value_name = "CHAIN_ID")]
+    pub chain_ids: Vec<chain::Id>,
+
+    /// override a configuration setting, e.g. `validator.0.reconnect=false`
+    /// (may be repeated; the configuration file is left unchanged)
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+
+    /// start even if a chain's signing providers have different consensus
+    /// keys (e.g. mid-migration), signing with the first provider's key
+    #[clap(long = "expect-different")]
+    pub expect_different: bool,
+
+    /// handle a single sign request from the (only) configured validator,
+    /// print the response, and exit: for test harnesses and debugging
+    #[clap(long = "once")]
+    pub once: bool,
+
+    /// seconds `--once` waits for a sign request before failing (default: 30)
+    #[clap(long = "once-timeout", value_name = "SECS", requires = "once")]
+    pub once_timeout: Option<u64>,
+
     /// enable verbose debug logging
     #[clap(short = 'v', long = "verbose")]
     pub verbose: bool,
@@ -26,7 +70,11 @@ impl Runnable for StartCommand {
             env!("CARGO_PKG_VERSION")
         );
 
-        run_app(self.spawn_clients());
+        if self.once {
+            self.sign_once();
+        } else {
+            run_app(self.spawn_clients());
+        }
     }
 }
 
@@ -35,19 +83,197 @@ impl StartCommand {
     fn spawn_clients(&self) -> Vec<Client> {
         let config = APP.config();
 
-        chain::load_config(&config).unwrap_or_else(|e| {
+        self.load_chains(&config)
+            .and_then(|()| client::spawn_all(&config))
+            .unwrap_or_else(|e| {
+                status_err!("error loading configuration: {}", e);
+                process::exit(1);
+            })
+    }
+
+    /// Load chains and signing keys from the given configuration
+    fn load_chains(&self, config: &KmsConfig) -> Result<(), Error> {
+        resources::check(config)?;
+        config.check_chain_files()?;
+        check_tls(config)?;
+
+        match self.wait_for_backend {
+            Some(secs) => chain::load_config_with_retry(config, Duration::from_secs(secs))?,
+            None => chain::load_config(config)?,
+        }
+
+        chain::REGISTRY.reconcile_keys(self.expect_different)?;
+
+        log_summary(config);
+        Ok(())
+    }
+
+    /// Handle a single sign request, then exit
+    ///
+    /// This is deliberately only available as a command-line flag (there's no
+    /// equivalent configuration setting), so it can't be left enabled in a
+    /// production configuration file.
+    fn sign_once(&self) -> ! {
+        let config = APP.config();
+
+        if let Err(e) = self.load_chains(&config) {
             status_err!("error loading configuration: {}", e);
             process::exit(1);
+        }
+
+        let validator = match config.validator.as_slice() {
+            [validator] => validator.clone(),
+            validators => {
+                status_err!(
+                    "--once requires exactly one [[validator]] ({} configured; select one with --chain-id)",
+                    validators.len()
+                );
+                process::exit(1);
+            }
+        };
+
+        let timeout = Duration::from_secs(self.once_timeout.unwrap_or(DEFAULT_ONCE_TIMEOUT));
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            // The receiver is only gone if we've already timed out
+            let _ = sender.send(client::sign_once(validator));
         });
 
-        // Spawn the validator client threads
-        config
-            .validator
-            .iter()
-            .cloned()
-            .map(Client::spawn)
-            .collect()
+        match receiver.recv_timeout(timeout) {
+            Ok(Ok(response)) => match response.remote_error() {
+                None => {
+                    println!("{:?}", response);
+                    status_ok!("Signed", "sign request handled; exiting");
+                    process::exit(0);
+                }
+                Some(error) => {
+                    println!("{:?}", response);
+                    status_err!("sign request rejected: {}", error.description);
+                    process::exit(1);
+                }
+            },
+            Ok(Err(e)) => {
+                status_err!("error handling sign request: {}", e);
+                process::exit(1);
+            }
+            Err(_) => {
+                status_err!("no sign request received within {}s", timeout.as_secs());
+                process::exit(1);
+            }
+        }
+    }
+}
+
+/// Log a (secret-free) summary of what this instance is about to run
+fn log_summary(config: &KmsConfig) {
+    let providers = PROVIDERS
+        .iter()
+        .filter(|provider| provider.compiled_in)
+        .map(|provider| provider.name)
+        .collect::<Vec<_>>();
+
+    info!("compiled-in providers: {}", providers.join(", "));
+
+    let registry = chain::REGISTRY.get();
+
+    for chain_config in &config.chain {
+        let Some(chain) = registry.get_chain(&chain_config.id) else {
+            continue;
+        };
+
+        let key = match chain.keyring.default_pubkey() {
+            Ok(pubkey) => format!(
+                "{} key {}",
+                chain
+                    .keyring
+                    .default_provider()
+                    .map(|provider| provider.to_string())
+                    .unwrap_or_default(),
+                chain.keyring.format().serialize(pubkey)
+            ),
+            Err(e) => format!("no usable key ({})", e),
+        };
+
+        info!(
+            "[{}] {}; state file {} (fsync: {}, if missing: {}); protocol: {} (replies: {}); \
+             sign_extensions: {}; observe_only: {}; standby lock: {}; halt detection: {}",
+            chain.id,
+            key,
+            chain_config.state_file_path().display(),
+            chain_config.state_fsync.unwrap_or_default(),
+            chain_config.on_missing_state.unwrap_or_default(),
+            chain
+                .protocol_version
+                .map(|version| version.to_string())
+                .unwrap_or_else(|| "any".to_owned()),
+            chain.reply_encoding,
+            chain.sign_extensions,
+            chain.observe_only,
+            chain_config
+                .standby_lock
+                .as_ref()
+                .map(|lock| lock.path.display().to_string())
+                .unwrap_or_else(|| "none".to_owned()),
+            chain_config
+                .halt_detection
+                .as_ref()
+                .map(|halt_detection| halt_detection.rpc_addr.to_string())
+                .unwrap_or_else(|| "none".to_owned()),
+        );
     }
+
+    for validator in &config.validator {
+        info!(
+            "[{}@{}] protocol: {}; peer ID: {}; TLS: {}; max height: {}; reconnect: {}",
+            validator.chain_id,
+            validator.addr,
+            validator.protocol_version,
+            match &validator.addr {
+                net::Address::Tcp {
+                    peer_id: Some(_), ..
+                } => "verified",
+                net::Address::Tcp { peer_id: None, .. } => "UNVERIFIED",
+                net::Address::Unix { .. } => "n/a",
+            },
+            if validator.tls.is_some() { "on" } else { "off" },
+            validator
+                .max_height
+                .map(|height| height.to_string())
+                .unwrap_or_else(|| "none".to_owned()),
+            validator.reconnect
+        );
+    }
+}
+
+/// Ensure the TLS certificates and keys of all validators which use TLS can
+/// be loaded, rather than discovering a problem on the first connection
+fn check_tls(config: &KmsConfig) -> Result<(), Error> {
+    for validator in &config.validator {
+        let Some(tls_config) = &validator.tls else {
+            continue;
+        };
+
+        ensure!(
+            matches!(validator.addr, net::Address::Tcp { .. }),
+            ConfigError,
+            "[{}@{}] `tls` is only supported for TCP connections",
+            validator.chain_id,
+            validator.addr
+        );
+
+        tls::check(tls_config).map_err(|e| {
+            format_err!(
+                ConfigError,
+                "[{}@{}] {}",
+                validator.chain_id,
+                validator.addr,
+                e
+            )
+        })?;
+    }
+
+    Ok(())
 }
 
 /// Run the application.
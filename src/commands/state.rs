@@ -0,0 +1,564 @@
+//! `tmkms state` CLI (sub)commands
+
+use crate::{
+    chain::{self, checkpoint, State},
+    config::{chain::FsyncPolicy, CONFIG_ENV_VAR, CONFIG_FILE_NAME},
+    error::{Error, ErrorKind::*},
+    events::{Decision, SignEvent},
+    keyring::ed25519,
+    metrics,
+    prelude::*,
+};
+use abscissa_core::{Command, Runnable};
+use clap::{Parser, Subcommand};
+use sha2::{Digest, Sha256};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+use subtle_encoding::hex;
+use tendermint::{block, consensus, Hash};
+
+/// Sequence of sign requests `state simulate-guard` replays by default: a
+/// normal run of heights, followed by a height regression and a conflicting
+/// vote for a different block at an already signed height/round/step
+pub const DEFAULT_SIMULATED_SEQUENCE: &str =
+    "10/0/0@A,10/0/1@A,10/0/2@A,11/0/0@B,11/0/1@B,10/0/2@A,11/0/1@C,11/0/2@B";
+
+/// `state` subcommand
+#[derive(Command, Debug, Runnable, Subcommand)]
+pub enum StateCommand {
+    /// measure double-signing guard and state persistence latency
+    BenchmarkGuard(BenchmarkGuardCommand),
+
+    /// set a chain's state file to a given height (or another state file)
+    Import(ImportCommand),
+
+    /// print the last signed height/round/step for each chain
+    Inspect(InspectCommand),
+
+    /// replay a sequence of sign requests through the double-signing guard
+    /// against a throwaway state file, e.g. to validate alerting runbooks
+    SimulateGuard(SimulateGuardCommand),
+
+    /// verify a file of signed state checkpoints against its auditing key
+    VerifyCheckpoints(VerifyCheckpointsCommand),
+}
+
+impl StateCommand {
+    pub(super) fn config_path(&self) -> Option<&PathBuf> {
+        match self {
+            StateCommand::BenchmarkGuard(_) | StateCommand::VerifyCheckpoints(_) => None,
+            StateCommand::Import(import) => import.config.as_ref(),
+            StateCommand::Inspect(inspect) => inspect.config.as_ref(),
+            StateCommand::SimulateGuard(simulate) => simulate.config.as_ref(),
+        }
+    }
+
+    pub(super) fn state_dir(&self) -> Option<&PathBuf> {
+        match self {
+            StateCommand::BenchmarkGuard(_)
+            | StateCommand::SimulateGuard(_)
+            | StateCommand::VerifyCheckpoints(_) => None,
+            StateCommand::Import(import) => import.state_dir.as_ref(),
+            StateCommand::Inspect(inspect) => inspect.state_dir.as_ref(),
+        }
+    }
+}
+
+/// `state inspect` subcommand
+#[derive(Command, Debug, Parser)]
+pub struct InspectCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// directory containing all chains' state files (overrides `state_file`)
+    #[clap(long = "state-dir")]
+    pub state_dir: Option<PathBuf>,
+}
+
+impl Runnable for InspectCommand {
+    /// Print each chain's consensus state without modifying it
+    fn run(&self) {
+        let config = APP.config();
+        let mut success = true;
+
+        for chain in &config.chain {
+            let path = chain.state_file_path();
+
+            match State::read_consensus_state(&path) {
+                Ok(Some(state)) => status_ok!(
+                    &chain.id,
+                    "height={} round={} step={} block_id={} ({})",
+                    state.height,
+                    state.round,
+                    state.step,
+                    state.block_id_prefix(),
+                    path.display()
+                ),
+                Ok(None) => status_warn!("{}: no state file at {}", chain.id, path.display()),
+                Err(e) => {
+                    status_err!("{}: {}", chain.id, e);
+                    success = false;
+                }
+            }
+        }
+
+        if !success {
+            process::exit(1);
+        }
+    }
+}
+
+/// `state import` subcommand
+#[derive(Command, Debug, Parser)]
+pub struct ImportCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// directory containing all chains' state files (overrides `state_file`)
+    #[clap(long = "state-dir")]
+    pub state_dir: Option<PathBuf>,
+
+    /// chain ID whose state file should be written
+    #[clap(long = "chain-id")]
+    pub chain_id: chain::Id,
+
+    /// last height signed by this validator (e.g. from a block explorer):
+    /// nothing at or below it will be signed
+    #[clap(
+        long = "height",
+        required_unless_present = "from",
+        conflicts_with = "from"
+    )]
+    pub height: Option<block::Height>,
+
+    /// `priv_validator_state.json` to import, e.g. from the previous host
+    #[clap(long = "from")]
+    pub from: Option<PathBuf>,
+}
+
+impl Runnable for ImportCommand {
+    /// Write the imported state to the chain's state file
+    fn run(&self) {
+        if let Err(e) = self.import() {
+            status_err!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+impl ImportCommand {
+    /// Import the state, refusing to move the double-signing guard backwards
+    fn import(&self) -> Result<(), Error> {
+        let config = APP.config();
+
+        let chain_config = config
+            .chain
+            .iter()
+            .find(|chain| chain.id == self.chain_id)
+            .ok_or_else(|| format_err!(ConfigError, "chain {} not configured", self.chain_id))?;
+
+        let imported = match (&self.from, self.height) {
+            (Some(from), _) => State::read_consensus_state(from)?
+                .ok_or_else(|| format_err!(ConfigError, "no state file at {}", from.display()))?,
+            (None, Some(height)) => signed_through(height),
+            (None, None) => fail!(ConfigError, "either --height or --from is required"),
+        };
+
+        let path = chain_config.state_file_path();
+
+        if let Some(existing) = State::read_consensus_state(&path)? {
+            ensure!(
+                (existing.height, existing.round, existing.step)
+                    <= (imported.height, imported.round, imported.step),
+                StateSyncError,
+                "{} is at height={} round={} step={}, which is ahead of the imported state; \
+                 refusing to lower it",
+                path.display(),
+                existing.height,
+                existing.round,
+                existing.step
+            );
+        }
+
+        State::write_state(&path, imported.clone())?;
+
+        status_ok!(
+            "Imported",
+            "{}: height={} round={} step={} ({})",
+            self.chain_id,
+            imported.height,
+            imported.round,
+            imported.step,
+            path.display()
+        );
+
+        Ok(())
+    }
+}
+
+/// `state benchmark-guard` subcommand
+#[derive(Command, Debug, Parser)]
+pub struct BenchmarkGuardCommand {
+    /// directory to create the scratch state file in, e.g. the one holding
+    /// the real state files (default: system temporary directory)
+    #[clap(long = "dir")]
+    pub dir: Option<PathBuf>,
+
+    /// number of sign requests to simulate
+    #[clap(short = 'n', long = "iterations", default_value = "1000")]
+    pub iterations: u32,
+
+    /// state file fsync policy to benchmark: `always`, `batched`, or `os`
+    #[clap(long = "fsync", default_value = "always")]
+    pub fsync: FsyncPolicy,
+}
+
+impl Runnable for BenchmarkGuardCommand {
+    /// Simulate a sequence of non-conflicting sign requests against a scratch
+    /// state file, reporting how long the guard check and persisting the new
+    /// state took
+    fn run(&self) {
+        let dir = self.dir.clone().unwrap_or_else(env::temp_dir);
+
+        let scratch_dir = tempfile::tempdir_in(&dir).unwrap_or_else(|e| {
+            status_err!(
+                "couldn't create scratch directory in {}: {}",
+                dir.display(),
+                e
+            );
+            process::exit(1);
+        });
+
+        let state_path = scratch_dir
+            .path()
+            .join("benchmark_priv_validator_state.json");
+
+        let mut state = State::load_state(&state_path).unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        state.set_fsync_policy(self.fsync);
+        let mut latencies = Vec::with_capacity(self.iterations as usize);
+
+        for i in 0..self.iterations.max(1) {
+            // Proposal, prevote, and precommit for each successive height
+            let request_state = consensus::State {
+                height: (i / 3 + 1).into(),
+                round: 0u16.into(),
+                step: (i % 3) as i8,
+                block_id: None,
+            };
+
+            let started_at = Instant::now();
+
+            if let Err(e) = state.update_consensus_state(request_state) {
+                status_err!("{}", e);
+                process::exit(1);
+            }
+
+            latencies.push(started_at.elapsed());
+        }
+
+        latencies.sort();
+
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+        let micros = |d: Duration| d.as_micros();
+
+        status_ok!(
+            "Benchmarked",
+            "{} guarded state updates in {} (fsync: {}): min={}µs p50={}µs p99={}µs max={}µs",
+            latencies.len(),
+            dir.display(),
+            self.fsync,
+            micros(latencies[0]),
+            micros(percentile(50)),
+            micros(percentile(99)),
+            micros(latencies[latencies.len() - 1])
+        );
+    }
+}
+
+/// `state simulate-guard` subcommand
+#[derive(Command, Debug, Parser)]
+pub struct SimulateGuardCommand {
+    /// path to tmkms.toml, whose chains' state files are refused as the
+    /// throwaway state file (default: as for other commands, if it exists)
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// throwaway state file to simulate against, which must not exist yet
+    /// (default: a scratch file in the system temporary directory)
+    #[clap(long = "state-file")]
+    pub state_file: Option<PathBuf>,
+
+    /// chain ID to label logs, events, and metrics with
+    #[clap(long = "chain-id", default_value = "simulated-chain")]
+    pub chain_id: chain::Id,
+
+    /// comma-separated sign requests as `height/round/step`, optionally
+    /// followed by `@<block>` to name the block voted for (step 0 =
+    /// proposal, 1 = prevote, 2 = precommit)
+    #[clap(
+        long = "sequence",
+        value_delimiter = ',',
+        value_parser = parse_simulated_request,
+        default_value = DEFAULT_SIMULATED_SEQUENCE
+    )]
+    pub sequence: Vec<consensus::State>,
+}
+
+impl SimulateGuardCommand {
+    /// Path to the configuration file, if one was given or exists at the
+    /// default location. Unlike other commands, a configuration is optional.
+    pub fn config_path(&self) -> Option<PathBuf> {
+        if let Some(config) = &self.config {
+            return Some(config.clone());
+        }
+
+        let path = env::var(CONFIG_ENV_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(CONFIG_FILE_NAME));
+
+        path.exists().then_some(path)
+    }
+}
+
+impl Runnable for SimulateGuardCommand {
+    /// Replay the sequence, printing each decision along with the log line
+    /// and sign event it would produce, followed by the resulting metrics
+    fn run(&self) {
+        if let Err(e) = self.simulate() {
+            status_err!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+impl SimulateGuardCommand {
+    /// Replay the sequence against the throwaway state file
+    fn simulate(&self) -> Result<(), Error> {
+        let scratch_dir = tempfile::tempdir()?;
+
+        let state_path = match &self.state_file {
+            Some(path) => {
+                self.ensure_throwaway(path)?;
+                path.clone()
+            }
+            None => scratch_dir
+                .path()
+                .join("simulated_priv_validator_state.json"),
+        };
+
+        let mut state = State::load_state(&state_path)?;
+        let (mut accepted, mut rejected) = (0, 0);
+
+        for request_state in &self.sequence {
+            let msg_type = match request_state.step {
+                0 => "proposal",
+                1 => "prevote",
+                _ => "precommit",
+            };
+
+            let started_at = Instant::now();
+            let result = state.update_consensus_state(request_state.clone());
+            let guard_latency = started_at.elapsed();
+
+            let (decision, reason) = match result {
+                Ok(()) => {
+                    accepted += 1;
+                    println!("ACCEPTED {} at h/r/s {}", msg_type, request_state);
+                    (Decision::Accept, None)
+                }
+                Err(e) => {
+                    rejected += 1;
+                    println!("REJECTED {} at h/r/s {}", msg_type, request_state);
+                    println!("  log:   [{}] {}", self.chain_id, e);
+                    (Decision::Reject, Some(e.to_string()))
+                }
+            };
+
+            let event = SignEvent {
+                chain_id: self.chain_id.to_string(),
+                validator: "simulated".to_owned(),
+                decision,
+                msg_type: msg_type.to_owned(),
+                height: request_state.height.value(),
+                round: request_state.round.value(),
+                step: request_state.step,
+                latency_ms: 0,
+                guard_latency_us: Some(guard_latency.as_micros() as u64),
+                provider: None,
+                reason,
+            };
+
+            println!("  event: {}", serde_json::to_string(&event)?);
+        }
+
+        println!();
+        print!(
+            "{}",
+            metrics::render_states([(self.chain_id.as_str(), &state)].into_iter())
+        );
+
+        status_ok!(
+            "Simulated",
+            "{} sign requests: {} accepted, {} rejected ({})",
+            self.sequence.len(),
+            accepted,
+            rejected,
+            state_path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Refuse to simulate against a state file which already exists or which
+    /// belongs to a configured chain
+    fn ensure_throwaway(&self, path: &Path) -> Result<(), Error> {
+        ensure!(
+            fs::symlink_metadata(path).is_err(),
+            ConfigError,
+            "{} already exists: refusing to simulate against it (pick a new throwaway path)",
+            path.display()
+        );
+
+        if self.config_path().is_none() {
+            return Ok(());
+        }
+
+        let config = APP.config();
+        let path = absolute_path(path)?;
+
+        for chain_config in &config.chain {
+            ensure!(
+                absolute_path(&chain_config.state_file_path())? != path,
+                ConfigError,
+                "{} is the state file of chain {}: refusing to simulate against it",
+                path.display(),
+                chain_config.id
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// `state verify-checkpoints` subcommand
+#[derive(Command, Debug, Parser)]
+pub struct VerifyCheckpointsCommand {
+    /// file of checkpoints written by `state_checkpoint`
+    #[clap(long = "file")]
+    pub file: PathBuf,
+
+    /// hex-encoded Ed25519 public key of the auditing key (logged by the KMS
+    /// at startup)
+    #[clap(long = "key")]
+    pub key: String,
+}
+
+impl Runnable for VerifyCheckpointsCommand {
+    /// Verify the checkpoints, exiting with an error status unless they're
+    /// all valid
+    fn run(&self) {
+        match self.verify() {
+            Ok(count) => status_ok!(
+                "Verified",
+                "{} state checkpoint(s) in {}",
+                count,
+                self.file.display()
+            ),
+            Err(e) => {
+                status_err!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+impl VerifyCheckpointsCommand {
+    /// Verify the checkpoints against the given key, returning how many
+    /// there are
+    fn verify(&self) -> Result<usize, Error> {
+        let key = hex::decode(self.key.trim().to_ascii_lowercase())
+            .map_err(|e| format_err!(ParseError, "invalid hex key: {}", e))?;
+
+        let key = ed25519::VerifyingKey::try_from(key.as_slice())
+            .map_err(|_| format_err!(InvalidKey, "invalid Ed25519 public key: {}", self.key))?;
+
+        checkpoint::verify(&self.file, &key)
+    }
+}
+
+/// State after signing everything at the given height: its last possible
+/// round and step, so nothing at or below it can be signed again (the old
+/// host may have signed at any round), while every request at the next
+/// height is accepted
+fn signed_through(height: block::Height) -> consensus::State {
+    consensus::State {
+        height,
+        round: block::Round::try_from(i32::MAX).expect("invalid round"),
+        step: 2,
+        block_id: None,
+    }
+}
+
+/// Absolute form of a (possibly not yet existing) path, for comparisons
+fn absolute_path(path: &Path) -> Result<PathBuf, Error> {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_owned(),
+        _ => PathBuf::from("."),
+    };
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format_err!(ConfigError, "invalid state file path: {}", path.display()))?;
+
+    Ok(fs::canonicalize(&parent).unwrap_or(parent).join(file_name))
+}
+
+/// Parse a simulated sign request: `height/round/step`, optionally followed
+/// by `@<block>`
+fn parse_simulated_request(s: &str) -> Result<consensus::State, String> {
+    let (hrs, block) = match s.trim().split_once('@') {
+        Some((hrs, block)) => (hrs, Some(block)),
+        None => (s.trim(), None),
+    };
+
+    let invalid = || format!("expected height/round/step[@block], got `{}`", s);
+    let mut parts = hrs.split('/');
+    let (Some(height), Some(round), Some(step), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid());
+    };
+
+    let step: i8 = step.parse().map_err(|_| invalid())?;
+
+    if !(0..=2).contains(&step) {
+        return Err(format!("step must be 0, 1, or 2, got `{}`", s));
+    }
+
+    // Blocks are named for readability: derive a stable block ID from the name
+    let block_id = block.map(|name| block::Id {
+        hash: Hash::Sha256(Sha256::digest(name.as_bytes()).into()),
+        part_set_header: Default::default(),
+    });
+
+    Ok(consensus::State {
+        height: height.parse().map_err(|_| invalid())?,
+        round: round
+            .parse::<u32>()
+            .map_err(|_| invalid())?
+            .try_into()
+            .map_err(|_| invalid())?,
+        step,
+        block_id,
+    })
+}
@@ -0,0 +1,123 @@
+//! `tmkms status`: report the live runtime state of a running KMS
+
+use crate::{
+    control::{self, Command as ControlCommand},
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    status::Report,
+};
+use abscissa_core::Command;
+use clap::{Parser, ValueEnum};
+use std::{path::PathBuf, process};
+
+/// Output formats for `status`
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum StatusFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+
+    /// JSON
+    Json,
+}
+
+/// The `status` command
+#[derive(Command, Debug, Parser)]
+pub struct StatusCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// output format
+    #[clap(long = "format", value_enum, default_value_t)]
+    pub format: StatusFormat,
+}
+
+impl Runnable for StatusCommand {
+    /// Query the running KMS and print its state to stdout
+    fn run(&self) {
+        let report = self.query().unwrap_or_else(|e| {
+            status_err!("{}", e);
+            process::exit(1);
+        });
+
+        match self.format {
+            StatusFormat::Text => print_report(&report),
+            StatusFormat::Json => println!(
+                "{}",
+                serde_json::to_string_pretty(&report).expect("JSON serialization failed")
+            ),
+        }
+    }
+}
+
+impl StatusCommand {
+    /// Fetch the report from the running KMS's control socket
+    fn query(&self) -> Result<Report, Error> {
+        let config = APP.config();
+
+        let control_socket = config.control_socket.as_ref().ok_or_else(|| {
+            format_err!(
+                ConfigError,
+                "no `control_socket` configured: can't query the running KMS"
+            )
+        })?;
+
+        let reply = control::send(control_socket, ControlCommand::Status)?;
+
+        serde_json::from_str(&reply).map_err(|e| {
+            format_err!(ProtocolError, "malformed status from control socket: {}", e).into()
+        })
+    }
+}
+
+/// Print a report as text
+fn print_report(report: &Report) {
+    match &report.maintenance_since {
+        Some(since) => println!("maintenance mode: PAUSED since {}", since),
+        None => println!("maintenance mode: off (signing)"),
+    }
+
+    for chain in &report.chains {
+        let flag = |name: &str, value: Option<bool>| match value {
+            Some(true) => format!("; {}", name.to_uppercase()),
+            _ => String::new(),
+        };
+
+        println!();
+        println!(
+            "{}: h/r/s {}/{}/{}; last signed: {}; provider: {}{}{}{}",
+            chain.chain_id,
+            chain.height,
+            chain.round,
+            chain.step,
+            chain.last_signed_at.as_deref().unwrap_or("never"),
+            chain.provider.as_deref().unwrap_or("none"),
+            flag("observe-only", Some(chain.observe_only)),
+            flag("halted", chain.halted),
+            flag("signer quiet", chain.signer_quiet),
+        );
+
+        if chain.consecutive_rejections > 0 {
+            println!(
+                "  {} consecutive rejected sign requests",
+                chain.consecutive_rejections
+            );
+        }
+
+        if chain.validators.is_empty() {
+            println!("  no validators");
+        }
+
+        for validator in &chain.validators {
+            println!(
+                "  {}: {} since {}; errors: {}",
+                validator.addr, validator.state, validator.since, validator.errors
+            );
+
+            if let Some(last_error) = &validator.last_error {
+                println!("    last error: {}", last_error);
+            }
+        }
+    }
+}
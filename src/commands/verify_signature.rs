@@ -0,0 +1,140 @@
+//! `tmkms verify-signature`: check which configured key produced a signature
+//!
+//! This is a read-only audit tool: it fetches the public keys of every key in
+//! the chain's keyring from their signing providers and checks the signature
+//! against each of them, but never signs anything or touches the state file.
+
+use crate::{
+    chain,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use abscissa_core::Command;
+use clap::Parser;
+use signature::Verifier;
+use std::{path::PathBuf, process};
+use subtle_encoding::hex;
+use tendermint::{PublicKey, TendermintKey};
+
+/// The `verify-signature` command
+#[derive(Command, Debug, Parser)]
+pub struct VerifySignatureCommand {
+    /// path to tmkms.toml
+    #[clap(short = 'c', long = "config")]
+    pub config: Option<PathBuf>,
+
+    /// chain ID whose key should be checked
+    #[clap(long = "chain-id")]
+    pub chain_id: chain::Id,
+
+    /// hex-encoded message which was signed
+    #[clap(long = "message")]
+    pub message: String,
+
+    /// hex-encoded signature (64-byte Ed25519, or 64-byte compact secp256k1)
+    #[clap(long = "signature")]
+    pub signature: String,
+}
+
+impl Runnable for VerifySignatureCommand {
+    /// Verify the signature, exiting with an error status unless it's valid
+    fn run(&self) {
+        match self.verify() {
+            Ok(true) => (),
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                status_err!("{}", e);
+                process::exit(2);
+            }
+        }
+    }
+}
+
+impl VerifySignatureCommand {
+    /// Verify the signature against every public key in the chain's keyring,
+    /// returning whether any of them produced it
+    fn verify(&self) -> Result<bool, Error> {
+        let message = decode_hex("message", &self.message)?;
+        let signature = decode_hex("signature", &self.signature)?;
+
+        let registry = chain::load_config_readonly(&APP.config())?;
+        let chain = registry.get_chain(&self.chain_id).ok_or_else(|| {
+            format_err!(
+                ConfigError,
+                "chain {} missing from configuration",
+                self.chain_id
+            )
+        })?;
+
+        let mut checked = vec![];
+        let mut first_error = None;
+
+        for key in chain.keyring.public_keys() {
+            let (key_type, public_key) = match key {
+                TendermintKey::AccountKey(pk) => ("account", pk),
+                TendermintKey::ConsensusKey(pk) => ("consensus", pk),
+            };
+
+            let identity = format!("{} key {}", key_type, public_key.to_hex());
+
+            // A signature which is malformed for one kind of key may still be
+            // valid for another, so errors only count if no key could check it
+            match verify(public_key, &message, &signature) {
+                Ok(true) => {
+                    status_ok!(
+                        "VALID",
+                        "signature was produced by {} {}",
+                        self.chain_id,
+                        identity
+                    );
+                    return Ok(true);
+                }
+                Ok(false) => checked.push(identity),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        if checked.is_empty() {
+            return Err(
+                first_error.unwrap_or_else(|| format_err!(InvalidKey, "keyring is empty").into())
+            );
+        }
+
+        status_err!(
+            "INVALID: signature was not produced by any {} key: {}",
+            self.chain_id,
+            checked.join(", ")
+        );
+        Ok(false)
+    }
+}
+
+/// Verify a signature with the given public key
+fn verify(public_key: &PublicKey, message: &[u8], signature: &[u8]) -> Result<bool, Error> {
+    match public_key {
+        PublicKey::Ed25519(pk) => {
+            let verification_key = ed25519_consensus::VerificationKey::try_from(pk.as_bytes())
+                .map_err(|e| format_err!(InvalidKey, "invalid Ed25519 public key: {}", e))?;
+
+            let signature = ed25519_consensus::Signature::try_from(signature)
+                .map_err(|e| format_err!(ParseError, "invalid Ed25519 signature: {}", e))?;
+
+            Ok(verification_key.verify(&signature, message).is_ok())
+        }
+        PublicKey::Secp256k1(verifying_key) => {
+            let signature = k256::ecdsa::Signature::from_slice(signature)
+                .map_err(|e| format_err!(ParseError, "invalid secp256k1 signature: {}", e))?;
+
+            Ok(verifying_key.verify(message, &signature).is_ok())
+        }
+        _ => fail!(InvalidKey, "unsupported public key type"),
+    }
+}
+
+/// Decode a hex-encoded command-line argument
+fn decode_hex(name: &str, value: &str) -> Result<Vec<u8>, Error> {
+    hex::decode(value.trim().to_ascii_lowercase())
+        .map_err(|e| format_err!(ParseError, "invalid hex {}: {}", name, e).into())
+}
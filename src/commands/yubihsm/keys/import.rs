@@ -34,6 +34,11 @@ pub struct ImportCommand {
     #[clap(short = 'l', long = "label")]
     pub label: Option<String>,
 
+    /// allow the imported consensus key to be exported under a wrap key
+    /// (json/base64 keys are imported as non-exportable by default)
+    #[clap(long = "exportable")]
+    pub exportable: bool,
+
     /// path to key to import
     pub path: PathBuf,
 }
@@ -65,8 +70,28 @@ impl Runnable for ImportCommand {
 }
 
 impl ImportCommand {
+    /// Capabilities to grant a plaintext consensus key imported into the HSM.
+    ///
+    /// A key which is exportable under wrap can be extracted from the HSM by
+    /// anyone holding the wrap key, so consensus keys are only made exportable
+    /// when explicitly requested (e.g. to replicate them to other HSMs).
+    fn consensus_key_capabilities(&self) -> yubihsm::Capability {
+        if self.exportable {
+            status_warn!(
+                "importing consensus key as exportable: it can be extracted from the HSM under a wrap key"
+            );
+            yubihsm::Capability::SIGN_EDDSA | yubihsm::Capability::EXPORTABLE_UNDER_WRAP
+        } else {
+            yubihsm::Capability::SIGN_EDDSA
+        }
+    }
+
     /// Import a wrapped object into the HSM
     fn import_wrapped(&self, wrapped_key_base64: &str) {
+        if self.exportable {
+            status_warn!("ignoring --exportable (wrapped keys use original capabilities)");
+        }
+
         if let Some(id) = self.key_id {
             status_warn!("ignoring key ID: {} (wrapped keys use original key ID)", id);
         }
@@ -182,7 +207,7 @@ impl ImportCommand {
             key_id,
             label,
             DEFAULT_DOMAINS,
-            yubihsm::Capability::SIGN_EDDSA | yubihsm::Capability::EXPORTABLE_UNDER_WRAP,
+            self.consensus_key_capabilities(),
             yubihsm::asymmetric::Algorithm::Ed25519,
             seed.as_bytes(),
         ) {
@@ -227,7 +252,7 @@ impl ImportCommand {
             key_id,
             label,
             DEFAULT_DOMAINS,
-            yubihsm::Capability::SIGN_EDDSA | yubihsm::Capability::EXPORTABLE_UNDER_WRAP,
+            self.consensus_key_capabilities(),
             yubihsm::asymmetric::Algorithm::Ed25519,
             &secret.as_bytes()[..],
         ) {
@@ -1,13 +1,25 @@
 //! Configuration file structures (with serde-derived parser)
 
 pub mod chain;
+pub mod include;
+pub mod overrides;
 pub mod provider;
 pub mod validator;
 
 pub use self::validator::*;
 
 use self::{chain::ChainConfig, provider::ProviderConfig};
-use serde::Deserialize;
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use abscissa_core::Config;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
 
 /// Environment variable containing path to config file
 pub const CONFIG_ENV_VAR: &str = "TMKMS_CONFIG_FILE";
@@ -15,8 +27,11 @@ pub const CONFIG_ENV_VAR: &str = "TMKMS_CONFIG_FILE";
 /// Name of the KMS configuration file
 pub const CONFIG_FILE_NAME: &str = "tmkms.toml";
 
+/// Placeholder written in place of secrets when serializing configuration
+pub const REDACTED: &str = "REDACTED";
+
 /// KMS configuration (i.e. TOML file parsed with serde)
-#[derive(Default, Deserialize, Debug)]
+#[derive(Default, Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct KmsConfig {
     /// Chains the KMS is providing key management service for
@@ -24,9 +39,179 @@ pub struct KmsConfig {
     pub chain: Vec<ChainConfig>,
 
     /// Cryptographic signature provider configuration
+    #[serde(deserialize_with = "ProviderConfig::deserialize_checked")]
     pub providers: ProviderConfig,
 
     /// Addresses of validator nodes
     #[serde(default)]
     pub validator: Vec<ValidatorConfig>,
+
+    /// Path of a Unix socket streaming newline-delimited JSON events for each
+    /// sign decision (see [`crate::events`])
+    pub event_socket: Option<PathBuf>,
+
+    /// Path of a Unix socket accepting local control commands, e.g. from
+    /// `tmkms maintenance` and `tmkms status` (see [`crate::control`]).
+    /// Disabled by default.
+    pub control_socket: Option<PathBuf>,
+
+    /// Address to serve Prometheus metrics on, e.g. `127.0.0.1:9975` (see
+    /// [`crate::metrics`]). Disabled by default.
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Shut down once the resident set size exceeds this many megabytes, so a
+    /// memory leak leads to a clean restart (see [`crate::watchdog`]). Disabled
+    /// by default.
+    pub max_rss_mb: Option<u64>,
+
+    /// Maximum number of `[[chain]]` entries, guarding against accidentally
+    /// huge configurations (default: 256, see [`crate::resources`])
+    pub max_chains: Option<usize>,
+
+    /// Directory of `*.toml` configuration fragments adding `[[chain]]`,
+    /// `[[validator]]`, and `[[providers.*]]` entries, relative to this
+    /// file's directory (see [`include`]). Disabled by default. Omitted when
+    /// serialized, e.g. by `tmkms config dump`, which shows the merged result.
+    #[serde(skip_serializing)]
+    pub include_dir: Option<PathBuf>,
+}
+
+impl KmsConfig {
+    /// Load the configuration file at the given path, merging in the
+    /// fragments in its `include_dir` (see [`include`]) and then applying
+    /// `key=value` overrides (see [`overrides`]) before parsing it. The files
+    /// themselves are left untouched.
+    pub fn load_with_overrides(path: &Path, overrides: &[String]) -> Result<Self, Error> {
+        let toml_string = fs::read_to_string(path)
+            .map_err(|e| format_err!(ConfigError, "couldn't read {}: {}", path.display(), e))?;
+
+        let mut document = toml_string
+            .parse::<toml::Table>()
+            .map_err(|e| format_err!(ConfigError, "couldn't parse {}: {}", path.display(), e))?;
+
+        for fragment in include::merge(&mut document, path)? {
+            status_info!("Including", "{}", fragment.display());
+        }
+
+        for expr in overrides {
+            let (key, value) = overrides::apply(&mut document, expr)?;
+            status_info!("Override", "{} = {}", key, value);
+        }
+
+        Self::load_toml(document.to_string())
+            .map_err(|e| format_err!(ConfigError, "invalid merged configuration: {}", e).into())
+    }
+
+    /// Only activate the given chains: validators for all other chains are
+    /// dropped, leaving those chains loaded but inactive
+    pub fn select_chains(&mut self, chain_ids: &[crate::chain::Id]) -> Result<(), Error> {
+        ensure!(
+            chain_ids
+                .iter()
+                .any(|id| self.chain.iter().any(|chain| &chain.id == id)),
+            ConfigError,
+            "none of the selected chains are configured: {}",
+            chain_ids
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        for id in chain_ids {
+            if !self.chain.iter().any(|chain| &chain.id == id) {
+                status_warn!("selected chain {} is not configured", id);
+            }
+        }
+
+        for chain in &self.chain {
+            if !chain_ids.contains(&chain.id) {
+                status_info!("Skipping", "{} (not selected with --chain-id)", chain.id);
+            }
+        }
+
+        self.validator
+            .retain(|validator| chain_ids.contains(&validator.chain_id));
+
+        Ok(())
+    }
+
+    /// Relocate all chain state files into the given directory, keeping their
+    /// file names
+    pub fn override_state_dir(&mut self, state_dir: &Path) {
+        for chain in &mut self.chain {
+            let file_name = chain
+                .state_file_path()
+                .file_name()
+                .expect("state file path has no file name")
+                .to_owned();
+
+            chain.state_file = Some(state_dir.join(file_name));
+        }
+    }
+
+    /// Ensure no two chains share a state file or standby lock file, and that
+    /// no lock file is also a state file.
+    ///
+    /// Chains may each choose their own durability settings (`state_fsync`,
+    /// `standby_lock`, etc), but each needs its own files: a chain sharing
+    /// another's state file would be guarded against the wrong heights, and
+    /// a shared lease would let one chain's signing keep another's lock
+    /// alive after it stopped.
+    pub fn check_chain_files(&self) -> Result<(), Error> {
+        let mut files = Vec::<(PathBuf, &str, &str)>::new();
+
+        for chain in &self.chain {
+            let state_file = (chain.state_file_path(), "state file");
+            let lock_file = chain
+                .standby_lock
+                .as_ref()
+                .map(|lock| (lock.path.clone(), "standby lock file"));
+
+            for (path, kind) in std::iter::once(state_file).chain(lock_file) {
+                let normalized = normalize_path(&path);
+
+                if let Some((_, other_chain, other_kind)) =
+                    files.iter().find(|(other, ..)| *other == normalized)
+                {
+                    fail!(
+                        ConfigError,
+                        "[{}] {} {} is also the {} of chain {}: each chain needs its own",
+                        chain.id,
+                        kind,
+                        path.display(),
+                        other_kind,
+                        other_chain
+                    );
+                }
+
+                files.push((normalized, chain.id.as_str(), kind));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Make a path (which may not exist yet) comparable to others referring to
+/// the same file, by resolving its parent directory if possible
+fn normalize_path(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(parent), Some(file_name)) => parent.join(file_name),
+        _ => path.to_owned(),
+    }
+}
+
+/// Serialize a secret configuration value as [`REDACTED`]
+#[cfg(any(feature = "yubihsm", feature = "fortanixdsm"))]
+pub(crate) fn redact<T, S: serde::Serializer>(
+    _secret: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(REDACTED)
 }
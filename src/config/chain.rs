@@ -1,14 +1,43 @@
 //! Chain configuration
 
+mod approval;
+mod checkpoint;
+mod duplicate;
+mod fsync;
+mod halt;
 mod hook;
+mod lock;
+mod missing_state;
+mod quiet;
+mod reply_encoding;
+mod timeout;
 
-pub use self::hook::HookConfig;
-use crate::{chain, keyring};
-use serde::Deserialize;
-use std::path::PathBuf;
+pub use self::{
+    approval::ApprovalHookConfig,
+    checkpoint::StateCheckpointConfig,
+    duplicate::DuplicateRequestPolicy,
+    fsync::FsyncPolicy,
+    halt::HaltDetectionConfig,
+    hook::HookConfig,
+    lock::StandbyLockConfig,
+    missing_state::MissingStatePolicy,
+    quiet::{QuietAction, SignWatchdogConfig},
+    reply_encoding::ReplyEncoding,
+    timeout::SignTimeoutConfig,
+};
+use crate::{
+    chain,
+    config::validator::ProtocolVersion,
+    error::{Error, ErrorKind::*},
+    keyring,
+    prelude::*,
+    privval::SignedMsgType,
+};
+use serde::{Deserialize, Serialize};
+use std::{path::PathBuf, time::Duration};
 
 /// Chain configuration
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct ChainConfig {
     /// Chain ID of this Tendermint network/chain
@@ -27,11 +56,166 @@ pub struct ChainConfig {
     #[serde(default)]
     pub sign_extensions: bool,
 
+    /// Validate sign requests and apply the double-signing guard to them, but
+    /// never sign them (default: false). Useful for trialling a new deployment
+    /// alongside the existing signer: what would have been signed is logged,
+    /// and the validator receives an error response instead of a signature.
+    #[serde(default)]
+    pub observe_only: bool,
+
     /// Path to chain-specific `priv_validator_state.json` file
     pub state_file: Option<PathBuf>,
 
+    /// When updates to the state file are flushed to stable storage:
+    /// `always`, `batched`, or `os` (default: `always`). See [`FsyncPolicy`].
+    pub state_fsync: Option<FsyncPolicy>,
+
+    /// What to do if the state file doesn't exist on startup: `init_zero` to
+    /// create one at height 0, or `refuse` to exit (default: `refuse`). See
+    /// [`MissingStatePolicy`].
+    pub on_missing_state: Option<MissingStatePolicy>,
+
     /// User-specified command to run to obtain the current block height for
     /// this chain. This will be executed at launch time to populate the
     /// initial block height if configured
     pub state_hook: Option<HookConfig>,
+
+    /// Warm-standby coordination lock. When configured, only the KMS instance
+    /// holding the lease will sign for this chain.
+    pub standby_lock: Option<StandbyLockConfig>,
+
+    /// Detect when this chain appears to have halted by polling a node's RPC
+    /// endpoint. This only affects logging and reconnect backoff: signing
+    /// behavior is unaffected.
+    pub halt_detection: Option<HaltDetectionConfig>,
+
+    /// Warn (or exit) when no sign request has been accepted for this chain
+    /// within an expected cadence
+    pub sign_watchdog: Option<SignWatchdogConfig>,
+
+    /// Post-processing to apply to signatures before they're sent to the
+    /// validator: `ed25519` or `secp256k1` (low-S normalization). Disabled
+    /// by default.
+    pub signature_post_process: Option<keyring::PostProcess>,
+
+    /// Message types this chain is allowed to sign: any of `proposal`,
+    /// `prevote`, and `precommit` (default: all of them)
+    pub allowed_message_types: Option<Vec<SignedMsgType>>,
+
+    /// Tendermint/CometBFT protocol version this chain runs. When set, the
+    /// chain's validators must be configured with the same version.
+    pub protocol_version: Option<ProtocolVersion>,
+
+    /// After the double-signing guard rejects a request, delay processing
+    /// requests from newly connected validators until this many milliseconds
+    /// have passed since the rejection (default: 0, i.e. no delay)
+    pub rejection_grace_period_ms: Option<u64>,
+
+    /// Wire encoding of replies to validators: `protobuf-v0.34`,
+    /// `protobuf-v0.37`, or `protobuf-v0.38` (default: matching
+    /// `protocol_version`, or `protobuf-v0.38` if it isn't set). See
+    /// [`ReplyEncoding`].
+    pub reply_encoding: Option<ReplyEncoding>,
+
+    /// Time budgets for the signing provider, per message type. A provider
+    /// which hasn't returned a signature within its budget is abandoned, and
+    /// the request fails rather than being answered late.
+    pub sign_timeout: Option<SignTimeoutConfig>,
+
+    /// Never sign below this height, even if the state file is behind it
+    /// (e.g. the chain's height when this KMS was deployed). Raised by every
+    /// height signed while running.
+    pub min_height: Option<tendermint::block::Height>,
+
+    /// Never sign above this height, as a sanity ceiling for wildly wrong
+    /// request heights (must be greater than `min_height`)
+    pub max_height: Option<tendermint::block::Height>,
+
+    /// External command which must approve sign requests (by default only
+    /// proposals) before they're signed. Disabled by default.
+    pub approval_hook: Option<ApprovalHookConfig>,
+
+    /// What to do with a sign request identical to the one last signed, e.g.
+    /// from both instances of a flapping active/standby validator: `resign`,
+    /// `cached`, or `reject` (default: `resign`). See
+    /// [`DuplicateRequestPolicy`].
+    pub on_duplicate_request: Option<DuplicateRequestPolicy>,
+
+    /// Periodically append a checkpoint of this chain's double-signing state,
+    /// signed by a separate auditing key, to a file. Disabled by default.
+    pub state_checkpoint: Option<StateCheckpointConfig>,
+}
+
+impl ChainConfig {
+    /// Ensure the configured protocol version is one we can sign for, and that
+    /// the reply encoding is compatible with it
+    ///
+    /// All supported versions (v0.34 and newer) share the same consensus step
+    /// ordering and canonical Protobuf encoding of proposals and votes. Vote
+    /// extensions were introduced in v0.38, and are dropped by older reply
+    /// encodings.
+    pub fn check_protocol_version(&self) -> Result<(), Error> {
+        match self.protocol_version {
+            Some(ProtocolVersion::V0_33) => fail!(
+                ConfigError,
+                "chain {}: protocol_version v0.33 uses Amino-encoded sign bytes, which are unsupported",
+                self.id
+            ),
+            Some(ProtocolVersion::V0_34) if self.sign_extensions => fail!(
+                ConfigError,
+                "chain {}: sign_extensions requires protocol_version v0.38 or newer",
+                self.id
+            ),
+            _ => (),
+        }
+
+        let reply_encoding = self.reply_encoding();
+
+        match reply_encoding {
+            ReplyEncoding::Amino => fail!(
+                ConfigError,
+                "chain {}: Amino-encoded replies are unsupported",
+                self.id
+            ),
+            ReplyEncoding::ProtobufV0_37 | ReplyEncoding::ProtobufV0_38
+                if self.protocol_version == Some(ProtocolVersion::V0_34) =>
+            {
+                fail!(
+                    ConfigError,
+                    "chain {}: reply_encoding {} is newer than protocol_version v0.34",
+                    self.id,
+                    reply_encoding
+                )
+            }
+            ReplyEncoding::ProtobufV0_34 | ReplyEncoding::ProtobufV0_37 if self.sign_extensions => {
+                fail!(
+                    ConfigError,
+                    "chain {}: sign_extensions requires reply_encoding protobuf-v0.38 (got {})",
+                    self.id,
+                    reply_encoding
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Wire encoding of replies to validators
+    pub fn reply_encoding(&self) -> ReplyEncoding {
+        self.reply_encoding
+            .unwrap_or_else(|| ReplyEncoding::for_protocol_version(self.protocol_version))
+    }
+
+    /// Grace period following a double-signing guard rejection
+    pub fn rejection_grace_period(&self) -> Duration {
+        Duration::from_millis(self.rejection_grace_period_ms.unwrap_or(0))
+    }
+
+    /// Path to this chain's `priv_validator_state.json` file, defaulting to
+    /// `<chain id>_priv_validator_state.json` in the current directory
+    pub fn state_file_path(&self) -> PathBuf {
+        match self.state_file {
+            Some(ref path) => path.to_owned(),
+            None => PathBuf::from(&format!("{}_priv_validator_state.json", self.id)),
+        }
+    }
 }
@@ -0,0 +1,22 @@
+use crate::privval::SignedMsgType;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+
+/// Configuration for an external approval hook, which must approve sign
+/// requests before they're signed
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ApprovalHookConfig {
+    /// Command (with arguments) to invoke. The request is written to its
+    /// stdin as JSON, and it approves by exiting with status 0.
+    pub cmd: Vec<OsString>,
+
+    /// Deadline (in milliseconds) for the command to approve (default 500)
+    pub timeout_ms: Option<u64>,
+
+    /// Message types which require approval (default: `proposal`)
+    pub message_types: Option<Vec<SignedMsgType>>,
+
+    /// Only require approval at or above this height (default: any height)
+    pub min_height: Option<tendermint::block::Height>,
+}
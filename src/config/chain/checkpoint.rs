@@ -0,0 +1,56 @@
+//! Configuration for signed state checkpoints
+
+use serde::{Deserialize, Serialize};
+use std::{num::NonZeroU64, path::PathBuf, time::Duration};
+
+/// Default number of seconds between checkpoints
+pub const DEFAULT_INTERVAL_SECS: u64 = 300;
+
+/// Configuration for periodically appending a signed checkpoint of the
+/// chain's double-signing state to a file
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StateCheckpointConfig {
+    /// File checkpoints are appended to, one JSON object per line. Put it on
+    /// append-only storage (e.g. `chattr +a`, or a log shipper) so past
+    /// checkpoints can't be rewritten.
+    pub path: PathBuf,
+
+    /// Base64-encoded Ed25519 auditing key which signs the checkpoints (e.g.
+    /// from `tmkms softsign keygen`). Must not be the chain's consensus key.
+    pub key: PathBuf,
+
+    /// Seconds between checkpoints (default: 300). Must not be 0, which
+    /// would append to the file in a tight loop.
+    pub interval_secs: Option<NonZeroU64>,
+}
+
+impl StateCheckpointConfig {
+    /// Time between checkpoints
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(
+            self.interval_secs
+                .map_or(DEFAULT_INTERVAL_SECS, NonZeroU64::get),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(interval_secs: &str) -> Result<StateCheckpointConfig, toml::de::Error> {
+        toml::from_str(&format!(
+            "path = \"checkpoints.jsonl\"\nkey = \"audit.key\"\n{}",
+            interval_secs
+        ))
+    }
+
+    #[test]
+    fn interval() {
+        let interval = |s| parse(s).unwrap().interval();
+        assert_eq!(interval(""), Duration::from_secs(DEFAULT_INTERVAL_SECS));
+        assert_eq!(interval("interval_secs = 60"), Duration::from_secs(60));
+        assert!(parse("interval_secs = 0").is_err());
+    }
+}
@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// What to do with a sign request identical to the one last signed for a
+/// chain, e.g. sent by both instances of a flapping active/standby validator
+///
+/// Sign requests for a chain are always handled one at a time, so a
+/// duplicate is only ever seen after the first request has been signed.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateRequestPolicy {
+    /// Sign it again with the signing provider
+    #[default]
+    Resign,
+
+    /// Answer with the signature already produced, without involving the
+    /// signing provider
+    Cached,
+
+    /// Refuse it with an error response
+    Reject,
+}
+
+impl Display for DuplicateRequestPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DuplicateRequestPolicy::Resign => "resign",
+            DuplicateRequestPolicy::Cached => "cached",
+            DuplicateRequestPolicy::Reject => "reject",
+        })
+    }
+}
@@ -0,0 +1,57 @@
+use crate::{
+    error::{Error, ErrorKind::ConfigError},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+/// When state file updates are flushed to stable storage
+///
+/// Both `always` and `batched` make every update durable before its signature
+/// is released. Only `os` weakens this: after a crash or power loss, a
+/// signature may have been released whose state update was lost, and the KMS
+/// may double sign after restarting.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FsyncPolicy {
+    /// `fsync` the state file and its directory on every update
+    #[default]
+    Always,
+
+    /// Like `always`, but concurrent updates to state files in the same
+    /// directory share a single directory `fsync`. Every update is still
+    /// durable before its signature is released.
+    Batched,
+
+    /// Leave flushing to the operating system (UNSAFE: trades durability for
+    /// latency)
+    Os,
+}
+
+impl Display for FsyncPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FsyncPolicy::Always => "always",
+            FsyncPolicy::Batched => "batched",
+            FsyncPolicy::Os => "os",
+        })
+    }
+}
+
+impl FromStr for FsyncPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        let policy = match s {
+            "always" => FsyncPolicy::Always,
+            "batched" => FsyncPolicy::Batched,
+            "os" => FsyncPolicy::Os,
+            other => fail!(ConfigError, "invalid fsync policy: {}", other),
+        };
+
+        Ok(policy)
+    }
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use tendermint_config::net;
+
+/// Configuration for detecting when a chain has halted
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HaltDetectionConfig {
+    /// Address of a node's RPC endpoint (e.g. `tcp://127.0.0.1:26657`)
+    pub rpc_addr: net::Address,
+
+    /// Time (in seconds) without a new block after which the chain is
+    /// considered halted (default 60)
+    pub halt_after_secs: Option<u64>,
+
+    /// Interval (in seconds) at which to poll the RPC endpoint (default 10)
+    pub poll_interval_secs: Option<u64>,
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Configuration for a warm-standby coordination lock
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct StandbyLockConfig {
+    /// Path to the lease file shared between the active and standby KMS
+    pub path: PathBuf,
+
+    /// Unique name of this KMS instance (e.g. `kms-a`)
+    pub holder: String,
+
+    /// Time (in seconds) a lease remains valid without being renewed (default 30)
+    pub ttl_secs: Option<u64>,
+}
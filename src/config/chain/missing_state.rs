@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+
+/// What to do when a chain's state file doesn't exist on startup
+///
+/// A missing state file is indistinguishable from a validator which has never
+/// signed. After a host migration which lost the state file, starting from
+/// height 0 would allow signing below the last height signed on the old host,
+/// i.e. double signing.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingStatePolicy {
+    /// Create a new state file at height 0 (only safe for validators which
+    /// have never signed)
+    InitZero,
+
+    /// Refuse to start until the state file is restored or imported with
+    /// `tmkms state import`
+    #[default]
+    Refuse,
+}
+
+impl Display for MissingStatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MissingStatePolicy::InitZero => "init_zero",
+            MissingStatePolicy::Refuse => "refuse",
+        })
+    }
+}
@@ -0,0 +1,29 @@
+//! Configuration for the sign cadence watchdog
+
+use serde::{Deserialize, Serialize};
+
+/// Configuration for detecting when this signer has gone quiet, i.e. stopped
+/// sending signatures for a chain
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SignWatchdogConfig {
+    /// Time (in seconds) without a signature sent after which this
+    /// signer is considered quiet. Set it to several of the chain's block
+    /// times.
+    pub quiet_after_secs: u64,
+
+    /// What to do once this signer is quiet: `warn` or `exit` (default: `warn`)
+    pub action: Option<QuietAction>,
+}
+
+/// What to do once a signer has gone quiet
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QuietAction {
+    /// Log a warning and report it via metrics
+    #[default]
+    Warn,
+
+    /// Additionally exit, so an orchestrator can restart the KMS
+    Exit,
+}
@@ -0,0 +1,53 @@
+//! Wire encoding of privval replies
+
+use crate::config::validator::ProtocolVersion;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Encoding of the responses sent to validators
+///
+/// Sign requests are always decoded with the newest Protobuf definitions,
+/// which are backwards compatible. Replies can be re-encoded with an older
+/// version's definitions so they only contain fields that version's privval
+/// peers know about.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum ReplyEncoding {
+    /// Amino (Tendermint v0.33 and older): unsupported, rejected at startup
+    #[serde(rename = "amino")]
+    Amino,
+
+    /// Tendermint v0.34 Protobuf
+    #[serde(rename = "protobuf-v0.34")]
+    ProtobufV0_34,
+
+    /// CometBFT v0.37 Protobuf
+    #[serde(rename = "protobuf-v0.37")]
+    ProtobufV0_37,
+
+    /// CometBFT v0.38 Protobuf (with vote extensions)
+    #[serde(rename = "protobuf-v0.38")]
+    ProtobufV0_38,
+}
+
+impl ReplyEncoding {
+    /// Default encoding for the given protocol version: the newest encoding
+    /// unless a protocol version is configured
+    pub fn for_protocol_version(protocol_version: Option<ProtocolVersion>) -> Self {
+        match protocol_version {
+            Some(ProtocolVersion::V0_33) => ReplyEncoding::Amino,
+            Some(ProtocolVersion::V0_34) => ReplyEncoding::ProtobufV0_34,
+            Some(ProtocolVersion::V0_38) | None => ReplyEncoding::ProtobufV0_38,
+        }
+    }
+}
+
+impl fmt::Display for ReplyEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReplyEncoding::Amino => "amino",
+            ReplyEncoding::ProtobufV0_34 => "protobuf-v0.34",
+            ReplyEncoding::ProtobufV0_37 => "protobuf-v0.37",
+            ReplyEncoding::ProtobufV0_38 => "protobuf-v0.38",
+        })
+    }
+}
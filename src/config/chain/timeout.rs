@@ -0,0 +1,62 @@
+use crate::privval::SignedMsgType;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Time budgets for signing providers, per message type
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignTimeoutConfig {
+    /// Budget (in milliseconds) for message types without their own
+    /// (default: unlimited)
+    pub default_ms: Option<u64>,
+
+    /// Budget (in milliseconds) for signing proposals (default: `default_ms`)
+    pub proposal_ms: Option<u64>,
+
+    /// Budget (in milliseconds) for signing prevotes (default: `default_ms`)
+    pub prevote_ms: Option<u64>,
+
+    /// Budget (in milliseconds) for signing precommits, including their vote
+    /// extensions (default: `default_ms`)
+    pub precommit_ms: Option<u64>,
+}
+
+impl SignTimeoutConfig {
+    /// Get the budget for signing the given message type, if any
+    pub fn for_msg_type(&self, msg_type: SignedMsgType) -> Option<Duration> {
+        let millis = match msg_type {
+            SignedMsgType::Proposal => self.proposal_ms,
+            SignedMsgType::Prevote => self.prevote_ms,
+            SignedMsgType::Precommit => self.precommit_ms,
+            SignedMsgType::Unknown => None,
+        };
+
+        millis.or(self.default_ms).map(Duration::from_millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_to_default() {
+        let config = SignTimeoutConfig {
+            default_ms: Some(1000),
+            proposal_ms: Some(3000),
+            ..Default::default()
+        };
+
+        let ms = Duration::from_millis;
+        assert_eq!(config.for_msg_type(SignedMsgType::Proposal), Some(ms(3000)));
+        assert_eq!(config.for_msg_type(SignedMsgType::Prevote), Some(ms(1000)));
+        assert_eq!(
+            config.for_msg_type(SignedMsgType::Precommit),
+            Some(ms(1000))
+        );
+        assert_eq!(
+            SignTimeoutConfig::default().for_msg_type(SignedMsgType::Prevote),
+            None
+        );
+    }
+}
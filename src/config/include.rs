@@ -0,0 +1,249 @@
+//! Configuration fragments merged in from an `include_dir`
+//!
+//! Every `*.toml` file directly inside the directory is a fragment, merged
+//! into the main configuration file in file name order. Fragments may only
+//! add entries to the `[[chain]]`, `[[validator]]`, and `[[providers.*]]`
+//! arrays, which are appended after the main file's own entries. Everything
+//! else (e.g. `metrics_addr`) can only be set in the main file, so no setting
+//! ever depends on which file wins. A chain ID configured in more than one
+//! file is an error.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    Map,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+/// Top-level arrays fragments may add entries to
+const MERGED_ARRAYS: &[&str] = &["chain", "validator"];
+
+/// Merge the fragments in the `include_dir` of the given configuration file
+/// (if it has one) into its parsed document, returning their paths
+pub fn merge(document: &mut Table, config_path: &Path) -> Result<Vec<PathBuf>, Error> {
+    let include_dir = match document.get("include_dir") {
+        Some(Value::String(dir)) => config_path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(dir),
+        Some(_) => fail!(ConfigError, "`include_dir` must be a path"),
+        None => return Ok(vec![]),
+    };
+
+    let mut fragments = fs::read_dir(&include_dir)
+        .map_err(|e| {
+            format_err!(
+                ConfigError,
+                "couldn't read include_dir {}: {}",
+                include_dir.display(),
+                e
+            )
+        })?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    fragments.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"));
+    fragments.sort();
+
+    // File each chain ID was configured in, to report conflicts
+    let mut chain_sources = Map::new();
+    record_chains(document, config_path, &mut chain_sources)?;
+
+    for fragment_path in &fragments {
+        let fragment = fs::read_to_string(fragment_path)
+            .map_err(|e| {
+                format_err!(
+                    ConfigError,
+                    "couldn't read {}: {}",
+                    fragment_path.display(),
+                    e
+                )
+            })?
+            .parse::<Table>()
+            .map_err(|e| {
+                format_err!(
+                    ConfigError,
+                    "couldn't parse {}: {}",
+                    fragment_path.display(),
+                    e
+                )
+            })?;
+
+        record_chains(&fragment, fragment_path, &mut chain_sources)?;
+        merge_fragment(document, fragment, fragment_path)?;
+    }
+
+    Ok(fragments)
+}
+
+/// Append the entries of a single fragment to the document
+fn merge_fragment(document: &mut Table, fragment: Table, path: &Path) -> Result<(), Error> {
+    for (key, value) in fragment {
+        if MERGED_ARRAYS.contains(&key.as_str()) {
+            append(document, &key, &key, value, path)?;
+        } else if key == "providers" {
+            let Value::Table(providers) = value else {
+                fail!(
+                    ConfigError,
+                    "{}: `providers` must be a table",
+                    path.display()
+                );
+            };
+
+            let document_providers = match document
+                .entry("providers")
+                .or_insert_with(|| Value::Table(Table::new()))
+            {
+                Value::Table(table) => table,
+                _ => fail!(ConfigError, "`providers` must be a table"),
+            };
+
+            for (provider, entries) in providers {
+                let name = format!("providers.{}", provider);
+                append(document_providers, &provider, &name, entries, path)?;
+            }
+        } else {
+            fail!(
+                ConfigError,
+                "{}: `{}` can only be set in the main configuration file",
+                path.display(),
+                key
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Append the given array of tables (named as in the fragment at the given
+/// path) to the array with the given key
+fn append(
+    table: &mut Table,
+    key: &str,
+    name: &str,
+    value: Value,
+    path: &Path,
+) -> Result<(), Error> {
+    let Value::Array(entries) = value else {
+        fail!(
+            ConfigError,
+            "{}: `{}` must be an array of tables (`[[{}]]`)",
+            path.display(),
+            name,
+            name
+        );
+    };
+
+    match table.entry(key).or_insert_with(|| Value::Array(Vec::new())) {
+        Value::Array(array) => array.extend(entries),
+        _ => fail!(ConfigError, "`{}` must be an array of tables", name),
+    }
+
+    Ok(())
+}
+
+/// Record the IDs of the chains configured in the given document, refusing
+/// any which were already configured in another file
+fn record_chains(
+    document: &Table,
+    path: &Path,
+    chain_sources: &mut Map<String, PathBuf>,
+) -> Result<(), Error> {
+    let Some(Value::Array(chains)) = document.get("chain") else {
+        return Ok(());
+    };
+
+    for id in chains.iter().filter_map(|chain| chain.get("id")?.as_str()) {
+        if let Some(other_path) = chain_sources.insert(id.to_owned(), path.to_owned()) {
+            fail!(
+                ConfigError,
+                "duplicate chain ID {}: configured in both {} and {}",
+                id,
+                other_path.display(),
+                path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+include_dir = "tmkms.d"
+
+[[chain]]
+id = "chain-a"
+key_format = { type = "hex" }
+
+[providers]
+"#;
+
+    /// Write the main configuration and the given fragments, then merge them
+    fn merge_fragments(fragments: &[(&str, &str)]) -> Result<Table, Error> {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("tmkms.toml");
+        fs::create_dir(dir.path().join("tmkms.d")).unwrap();
+
+        for (name, contents) in fragments {
+            fs::write(dir.path().join("tmkms.d").join(name), contents).unwrap();
+        }
+
+        let mut document = CONFIG.parse::<Table>().unwrap();
+        merge(&mut document, &config_path).map(|_| document)
+    }
+
+    #[test]
+    fn merge_in_file_name_order() {
+        let document = merge_fragments(&[
+            (
+                "20-chain-c.toml",
+                "[[chain]]\nid = \"chain-c\"\nkey_format = { type = \"hex\" }\n",
+            ),
+            (
+                "10-chain-b.toml",
+                "[[chain]]\nid = \"chain-b\"\nkey_format = { type = \"hex\" }\n\n\
+                 [[providers.softsign]]\nchain_ids = [\"chain-b\"]\npath = \"b.key\"\n",
+            ),
+            ("README.md", "not a fragment"),
+        ])
+        .unwrap();
+
+        let ids = document["chain"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|chain| chain["id"].as_str().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(ids, ["chain-a", "chain-b", "chain-c"]);
+        assert_eq!(
+            document["providers"]["softsign"][0]["path"].as_str(),
+            Some("b.key")
+        );
+    }
+
+    #[test]
+    fn reject_conflicts() {
+        let err = merge_fragments(&[(
+            "chain-a.toml",
+            "[[chain]]\nid = \"chain-a\"\nkey_format = { type = \"hex\" }\n",
+        )])
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("duplicate chain ID chain-a"));
+        assert!(err.contains("chain-a.toml"));
+
+        let err = merge_fragments(&[("metrics.toml", "metrics_addr = \"127.0.0.1:9975\"\n")])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("`metrics_addr` can only be set in the main configuration file"));
+    }
+}
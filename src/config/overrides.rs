@@ -0,0 +1,148 @@
+//! Ephemeral `key=value` overrides of configuration file settings, e.g.
+//! `tmkms start --set validator.0.reconnect=false`
+//!
+//! Keys are dot-separated paths into the TOML document, where numeric
+//! components index into arrays (e.g. `chain.1.state_file`). Values are parsed
+//! as TOML values, falling back to strings: `10`, `true`, and `"10"` are an
+//! integer, a boolean, and a string respectively, while `/tmp/state.json` is a
+//! string.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use toml::{Table, Value};
+
+/// Apply a `key=value` override to the given TOML document, returning the key
+/// and the effective value
+pub fn apply(document: &mut Table, expr: &str) -> Result<(String, Value), Error> {
+    let (key, raw_value) = expr.split_once('=').ok_or_else(|| {
+        format_err!(
+            ConfigError,
+            "invalid override `{}`: expected `key=value`",
+            expr
+        )
+    })?;
+
+    let key = key.trim();
+    let value = parse_value(raw_value.trim());
+    let mut components = key.split('.').peekable();
+    let mut table = document;
+
+    while let Some(component) = components.next() {
+        ensure!(
+            !component.is_empty(),
+            ConfigError,
+            "invalid override key `{}`",
+            key
+        );
+
+        if components.peek().is_none() {
+            table.insert(component.to_owned(), value.clone());
+            return Ok((key.to_owned(), value));
+        }
+
+        let entry = table
+            .entry(component)
+            .or_insert_with(|| Value::Table(Table::new()));
+
+        table = match entry {
+            Value::Table(next) => next,
+            Value::Array(array) => {
+                let index = components
+                    .next()
+                    .and_then(|index| index.parse::<usize>().ok())
+                    .ok_or_else(|| {
+                        format_err!(
+                            ConfigError,
+                            "override key `{}`: `{}` is an array and must be followed by an index",
+                            key,
+                            component
+                        )
+                    })?;
+
+                let len = array.len();
+                let last = components.peek().is_none();
+
+                match array.get_mut(index) {
+                    Some(element) if last => {
+                        *element = value.clone();
+                        return Ok((key.to_owned(), value));
+                    }
+                    Some(Value::Table(next)) => next,
+                    Some(_) => fail!(ConfigError, "override key `{}`: not a table", key),
+                    None => fail!(
+                        ConfigError,
+                        "override key `{}`: index {} out of range ({} `{}` entries)",
+                        key,
+                        index,
+                        len,
+                        component
+                    ),
+                }
+            }
+            _ => fail!(
+                ConfigError,
+                "override key `{}`: `{}` is not a table",
+                key,
+                component
+            ),
+        };
+    }
+
+    fail!(ConfigError, "invalid override key `{}`", key)
+}
+
+/// Parse an override value as TOML, treating it as a string if it isn't one
+fn parse_value(raw_value: &str) -> Value {
+    format!("value = {}", raw_value)
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw_value.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+[[validator]]
+addr = "unix:///tmp/validator.sock"
+chain_id = "test_chain_id"
+
+[providers]
+"#;
+
+    #[test]
+    fn apply_overrides() {
+        let mut document = CONFIG.parse::<Table>().unwrap();
+
+        apply(&mut document, "validator.0.reconnect=false").unwrap();
+        apply(&mut document, "validator.0.max_height = \"500\"").unwrap();
+        apply(&mut document, "event_socket=/tmp/events.sock").unwrap();
+
+        let validator = &document["validator"][0];
+        assert_eq!(validator["reconnect"], Value::Boolean(false));
+        assert_eq!(validator["max_height"], Value::String("500".to_owned()));
+        assert_eq!(
+            document["event_socket"],
+            Value::String("/tmp/events.sock".to_owned())
+        );
+    }
+
+    #[test]
+    fn reject_invalid_overrides() {
+        let mut document = CONFIG.parse::<Table>().unwrap();
+
+        for expr in [
+            "reconnect",
+            "validator.reconnect=false",
+            "validator.1.reconnect=false",
+            "validator.0.chain_id.x=1",
+            ".x=1",
+        ] {
+            assert!(apply(&mut document, expr).is_err(), "{}", expr);
+        }
+    }
+}
@@ -4,6 +4,7 @@
 pub mod fortanixdsm;
 #[cfg(feature = "ledger")]
 pub mod ledgertm;
+mod policy;
 #[cfg(feature = "softsign")]
 pub mod softsign;
 #[cfg(feature = "yubihsm")]
@@ -18,11 +19,55 @@ use self::softsign::SoftsignConfig;
 #[cfg(feature = "yubihsm")]
 use self::yubihsm::YubihsmConfig;
 
-use serde::Deserialize;
+pub use self::policy::KeyPolicyConfig;
+
+use crate::{
+    error::{Error, ErrorKind::ConfigError},
+    prelude::*,
+};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use std::fmt;
 
+/// All signing providers `tmkms` supports, whether or not they're compiled
+/// into this build
+pub const PROVIDERS: &[ProviderInfo] = &[
+    ProviderInfo {
+        name: "softsign",
+        feature: "softsign",
+        compiled_in: cfg!(feature = "softsign"),
+    },
+    ProviderInfo {
+        name: "yubihsm",
+        feature: "yubihsm",
+        compiled_in: cfg!(feature = "yubihsm"),
+    },
+    ProviderInfo {
+        name: "ledgertm",
+        feature: "ledger",
+        compiled_in: cfg!(feature = "ledger"),
+    },
+    ProviderInfo {
+        name: "fortanixdsm",
+        feature: "fortanixdsm",
+        compiled_in: cfg!(feature = "fortanixdsm"),
+    },
+];
+
+/// A signing provider
+#[derive(Clone, Copy, Debug)]
+pub struct ProviderInfo {
+    /// Name of the provider's `[[providers.<name>]]` config section
+    pub name: &'static str,
+
+    /// Cargo feature which compiles the provider in
+    pub feature: &'static str,
+
+    /// Is the provider compiled into this build?
+    pub compiled_in: bool,
+}
+
 /// Provider configuration
-#[derive(Default, Deserialize, Debug)]
+#[derive(Default, Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct ProviderConfig {
     /// Software-backed signer
@@ -46,9 +91,56 @@ pub struct ProviderConfig {
     pub fortanixdsm: Vec<FortanixDsmConfig>,
 }
 
+impl ProviderConfig {
+    /// Deserialize the `[providers]` section, reporting providers which aren't
+    /// compiled into this build by name rather than as unknown fields
+    pub(crate) fn deserialize_checked<'de, D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let table = toml::Table::deserialize(deserializer)?;
+
+        for name in table.keys() {
+            check_compiled_in(name).map_err(de::Error::custom)?;
+        }
+
+        toml::Value::Table(table)
+            .try_into()
+            .map_err(de::Error::custom)
+    }
+}
+
+/// Ensure the provider with the given name is compiled into this build
+pub fn check_compiled_in(name: &str) -> Result<(), Error> {
+    let available = PROVIDERS
+        .iter()
+        .filter(|provider| provider.compiled_in)
+        .map(|provider| provider.name)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    match PROVIDERS.iter().find(|provider| provider.name == name) {
+        Some(provider) if provider.compiled_in => Ok(()),
+        Some(provider) => fail!(
+            ConfigError,
+            "provider `{}` is not compiled into this build (rebuild tmkms with `--features={}`); \
+             available providers: {}",
+            name,
+            provider.feature,
+            available
+        ),
+        None => fail!(
+            ConfigError,
+            "unknown provider `{}`; available providers: {}",
+            name,
+            available
+        ),
+    }
+}
+
 /// Types of cryptographic keys
 // TODO(tarcieri): move this into a provider-agnostic module
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum KeyType {
     /// Account keys
     #[serde(rename = "account")]
@@ -74,3 +166,27 @@ impl fmt::Display for KeyType {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::KmsConfig;
+    use abscissa_core::Config;
+
+    fn load_error(toml_string: &str) -> String {
+        KmsConfig::load_toml(toml_string).unwrap_err().to_string()
+    }
+
+    #[cfg(not(feature = "ledger"))]
+    #[test]
+    fn provider_not_compiled_in() {
+        let msg = load_error("[[providers.ledgertm]]\nchain_ids = [\"test_chain_id\"]\n");
+        assert!(msg.contains("provider `ledgertm` is not compiled into this build"));
+        assert!(msg.contains("--features=ledger"));
+    }
+
+    #[test]
+    fn unknown_provider() {
+        let msg = load_error("[[providers.hsm9000]]\n");
+        assert!(msg.contains("unknown provider `hsm9000`; available providers:"));
+    }
+}
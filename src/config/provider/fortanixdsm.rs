@@ -1,28 +1,65 @@
 //! Configuration for the Fortanix DSM backend
 
-use super::KeyType;
-use crate::chain;
+use super::{KeyPolicyConfig, KeyType};
+use crate::{backoff::Jitter, chain};
 use sdkms::api_model::SobjectDescriptor;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use uuid::Uuid;
 
 /// The (optional) `[providers.fortanixdsm]` config section
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct FortanixDsmConfig {
     /// Fortanix DSM API endpoint, e.g. https://amer.smartkey.io
     pub api_endpoint: String,
 
     /// API key for authenticating to DSM
+    #[serde(serialize_with = "crate::config::redact")]
     pub api_key: String,
 
     /// List of signing keys
     #[serde(default)]
     pub signing_keys: Vec<SigningKeyConfig>,
+
+    /// Number of attempts for fetching public keys at startup (default 3).
+    /// Only connectivity problems, server (5xx) errors, and rate limiting
+    /// (429) are retried. Signing requests are never retried.
+    pub lookup_attempts: Option<u32>,
+
+    /// Delay (in milliseconds) before the first lookup retry, doubling on
+    /// each subsequent attempt (default 500)
+    pub lookup_retry_delay_ms: Option<u64>,
+
+    /// How lookup retry delays are randomized: `none`, `full`, `equal`, or
+    /// `decorrelated` (default `full`)
+    pub lookup_retry_jitter: Option<Jitter>,
+
+    /// Interval (in seconds) at which each signing key is looked up again to
+    /// detect it being swapped for a different key in DSM, e.g. rekeyed
+    /// under the same name (default: disabled)
+    pub key_check_interval_secs: Option<u64>,
+
+    /// What to do once a swapped key is detected: `refuse` to sign until
+    /// tmkms is restarted, or just `warn` (default `refuse`)
+    pub on_key_change: Option<KeyChangeAction>,
+}
+
+/// Action taken when a signing key is found to have changed in DSM
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyChangeAction {
+    /// Refuse to sign with the key until tmkms is restarted, which loads
+    /// (and logs) the new public key
+    #[default]
+    Refuse,
+
+    /// Log an error (and export the change as a metric), but keep signing
+    Warn,
 }
 
 /// Signing key configuration
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct SigningKeyConfig {
     /// Chains this signing key is authorized to be used from
@@ -35,10 +72,14 @@ pub struct SigningKeyConfig {
     /// Type of key
     #[serde(default, rename = "type")]
     pub key_type: KeyType,
+
+    /// Usage policy enforced before signing with this key, whether Ed25519 or
+    /// secp256k1 (see [`KeyPolicyConfig`])
+    pub policy: Option<KeyPolicyConfig>,
 }
 
 /// A key (i.e. security object) stored in Fortanix DSM
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub enum KeyDescriptor {
     /// Specify a DSM key by its unique id
@@ -48,6 +89,15 @@ pub enum KeyDescriptor {
     KeyName(String),
 }
 
+impl fmt::Display for KeyDescriptor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyDescriptor::KeyId(id) => write!(f, "key_id={}", id),
+            KeyDescriptor::KeyName(name) => write!(f, "key_name={}", name),
+        }
+    }
+}
+
 impl From<KeyDescriptor> for SobjectDescriptor {
     fn from(x: KeyDescriptor) -> Self {
         match x {
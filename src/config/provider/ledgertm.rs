@@ -1,10 +1,10 @@
 //! Configuration for Ledger Tendermint signer
 
 use crate::chain;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Ledger Tendermint signer configuration
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct LedgerTendermintConfig {
     /// Chains this signing key is authorized to be used from
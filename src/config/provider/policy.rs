@@ -0,0 +1,23 @@
+//! Usage policies for signing keys
+
+use crate::privval::SignedMsgType;
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroU32;
+
+/// Constraints on how a consensus key may be used, enforced by the keyring
+/// before the key's provider is asked for a signature.
+///
+/// This is a local layer of defense in depth on top of the chain's own
+/// settings: a key with a policy also refuses to sign for any chain not in
+/// its `chain_ids`, however the request reached it.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct KeyPolicyConfig {
+    /// Message types the key may sign: any of `proposal`, `prevote`, and
+    /// `precommit` (default: all of them)
+    pub allowed_message_types: Option<Vec<SignedMsgType>>,
+
+    /// Maximum number of sign requests the key may sign in any 60 second
+    /// window, across all of its chains (default: unlimited)
+    pub max_signs_per_minute: Option<NonZeroU32>,
+}
@@ -1,19 +1,19 @@
 //! Configuration for software-backed signer (using ed25519-dalek)
 
-use super::KeyType;
+use super::{KeyPolicyConfig, KeyType};
 use crate::{
     chain,
     error::{Error, ErrorKind::ConfigError},
     prelude::*,
 };
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::{
     path::{Path, PathBuf},
     str::FromStr,
 };
 
 /// Software signer configuration
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct SoftsignConfig {
     /// Chains this signing key is authorized to be used from
@@ -29,10 +29,42 @@ pub struct SoftsignConfig {
     /// Path to a file containing a cryptographic key
     // TODO: use `abscissa_core::Secret` to wrap this `PathBuf`
     pub path: SoftPrivateKey,
+
+    /// How the key file is encrypted, if it is (see [`KeyEncryption`])
+    pub encryption: Option<KeyEncryption>,
+
+    /// Usage policy enforced before signing with this key, whether Ed25519 or
+    /// secp256k1 (see [`KeyPolicyConfig`])
+    pub policy: Option<KeyPolicyConfig>,
+}
+
+/// Encryption of a softsign key file, e.g. as produced by
+/// `tmkms softsign encrypt`
+///
+/// Keys are decrypted at startup by running the corresponding tool, reading
+/// the plaintext from its standard output: it is only ever held in memory.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+pub enum KeyEncryption {
+    /// Encrypted with [age](https://age-encryption.org), decrypted with the
+    /// given identity file (e.g. one generated by an age plugin for a
+    /// hardware token)
+    Age {
+        /// Path to the age identity file
+        identity: PathBuf,
+    },
+
+    /// Encrypted with GnuPG, either to a public key (decrypted by
+    /// `gpg-agent`) or symmetrically with a passphrase
+    Gpg {
+        /// Environment variable containing the passphrase for symmetrically
+        /// encrypted keys
+        passphrase_env: Option<String>,
+    },
 }
 
 /// Software-backed private key (stored in a file)
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct SoftPrivateKey(PathBuf);
 
@@ -44,7 +76,7 @@ impl AsRef<Path> for SoftPrivateKey {
 }
 
 /// Private key format
-#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq)]
+#[derive(Copy, Clone, Debug, Default, Deserialize, Serialize, Eq, Hash, PartialEq)]
 pub enum KeyFormat {
     /// Base64-encoded
     #[serde(rename = "base64")]
@@ -1,15 +1,15 @@
 //! Configuration for the `YubiHSM` backend
 
-use super::KeyType;
-use crate::{chain, prelude::*};
-use serde::Deserialize;
+use super::{KeyPolicyConfig, KeyType};
+use crate::{chain, config, prelude::*};
+use serde::{Deserialize, Serialize, Serializer};
 use std::{fmt, fs, path::PathBuf, process};
 use tendermint_config::net;
 use yubihsm::Credentials;
 use zeroize::{Zeroize, Zeroizing};
 
 /// The (optional) `[providers.yubihsm]` config section
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct YubihsmConfig {
     /// Adapter configuration
@@ -25,13 +25,33 @@ pub struct YubihsmConfig {
     /// Serial number of the YubiHSM to connect to
     pub serial_number: Option<String>,
 
+    /// What to do if, after reconnecting, the YubiHSM reports a different
+    /// public key for a consensus key than the one loaded at startup:
+    /// `refuse` to sign with it until tmkms is restarted, or `warn-and-stop`
+    /// to exit (default `refuse`)
+    pub on_pubkey_change: Option<PubkeyChangeAction>,
+
     /// Configuration for `yubihsm-connector` compatible HTTP server.
     #[cfg(feature = "yubihsm-server")]
     pub connector_server: Option<ConnectorServerConfig>,
 }
 
+/// Action taken when a YubiHSM reports a different public key for a key
+/// after reconnecting, e.g. because the device was swapped
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PubkeyChangeAction {
+    /// Treat it as tampering: refuse to sign with the key until tmkms is
+    /// restarted, which loads (and logs) the new public key
+    #[default]
+    Refuse,
+
+    /// Log an error and exit
+    WarnAndStop,
+}
+
 /// Configuration for an individual YubiHSM
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(deny_unknown_fields, tag = "type")]
 pub enum AdapterConfig {
     /// Connect to the YubiHSM2 directly via USB
@@ -51,7 +71,7 @@ pub enum AdapterConfig {
 }
 
 /// Configuration options for this connector
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields, untagged)]
 pub enum AuthConfig {
     /// Path to a separate password file
@@ -106,8 +126,14 @@ impl fmt::Debug for Password {
     }
 }
 
+impl Serialize for Password {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        config::redact(self, serializer)
+    }
+}
+
 /// Signing key configuration
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct SigningKeyConfig {
     /// Chains this signing key is authorized to be used from
@@ -119,6 +145,10 @@ pub struct SigningKeyConfig {
     /// Type of key
     #[serde(default, rename = "type")]
     pub key_type: KeyType,
+
+    /// Usage policy enforced before signing with this key, whether Ed25519 or
+    /// secp256k1 (see [`KeyPolicyConfig`])
+    pub policy: Option<KeyPolicyConfig>,
 }
 
 /// Default value for `AdapterConfig::Usb { timeout_ms }`
@@ -128,7 +158,7 @@ fn usb_timeout_ms_default() -> u64 {
 
 /// Configuration for `yubihsm-connector` compatible service
 #[cfg(feature = "yubihsm-server")]
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct ConnectorServerConfig {
     /// Listen address to run the connector service at
@@ -140,7 +170,7 @@ pub struct ConnectorServerConfig {
 
 /// Overrides for when using the `tmkms yubihsm` command-line interface
 #[cfg(feature = "yubihsm-server")]
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct CliConfig {
     /// Override the auth key to use when using the CLI. This will additionally
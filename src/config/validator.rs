@@ -1,7 +1,11 @@
 //! Validator configuration
 
+mod tls;
+
+pub use self::tls::TlsConfig;
+use crate::{backoff::Jitter, connection::tcp};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::{fmt, path::PathBuf, time::Duration};
 use tendermint::chain;
 use tendermint_config::net;
 use tendermint_p2p::secret_connection;
@@ -20,9 +24,54 @@ pub struct ValidatorConfig {
     #[serde(default = "reconnect_default")]
     pub reconnect: bool,
 
+    /// Delay in seconds before reconnecting after an error (default: 1)
+    pub reconnect_delay_secs: Option<u64>,
+
+    /// Maximum delay in seconds between reconnect attempts. The delay doubles
+    /// after each consecutive failure to connect, up to this limit, and is
+    /// reset once connected. (default: `reconnect_delay_secs`, i.e. no backoff)
+    pub max_reconnect_delay_secs: Option<u64>,
+
+    /// How reconnect delays are randomized: `none`, `full`, `equal`, or
+    /// `decorrelated` (default: `full` with `max_reconnect_delay_secs`, else
+    /// `none`, i.e. a fixed `reconnect_delay_secs`). See [`Jitter`].
+    pub reconnect_jitter: Option<Jitter>,
+
+    /// Minimum delay in seconds before reconnecting while the chain appears
+    /// halted (see the chain's `halt_detection`) (default: 30)
+    pub halted_reconnect_delay_secs: Option<u64>,
+
     /// Optional timeout value in seconds
     pub timeout: Option<u16>,
 
+    /// Tear down the connection (and reconnect) if the validator sends no
+    /// requests for this many seconds. Validators ping the KMS every few
+    /// seconds, so a quiet connection is most likely dead or half-open. 0
+    /// disables the idle timeout. (default: `timeout` for TCP connections, 60
+    /// for Unix sockets)
+    pub idle_timeout_secs: Option<u64>,
+
+    /// Send TCP keepalive probes once the connection has been idle for this
+    /// many seconds, and then at the same interval, so the OS notices a dead
+    /// peer even while we're not reading. Disabled by default.
+    pub tcp_keepalive_secs: Option<u64>,
+
+    /// Disable Nagle's algorithm on TCP connections (default: true). Sign
+    /// requests and responses are small and latency-sensitive, so batching
+    /// writes only delays them.
+    pub tcp_nodelay: Option<bool>,
+
+    /// Size in bytes of the TCP receive buffer (default: chosen by the OS)
+    pub recv_buffer_size: Option<usize>,
+
+    /// Size in bytes of the TCP send buffer (default: chosen by the OS)
+    pub send_buffer_size: Option<usize>,
+
+    /// Wrap the TCP connection in mutual TLS, underneath the secret
+    /// connection (requires the `tls` feature). Disabled by default. See
+    /// [`TlsConfig`].
+    pub tls: Option<TlsConfig>,
+
     /// Path to our Ed25519 identity key (if applicable)
     pub secret_key: Option<PathBuf>,
 
@@ -31,25 +80,80 @@ pub struct ValidatorConfig {
 
     /// Version of Secret Connection protocol to use when connecting
     pub protocol_version: ProtocolVersion,
+
+    /// Log a hex preview of (at most) this many leading bytes of any request
+    /// which gets rejected, e.g. for a double sign or a malformed payload.
+    /// Disabled if unset, and never applies to accepted requests. Capped at
+    /// `MAX_PAYLOAD_PREVIEW_LEN`.
+    pub rejected_payload_preview: Option<usize>,
 }
 
-/// Protocol version (based on the Tendermint version)
+impl ValidatorConfig {
+    /// How reconnect delays are randomized. Without any backoff configured,
+    /// reconnects wait exactly `reconnect_delay_secs`.
+    pub fn reconnect_jitter(&self) -> Jitter {
+        match (self.reconnect_jitter, self.max_reconnect_delay_secs) {
+            (Some(jitter), _) => jitter,
+            (None, Some(_)) => Jitter::Full,
+            (None, None) => Jitter::None,
+        }
+    }
+
+    /// How long the connection may go without requests before it's torn down
+    /// (`None` if it never is, i.e. the configured timeout is 0, which isn't a
+    /// valid socket read timeout)
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        let timeout = match (self.idle_timeout_secs, &self.addr) {
+            (Some(secs), _) => Duration::from_secs(secs),
+            (None, net::Address::Tcp { .. }) => {
+                Duration::from_secs(self.timeout.unwrap_or(tcp::DEFAULT_TIMEOUT).into())
+            }
+            (None, net::Address::Unix { .. }) => DEFAULT_UNIX_IDLE_TIMEOUT,
+        };
+
+        Some(timeout).filter(|timeout| !timeout.is_zero())
+    }
+}
+
+/// Maximum number of bytes of a rejected payload which will be logged
+pub const MAX_PAYLOAD_PREVIEW_LEN: usize = 128;
+
+/// Default idle timeout for Unix socket connections
+pub const DEFAULT_UNIX_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Protocol version (based on the Tendermint version).
+///
+/// Variants are declared oldest first, so comparisons order them by version.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize)]
 #[allow(non_camel_case_types)]
 pub enum ProtocolVersion {
+    /// Tendermint v0.33
+    #[serde(rename = "v0.33")]
+    V0_33,
+
     /// Tendermint v0.34 and newer.
     #[serde(rename = "v0.34")]
     V0_34,
 
-    /// Tendermint v0.33
-    #[serde(rename = "v0.33")]
-    V0_33,
+    /// CometBFT v0.38 and newer (vote extensions)
+    #[serde(rename = "v0.38")]
+    V0_38,
+}
+
+impl fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ProtocolVersion::V0_34 => "v0.34",
+            ProtocolVersion::V0_33 => "v0.33",
+            ProtocolVersion::V0_38 => "v0.38",
+        })
+    }
 }
 
 impl From<ProtocolVersion> for secret_connection::Version {
     fn from(version: ProtocolVersion) -> secret_connection::Version {
         match version {
-            ProtocolVersion::V0_34 => secret_connection::Version::V0_34,
+            ProtocolVersion::V0_34 | ProtocolVersion::V0_38 => secret_connection::Version::V0_34,
             ProtocolVersion::V0_33 => secret_connection::Version::V0_33,
         }
     }
@@ -59,3 +163,44 @@ impl From<ProtocolVersion> for secret_connection::Version {
 fn reconnect_default() -> bool {
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(extra: &str) -> ValidatorConfig {
+        toml::from_str(&format!(
+            "addr = \"tcp://f88883b673fc69d7869cab098de3bafc2ff76eb8@127.0.0.1:26658\"\n\
+             chain_id = \"test-chain\"\n\
+             protocol_version = \"v0.34\"\n{}",
+            extra
+        ))
+        .unwrap()
+    }
+
+    fn idle_timeout(extra: &str) -> Option<Duration> {
+        config(extra).idle_timeout()
+    }
+
+    #[test]
+    fn protocol_versions_are_ordered() {
+        assert!(ProtocolVersion::V0_33 < ProtocolVersion::V0_34);
+        assert!(ProtocolVersion::V0_34 < ProtocolVersion::V0_38);
+    }
+
+    #[test]
+    fn reconnect_jitter_defaults() {
+        let jitter = |extra: &str| config(extra).reconnect_jitter();
+        assert_eq!(jitter(""), Jitter::None);
+        assert_eq!(jitter("max_reconnect_delay_secs = 30"), Jitter::Full);
+        assert_eq!(jitter("reconnect_jitter = \"equal\""), Jitter::Equal);
+    }
+
+    #[test]
+    fn zero_idle_timeout_disables_it() {
+        let secs = Duration::from_secs;
+        assert_eq!(idle_timeout(""), Some(secs(tcp::DEFAULT_TIMEOUT.into())));
+        assert_eq!(idle_timeout("idle_timeout_secs = 30"), Some(secs(30)));
+        assert_eq!(idle_timeout("idle_timeout_secs = 0"), None);
+    }
+}
@@ -0,0 +1,27 @@
+//! TLS configuration for validator connections
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Mutual TLS wrapping the TCP connection to a validator, underneath the
+/// secret connection. Intended for deployments which put a TLS-terminating
+/// proxy in front of the validator's privval listener.
+///
+/// The KMS dials the validator, so it's the TLS client: it presents `cert`
+/// to the proxy, and only accepts a server certificate issued by `ca_cert`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    /// Path to the PEM-encoded CA certificate used to verify the server
+    pub ca_cert: PathBuf,
+
+    /// Path to the PEM-encoded client certificate to present to the server
+    pub cert: PathBuf,
+
+    /// Path to the PEM-encoded (PKCS#8) private key for `cert`
+    pub key: PathBuf,
+
+    /// Name the server certificate must be valid for (default: the host in
+    /// the validator's `addr`)
+    pub server_name: Option<String>,
+}
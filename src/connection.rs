@@ -6,7 +6,9 @@ use tendermint_p2p::secret_connection::SecretConnection;
 
 use self::unix::UnixConnection;
 
+pub mod stats;
 pub mod tcp;
+pub mod tls;
 pub mod unix;
 
 /// Connections to a validator
@@ -0,0 +1,235 @@
+//! Per-connection transport statistics
+//!
+//! Tracked for every validator connection, independently of the signing
+//! metrics, so connectivity problems between the KMS and its validators can
+//! be told apart from problems with signing providers. Exported through
+//! [`crate::metrics`] as the `tmkms_validator_*` metrics.
+
+use super::Connection;
+use crate::{config::ValidatorConfig, Map};
+use std::{
+    fmt, io,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tendermint_config::net;
+
+/// Statistics of every validator connection, by chain ID, address and mode
+static CONNECTIONS: Mutex<Map<(String, String, Mode), Arc<Stats>>> = Mutex::new(Map::new());
+
+/// Transport used to connect to a validator
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Mode {
+    /// Secret connection over plain TCP
+    Tcp,
+
+    /// Secret connection tunneled over TLS
+    Tls,
+
+    /// Unix domain socket
+    Unix,
+}
+
+impl Mode {
+    /// Transport used for the given validator
+    pub fn of(config: &ValidatorConfig) -> Self {
+        match config.addr {
+            net::Address::Tcp { .. } if config.tls.is_some() => Mode::Tls,
+            net::Address::Tcp { .. } => Mode::Tcp,
+            net::Address::Unix { .. } => Mode::Unix,
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Tcp => "tcp",
+            Mode::Tls => "tls",
+            Mode::Unix => "unix",
+        })
+    }
+}
+
+/// Statistics of a validator connection since startup
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Whether a session is currently open
+    connected: AtomicBool,
+
+    /// Number of sessions opened
+    sessions: AtomicU64,
+
+    /// Bytes received from the validator
+    received_bytes: AtomicU64,
+
+    /// Bytes sent to the validator
+    sent_bytes: AtomicU64,
+
+    /// Time the most recent session took to connect and handshake
+    handshake: Mutex<Option<Duration>>,
+
+    /// When data was last received from the validator
+    last_received_at: Mutex<Option<Instant>>,
+}
+
+impl Stats {
+    /// Record a session having been opened after the given handshake time
+    pub fn connected(&self, handshake: Duration) {
+        self.connected.store(true, Ordering::SeqCst);
+        self.sessions.fetch_add(1, Ordering::SeqCst);
+        *self.handshake.lock().unwrap() = Some(handshake);
+    }
+
+    /// Is a session currently open?
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::SeqCst)
+    }
+
+    /// Number of sessions opened after the first
+    pub fn reconnects(&self) -> u64 {
+        self.sessions.load(Ordering::SeqCst).saturating_sub(1)
+    }
+
+    /// Bytes received from the validator
+    pub fn received_bytes(&self) -> u64 {
+        self.received_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Bytes sent to the validator
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Time the most recent session took to connect and handshake
+    pub fn handshake(&self) -> Option<Duration> {
+        *self.handshake.lock().unwrap()
+    }
+
+    /// Time since data was last received from the validator
+    pub fn since_last_received(&self) -> Option<Duration> {
+        self.last_received_at
+            .lock()
+            .unwrap()
+            .map(|received_at| received_at.elapsed())
+    }
+}
+
+/// Get the statistics of the given validator's connection, tracking them
+/// from now on if they aren't yet
+pub fn register(config: &ValidatorConfig) -> Arc<Stats> {
+    CONNECTIONS
+        .lock()
+        .unwrap()
+        .entry((
+            config.chain_id.to_string(),
+            config.addr.to_string(),
+            Mode::of(config),
+        ))
+        .or_default()
+        .clone()
+}
+
+/// Statistics of every validator connection, with their chain ID, address
+/// and mode
+pub fn connections() -> Vec<(String, String, Mode, Arc<Stats>)> {
+    CONNECTIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((chain_id, addr, mode), stats)| {
+            (chain_id.clone(), addr.clone(), *mode, stats.clone())
+        })
+        .collect()
+}
+
+/// Connection which records its traffic in the given statistics, and marks
+/// them disconnected when it's dropped
+pub struct Metered {
+    /// Underlying connection
+    inner: Box<dyn Connection>,
+
+    /// Statistics of the connection
+    stats: Arc<Stats>,
+}
+
+impl Metered {
+    /// Record the traffic of a freshly opened connection
+    pub fn new(inner: Box<dyn Connection>, stats: Arc<Stats>) -> Self {
+        Self { inner, stats }
+    }
+}
+
+impl io::Read for Metered {
+    fn read(&mut self, data: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(data)?;
+
+        if len > 0 {
+            self.stats
+                .received_bytes
+                .fetch_add(len as u64, Ordering::SeqCst);
+            *self.stats.last_received_at.lock().unwrap() = Some(Instant::now());
+        }
+
+        Ok(len)
+    }
+}
+
+impl io::Write for Metered {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(data)?;
+        self.stats
+            .sent_bytes
+            .fetch_add(len as u64, Ordering::SeqCst);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Connection for Metered {}
+
+impl Drop for Metered {
+    fn drop(&mut self) {
+        self.stats.connected.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::unix::UnixConnection;
+    use std::io::{Cursor, Read, Write};
+
+    #[test]
+    fn record_traffic() {
+        let stats = Arc::new(Stats::default());
+        let socket = UnixConnection::new(Cursor::new(b"request".to_vec()));
+
+        stats.connected(Duration::from_millis(20));
+        let mut conn = Metered::new(Box::new(socket), stats.clone());
+        assert!(stats.is_connected());
+        assert_eq!(stats.since_last_received(), None);
+
+        let mut request = [0u8; 7];
+        conn.read_exact(&mut request).unwrap();
+        conn.write_all(b"response!").unwrap();
+
+        assert_eq!(stats.received_bytes(), 7);
+        assert_eq!(stats.sent_bytes(), 9);
+        assert!(stats.since_last_received().is_some());
+        assert_eq!(stats.handshake(), Some(Duration::from_millis(20)));
+
+        drop(conn);
+        assert!(!stats.is_connected());
+        assert_eq!(stats.reconnects(), 0);
+
+        stats.connected(Duration::from_millis(30));
+        assert_eq!(stats.reconnects(), 1);
+    }
+}
@@ -1,22 +1,140 @@
 //! TCP socket connection to a validator
 
-use std::{net::TcpStream, path::PathBuf, time::Duration};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    time::Duration,
+};
 
+use socket2::{SockRef, TcpKeepalive};
 use subtle::ConstantTimeEq;
 use tendermint::node;
 use tendermint_p2p::error::ErrorDetail as TmError;
 use tendermint_p2p::secret_connection::{self, PublicKey, SecretConnection};
 
+use super::tls;
 use crate::{
+    config::{TlsConfig, ValidatorConfig},
     error::{Error, ErrorKind::*},
     key_utils,
     prelude::*,
 };
 
 /// Default timeout in seconds
-const DEFAULT_TIMEOUT: u16 = 10;
+pub const DEFAULT_TIMEOUT: u16 = 10;
+
+/// Options applied to the TCP socket
+#[derive(Clone, Copy, Debug)]
+pub struct SocketOptions {
+    /// Set `TCP_NODELAY`, i.e. disable Nagle's algorithm
+    pub nodelay: bool,
+
+    /// Receive buffer size (`SO_RCVBUF`), if not left to the OS
+    pub recv_buffer_size: Option<usize>,
+
+    /// Send buffer size (`SO_SNDBUF`), if not left to the OS
+    pub send_buffer_size: Option<usize>,
+
+    /// Idle time before (and interval between) TCP keepalive probes, if
+    /// enabled
+    pub keepalive: Option<Duration>,
+
+    /// Read timeout once the secret connection is established, i.e. how long
+    /// the validator may go without sending a request (`None` for no limit)
+    pub idle_timeout: Option<Duration>,
+}
+
+impl From<&ValidatorConfig> for SocketOptions {
+    fn from(config: &ValidatorConfig) -> Self {
+        Self {
+            nodelay: config.tcp_nodelay.unwrap_or(true),
+            recv_buffer_size: config.recv_buffer_size,
+            send_buffer_size: config.send_buffer_size,
+            keepalive: config.tcp_keepalive_secs.map(Duration::from_secs),
+            idle_timeout: config.idle_timeout(),
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Apply these options (other than the idle timeout) to the given socket
+    fn apply(&self, socket: &TcpStream) -> Result<(), Error> {
+        socket.set_nodelay(self.nodelay)?;
+        let sock_ref = SockRef::from(socket);
+
+        if let Some(keepalive) = self.keepalive {
+            sock_ref.set_tcp_keepalive(
+                &TcpKeepalive::new()
+                    .with_time(keepalive)
+                    .with_interval(keepalive),
+            )?;
+        }
+
+        if let Some(size) = self.recv_buffer_size {
+            sock_ref.set_recv_buffer_size(size)?;
+        }
+
+        if let Some(size) = self.send_buffer_size {
+            sock_ref.set_send_buffer_size(size)?;
+        }
 
-/// Open a TCP socket connection encrypted with SecretConnection
+        // The kernel may adjust (e.g. double) requested buffer sizes, so log
+        // what actually took effect
+        debug!(
+            "socket options: TCP_NODELAY={} SO_RCVBUF={} SO_SNDBUF={} SO_KEEPALIVE={} idle timeout={:?}",
+            socket.nodelay()?,
+            sock_ref.recv_buffer_size()?,
+            sock_ref.send_buffer_size()?,
+            sock_ref.keepalive()?,
+            self.idle_timeout
+        );
+
+        Ok(())
+    }
+}
+
+/// TCP stream to a validator, optionally wrapped in TLS
+pub enum Stream {
+    /// Plain TCP
+    Plain(TcpStream),
+
+    /// TCP wrapped in TLS
+    #[cfg(feature = "tls")]
+    Tls(Box<native_tls::TlsStream<TcpStream>>),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(socket) => socket.read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(socket) => socket.write(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(socket) => socket.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Open a TCP socket connection encrypted with SecretConnection, optionally
+/// wrapped in TLS underneath it
+#[allow(clippy::too_many_arguments)]
 pub fn open_secret_connection(
     host: &str,
     port: u16,
@@ -24,7 +142,9 @@ pub fn open_secret_connection(
     peer_id: &Option<node::Id>,
     timeout: Option<u16>,
     protocol_version: secret_connection::Version,
-) -> Result<SecretConnection<TcpStream>, Error> {
+    socket_options: SocketOptions,
+    tls_config: Option<&TlsConfig>,
+) -> Result<SecretConnection<Stream>, Error> {
     let identity_key_path = identity_key_path.as_ref().ok_or_else(|| {
         format_err!(
             ConfigError,
@@ -41,8 +161,17 @@ pub fn open_secret_connection(
     let timeout = Duration::from_secs(timeout.unwrap_or(DEFAULT_TIMEOUT).into());
     socket.set_read_timeout(Some(timeout))?;
     socket.set_write_timeout(Some(timeout))?;
+    socket_options.apply(&socket)?;
+
+    // Kept to adjust the read timeout once the handshake is done
+    let socket_handle = socket.try_clone()?;
+
+    let stream = match tls_config {
+        Some(tls_config) => tls::connect(host, socket, tls_config)?,
+        None => Stream::Plain(socket),
+    };
 
-    let connection = match SecretConnection::new(socket, identity_key.into(), protocol_version) {
+    let connection = match SecretConnection::new(stream, identity_key.into(), protocol_version) {
         Ok(conn) => conn,
         Err(error) => match error.detail() {
             TmError::Crypto(_) => fail!(CryptoError, format!("{error}")),
@@ -67,5 +196,6 @@ pub fn open_secret_connection(
         }
     }
 
+    socket_handle.set_read_timeout(socket_options.idle_timeout)?;
     Ok(connection)
 }
@@ -0,0 +1,215 @@
+//! Mutual TLS underneath the secret connection to a validator
+//!
+//! This is defense in depth for deployments which put a TLS-terminating
+//! proxy in front of the validator: the secret connection handshake still
+//! authenticates the validator itself, so TLS only has to authenticate the
+//! two ends of the proxied hop.
+
+use super::tcp::Stream;
+use crate::{
+    config::TlsConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::net::TcpStream;
+
+/// Ensure the given TLS configuration is usable, i.e. that its certificates
+/// and key can be loaded (and the key matches the certificate)
+pub fn check(config: &TlsConfig) -> Result<(), Error> {
+    imp::Connector::new(config).map(|_| ())
+}
+
+/// Perform a TLS handshake with the server on the other end of `socket`
+pub fn connect(host: &str, socket: TcpStream, config: &TlsConfig) -> Result<Stream, Error> {
+    imp::Connector::new(config)?.connect(host, socket)
+}
+
+#[cfg(feature = "tls")]
+mod imp {
+    use super::*;
+    use native_tls::{Certificate, Identity, Protocol, TlsConnector};
+    use std::{fs, path::Path};
+
+    /// TLS connector built from a validator's TLS configuration
+    pub(super) struct Connector {
+        /// Underlying connector
+        inner: TlsConnector,
+
+        /// Name the server certificate must be valid for, if not the host
+        server_name: Option<String>,
+    }
+
+    impl Connector {
+        /// Load the certificates and key referenced by the configuration
+        pub(super) fn new(config: &TlsConfig) -> Result<Self, Error> {
+            let ca_cert = Certificate::from_pem(&read_pem(&config.ca_cert)?).map_err(|e| {
+                format_err!(
+                    ConfigError,
+                    "invalid TLS CA certificate {}: {}",
+                    config.ca_cert.display(),
+                    e
+                )
+            })?;
+
+            let identity = Identity::from_pkcs8(&read_pem(&config.cert)?, &read_pem(&config.key)?)
+                .map_err(|e| {
+                    format_err!(
+                        ConfigError,
+                        "invalid TLS certificate {} or key {}: {}",
+                        config.cert.display(),
+                        config.key.display(),
+                        e
+                    )
+                })?;
+
+            let inner = TlsConnector::builder()
+                .identity(identity)
+                .add_root_certificate(ca_cert)
+                .disable_built_in_roots(true)
+                .min_protocol_version(Some(Protocol::Tlsv12))
+                .build()
+                .map_err(|e| {
+                    format_err!(
+                        ConfigError,
+                        "invalid TLS configuration (does {} match {}?): {}",
+                        config.key.display(),
+                        config.cert.display(),
+                        e
+                    )
+                })?;
+
+            Ok(Self {
+                inner,
+                server_name: config.server_name.clone(),
+            })
+        }
+
+        /// Perform a TLS handshake with the server on the other end of
+        /// `socket`, verifying its certificate
+        pub(super) fn connect(&self, host: &str, socket: TcpStream) -> Result<Stream, Error> {
+            let server_name = self.server_name.as_deref().unwrap_or(host);
+
+            let stream = self.inner.connect(server_name, socket).map_err(|e| {
+                format_err!(
+                    VerificationError,
+                    "TLS handshake with {} failed: {}",
+                    server_name,
+                    e
+                )
+            })?;
+
+            Ok(Stream::Tls(Box::new(stream)))
+        }
+    }
+
+    /// Read a PEM file referenced by the TLS configuration
+    fn read_pem(path: &Path) -> Result<Vec<u8>, Error> {
+        fs::read(path).map_err(|e| {
+            format_err!(
+                ConfigError,
+                "couldn't read TLS file {}: {}",
+                path.display(),
+                e
+            )
+            .into()
+        })
+    }
+}
+
+#[cfg(not(feature = "tls"))]
+mod imp {
+    use super::*;
+
+    /// Placeholder for builds without the `tls` feature
+    pub(super) enum Connector {}
+
+    impl Connector {
+        /// Always fails: TLS isn't compiled in
+        pub(super) fn new(_config: &TlsConfig) -> Result<Self, Error> {
+            fail!(
+                ConfigError,
+                "validator `tls` is not supported by this build (rebuild tmkms with `--features=tls`)"
+            )
+        }
+
+        /// Unreachable, as no `Connector` can be constructed
+        pub(super) fn connect(&self, _host: &str, _socket: TcpStream) -> Result<Stream, Error> {
+            match *self {}
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tls"))]
+mod tests {
+    use super::*;
+    use native_tls::{Identity, TlsAcceptor};
+    use std::{
+        fs,
+        io::{Read, Write},
+        net::TcpListener,
+        path::PathBuf,
+        thread,
+    };
+
+    fn config(ca_cert: &str, cert: &str, key: &str) -> TlsConfig {
+        let path = |name: &str| PathBuf::from(format!("tests/support/tls/{name}"));
+
+        TlsConfig {
+            ca_cert: path(ca_cert),
+            cert: path(cert),
+            key: path(key),
+            server_name: None,
+        }
+    }
+
+    #[test]
+    fn check_config() {
+        assert!(check(&config("ca.pem", "client.pem", "client.key")).is_ok());
+        assert!(check(&config("ca.pem", "client.pem", "server.key")).is_err());
+        assert!(check(&config("ca.pem", "client.pem", "nonexistent.key")).is_err());
+    }
+
+    #[test]
+    fn handshake() {
+        let identity = Identity::from_pkcs8(
+            &fs::read("tests/support/tls/server.pem").unwrap(),
+            &fs::read("tests/support/tls/server.key").unwrap(),
+        )
+        .unwrap();
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+        let acceptor2 = acceptor.clone();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            let mut stream = acceptor.accept(socket).unwrap();
+            let mut buf = [0u8; 5];
+            stream.read_exact(&mut buf).unwrap();
+            stream.write_all(&buf).unwrap();
+        });
+
+        let socket = TcpStream::connect(addr).unwrap();
+        let config = config("ca.pem", "client.pem", "client.key");
+        let mut stream = connect("localhost", socket, &config).unwrap();
+        stream.write_all(b"hello").unwrap();
+
+        let mut buf = [0u8; 5];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        server.join().unwrap();
+
+        // The server certificate isn't valid for this name
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (socket, _) = listener.accept().unwrap();
+            assert!(acceptor2.accept(socket).is_err());
+        });
+
+        let socket = TcpStream::connect(addr).unwrap();
+        assert!(connect("example.com", socket, &config).is_err());
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+//! Local control socket
+//!
+//! When `control_socket` is set in `tmkms.toml`, the KMS listens on the given
+//! Unix socket for one-line commands (see [`Command`]) and answers each with
+//! a single line, which begins with `error: ` if the command failed. This is
+//! what `tmkms maintenance` and `tmkms status` talk to.
+//!
+//! Anyone who can connect can pause signing, so the socket is only accessible
+//! to the user running the KMS.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    maintenance,
+    prelude::*,
+    status,
+};
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::{FileTypeExt, PermissionsExt},
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+/// Maximum time spent reading a command or writing its reply
+pub const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Commands accepted on the control socket
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// Enter maintenance mode, refusing all sign requests
+    Pause,
+
+    /// Leave maintenance mode
+    Resume,
+
+    /// Report whether maintenance mode is active
+    Maintenance,
+
+    /// Report the live runtime state as JSON (see [`status::Report`])
+    Status,
+}
+
+impl Command {
+    /// Name of the command on the wire
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Command::Pause => "pause",
+            Command::Resume => "resume",
+            Command::Maintenance => "maintenance",
+            Command::Status => "status",
+        }
+    }
+
+    /// Run the command, returning its reply
+    fn run(self) -> Result<String, Error> {
+        match self {
+            Command::Pause => maintenance::pause(),
+            Command::Resume => maintenance::resume(),
+            Command::Maintenance => Ok(maintenance::status()),
+            Command::Status => Ok(serde_json::to_string(&status::report())?),
+        }
+    }
+}
+
+impl FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Error> {
+        match s {
+            "pause" => Ok(Command::Pause),
+            "resume" => Ok(Command::Resume),
+            "maintenance" => Ok(Command::Maintenance),
+            "status" => Ok(Command::Status),
+            _ => fail!(ParseError, "unknown control command: `{}`", s),
+        }
+    }
+}
+
+/// Bind the socket inside a private (0700) directory, restrict it to 0600,
+/// and only then move it into place, so it's never reachable by other users.
+///
+/// Refuses to replace anything at `path` other than a socket, so a mistyped
+/// `control_socket` can't clobber e.g. a state or key file.
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "path exists and is not a socket",
+            ));
+        }
+    }
+
+    let dir = tempfile::Builder::new()
+        .prefix(".tmkms-control")
+        .tempdir_in(
+            path.parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new(".")),
+        )?;
+
+    let private_path = dir.path().join("control.sock");
+    let listener = UnixListener::bind(&private_path)?;
+    fs::set_permissions(&private_path, fs::Permissions::from_mode(0o600))?;
+    fs::rename(&private_path, path)?;
+
+    Ok(listener)
+}
+
+/// Start listening for commands on the given Unix socket
+pub fn init(path: &Path) -> Result<(), Error> {
+    // Remove a socket left behind by a previous run (but nothing else)
+    if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+
+    let listener = bind(path).map_err(|e| {
+        format_err!(
+            ConfigError,
+            "couldn't bind control socket {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    thread::Builder::new()
+        .name("control-socket".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = handle_client(stream) {
+                            warn!("control socket error: {}", e);
+                        }
+                    }
+                    Err(e) => warn!("error accepting control socket client: {}", e),
+                }
+            }
+        })?;
+
+    info!("accepting control commands on {}", path.display());
+    Ok(())
+}
+
+/// Send a command to a running KMS's control socket, returning its reply
+pub fn send(path: &Path, command: Command) -> Result<String, Error> {
+    let mut stream = UnixStream::connect(path).map_err(|e| {
+        format_err!(
+            IoError,
+            "couldn't connect to control socket {} (is tmkms running?): {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    writeln!(stream, "{}", command.as_str())?;
+
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)?;
+    let reply = reply.trim_end();
+
+    match reply.strip_prefix("error: ") {
+        Some(e) => fail!(ProtocolError, "{}", e),
+        None => Ok(reply.to_owned()),
+    }
+}
+
+/// Read a command from a control socket client and reply to it
+fn handle_client(mut stream: UnixStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+
+    let reply = match line.trim().parse::<Command>().and_then(Command::run) {
+        Ok(reply) => reply,
+        Err(e) => format!("error: {}", e),
+    };
+
+    writeln!(stream, "{}", reply)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        for command in [
+            Command::Pause,
+            Command::Resume,
+            Command::Maintenance,
+            Command::Status,
+        ] {
+            assert_eq!(command.as_str().parse::<Command>().unwrap(), command);
+        }
+
+        assert!("reboot".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn bind_refuses_to_replace_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state.json");
+        fs::write(&path, b"{}").unwrap();
+
+        let err = bind(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&path).unwrap(), b"{}");
+
+        // A socket is replaced
+        let path = dir.path().join("control.sock");
+        drop(bind(&path).unwrap());
+        bind(&path).unwrap();
+    }
+}
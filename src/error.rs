@@ -1,5 +1,9 @@
 //! Error types
 
+mod class;
+
+pub use self::class::ErrorClass;
+
 use crate::{chain, prelude::*};
 use abscissa_core::error::{BoxError, Context};
 use std::{
@@ -66,6 +70,10 @@ pub enum ErrorKind {
     #[error("parse error")]
     ParseError,
 
+    /// Signing key usage policy violated
+    #[error("key policy violation")]
+    PolicyError,
+
     /// KMS state has been poisoned
     #[error("internal state poisoned")]
     PoisonError,
@@ -82,6 +90,10 @@ pub enum ErrorKind {
     #[error("signing operation failed")]
     SigningError,
 
+    /// Consensus state couldn't be persisted (signing fails closed)
+    #[error("error persisting consensus state")]
+    StateSyncError,
+
     /// Errors originating in the Tendermint crate
     #[error("Tendermint error")]
     TendermintError,
@@ -108,6 +120,11 @@ impl ErrorKind {
 pub struct Error(Box<Context<ErrorKind>>);
 
 impl Error {
+    /// Classify this error, e.g. to alert on it (see [`ErrorClass`])
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::of(self)
+    }
+
     /// Create an error from a panic
     pub fn from_panic(panic_msg: Box<dyn Any>) -> Self {
         let err_msg = if let Some(msg) = panic_msg.downcast_ref::<String>() {
@@ -198,6 +215,11 @@ impl From<tendermint::Error> for Error {
 
 impl From<chain::state::StateError> for Error {
     fn from(other: chain::state::StateError) -> Self {
-        ErrorKind::DoubleSign.context(other).into()
+        let kind = match other.kind() {
+            chain::state::StateErrorKind::SyncError => ErrorKind::StateSyncError,
+            _ => ErrorKind::DoubleSign,
+        };
+
+        kind.context(other).into()
     }
 }
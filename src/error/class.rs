@@ -0,0 +1,343 @@
+//! Classification of errors for alerting
+//!
+//! Errors from signing providers mostly surface as strings, which are hard to
+//! alert on. [`ErrorClass`] buckets an [`Error`] by what went wrong, based on
+//! the innermost cause we recognize (I/O errors, YubiHSM and Fortanix DSM
+//! client errors), falling back to the error's [`ErrorKind`].
+
+use super::{Error, ErrorKind};
+use std::{error::Error as _, fmt, io};
+
+/// Class of an error, e.g. for a metric label
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ErrorClass {
+    /// Authentication or authorization failed, e.g. a bad password or an
+    /// expired/revoked API key
+    Auth,
+
+    /// Couldn't reach the backend, or the connection broke
+    Connectivity,
+
+    /// The backend didn't respond in time
+    Timeout,
+
+    /// The backend is up but unavailable for signing, e.g. sealed or locked
+    BackendSealed,
+
+    /// The key is missing, disabled, or of the wrong type
+    BadKey,
+
+    /// The backend's response couldn't be parsed
+    MalformedResponse,
+
+    /// Refused by a signing key's usage policy
+    Policy,
+
+    /// Anything else
+    Other,
+}
+
+impl ErrorClass {
+    /// Classify the given error
+    pub fn of(err: &Error) -> Self {
+        let mut class = None;
+        let mut source = err.source();
+
+        // The innermost recognized cause is the most specific
+        while let Some(cause) = source {
+            class = classify_cause(cause).or(class);
+            source = cause.source();
+        }
+
+        class.unwrap_or_else(|| Self::of_kind(*err.kind()))
+    }
+
+    /// Class of errors of the given kind with no recognized cause
+    fn of_kind(kind: ErrorKind) -> Self {
+        match kind {
+            ErrorKind::AccessError => ErrorClass::Auth,
+            ErrorKind::InvalidKey => ErrorClass::BadKey,
+            ErrorKind::IoError => ErrorClass::Connectivity,
+            ErrorKind::PolicyError => ErrorClass::Policy,
+            ErrorKind::ParseError | ErrorKind::ProtocolError | ErrorKind::SerializationError => {
+                ErrorClass::MalformedResponse
+            }
+            _ => ErrorClass::Other,
+        }
+    }
+}
+
+impl fmt::Display for ErrorClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ErrorClass::Auth => "auth",
+            ErrorClass::Connectivity => "connectivity",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::BackendSealed => "backend_sealed",
+            ErrorClass::BadKey => "bad_key",
+            ErrorClass::MalformedResponse => "malformed_response",
+            ErrorClass::Policy => "policy",
+            ErrorClass::Other => "other",
+        })
+    }
+}
+
+/// Classify a single cause in an error's source chain, if we recognize it
+fn classify_cause(cause: &(dyn std::error::Error + 'static)) -> Option<ErrorClass> {
+    if let Some(err) = cause.downcast_ref::<io::Error>() {
+        return Some(classify_io(err));
+    }
+
+    #[cfg(feature = "fortanixdsm")]
+    if let Some(err) = cause.downcast_ref::<sdkms::Error>() {
+        return classify_dsm(err);
+    }
+
+    #[cfg(feature = "yubihsm")]
+    if let Some(class) = classify_yubihsm(cause) {
+        return Some(class);
+    }
+
+    None
+}
+
+/// Classify an I/O error
+fn classify_io(err: &io::Error) -> ErrorClass {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ErrorClass::Timeout,
+        io::ErrorKind::PermissionDenied => ErrorClass::Auth,
+        io::ErrorKind::InvalidData => ErrorClass::MalformedResponse,
+        _ => ErrorClass::Connectivity,
+    }
+}
+
+/// Classify a Fortanix DSM client error
+#[cfg(feature = "fortanixdsm")]
+fn classify_dsm(err: &sdkms::Error) -> Option<ErrorClass> {
+    use sdkms::Error;
+
+    Some(match err {
+        Error::Unauthorized(_) | Error::Forbidden(_) => ErrorClass::Auth,
+        Error::Locked(_) => ErrorClass::BackendSealed,
+        Error::NotFound(_) => ErrorClass::BadKey,
+        Error::EncoderError(_) => ErrorClass::MalformedResponse,
+        Error::IoError(err) => classify_io(err),
+        Error::NetworkError(_) | Error::TlsError(_) => ErrorClass::Connectivity,
+        // Formatted as "<code> <reason>\n<message>"
+        Error::StatusCode(status) => match status.split_whitespace().next() {
+            Some("503") => ErrorClass::BackendSealed,
+            Some("408" | "504") => ErrorClass::Timeout,
+            Some("502") => ErrorClass::Connectivity,
+            _ => ErrorClass::Other,
+        },
+        Error::BadRequest(_) | Error::Conflict(_) => return None,
+    })
+}
+
+/// Classify a YubiHSM client, session, connector, or device error
+#[cfg(feature = "yubihsm")]
+fn classify_yubihsm(cause: &(dyn std::error::Error + 'static)) -> Option<ErrorClass> {
+    use yubihsm::{client, connector, device, session};
+
+    if let Some(err) = cause.downcast_ref::<client::Error>() {
+        return match err.kind() {
+            client::ErrorKind::AuthenticationError => Some(ErrorClass::Auth),
+            client::ErrorKind::ClosedSessionError
+            | client::ErrorKind::ConnectorError
+            | client::ErrorKind::CreateFailed => Some(ErrorClass::Connectivity),
+            client::ErrorKind::ProtocolError | client::ErrorKind::ResponseError => {
+                Some(ErrorClass::MalformedResponse)
+            }
+            client::ErrorKind::DeviceError => None,
+        };
+    }
+
+    if let Some(err) = cause.downcast_ref::<session::Error>() {
+        return match err.kind() {
+            session::ErrorKind::AuthenticationError => Some(ErrorClass::Auth),
+            session::ErrorKind::ClosedError | session::ErrorKind::CreateFailed => {
+                Some(ErrorClass::Connectivity)
+            }
+            session::ErrorKind::ProtocolError | session::ErrorKind::ResponseError => {
+                Some(ErrorClass::MalformedResponse)
+            }
+            _ => None,
+        };
+    }
+
+    if let Some(err) = cause.downcast_ref::<connector::Error>() {
+        return Some(match err.kind() {
+            connector::ErrorKind::AccessDenied => ErrorClass::Auth,
+            connector::ErrorKind::ResponseError => ErrorClass::MalformedResponse,
+            connector::ErrorKind::AddrInvalid => ErrorClass::Other,
+            _ => ErrorClass::Connectivity,
+        });
+    }
+
+    if let Some(kind) = cause.downcast_ref::<device::ErrorKind>() {
+        return Some(match kind {
+            device::ErrorKind::InsufficientPermissions
+            | device::ErrorKind::AuthenticationFailed => ErrorClass::Auth,
+            device::ErrorKind::ObjectNotFound | device::ErrorKind::InvalidId => ErrorClass::BadKey,
+            _ => ErrorClass::Other,
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    /// A signing error caused by the given provider error, as produced by
+    /// `keyring::ed25519::Signer::sign`
+    fn signing_error(cause: impl std::error::Error + Send + Sync + 'static) -> Error {
+        ErrorKind::SigningError
+            .context(signature::Error::from_source(cause))
+            .into()
+    }
+
+    #[test]
+    fn network_failures() {
+        for (kind, class) in [
+            (io::ErrorKind::TimedOut, ErrorClass::Timeout),
+            (io::ErrorKind::WouldBlock, ErrorClass::Timeout),
+            (io::ErrorKind::ConnectionRefused, ErrorClass::Connectivity),
+            (io::ErrorKind::ConnectionReset, ErrorClass::Connectivity),
+            (io::ErrorKind::BrokenPipe, ErrorClass::Connectivity),
+            (io::ErrorKind::UnexpectedEof, ErrorClass::Connectivity),
+            (io::ErrorKind::PermissionDenied, ErrorClass::Auth),
+            (io::ErrorKind::InvalidData, ErrorClass::MalformedResponse),
+        ] {
+            let err = io::Error::new(kind, "test");
+            assert_eq!(ErrorClass::of(&signing_error(err)), class, "{:?}", kind);
+            assert_eq!(
+                ErrorClass::of(&io::Error::new(kind, "test").into()),
+                class,
+                "{:?}",
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn error_kinds() {
+        for (kind, class) in [
+            (ErrorKind::AccessError, ErrorClass::Auth),
+            (ErrorKind::InvalidKey, ErrorClass::BadKey),
+            (ErrorKind::PolicyError, ErrorClass::Policy),
+            (ErrorKind::ProtocolError, ErrorClass::MalformedResponse),
+            (ErrorKind::SigningError, ErrorClass::Other),
+        ] {
+            assert_eq!(ErrorClass::of(&kind.into()), class, "{:?}", kind);
+        }
+
+        // Causes we don't recognize fall back to the kind
+        let err: Error = format_err!(ErrorKind::InvalidKey, "not in keyring").into();
+        assert_eq!(ErrorClass::of(&err), ErrorClass::BadKey);
+    }
+
+    #[cfg(feature = "fortanixdsm")]
+    #[test]
+    fn dsm_failures() {
+        use sdkms::Error;
+
+        for (err, class) in [
+            (Error::Unauthorized("expired".into()), ErrorClass::Auth),
+            (Error::Forbidden("denied".into()), ErrorClass::Auth),
+            (Error::Locked("locked".into()), ErrorClass::BackendSealed),
+            (Error::NotFound("no such key".into()), ErrorClass::BadKey),
+            (
+                Error::StatusCode("503 Service Unavailable\nsealed".into()),
+                ErrorClass::BackendSealed,
+            ),
+            (
+                Error::StatusCode("504 Gateway Timeout\n".into()),
+                ErrorClass::Timeout,
+            ),
+            (
+                Error::StatusCode("408 Request Timeout\n".into()),
+                ErrorClass::Timeout,
+            ),
+            (
+                Error::StatusCode("502 Bad Gateway\n".into()),
+                ErrorClass::Connectivity,
+            ),
+            (
+                Error::StatusCode("500 Internal Server Error\n".into()),
+                ErrorClass::Other,
+            ),
+            (
+                Error::IoError(io::Error::new(io::ErrorKind::TimedOut, "read")),
+                ErrorClass::Timeout,
+            ),
+            (
+                Error::EncoderError(serde_json::from_str::<u8>("{").unwrap_err()),
+                ErrorClass::MalformedResponse,
+            ),
+            (Error::BadRequest("bad".into()), ErrorClass::Other),
+        ] {
+            let description = err.to_string();
+            assert_eq!(
+                ErrorClass::of(&signing_error(err)),
+                class,
+                "{}",
+                description
+            );
+        }
+    }
+
+    #[cfg(feature = "yubihsm")]
+    #[test]
+    fn yubihsm_failures() {
+        use yubihsm::{client, connector, device, session};
+
+        let connector_error =
+            |kind: connector::ErrorKind| -> client::Error { connector::Error::from(kind).into() };
+
+        for (err, class) in [
+            (
+                client::Error::from(client::ErrorKind::AuthenticationError),
+                ErrorClass::Auth,
+            ),
+            (
+                connector_error(connector::ErrorKind::ConnectionFailed),
+                ErrorClass::Connectivity,
+            ),
+            (
+                connector_error(connector::ErrorKind::AccessDenied),
+                ErrorClass::Auth,
+            ),
+            (
+                connector_error(connector::ErrorKind::ResponseError),
+                ErrorClass::MalformedResponse,
+            ),
+            (
+                session::Error::from(device::ErrorKind::ObjectNotFound).into(),
+                ErrorClass::BadKey,
+            ),
+            (
+                session::Error::from(device::ErrorKind::InsufficientPermissions).into(),
+                ErrorClass::Auth,
+            ),
+        ] {
+            let description = err.to_string();
+            assert_eq!(
+                ErrorClass::of(&signing_error(err)),
+                class,
+                "{}",
+                description
+            );
+        }
+
+        // Connector errors caused by timeouts are timeouts
+        let err: client::Error = connector::Error::from(
+            connector::ErrorKind::IoError
+                .context(io::Error::new(io::ErrorKind::TimedOut, "read timed out")),
+        )
+        .into();
+        assert_eq!(ErrorClass::of(&signing_error(err)), ErrorClass::Timeout);
+    }
+}
@@ -0,0 +1,303 @@
+//! Stream of sign decisions over a Unix socket
+//!
+//! When `event_socket` is set in `tmkms.toml`, the KMS listens on the given
+//! Unix socket and writes one JSON object per line to every connected client
+//! for each sign request it decides on:
+//!
+//! ```json
+//! {"timestamp":"2024-01-01T00:00:00.000000Z","chain_id":"cosmoshub-4",
+//!  "validator":"tcp://...","decision":"accept","msg_type":"prevote",
+//!  "height":123,"round":0,"step":1,"latency_ms":3,"guard_latency_us":850,
+//!  "provider":"yubihsm","reason":null,"dropped":0}
+//! ```
+//!
+//! - `decision`: `"accept"` if the request was signed, `"reject"` if it was
+//!   refused (e.g. attempted double sign, disallowed message type, or height
+//!   above `max_height`), `"observe"` if it would have been signed but the
+//!   chain is `observe_only`, or `"error"` if signing failed (e.g. provider
+//!   error)
+//! - `step`: 0 for proposals, 1 for prevotes, 2 for precommits
+//! - `guard_latency_us`: time spent by the double-signing guard checking and
+//!   persisting the request's state, or `null` if it wasn't reached
+//! - `provider`: signing provider of the chain's key, if any
+//! - `reason`: why the request was rejected or failed (`null` when accepted)
+//! - `dropped`: events dropped so far because consumers couldn't keep up
+//!
+//! Events are queued in a bounded buffer and written by a background thread,
+//! so a slow consumer never blocks signing: when the buffer is full events are
+//! dropped (and counted), and clients which can't accept a line within
+//! [`WRITE_TIMEOUT`] are disconnected.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use chrono::{SecondsFormat, Utc};
+use once_cell::sync::OnceCell;
+use serde::Serialize;
+use std::{
+    fs,
+    io::Write,
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Maximum number of events buffered before new ones are dropped
+pub const BUFFER_SIZE: usize = 1024;
+
+/// Maximum time spent writing an event to a single client
+pub const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Global event stream (if enabled)
+static STREAM: OnceCell<EventStream> = OnceCell::new();
+
+/// Outcome of a sign request
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    /// Request was signed
+    Accept,
+
+    /// Request was refused
+    Reject,
+
+    /// Request passed all checks but wasn't signed as the chain is
+    /// `observe_only`
+    Observe,
+
+    /// Signing failed
+    Error,
+}
+
+/// A sign decision
+#[derive(Clone, Debug, Serialize)]
+pub struct SignEvent {
+    /// Chain ID
+    pub chain_id: String,
+
+    /// Address of the validator which sent the request
+    pub validator: String,
+
+    /// Outcome of the request
+    pub decision: Decision,
+
+    /// Type of the message (i.e. `proposal`, `prevote`, or `precommit`)
+    pub msg_type: String,
+
+    /// Requested height
+    pub height: u64,
+
+    /// Requested round
+    pub round: u32,
+
+    /// Requested step
+    pub step: i8,
+
+    /// Time spent handling the request
+    pub latency_ms: u64,
+
+    /// Time the double-signing guard spent checking and persisting the
+    /// request's height/round/step (if it was reached)
+    pub guard_latency_us: Option<u64>,
+
+    /// Signing provider of the chain's key
+    pub provider: Option<String>,
+
+    /// Why the request was rejected or failed
+    pub reason: Option<String>,
+}
+
+/// Line written to clients: an event along with stream metadata
+#[derive(Serialize)]
+struct Line<'a> {
+    /// Time the event was emitted
+    timestamp: String,
+
+    /// The event itself
+    #[serde(flatten)]
+    event: &'a SignEvent,
+
+    /// Total number of events dropped so far
+    dropped: u64,
+}
+
+/// Bounded queue of serialized events
+struct EventStream {
+    /// Sender half of the queue, drained by the broadcaster thread
+    sender: SyncSender<String>,
+
+    /// Number of events dropped because the queue was full
+    dropped: AtomicU64,
+}
+
+impl EventStream {
+    /// Queue an event without blocking, counting it as dropped if the queue
+    /// is full
+    fn send(&self, event: &SignEvent) {
+        let line = Line {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            event,
+            dropped: self.dropped.load(Ordering::Relaxed),
+        };
+
+        let mut json = serde_json::to_string(&line).expect("event serialization failed");
+        json.push('\n');
+
+        match self.sender.try_send(json) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) | Err(TrySendError::Disconnected(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Start listening for event stream clients on the given Unix socket
+pub fn init(path: &Path) -> Result<(), Error> {
+    // Remove a socket left behind by a previous run (but nothing else)
+    if fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path).map_err(|e| {
+        format_err!(
+            ConfigError,
+            "couldn't bind event socket {}: {}",
+            path.display(),
+            e
+        )
+    })?;
+
+    let (sender, receiver) = mpsc::sync_channel::<String>(BUFFER_SIZE);
+
+    STREAM
+        .set(EventStream {
+            sender,
+            dropped: AtomicU64::new(0),
+        })
+        .map_err(|_| format_err!(ConfigError, "event socket already initialized"))?;
+
+    let clients = Arc::new(Mutex::new(Vec::<UnixStream>::new()));
+    let broadcast_clients = Arc::clone(&clients);
+
+    thread::Builder::new()
+        .name("event-socket-listener".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                match stream.and_then(|s| s.set_write_timeout(Some(WRITE_TIMEOUT)).map(|_| s)) {
+                    Ok(stream) => clients.lock().unwrap().push(stream),
+                    Err(e) => warn!("error accepting event socket client: {}", e),
+                }
+            }
+        })?;
+
+    thread::Builder::new()
+        .name("event-socket-broadcaster".to_owned())
+        .spawn(move || {
+            for line in receiver {
+                broadcast_clients
+                    .lock()
+                    .unwrap()
+                    .retain_mut(|client| client.write_all(line.as_bytes()).is_ok());
+            }
+        })?;
+
+    info!("streaming sign events to {}", path.display());
+    Ok(())
+}
+
+/// Is the event stream enabled?
+pub fn is_enabled() -> bool {
+    STREAM.get().is_some()
+}
+
+/// Emit an event to the stream (if enabled), dropping it if the buffer is full
+pub fn emit(event: &SignEvent) {
+    if let Some(stream) = STREAM.get() {
+        stream.send(event);
+    }
+}
+
+/// Number of events dropped so far
+pub fn dropped() -> u64 {
+    STREAM
+        .get()
+        .map(|stream| stream.dropped.load(Ordering::Relaxed))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+
+    fn event(decision: Decision) -> SignEvent {
+        SignEvent {
+            chain_id: "test-chain".to_owned(),
+            validator: "unix:///tmp/validator.sock".to_owned(),
+            decision,
+            msg_type: "prevote".to_owned(),
+            height: 123,
+            round: 0,
+            step: 1,
+            latency_ms: 2,
+            guard_latency_us: Some(500),
+            provider: Some("softsign".to_owned()),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn streams_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.sock");
+
+        assert!(!is_enabled());
+        init(&path).unwrap();
+
+        let client = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(client);
+
+        // Wait for the listener thread to register the client
+        thread::sleep(Duration::from_millis(100));
+        emit(&event(Decision::Reject));
+
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["chain_id"], "test-chain");
+        assert_eq!(json["decision"], "reject");
+        assert_eq!(json["height"], 123);
+        assert_eq!(json["provider"], "softsign");
+        assert_eq!(json["dropped"], 0);
+        assert!(json["reason"].is_null());
+    }
+
+    #[test]
+    fn drops_events_when_full() {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let stream = EventStream {
+            sender,
+            dropped: AtomicU64::new(0),
+        };
+
+        stream.send(&event(Decision::Accept));
+        stream.send(&event(Decision::Accept));
+        stream.send(&event(Decision::Accept));
+        assert_eq!(stream.dropped.load(Ordering::Relaxed), 2);
+
+        let queued: serde_json::Value = serde_json::from_str(&receiver.recv().unwrap()).unwrap();
+        assert_eq!(queued["decision"], "accept");
+    }
+}
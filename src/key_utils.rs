@@ -31,15 +31,16 @@ pub fn load_base64_secret(path: impl AsRef<Path>) -> Result<Zeroizing<Vec<u8>>,
         )
     })?);
 
+    decode_base64_secret(&base64_data, path.as_ref())
+}
+
+/// Decode Base64-encoded secret data read from the given path
+pub fn decode_base64_secret(base64_data: &str, path: &Path) -> Result<Zeroizing<Vec<u8>>, Error> {
     // TODO(tarcieri): constant-time string trimming
-    let data = Zeroizing::new(base64::decode(base64_data.trim_end()).map_err(|e| {
-        format_err!(
-            IoError,
-            "can't decode key from `{}`: {}",
-            path.as_ref().display(),
-            e
-        )
-    })?);
+    let data =
+        Zeroizing::new(base64::decode(base64_data.trim_end()).map_err(|e| {
+            format_err!(IoError, "can't decode key from `{}`: {}", path.display(), e)
+        })?);
 
     Ok(data)
 }
@@ -3,22 +3,43 @@
 pub mod ecdsa;
 pub mod ed25519;
 pub mod format;
+pub mod policy;
+pub mod post_process;
 pub mod providers;
 pub mod signature;
 
-pub use self::{format::Format, providers::SigningProvider, signature::Signature};
+pub use self::{
+    format::Format,
+    policy::KeyPolicy,
+    post_process::{PostProcess, SignaturePostProcess},
+    providers::SigningProvider,
+    signature::Signature,
+};
 use crate::{
     chain,
     config::provider::ProviderConfig,
-    error::{Error, ErrorKind::*},
+    error::{Error, ErrorClass, ErrorKind::*},
     prelude::*,
+    privval::SignedMsgType,
     Map,
 };
+use std::{
+    io,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 use tendermint::{account, TendermintKey};
 
 /// File encoding for software-backed secret keys
 pub type SecretKeyEncoding = subtle_encoding::Base64;
 
+/// Number of failed sign requests of each error class for each chain
+static SIGNING_ERRORS: Mutex<Map<(String, ErrorClass), u64>> = Mutex::new(Map::new());
+
 /// Signing keyring
 pub struct KeyRing {
     /// ECDSA keys in the keyring
@@ -27,17 +48,27 @@ pub struct KeyRing {
     /// Ed25519 keys in the keyring
     ed25519_keys: Map<TendermintKey, ed25519::Signer>,
 
+    /// Every Ed25519 signer added, in the order their providers were loaded,
+    /// including ones for a key another provider already provides (e.g.
+    /// while migrating a consensus key between providers)
+    consensus_signers: Vec<ed25519::Signer>,
+
     /// Formatting configuration when displaying keys (e.g. bech32)
     format: Format,
+
+    /// Post-processing applied to signatures produced by [`KeyRing::sign`]
+    post_process: Option<Box<dyn SignaturePostProcess>>,
 }
 
 impl KeyRing {
     /// Create a new keyring
-    pub fn new(format: Format) -> Self {
+    pub fn new(format: Format, post_process: Option<PostProcess>) -> Self {
         Self {
             ecdsa_keys: Map::new(),
             ed25519_keys: Map::new(),
+            consensus_signers: Vec::new(),
             format,
+            post_process: post_process.map(PostProcess::post_processor),
         }
     }
 
@@ -92,17 +123,93 @@ impl KeyRing {
             provider, key_type, public_key_serialized
         );
 
-        if let Some(other) = self.ed25519_keys.insert(public_key, signer) {
-            fail!(
+        if let Some(other) = self.ed25519_keys.get(&public_key) {
+            ensure!(
+                other.provider() != provider,
                 InvalidKey,
                 "[keyring:{}] duplicate key {} already registered as {}",
                 provider,
                 public_key_serialized,
                 other.provider(),
-            )
+            );
+
+            // The same key in two providers, e.g. during a migration
+            info!(
+                "[keyring:{}] consensus key {} also provided by {}; signing with {}",
+                provider,
+                public_key_serialized,
+                other.provider(),
+                other.provider()
+            );
         } else {
-            Ok(())
+            self.ed25519_keys.insert(public_key, signer.clone());
         }
+
+        self.consensus_signers.push(signer);
+        Ok(())
+    }
+
+    /// Ensure all of this keyring's providers agree on the chain's consensus
+    /// public key, rather than ambiguously picking one of several.
+    ///
+    /// If they don't, each key and its provider is logged and an error is
+    /// returned, unless `expect_different` is set, in which case only the key
+    /// of the first provider loaded is kept.
+    pub fn reconcile(&mut self, chain_id: &chain::Id, expect_different: bool) -> Result<(), Error> {
+        if self.ed25519_keys.len() <= 1 {
+            return Ok(());
+        }
+
+        let sources = self
+            .consensus_signers
+            .iter()
+            .map(|signer| {
+                format!(
+                    "{} from {}",
+                    self.format.serialize(signer.public_key()),
+                    signer.provider()
+                )
+            })
+            .collect::<Vec<_>>();
+
+        for source in &sources {
+            warn!("[{}] consensus key {}", chain_id, source);
+        }
+
+        ensure!(
+            expect_different,
+            ConfigError,
+            "[{}] signing providers disagree on the consensus key ({}); refusing to start \
+             rather than pick one (compare them with `tmkms compare-signers`, or pass \
+             `--expect-different` to sign with the key {})",
+            chain_id,
+            sources.join(", "),
+            sources[0]
+        );
+
+        let public_key = self.consensus_signers[0].public_key();
+        self.ed25519_keys.retain(|key, _| *key == public_key);
+        self.consensus_signers
+            .retain(|signer| signer.public_key() == public_key);
+
+        warn!(
+            "[{}] --expect-different: signing with consensus key {}, ignoring the others",
+            chain_id, sources[0]
+        );
+
+        Ok(())
+    }
+
+    /// Get every Ed25519 signer in this keyring, in the order their providers
+    /// were loaded, including several for the same key
+    pub fn consensus_signers(&self) -> &[ed25519::Signer] {
+        &self.consensus_signers
+    }
+
+    /// Get every public key in this keyring: its Ed25519 keys followed by its
+    /// ECDSA keys
+    pub fn public_keys(&self) -> impl Iterator<Item = &TendermintKey> {
+        self.ed25519_keys.keys().chain(self.ecdsa_keys.keys())
     }
 
     /// Get the default Ed25519 (i.e. consensus) public key for this keyring
@@ -128,6 +235,20 @@ impl KeyRing {
         }
     }
 
+    /// Formatting configuration used when displaying keys
+    pub fn format(&self) -> &Format {
+        &self.format
+    }
+
+    /// Get the provider backing the default key in this keyring
+    pub fn default_provider(&self) -> Option<SigningProvider> {
+        self.ed25519_keys
+            .values()
+            .next()
+            .map(ed25519::Signer::provider)
+            .or_else(|| self.ecdsa_keys.values().next().map(ecdsa::Signer::provider))
+    }
+
     /// Get ECDSA public key bytes for a given account ID
     pub fn get_account_pubkey(&self, account_id: account::Id) -> Option<tendermint::PublicKey> {
         for key in self.ecdsa_keys.keys() {
@@ -150,7 +271,9 @@ impl KeyRing {
         for (key, signer) in &self.ecdsa_keys {
             if let TendermintKey::AccountKey(pk) = key {
                 if account_id == account::Id::from(*pk) {
-                    return signer.sign(msg);
+                    let signature = signer.sign(msg)?;
+                    Signature::Ecdsa(signature).check_canonical()?;
+                    return Ok(signature);
                 }
             }
         }
@@ -162,9 +285,93 @@ impl KeyRing {
         )
     }
 
+    /// Check a sign request of the given type for the given chain against the
+    /// usage policy of the key which would sign it (if it has one), before any
+    /// provider is called, without counting it against the key's rate limit
+    /// (see [`KeyRing::record_policy`])
+    pub fn check_policy(
+        &self,
+        public_key: Option<&TendermintKey>,
+        chain_id: &chain::Id,
+        msg_type: SignedMsgType,
+    ) -> Result<(), Error> {
+        match self.policy(public_key) {
+            Some(policy) => policy.check(chain_id, msg_type, Instant::now()),
+            None => Ok(()),
+        }
+    }
+
+    /// Count a sign request which is about to be signed against the rate limit
+    /// of the key which will sign it (if it has a policy), checking the policy
+    /// again first. Only called once every other check has accepted the
+    /// request, so refused requests never use up the limit.
+    pub fn record_policy(
+        &self,
+        public_key: Option<&TendermintKey>,
+        chain_id: &chain::Id,
+        msg_type: SignedMsgType,
+    ) -> Result<(), Error> {
+        match self.policy(public_key) {
+            Some(policy) => policy.record(chain_id, msg_type, Instant::now()),
+            None => Ok(()),
+        }
+    }
+
+    /// Get the usage policy of the key which would sign with the given public
+    /// key, chosen like [`KeyRing::sign`] does: Ed25519 keys first, then ECDSA
+    fn policy(&self, public_key: Option<&TendermintKey>) -> Option<&KeyPolicy> {
+        if !self.ed25519_keys.is_empty() {
+            match public_key {
+                Some(public_key) => self.ed25519_keys.get(public_key),
+                None => self.ed25519_keys.values().next(),
+            }
+            .and_then(ed25519::Signer::policy)
+        } else {
+            match public_key {
+                Some(public_key) => self.ecdsa_keys.get(public_key),
+                None => self.ecdsa_keys.values().next(),
+            }
+            .and_then(ecdsa::Signer::policy)
+        }
+    }
+
     /// Sign a message using the secret key associated with the given public key
-    /// (if it is in our keyring)
+    /// (if it is in our keyring), applying any configured post-processing and
+    /// rejecting non-canonical signatures
     pub fn sign(&self, public_key: Option<&TendermintKey>, msg: &[u8]) -> Result<Signature, Error> {
+        self.sign_within(public_key, msg, None)
+    }
+
+    /// Sign a message like [`KeyRing::sign`], giving up if the signing
+    /// provider hasn't produced a signature within the given deadline (if any).
+    ///
+    /// The provider is called on a worker thread which is abandoned if it
+    /// overruns: it finishes in the background (e.g. once an HSM responds), and
+    /// its signature is dropped rather than ever being released.
+    pub fn sign_within(
+        &self,
+        public_key: Option<&TendermintKey>,
+        msg: &[u8],
+        deadline: Option<Duration>,
+    ) -> Result<Signature, Error> {
+        let signature = self.sign_raw(public_key, msg, deadline)?;
+
+        let signature = match &self.post_process {
+            Some(post_process) => post_process.post_process(signature)?,
+            None => signature,
+        };
+
+        signature.check_canonical()?;
+        Ok(signature)
+    }
+
+    /// Sign a message without post-processing the resulting signature
+    fn sign_raw(
+        &self,
+        public_key: Option<&TendermintKey>,
+        msg: &[u8],
+        deadline: Option<Duration>,
+    ) -> Result<Signature, Error> {
         if self.ed25519_keys.len() > 1 || self.ecdsa_keys.len() > 1 {
             fail!(SigningError, "expected only one key in keyring");
         }
@@ -188,7 +395,9 @@ impl KeyRing {
                     .ok_or_else(|| format_err!(InvalidKey, "ed25519 keyring is empty")),
             }?;
 
-            Ok(Signature::Ed25519(signer.sign(msg)?))
+            let signer = signer.clone();
+            let msg = msg.to_vec();
+            call_provider(deadline, move || signer.sign(&msg)).map(Signature::Ed25519)
         } else if !self.ecdsa_keys.is_empty() {
             let signer = match public_key {
                 Some(public_key) => self.ecdsa_keys.get(public_key).ok_or_else(|| {
@@ -208,13 +417,77 @@ impl KeyRing {
                     .ok_or_else(|| format_err!(InvalidKey, "ecdsa keyring is empty")),
             }?;
 
-            Ok(Signature::Ecdsa(signer.sign(msg)?))
+            let signer = signer.clone();
+            let msg = msg.to_vec();
+            call_provider(deadline, move || signer.sign(&msg)).map(Signature::Ecdsa)
         } else {
             Err(format_err!(InvalidKey, "keyring is empty").into())
         }
     }
 }
 
+/// Call a signing provider, failing with a timeout if it hasn't returned
+/// within the given deadline (if any)
+fn call_provider<T: Send + 'static>(
+    deadline: Option<Duration>,
+    call: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Error> {
+    let deadline = match deadline {
+        Some(deadline) => deadline,
+        None => return call(),
+    };
+
+    let (tx, rx) = mpsc::sync_channel(1);
+
+    thread::Builder::new()
+        .name("signing-provider".to_owned())
+        .spawn(move || {
+            // The receiver is gone if we've already given up on this call
+            let _ = tx.send(call());
+        })
+        .map_err(|e| format_err!(SigningError, "error spawning thread: {}", e))?;
+
+    match rx.recv_timeout(deadline) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(SigningError
+            .context(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "signing provider didn't respond within {} ms; discarding its signature",
+                    deadline.as_millis()
+                ),
+            ))
+            .into()),
+        Err(RecvTimeoutError::Disconnected) => {
+            fail!(SigningError, "signing provider panicked")
+        }
+    }
+}
+
+/// Record a sign request for the given chain which the keyring (i.e. a key's
+/// usage policy or its signing provider) failed, returning the error's class
+pub fn record_signing_error(chain_id: &chain::Id, err: &Error) -> ErrorClass {
+    let class = err.class();
+
+    *SIGNING_ERRORS
+        .lock()
+        .unwrap()
+        .entry((chain_id.to_string(), class))
+        .or_default() += 1;
+
+    class
+}
+
+/// Number of failed sign requests of each error class for each chain so far
+pub fn signing_errors() -> Vec<(String, ErrorClass, u64)> {
+    SIGNING_ERRORS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((chain_id, class), count)| (chain_id.clone(), *class, *count))
+        .collect()
+}
+
 /// Initialize the keyring from the configuration file
 pub fn load_config(registry: &mut chain::Registry, config: &ProviderConfig) -> Result<(), Error> {
     #[cfg(feature = "softsign")]
@@ -231,3 +504,142 @@ pub fn load_config(registry: &mut chain::Registry, config: &ProviderConfig) -> R
 
     Ok(())
 }
+
+/// Spawn background checks of keys whose providers support detecting them
+/// being swapped while running (presently Fortanix DSM keys with
+/// `key_check_interval_secs` configured)
+pub fn spawn_key_checks() {
+    #[cfg(feature = "fortanixdsm")]
+    providers::fortanixdsm::spawn_key_checks();
+}
+
+#[cfg(all(test, feature = "softsign", feature = "yubihsm"))]
+mod tests {
+    use super::*;
+    use crate::config::provider::KeyPolicyConfig;
+
+    /// Software signer for the given secret key, claiming to be from the
+    /// given provider
+    fn signer(provider: SigningProvider, secret_key: u8) -> ed25519::Signer {
+        let signing_key = ed25519::SigningKey::try_from(&[secret_key; 32][..]).unwrap();
+        let public_key = TendermintKey::ConsensusKey(signing_key.verifying_key().into());
+        ed25519::Signer::new(provider, public_key, Box::new(signing_key))
+    }
+
+    fn keyring(signers: impl IntoIterator<Item = ed25519::Signer>) -> KeyRing {
+        let mut keyring = KeyRing::new(Format::Hex, None);
+
+        for signer in signers {
+            keyring.add_ed25519(signer).unwrap();
+        }
+
+        keyring
+    }
+
+    #[test]
+    fn ecdsa_policy() {
+        let chain_id: chain::Id = "ecdsa-policy-chain".parse().unwrap();
+        let signing_key = k256::ecdsa::SigningKey::from_slice(&[1; 32]).unwrap();
+        let public_key =
+            tendermint::PublicKey::from_raw_secp256k1(&signing_key.verifying_key().to_sec1_bytes())
+                .unwrap();
+
+        let policy = KeyPolicy::new(
+            std::slice::from_ref(&chain_id),
+            &KeyPolicyConfig {
+                allowed_message_types: Some(vec![SignedMsgType::Prevote]),
+                ..Default::default()
+            },
+        );
+
+        let mut keyring = KeyRing::new(Format::Hex, None);
+        keyring
+            .add_ecdsa(
+                ecdsa::Signer::new(
+                    SigningProvider::SoftSign,
+                    TendermintKey::AccountKey(public_key),
+                    Box::new(signing_key),
+                )
+                .with_policy(policy),
+            )
+            .unwrap();
+
+        assert!(keyring
+            .check_policy(None, &chain_id, SignedMsgType::Prevote)
+            .is_ok());
+        assert!(keyring
+            .check_policy(None, &chain_id, SignedMsgType::Proposal)
+            .is_err());
+    }
+
+    #[test]
+    fn same_key_from_two_providers() {
+        let chain_id = "migration-chain".parse().unwrap();
+        let mut keyring = keyring([
+            signer(SigningProvider::SoftSign, 1),
+            signer(SigningProvider::Yubihsm, 1),
+        ]);
+
+        assert!(keyring.reconcile(&chain_id, false).is_ok());
+        assert_eq!(keyring.consensus_signers().len(), 2);
+        assert!(keyring.sign(None, b"test").is_ok());
+
+        // The same key twice from the same provider is still a mistake
+        assert!(keyring
+            .add_ed25519(signer(SigningProvider::SoftSign, 1))
+            .is_err());
+    }
+
+    /// Signer which takes a second to produce a signature
+    struct SlowSigner(ed25519::SigningKey);
+
+    impl ::signature::Signer<ed25519::Signature> for SlowSigner {
+        fn try_sign(&self, msg: &[u8]) -> Result<ed25519::Signature, ::signature::Error> {
+            thread::sleep(Duration::from_secs(1));
+            self.0.try_sign(msg)
+        }
+    }
+
+    #[test]
+    fn sign_within_deadline() {
+        let signing_key = ed25519::SigningKey::try_from(&[3; 32][..]).unwrap();
+        let public_key = TendermintKey::ConsensusKey(signing_key.verifying_key().into());
+        let keyring = keyring([ed25519::Signer::new(
+            SigningProvider::SoftSign,
+            public_key,
+            Box::new(SlowSigner(signing_key)),
+        )]);
+
+        let started_at = Instant::now();
+        let err = match keyring.sign_within(None, b"test", Some(Duration::from_millis(50))) {
+            Ok(_) => panic!("signed despite the deadline passing"),
+            Err(e) => e,
+        };
+        assert!(started_at.elapsed() < Duration::from_millis(500));
+        assert_eq!(err.class(), ErrorClass::Timeout);
+
+        assert!(keyring
+            .sign_within(None, b"test", Some(Duration::from_secs(5)))
+            .is_ok());
+    }
+
+    #[test]
+    fn different_keys() {
+        let chain_id = "migration-chain".parse().unwrap();
+        let first = signer(SigningProvider::Yubihsm, 2);
+        let signers = [first.clone(), signer(SigningProvider::SoftSign, 1)];
+
+        let err = keyring(signers.clone())
+            .reconcile(&chain_id, false)
+            .unwrap_err();
+        assert_eq!(*err.kind(), ConfigError);
+        assert!(err.to_string().contains("disagree on the consensus key"));
+
+        // With `--expect-different` the first provider's key is kept
+        let mut keyring = keyring(signers);
+        keyring.reconcile(&chain_id, true).unwrap();
+        assert_eq!(keyring.default_pubkey().unwrap(), first.public_key());
+        assert_eq!(keyring.consensus_signers().len(), 1);
+        assert!(keyring.sign(None, b"test").is_ok());
+    }
+}
@@ -4,8 +4,7 @@ pub use k256::{ecdsa::Signature, EncodedPoint as PublicKey};
 
 use crate::{
     error::{Error, ErrorKind::*},
-    keyring::SigningProvider,
-    prelude::*,
+    keyring::{KeyPolicy, SigningProvider},
 };
 use std::sync::Arc;
 use tendermint::TendermintKey;
@@ -23,6 +22,9 @@ pub struct Signer {
 
     /// Signer trait object
     signer: Arc<Box<dyn signature::Signer<Signature> + Send + Sync>>,
+
+    /// Usage policy enforced before signing (shared by all clones)
+    policy: Option<Arc<KeyPolicy>>,
 }
 
 impl Signer {
@@ -37,9 +39,16 @@ impl Signer {
             provider,
             public_key,
             signer: Arc::new(signer),
+            policy: None,
         }
     }
 
+    /// Enforce the given usage policy before signing with this signer
+    pub fn with_policy(mut self, policy: KeyPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
     /// Get the Tendermint public key for this signer
     pub fn public_key(&self) -> TendermintKey {
         self.public_key
@@ -50,11 +59,16 @@ impl Signer {
         self.provider
     }
 
+    /// Get the usage policy for this signer, if it has one
+    pub fn policy(&self) -> Option<&KeyPolicy> {
+        self.policy.as_deref()
+    }
+
     /// Sign the given message using this signer
     pub fn sign(&self, msg: &[u8]) -> Result<Signature, Error> {
         Ok(self
             .signer
             .try_sign(msg)
-            .map_err(|e| format_err!(SigningError, "{}", e))?)
+            .map_err(|e| SigningError.context(e))?)
     }
 }
@@ -8,8 +8,7 @@ pub use ed25519::Signature;
 
 use crate::{
     error::{Error, ErrorKind::*},
-    keyring::SigningProvider,
-    prelude::*,
+    keyring::{KeyPolicy, SigningProvider},
 };
 use std::sync::Arc;
 use tendermint::TendermintKey;
@@ -27,6 +26,9 @@ pub struct Signer {
 
     /// Signer trait object
     signer: Arc<Box<dyn signature::Signer<Signature> + Send + Sync>>,
+
+    /// Usage policy enforced before signing (shared by all clones)
+    policy: Option<Arc<KeyPolicy>>,
 }
 
 impl Signer {
@@ -40,9 +42,16 @@ impl Signer {
             provider,
             public_key,
             signer: Arc::new(signer),
+            policy: None,
         }
     }
 
+    /// Enforce the given usage policy before signing with this signer
+    pub fn with_policy(mut self, policy: KeyPolicy) -> Self {
+        self.policy = Some(Arc::new(policy));
+        self
+    }
+
     /// Get the Tendermint public key for this signer
     pub fn public_key(&self) -> TendermintKey {
         self.public_key
@@ -53,11 +62,16 @@ impl Signer {
         self.provider
     }
 
+    /// Get the usage policy for this signer, if it has one
+    pub fn policy(&self) -> Option<&KeyPolicy> {
+        self.policy.as_deref()
+    }
+
     /// Sign the given message using this signer
     pub fn sign(&self, msg: &[u8]) -> Result<Signature, Error> {
         Ok(self
             .signer
             .try_sign(msg)
-            .map_err(|e| format_err!(SigningError, "{}", e))?)
+            .map_err(|e| SigningError.context(e))?)
     }
 }
@@ -0,0 +1,311 @@
+//! Usage policies for signing keys, enforced before any provider call
+//!
+//! A policy (see [`KeyPolicyConfig`]) is shared by every chain's copy of its
+//! key, so the rate limit applies to the key as a whole. Each violation is
+//! counted per chain and policy type and exported as
+//! `tmkms_policy_violations_total` (see [`crate::metrics`]).
+//!
+//! A request is checked with [`KeyPolicy::check`] before anything else looks
+//! at it, but only uses up a rate limit slot once it's about to be signed
+//! ([`KeyPolicy::record`]), so requests refused by later checks (e.g. the
+//! double-signing guard) can't run the limit down.
+
+use crate::{
+    chain,
+    config::provider::KeyPolicyConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+    privval::SignedMsgType,
+    Map,
+};
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Window over which `max_signs_per_minute` is enforced
+pub const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+/// Number of violations of each policy type for each chain
+static VIOLATIONS: Mutex<Map<(String, Violation), u64>> = Mutex::new(Map::new());
+
+/// Usage policy of a signing key
+#[derive(Debug)]
+pub struct KeyPolicy {
+    /// Chains the key may sign for
+    chain_ids: Vec<chain::Id>,
+
+    /// Message types the key may sign (`None` for any)
+    allowed_message_types: Option<Vec<SignedMsgType>>,
+
+    /// Maximum number of signs within [`RATE_LIMIT_WINDOW`]
+    max_signs_per_minute: Option<u32>,
+
+    /// Times of the signs within the last [`RATE_LIMIT_WINDOW`]
+    recent_signs: Mutex<VecDeque<Instant>>,
+}
+
+impl KeyPolicy {
+    /// Create the policy of a key authorized for the given chains
+    pub fn new(chain_ids: &[chain::Id], config: &KeyPolicyConfig) -> Self {
+        Self {
+            chain_ids: chain_ids.to_vec(),
+            allowed_message_types: config.allowed_message_types.clone(),
+            max_signs_per_minute: config.max_signs_per_minute.map(u32::from),
+            recent_signs: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Check whether a message of the given type may be signed for the given
+    /// chain at time `now`, without counting it against the rate limit
+    pub fn check(
+        &self,
+        chain_id: &chain::Id,
+        msg_type: SignedMsgType,
+        now: Instant,
+    ) -> Result<(), Error> {
+        self.enforce(chain_id, msg_type, now, false)
+    }
+
+    /// Check the policy again right before signing at time `now`, counting
+    /// the sign against the rate limit if it's allowed
+    pub fn record(
+        &self,
+        chain_id: &chain::Id,
+        msg_type: SignedMsgType,
+        now: Instant,
+    ) -> Result<(), Error> {
+        self.enforce(chain_id, msg_type, now, true)
+    }
+
+    /// Check the policy, counting the sign against the rate limit if `record`
+    /// is set and it's allowed, and counting any violation
+    fn enforce(
+        &self,
+        chain_id: &chain::Id,
+        msg_type: SignedMsgType,
+        now: Instant,
+        record: bool,
+    ) -> Result<(), Error> {
+        self.check_usage(chain_id, msg_type, now, record)
+            .map_err(|violation| {
+                *VIOLATIONS
+                    .lock()
+                    .unwrap()
+                    .entry((chain_id.to_string(), violation))
+                    .or_default() += 1;
+
+                format_err!(
+                    PolicyError,
+                    "refusing to sign {:?} for chain {}: {}",
+                    msg_type,
+                    chain_id,
+                    self.describe(violation)
+                )
+                .into()
+            })
+    }
+
+    /// Check the policy, returning which part of it was violated (if any)
+    fn check_usage(
+        &self,
+        chain_id: &chain::Id,
+        msg_type: SignedMsgType,
+        now: Instant,
+        record: bool,
+    ) -> Result<(), Violation> {
+        if !self.chain_ids.contains(chain_id) {
+            return Err(Violation::Chain);
+        }
+
+        if let Some(allowed_message_types) = &self.allowed_message_types {
+            if !allowed_message_types.contains(&msg_type) {
+                return Err(Violation::MessageType);
+            }
+        }
+
+        if let Some(max_signs) = self.max_signs_per_minute {
+            let mut recent_signs = self.recent_signs.lock().unwrap();
+
+            while recent_signs.front().is_some_and(|&signed_at| {
+                now.saturating_duration_since(signed_at) >= RATE_LIMIT_WINDOW
+            }) {
+                recent_signs.pop_front();
+            }
+
+            if recent_signs.len() >= max_signs as usize {
+                return Err(Violation::RateLimit);
+            }
+
+            if record {
+                recent_signs.push_back(now);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Describe a violation of this policy
+    fn describe(&self, violation: Violation) -> String {
+        match violation {
+            Violation::Chain => "key policy only allows its own `chain_ids`".to_owned(),
+            Violation::MessageType => "message type not allowed by key policy".to_owned(),
+            Violation::RateLimit => format!(
+                "key policy allows at most {} signs per minute",
+                self.max_signs_per_minute.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Part of a key policy which was violated
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Violation {
+    /// Sign request for a chain not in the key's `chain_ids`
+    Chain,
+
+    /// Message type not in `allowed_message_types`
+    MessageType,
+
+    /// More than `max_signs_per_minute` sign requests
+    RateLimit,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Violation::Chain => "chain_id",
+            Violation::MessageType => "message_type",
+            Violation::RateLimit => "rate_limit",
+        })
+    }
+}
+
+/// Number of violations of each policy type for each chain so far
+pub fn violations() -> Vec<(String, Violation, u64)> {
+    VIOLATIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|((chain_id, violation), count)| (chain_id.clone(), *violation, *count))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU32;
+
+    fn chain_id(id: &str) -> chain::Id {
+        id.parse().unwrap()
+    }
+
+    fn policy(config: KeyPolicyConfig) -> KeyPolicy {
+        KeyPolicy::new(&[chain_id("policy-chain")], &config)
+    }
+
+    fn violation_count(chain: &str, violation: Violation) -> u64 {
+        violations()
+            .into_iter()
+            .find(|(id, v, _)| id == chain && *v == violation)
+            .map(|(_, _, count)| count)
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn chain_ids() {
+        let policy = policy(KeyPolicyConfig::default());
+        let now = Instant::now();
+
+        assert!(policy
+            .check(&chain_id("policy-chain"), SignedMsgType::Prevote, now)
+            .is_ok());
+
+        let err = policy
+            .check(&chain_id("other-chain"), SignedMsgType::Prevote, now)
+            .unwrap_err();
+        assert_eq!(*err.kind(), PolicyError);
+        assert_eq!(violation_count("other-chain", Violation::Chain), 1);
+    }
+
+    #[test]
+    fn allowed_message_types() {
+        let policy = KeyPolicy::new(
+            &[chain_id("message-type-chain")],
+            &KeyPolicyConfig {
+                allowed_message_types: Some(vec![SignedMsgType::Prevote, SignedMsgType::Precommit]),
+                ..Default::default()
+            },
+        );
+        let chain = chain_id("message-type-chain");
+        let now = Instant::now();
+
+        assert!(policy.check(&chain, SignedMsgType::Prevote, now).is_ok());
+        assert!(policy.check(&chain, SignedMsgType::Precommit, now).is_ok());
+
+        let err = policy
+            .check(&chain, SignedMsgType::Proposal, now)
+            .unwrap_err();
+        assert!(err.to_string().contains("message type not allowed"));
+        assert_eq!(
+            violation_count("message-type-chain", Violation::MessageType),
+            1
+        );
+    }
+
+    #[test]
+    fn rate_limit() {
+        let policy = KeyPolicy::new(
+            &[chain_id("rate-limit-chain")],
+            &KeyPolicyConfig {
+                max_signs_per_minute: NonZeroU32::new(3),
+                ..Default::default()
+            },
+        );
+        let chain = chain_id("rate-limit-chain");
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Requests which are checked but never signed don't use up the limit
+        for _ in 0..10 {
+            assert!(policy.check(&chain, SignedMsgType::Prevote, at(0)).is_ok());
+        }
+
+        for secs in [0, 10, 20] {
+            assert!(policy
+                .record(&chain, SignedMsgType::Prevote, at(secs))
+                .is_ok());
+        }
+
+        // A fourth sign within the same minute is refused, and not counted
+        assert!(policy
+            .check(&chain, SignedMsgType::Precommit, at(59))
+            .is_err());
+        let err = policy
+            .record(&chain, SignedMsgType::Precommit, at(59))
+            .unwrap_err();
+        assert!(err.to_string().contains("at most 3 signs per minute"));
+        assert_eq!(violation_count("rate-limit-chain", Violation::RateLimit), 2);
+
+        // Once the first sign leaves the window, there's room for one more
+        assert!(policy
+            .record(&chain, SignedMsgType::Prevote, at(60))
+            .is_ok());
+        assert!(policy
+            .record(&chain, SignedMsgType::Prevote, at(65))
+            .is_err());
+
+        // ...and after a quiet minute, for the full limit again
+        for secs in [200, 201, 202] {
+            assert!(policy
+                .record(&chain, SignedMsgType::Prevote, at(secs))
+                .is_ok());
+        }
+        assert!(policy
+            .record(&chain, SignedMsgType::Prevote, at(203))
+            .is_err());
+        assert_eq!(violation_count("rate-limit-chain", Violation::RateLimit), 4);
+    }
+}
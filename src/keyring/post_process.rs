@@ -0,0 +1,106 @@
+//! Signature post-processing
+//!
+//! Hooks run on signatures after they're returned by a signing provider and
+//! before they're sent to the validator, allowing chain-specific adjustments
+//! (e.g. ECDSA malleability normalization) to be applied in one place.
+
+use super::Signature;
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+/// Post-processing applied to signatures produced by a signing provider
+pub trait SignaturePostProcess: Send + Sync {
+    /// Post-process the given signature
+    fn post_process(&self, signature: Signature) -> Result<Signature, Error>;
+}
+
+/// Selection of a built-in signature post-processor (configured per chain)
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Eq, PartialEq)]
+pub enum PostProcess {
+    /// Ed25519 signatures are passed through unmodified
+    #[serde(rename = "ed25519")]
+    Ed25519,
+
+    /// secp256k1 ECDSA signatures are normalized to "low S" form
+    #[serde(rename = "secp256k1")]
+    Secp256k1,
+}
+
+impl PostProcess {
+    /// Get the corresponding post-processor
+    pub fn post_processor(self) -> Box<dyn SignaturePostProcess> {
+        match self {
+            PostProcess::Ed25519 => Box::new(Ed25519),
+            PostProcess::Secp256k1 => Box::new(Secp256k1),
+        }
+    }
+}
+
+/// Identity post-processor for Ed25519 signatures
+pub struct Ed25519;
+
+impl SignaturePostProcess for Ed25519 {
+    fn post_process(&self, signature: Signature) -> Result<Signature, Error> {
+        match signature {
+            Signature::Ed25519(_) => Ok(signature),
+            Signature::Ecdsa(_) => fail!(
+                SigningError,
+                "expected Ed25519 signature, got ECDSA (check signature_post_process)"
+            ),
+        }
+    }
+}
+
+/// Low-S normalizing post-processor for secp256k1 ECDSA signatures.
+///
+/// Tendermint rejects secp256k1 signatures whose `s` component is in the
+/// upper half of the curve order, which some providers may produce.
+pub struct Secp256k1;
+
+impl SignaturePostProcess for Secp256k1 {
+    fn post_process(&self, signature: Signature) -> Result<Signature, Error> {
+        match signature {
+            Signature::Ecdsa(sig) => Ok(Signature::Ecdsa(sig.normalize_s().unwrap_or(sig))),
+            Signature::Ed25519(_) => fail!(
+                SigningError,
+                "expected ECDSA signature, got Ed25519 (check signature_post_process)"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::{ecdsa::SigningKey, elliptic_curve::ops::Neg};
+    use signature::Signer;
+
+    #[test]
+    fn secp256k1_normalizes_high_s() {
+        let signing_key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let sig: k256::ecdsa::Signature = signing_key.sign(b"test message");
+        assert!(sig.normalize_s().is_none());
+
+        let (r, s) = sig.split_scalars();
+        let high_s = k256::ecdsa::Signature::from_scalars(r, s.neg()).unwrap();
+        assert!(high_s.normalize_s().is_some());
+
+        match Secp256k1.post_process(Signature::Ecdsa(high_s)).unwrap() {
+            Signature::Ecdsa(normalized) => assert_eq!(normalized, sig),
+            Signature::Ed25519(_) => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn reject_mismatched_signature_type() {
+        let signing_key = SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let sig: k256::ecdsa::Signature = signing_key.sign(b"test message");
+        match Ed25519.post_process(Signature::Ecdsa(sig)) {
+            Err(e) => assert_eq!(*e.kind(), SigningError),
+            Ok(_) => panic!("expected mismatched signature type to be rejected"),
+        }
+    }
+}
@@ -1,11 +1,15 @@
 //! Fortanix DSM signing provider
 
 use crate::{
+    backoff::Backoff,
     chain,
-    config::provider::fortanixdsm::{FortanixDsmConfig, KeyDescriptor, SigningKeyConfig},
+    clock::{Clock, SystemClock},
+    config::provider::fortanixdsm::{
+        FortanixDsmConfig, KeyChangeAction, KeyDescriptor, SigningKeyConfig,
+    },
     config::provider::KeyType,
     error::{Error, ErrorKind::*},
-    keyring::{self, ed25519, SigningProvider},
+    keyring::{self, ed25519, KeyPolicy, SigningProvider},
     prelude::*,
 };
 use elliptic_curve::pkcs8::{
@@ -14,14 +18,29 @@ use elliptic_curve::pkcs8::{
 use elliptic_curve::PublicKey as EcPublicKey;
 use k256::ecdsa::{Error as SignError, Signature as EcdsaSignature};
 use sdkms::api_model::{
-    DigestAlgorithm, EllipticCurve, ObjectType, SignRequest, SignResponse, SobjectDescriptor,
+    DigestAlgorithm, EllipticCurve, ObjectType, SignRequest, SignResponse, Sobject,
+    SobjectDescriptor,
 };
 use sdkms::{Error as SdkmsError, SdkmsClient};
 use signature::Signer;
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
 use tendermint::public_key::{Ed25519, Secp256k1};
 use tendermint::{PublicKey, TendermintKey};
 use url::Url;
+use uuid::Uuid;
+
+/// Default number of attempts for public key lookups
+const DEFAULT_LOOKUP_ATTEMPTS: u32 = 3;
+
+/// Default delay before the first public key lookup retry (in milliseconds)
+const DEFAULT_LOOKUP_RETRY_DELAY_MS: u64 = 500;
 
 /// Create Fortanix DSM backed signer objects from the given configuration
 pub fn init(registry: &mut chain::Registry, configs: &[FortanixDsmConfig]) -> Result<(), Error> {
@@ -31,7 +50,7 @@ pub fn init(registry: &mut chain::Registry, configs: &[FortanixDsmConfig]) -> Re
     for config in configs {
         let client = make_sdkms_client(config)?;
         for key in &config.signing_keys {
-            add_key(registry, key, client.clone())?;
+            add_key(registry, config, key, client.clone())?;
         }
     }
     Ok(())
@@ -69,10 +88,56 @@ fn map_dsm_error(ctx: &str, e: SdkmsError) -> Error {
     format_err!(FortanixDsmError, "{}: {}", ctx, e).into()
 }
 
+/// Map an error looking up the given key, with guidance for the errors which
+/// need an operator to fix the key or the API key's app in DSM
+fn map_lookup_error(api_endpoint: &str, key: &str, e: SdkmsError) -> Error {
+    match e {
+        SdkmsError::Unauthorized(msg) => format_err!(
+            FortanixDsmError,
+            "DSM at {} rejected the API key looking up {} ({}): check `api_key` is valid and its app isn't disabled",
+            api_endpoint,
+            key,
+            msg
+        )
+        .into(),
+        SdkmsError::Forbidden(msg) => format_err!(
+            FortanixDsmError,
+            "API key's app lacks permission to use security object {} at {} ({}): check the app is in the key's group and the group's policy allows it",
+            key,
+            api_endpoint,
+            msg
+        )
+        .into(),
+        SdkmsError::NotFound(msg) => format_err!(
+            FortanixDsmError,
+            "security object {} not found at {} ({}): check the key's name or kid, and that it's in a group the app can access",
+            key,
+            api_endpoint,
+            msg
+        )
+        .into(),
+        e => map_dsm_error(&format!("failed to get security object {}", key), e),
+    }
+}
+
+/// Map an error signing with the given key, calling out a missing `Sign`
+/// permission, which DSM only checks when signing
+fn map_sign_error(key: &str, e: SdkmsError) -> SignError {
+    match e {
+        SdkmsError::Forbidden(msg) => SignError::from_source(format!(
+            "API key's app lacks permission to sign with {} ({}): check the key's permitted operations include `Sign`",
+            key, msg
+        )),
+        e => SignError::from_source(e),
+    }
+}
+
 struct SigningKey {
     client: Arc<SdkmsClient>,
+    key: String,
     descriptor: SobjectDescriptor,
     elliptic_curve: EllipticCurve,
+    watch: Option<Arc<KeyWatch>>,
 }
 
 impl SigningKey {
@@ -80,11 +145,12 @@ impl SigningKey {
         client: Arc<SdkmsClient>,
         descriptor: KeyDescriptor,
         key_type: KeyType,
+        config: &FortanixDsmConfig,
     ) -> Result<(Self, TendermintKey), Error> {
+        let key_label = descriptor.to_string();
         let descriptor: SobjectDescriptor = descriptor.into();
-        let key = client
-            .get_sobject(None, &descriptor)
-            .map_err(|e| map_dsm_error("failed to get security object", e))?;
+        let key = get_sobject_with_retry(&client, &descriptor, &key_label, config)?;
+        let identity = KeyIdentity::of(&key);
 
         let required_curve = match key_type {
             KeyType::Account => EllipticCurve::SecP256K1,
@@ -135,17 +201,44 @@ impl SigningKey {
             }
         };
 
+        let watch = config.key_check_interval_secs.map(|secs| {
+            let watch = Arc::new(KeyWatch::new(
+                key_label.clone(),
+                identity,
+                config.on_key_change.unwrap_or_default(),
+            ));
+
+            register_key_check(KeyCheck {
+                client: client.clone(),
+                descriptor: descriptor.clone(),
+                interval: Duration::from_secs(secs.max(1)),
+                watch: watch.clone(),
+            });
+
+            watch
+        });
+
         Ok((
             SigningKey {
                 client,
+                key: key_label,
                 descriptor,
                 elliptic_curve: required_curve,
+                watch,
             },
             public_key,
         ))
     }
 
     fn sign(&self, msg: &[u8], hash_alg: DigestAlgorithm) -> Result<SignResponse, SignError> {
+        if let Some(watch) = self.watch.as_ref().filter(|watch| watch.refuses_signing()) {
+            return Err(SignError::from_source(format!(
+                "{} changed in DSM since startup (now kid {}): refusing to sign until tmkms is restarted",
+                watch.key,
+                watch.detected_kid()
+            )));
+        }
+
         let req = SignRequest {
             key: Some(self.descriptor.clone()),
             data: Some(msg.to_owned().into()),
@@ -154,51 +247,324 @@ impl SigningKey {
             mode: None,
             deterministic_signature: None,
         };
-        self.client.sign(&req).map_err(SignError::from_source)
+        self.client
+            .sign(&req)
+            .map_err(|e| map_sign_error(&self.key, e))
     }
 }
 
 impl Signer<EcdsaSignature> for SigningKey {
     fn try_sign(&self, msg: &[u8]) -> Result<EcdsaSignature, SignError> {
         assert_eq!(self.elliptic_curve, EllipticCurve::SecP256K1);
-        let resp = self.sign(msg, DigestAlgorithm::Sha256)?;
-        EcdsaSignature::from_der(&resp.signature)
+        parse_with_refetch(
+            || Ok(self.sign(msg, DigestAlgorithm::Sha256)?.signature.to_vec()),
+            EcdsaSignature::from_der,
+        )
     }
 }
 
 impl Signer<ed25519::Signature> for SigningKey {
     fn try_sign(&self, msg: &[u8]) -> Result<ed25519::Signature, SignError> {
         assert_eq!(self.elliptic_curve, EllipticCurve::Ed25519);
-        let resp = self.sign(msg, DigestAlgorithm::Sha512)?;
-        ed25519::Signature::from_slice(&resp.signature)
+        parse_with_refetch(
+            || Ok(self.sign(msg, DigestAlgorithm::Sha512)?.signature.to_vec()),
+            ed25519::Signature::from_slice,
+        )
+    }
+}
+
+/// Identity of a key in DSM, as compared by key checks
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct KeyIdentity {
+    /// Security object ID of the key
+    kid: Option<Uuid>,
+
+    /// DER-encoded public key
+    pub_key: Option<Vec<u8>>,
+}
+
+impl KeyIdentity {
+    /// Identity of the given security object
+    fn of(key: &Sobject) -> Self {
+        Self {
+            kid: key.kid,
+            pub_key: key.pub_key.as_ref().map(|pub_key| pub_key.to_vec()),
+        }
+    }
+}
+
+/// Result of periodically looking up a signing key again, to detect it being
+/// swapped in DSM while tmkms is running (e.g. rekeyed under the same name,
+/// which would otherwise silently change the validator's signing identity)
+pub struct KeyWatch {
+    /// Descriptor of the key from the configuration, e.g. `key_name=...`
+    key: String,
+
+    /// What to do once a change is detected
+    on_change: KeyChangeAction,
+
+    /// Identity of the key when it was loaded
+    loaded: KeyIdentity,
+
+    /// Identity of the key at the most recent check
+    detected: Mutex<KeyIdentity>,
+
+    /// Has the key changed since it was loaded? Stays set until restart, even
+    /// if the original key comes back.
+    changed: AtomicBool,
+}
+
+impl KeyWatch {
+    fn new(key: String, loaded: KeyIdentity, on_change: KeyChangeAction) -> Self {
+        Self {
+            key,
+            on_change,
+            detected: Mutex::new(loaded.clone()),
+            loaded,
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Record the identity of the key found by a check
+    fn record(&self, detected: KeyIdentity) {
+        if detected != self.loaded && !self.changed.swap(true, Ordering::SeqCst) {
+            error!(
+                "[keyring:fortanixdsm] *** {} changed in DSM since startup (kid {} -> {}): {} ***",
+                self.key,
+                display_kid(self.loaded.kid),
+                display_kid(detected.kid),
+                match self.on_change {
+                    KeyChangeAction::Refuse => "refusing to sign until tmkms is restarted",
+                    KeyChangeAction::Warn => "still signing (`on_key_change = \"warn\"`)",
+                }
+            );
+        }
+
+        *self.detected.lock().unwrap() = detected;
+    }
+
+    /// Has the key changed since it was loaded?
+    pub fn is_changed(&self) -> bool {
+        self.changed.load(Ordering::SeqCst)
+    }
+
+    /// Should signing with this key be refused?
+    fn refuses_signing(&self) -> bool {
+        self.on_change == KeyChangeAction::Refuse && self.is_changed()
+    }
+
+    /// Descriptor of the key from the configuration
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    /// Security object ID of the key at the most recent check
+    pub fn detected_kid(&self) -> String {
+        display_kid(self.detected.lock().unwrap().kid)
+    }
+}
+
+/// Format a (possibly absent) security object ID
+fn display_kid(kid: Option<Uuid>) -> String {
+    kid.map(|kid| kid.to_string()).unwrap_or_default()
+}
+
+/// A key check to run in the background
+#[derive(Clone)]
+struct KeyCheck {
+    client: Arc<SdkmsClient>,
+    descriptor: SobjectDescriptor,
+    interval: Duration,
+    watch: Arc<KeyWatch>,
+}
+
+/// Key checks of all loaded keys
+static KEY_CHECKS: Mutex<Vec<KeyCheck>> = Mutex::new(Vec::new());
+
+/// Register a key check, replacing any from an earlier attempt to load the
+/// same key
+fn register_key_check(check: KeyCheck) {
+    let mut checks = KEY_CHECKS.lock().unwrap();
+    checks.retain(|other| other.watch.key != check.watch.key);
+    checks.push(check);
+}
+
+/// Key watches of all loaded keys with `key_check_interval_secs` configured
+pub fn key_watches() -> Vec<Arc<KeyWatch>> {
+    KEY_CHECKS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|check| check.watch.clone())
+        .collect()
+}
+
+/// Spawn a background thread checking each key which has key checks enabled
+pub fn spawn_key_checks() {
+    for check in KEY_CHECKS.lock().unwrap().iter().cloned() {
+        let key = check.watch.key.clone();
+
+        if let Err(e) = thread::Builder::new()
+            .name(format!("fortanixdsm-key-check-{}", key))
+            .spawn(move || check_loop(check))
+        {
+            error!(
+                "[keyring:fortanixdsm] couldn't spawn key check for {}: {}",
+                key, e
+            );
+        }
+    }
+}
+
+/// Look up a key again every interval, recording its identity
+fn check_loop(check: KeyCheck) {
+    loop {
+        thread::sleep(check.interval);
+
+        match check.client.get_sobject(None, &check.descriptor) {
+            Ok(key) => check.watch.record(KeyIdentity::of(&key)),
+            Err(e) => warn!(
+                "[keyring:fortanixdsm] key check for {} failed: {}",
+                check.watch.key, e
+            ),
+        }
+    }
+}
+
+/// Parse a signature returned by DSM, refetching it once if it's malformed
+/// (e.g. a truncated response).
+///
+/// Only parse errors are retried, never errors from the request itself. This
+/// is safe because we're re-signing the same message: at most one of the
+/// signatures is ever released.
+fn parse_with_refetch<S>(
+    mut fetch: impl FnMut() -> Result<Vec<u8>, SignError>,
+    parse: impl Fn(&[u8]) -> Result<S, SignError>,
+) -> Result<S, SignError> {
+    match parse(&fetch()?) {
+        Ok(signature) => Ok(signature),
+        Err(e) => {
+            debug!(
+                "[keyring:fortanixdsm] malformed signature in response ({}), refetching once",
+                e
+            );
+            parse(&fetch()?)
+        }
+    }
+}
+
+/// Look up a security object, retrying with (jittered) exponential backoff.
+///
+/// Lookups are read-only and therefore safe to retry, unlike signing.
+fn get_sobject_with_retry(
+    client: &SdkmsClient,
+    descriptor: &SobjectDescriptor,
+    key: &str,
+    config: &FortanixDsmConfig,
+) -> Result<Sobject, Error> {
+    retry_lookup(config, &SystemClock, || {
+        client.get_sobject(None, descriptor)
+    })
+    .map_err(|e| map_lookup_error(&config.api_endpoint, key, e))
+}
+
+/// Retry the given lookup as configured while it fails with transient errors
+/// (see [`is_transient`]), waiting between attempts on the given clock
+fn retry_lookup<T>(
+    config: &FortanixDsmConfig,
+    clock: &dyn Clock,
+    mut lookup: impl FnMut() -> Result<T, SdkmsError>,
+) -> Result<T, SdkmsError> {
+    let attempts = config
+        .lookup_attempts
+        .unwrap_or(DEFAULT_LOOKUP_ATTEMPTS)
+        .max(1);
+
+    let mut backoff = Backoff::new(
+        Duration::from_millis(
+            config
+                .lookup_retry_delay_ms
+                .unwrap_or(DEFAULT_LOOKUP_RETRY_DELAY_MS),
+        ),
+        Duration::MAX,
+        config.lookup_retry_jitter.unwrap_or_default(),
+    );
+
+    let mut attempt = 1;
+
+    loop {
+        match lookup() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < attempts && is_transient(&e) => {
+                let delay = backoff.next_delay();
+                debug!(
+                    "[keyring:fortanixdsm] security object lookup failed (attempt {}/{}), retrying in {} ms: {}",
+                    attempt,
+                    attempts,
+                    delay.as_millis(),
+                    e
+                );
+                clock.sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Might the given error go away by itself, i.e. is it a connectivity
+/// problem, a server error, or rate limiting? Anything else (e.g. a rejected
+/// API key or a missing key) fails the same way however often it's retried.
+fn is_transient(e: &SdkmsError) -> bool {
+    match e {
+        SdkmsError::IoError(_) | SdkmsError::NetworkError(_) | SdkmsError::TlsError(_) => true,
+        // Formatted as "<code> <reason>\n<message>"
+        SdkmsError::StatusCode(status) => status
+            .split_whitespace()
+            .next()
+            .is_some_and(|code| code == "429" || (code.len() == 3 && code.starts_with('5'))),
+        _ => false,
     }
 }
 
 fn add_key(
     registry: &mut chain::Registry,
+    dsm_config: &FortanixDsmConfig,
     config: &SigningKeyConfig,
     client: Arc<SdkmsClient>,
 ) -> Result<(), Error> {
-    let (signing_key, public_key) =
-        SigningKey::new(client, config.key.clone(), config.key_type.clone())?;
+    let (signing_key, public_key) = SigningKey::new(
+        client,
+        config.key.clone(),
+        config.key_type.clone(),
+        dsm_config,
+    )?;
 
     match config.key_type {
         KeyType::Account => {
-            let signer = keyring::ecdsa::Signer::new(
+            let mut signer = keyring::ecdsa::Signer::new(
                 SigningProvider::FortanixDsm,
                 public_key,
                 Box::new(signing_key),
             );
+
+            if let Some(policy) = &config.policy {
+                signer = signer.with_policy(KeyPolicy::new(&config.chain_ids, policy));
+            }
             for chain_id in &config.chain_ids {
                 registry.add_account_key(chain_id, signer.clone())?;
             }
         }
         KeyType::Consensus => {
-            let signer = ed25519::Signer::new(
+            let mut signer = ed25519::Signer::new(
                 SigningProvider::FortanixDsm,
                 public_key,
                 Box::new(signing_key),
             );
+
+            if let Some(policy) = &config.policy {
+                signer = signer.with_policy(KeyPolicy::new(&config.chain_ids, policy));
+            }
             for chain_id in &config.chain_ids {
                 registry.add_consensus_key(chain_id, signer.clone())?;
             }
@@ -229,3 +595,171 @@ impl TryFrom<SubjectPublicKeyInfoRef<'_>> for Ed25519PublicKey {
             .map(Ed25519PublicKey)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{backoff::Jitter, clock::MockClock};
+    use std::io;
+
+    fn identity(kid: u128, pub_key: &[u8]) -> KeyIdentity {
+        KeyIdentity {
+            kid: Some(Uuid::from_u128(kid)),
+            pub_key: Some(pub_key.to_vec()),
+        }
+    }
+
+    fn lookup_config() -> FortanixDsmConfig {
+        FortanixDsmConfig {
+            api_endpoint: "https://dsm.example.com".to_owned(),
+            api_key: String::new(),
+            signing_keys: vec![],
+            lookup_attempts: Some(3),
+            lookup_retry_delay_ms: Some(1),
+            lookup_retry_jitter: Some(Jitter::None),
+            key_check_interval_secs: None,
+            on_key_change: None,
+        }
+    }
+
+    #[test]
+    fn retry_transient_lookup_errors() {
+        for transient in [
+            SdkmsError::IoError(io::Error::from(io::ErrorKind::ConnectionRefused)),
+            SdkmsError::StatusCode("503 Service Unavailable\nmaintenance".to_owned()),
+            SdkmsError::StatusCode("429 Too Many Requests\nslow down".to_owned()),
+        ] {
+            let mut errors = vec![transient];
+            let mut calls = 0;
+            let clock = MockClock::new();
+
+            let result = retry_lookup(&lookup_config(), &clock, || {
+                calls += 1;
+                errors.pop().map_or(Ok(calls), Err)
+            });
+
+            assert_eq!(result.unwrap(), 2);
+            assert_eq!(clock.elapsed(), Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn fail_fast_on_permanent_lookup_errors() {
+        for permanent in [
+            SdkmsError::Unauthorized("invalid API key".to_owned()),
+            SdkmsError::Forbidden("no access".to_owned()),
+            SdkmsError::NotFound("no such key".to_owned()),
+            SdkmsError::BadRequest("malformed".to_owned()),
+            SdkmsError::StatusCode("422 Unprocessable Entity\ninvalid".to_owned()),
+        ] {
+            let mut errors = vec![permanent];
+            let mut calls = 0;
+            let clock = MockClock::new();
+
+            let result = retry_lookup(&lookup_config(), &clock, || {
+                calls += 1;
+                errors.pop().map_or(Ok(()), Err)
+            });
+
+            assert!(result.is_err());
+            assert_eq!(calls, 1);
+            assert_eq!(clock.elapsed(), Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn detect_key_change() {
+        let loaded = identity(1, b"original");
+
+        for on_change in [KeyChangeAction::Refuse, KeyChangeAction::Warn] {
+            let watch = KeyWatch::new("key_name=validator".to_owned(), loaded.clone(), on_change);
+
+            watch.record(loaded.clone());
+            assert!(!watch.is_changed());
+            assert!(!watch.refuses_signing());
+
+            // Rekeyed under the same name: new kid and public key
+            watch.record(identity(2, b"rekeyed"));
+            assert!(watch.is_changed());
+            assert_eq!(watch.detected_kid(), Uuid::from_u128(2).to_string());
+            assert_eq!(
+                watch.refuses_signing(),
+                on_change == KeyChangeAction::Refuse
+            );
+
+            // Only a restart acknowledges the change
+            watch.record(loaded.clone());
+            assert!(watch.is_changed());
+        }
+
+        // A different public key behind the same kid is a change too
+        let watch = KeyWatch::new(
+            "key_id=1".to_owned(),
+            loaded.clone(),
+            KeyChangeAction::Refuse,
+        );
+        watch.record(identity(1, b"replaced"));
+        assert!(watch.refuses_signing());
+    }
+
+    #[test]
+    fn refetch_malformed_signature_once() {
+        let valid = [0x42u8; 64];
+        let mut responses = vec![valid.to_vec(), valid[..63].to_vec()];
+
+        let signature = parse_with_refetch(
+            || Ok(responses.pop().unwrap()),
+            ed25519::Signature::from_slice,
+        )
+        .unwrap();
+
+        assert_eq!(signature.to_bytes(), valid);
+        assert!(responses.is_empty());
+    }
+
+    #[test]
+    fn reject_repeated_malformed_signatures() {
+        let mut fetches = 0;
+
+        let result = parse_with_refetch(
+            || {
+                fetches += 1;
+                Ok(vec![0u8; 63])
+            },
+            ed25519::Signature::from_slice,
+        );
+
+        assert!(result.is_err());
+        assert_eq!(fetches, 2);
+    }
+
+    #[test]
+    fn distinguish_lookup_errors() {
+        let lookup_error =
+            |e| map_lookup_error("https://dsm.example.com", "key_name=validator", e).to_string();
+
+        let forbidden = lookup_error(SdkmsError::Forbidden("access denied".to_owned()));
+        assert!(forbidden.contains("lacks permission to use security object key_name=validator"));
+        assert!(forbidden.contains("https://dsm.example.com"));
+        assert!(forbidden.contains("group's policy"));
+
+        let not_found = lookup_error(SdkmsError::NotFound("sobject not found".to_owned()));
+        assert!(not_found.contains("security object key_name=validator not found"));
+        assert!(not_found.contains("https://dsm.example.com"));
+
+        let unauthorized = lookup_error(SdkmsError::Unauthorized("invalid api key".to_owned()));
+        assert!(unauthorized.contains("rejected the API key"));
+
+        let other = lookup_error(SdkmsError::Locked("locked".to_owned()));
+        assert!(other.contains("failed to get security object key_name=validator"));
+    }
+
+    #[test]
+    fn call_out_missing_sign_permission() {
+        let forbidden = map_sign_error(
+            "key_name=validator",
+            SdkmsError::Forbidden("operation not allowed".to_owned()),
+        );
+        assert!(format!("{:?}", forbidden).contains("permitted operations include `Sign`"));
+    }
+}
@@ -2,6 +2,8 @@
 //!
 //! This is mainly intended for testing/CI. Ideally real validators will use HSMs.
 
+pub mod encryption;
+
 use crate::{
     chain,
     config::provider::{
@@ -10,12 +12,14 @@ use crate::{
     },
     error::{Error, ErrorKind::*},
     key_utils,
-    keyring::{self, ed25519, SigningProvider},
+    keyring::{self, ed25519, KeyPolicy, SigningProvider},
     prelude::*,
 };
 use k256::ecdsa;
+use std::fs;
 use tendermint::{PrivateKey, TendermintKey};
 use tendermint_config::PrivValidatorKey;
+use zeroize::Zeroizing;
 
 /// Create software-backed Ed25519 signer objects from the given configuration
 pub fn init(chain_registry: &mut chain::Registry, configs: &[SoftsignConfig]) -> Result<(), Error> {
@@ -36,12 +40,16 @@ pub fn init(chain_registry: &mut chain::Registry, configs: &[SoftsignConfig]) ->
 
                 let account_pubkey = TendermintKey::AccountKey(public_key);
 
-                let signer = keyring::ecdsa::Signer::new(
+                let mut signer = keyring::ecdsa::Signer::new(
                     SigningProvider::SoftSign,
                     account_pubkey,
                     Box::new(signer),
                 );
 
+                if let Some(policy) = &config.policy {
+                    signer = signer.with_policy(KeyPolicy::new(&config.chain_ids, policy));
+                }
+
                 for chain_id in &config.chain_ids {
                     chain_registry.add_account_key(chain_id, signer.clone())?;
                 }
@@ -60,12 +68,16 @@ pub fn init(chain_registry: &mut chain::Registry, configs: &[SoftsignConfig]) ->
                 let consensus_pubkey =
                     TendermintKey::ConsensusKey(signing_key.verifying_key().into());
 
-                let signer = ed25519::Signer::new(
+                let mut signer = ed25519::Signer::new(
                     SigningProvider::SoftSign,
                     consensus_pubkey,
                     Box::new(signing_key),
                 );
 
+                if let Some(policy) = &config.policy {
+                    signer = signer.with_policy(KeyPolicy::new(&config.chain_ids, policy));
+                }
+
                 for chain_id in &config.chain_ids {
                     chain_registry.add_consensus_key(chain_id, signer.clone())?;
                 }
@@ -76,14 +88,29 @@ pub fn init(chain_registry: &mut chain::Registry, configs: &[SoftsignConfig]) ->
     Ok(())
 }
 
+/// Ensure the key described by the given configuration can be loaded as the
+/// expected key type, without registering it
+pub fn check(config: &SoftsignConfig) -> Result<(), Error> {
+    match config.key_type {
+        KeyType::Account => load_secp256k1_key(config).map(|_| ()),
+        KeyType::Consensus => load_ed25519_key(config).map(|_| ()),
+    }
+}
+
 /// Load an Ed25519 key according to the provided configuration
 fn load_ed25519_key(config: &SoftsignConfig) -> Result<ed25519::SigningKey, Error> {
     let key_format = config.key_format.as_ref().cloned().unwrap_or_default();
+    let key_file = read_key_file(config)?;
 
     match key_format {
-        KeyFormat::Base64 => key_utils::load_base64_ed25519_key(&config.path),
+        KeyFormat::Base64 => {
+            let key_bytes = key_utils::decode_base64_secret(&key_file, config.path.as_ref())?;
+
+            Ok(ed25519::SigningKey::try_from(key_bytes.as_ref())
+                .map_err(|e| format_err!(InvalidKey, "invalid Ed25519 key: {}", e))?)
+        }
         KeyFormat::Json => {
-            let private_key = PrivValidatorKey::load_json_file(&config.path)
+            let private_key = PrivValidatorKey::parse_json(key_file.as_str())
                 .map_err(|e| {
                     format_err!(
                         ConfigError,
@@ -112,7 +139,8 @@ fn load_secp256k1_key(config: &SoftsignConfig) -> Result<ecdsa::SigningKey, Erro
         );
     }
 
-    let key_bytes = key_utils::load_base64_secret(&config.path)?;
+    let key_file = read_key_file(config)?;
+    let key_bytes = key_utils::decode_base64_secret(&key_file, config.path.as_ref())?;
 
     let secret_key = ecdsa::SigningKey::try_from(key_bytes.as_slice()).map_err(|e| {
         format_err!(
@@ -125,3 +153,17 @@ fn load_secp256k1_key(config: &SoftsignConfig) -> Result<ecdsa::SigningKey, Erro
 
     Ok(secret_key)
 }
+
+/// Read the contents of the configured key file, decrypting it if necessary
+fn read_key_file(config: &SoftsignConfig) -> Result<Zeroizing<String>, Error> {
+    let path = config.path.as_ref();
+
+    match &config.encryption {
+        Some(encryption) => encryption::decrypt(path, encryption).map_err(|e| {
+            format_err!(ConfigError, "couldn't decrypt {}: {}", path.display(), e).into()
+        }),
+        None => fs::read_to_string(path).map(Zeroizing::new).map_err(|e| {
+            format_err!(IoError, "couldn't read key from {}: {}", path.display(), e).into()
+        }),
+    }
+}
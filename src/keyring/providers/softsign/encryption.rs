@@ -0,0 +1,96 @@
+//! Encrypted softsign key files
+//!
+//! Rather than implementing the file formats ourselves, keys are encrypted and
+//! decrypted by running `age` or `gpg`, which must be on the `PATH`. Plaintext
+//! keys are passed over pipes and never touch the disk.
+
+use crate::{
+    config::provider::softsign::KeyEncryption,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::{
+    env,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+use zeroize::Zeroizing;
+
+/// Decrypt the key file at the given path, returning its plaintext contents
+pub fn decrypt(path: &Path, encryption: &KeyEncryption) -> Result<Zeroizing<String>, Error> {
+    let (mut command, stdin) = match encryption {
+        KeyEncryption::Age { identity } => {
+            let mut command = Command::new("age");
+            command.arg("--decrypt").arg("--identity").arg(identity);
+            (command, None)
+        }
+        KeyEncryption::Gpg { passphrase_env } => {
+            let mut command = Command::new("gpg");
+            command.args(["--batch", "--quiet"]);
+
+            let passphrase = match passphrase_env {
+                Some(var) => {
+                    command.args(["--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+                    command.arg("--no-symkey-cache");
+                    Some(passphrase(var)?)
+                }
+                None => None,
+            };
+
+            command.arg("--decrypt");
+            (command, passphrase)
+        }
+    };
+
+    command.arg(path);
+
+    let plaintext = run(command, stdin.as_ref().map(|p| p.as_bytes()))?;
+
+    String::from_utf8(plaintext.to_vec())
+        .map(Zeroizing::new)
+        .map_err(|_| format_err!(InvalidKey, "decrypted {} isn't UTF-8", path.display()).into())
+}
+
+/// Read a passphrase from the given environment variable
+pub fn passphrase(var: &str) -> Result<Zeroizing<String>, Error> {
+    env::var(var)
+        .map(Zeroizing::new)
+        .map_err(|_| format_err!(ConfigError, "passphrase variable {} is not set", var).into())
+}
+
+/// Run an encryption tool, feeding it the given data on standard input and
+/// returning its standard output
+pub fn run(mut command: Command, stdin: Option<&[u8]>) -> Result<Zeroizing<Vec<u8>>, Error> {
+    let program = command.get_program().to_string_lossy().into_owned();
+
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format_err!(ConfigError, "couldn't run `{}`: {}", program, e))?;
+
+    if let Some(data) = stdin {
+        // Dropping the handle closes the pipe, signaling end of input
+        child.stdin.take().unwrap().write_all(data)?;
+    }
+
+    let output = child.wait_with_output()?;
+    let stdout = Zeroizing::new(output.stdout);
+
+    ensure!(
+        output.status.success(),
+        InvalidKey,
+        "`{}` failed ({}): {}",
+        program,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    Ok(stdout)
+}
@@ -1,17 +1,44 @@
 //! YubiHSM2 signing provider
+//!
+//! The YubiHSM client transparently reconnects (opening a new session) when
+//! its session fails or times out. Since that connection could be to a
+//! different device than the one whose keys were loaded at startup, the
+//! first signature with a consensus key after each reconnect is preceded by
+//! fetching its public key again: if it differs, `on_pubkey_change` decides
+//! whether to refuse signing with it until restart or exit (see
+//! [`PubkeyWatch`]).
 
 use crate::{
     chain,
     config::provider::{
-        yubihsm::{SigningKeyConfig, YubihsmConfig},
+        yubihsm::{PubkeyChangeAction, SigningKeyConfig, YubihsmConfig},
         KeyType,
     },
     error::{Error, ErrorKind::*},
-    keyring::{self, SigningProvider},
+    keyring::{self, KeyPolicy, SigningProvider},
     prelude::*,
 };
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use subtle_encoding::hex;
 use tendermint::TendermintKey;
 
+/// Exit code used when exiting because a key's public key changed
+pub const EXIT_CODE: i32 = 5;
+
+/// Inactivity after which the YubiHSM closes a session, so that the client
+/// opens a new one (i.e. reconnects) on next use
+const SESSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Public key watches of all loaded consensus keys
+static PUBKEY_WATCHES: Mutex<Vec<Arc<PubkeyWatch>>> = Mutex::new(Vec::new());
+
 /// Create hardware-backed YubiHSM signer objects from the given configuration
 pub fn init(
     chain_registry: &mut chain::Registry,
@@ -30,10 +57,12 @@ pub fn init(
         );
     }
 
+    let on_pubkey_change = yubihsm_configs[0].on_pubkey_change.unwrap_or_default();
+
     for config in &yubihsm_configs[0].keys {
         match config.key_type {
             KeyType::Account => add_account_key(chain_registry, config)?,
-            KeyType::Consensus => add_consensus_key(chain_registry, config)?,
+            KeyType::Consensus => add_consensus_key(chain_registry, config, on_pubkey_change)?,
         }
     }
 
@@ -58,12 +87,16 @@ fn add_account_key(
         tendermint::PublicKey::from_raw_secp256k1(signer.public_key().compress().as_bytes())
             .expect("invalid secp256k1 key");
 
-    let signer = keyring::ecdsa::Signer::new(
+    let mut signer = keyring::ecdsa::Signer::new(
         SigningProvider::Yubihsm,
         TendermintKey::AccountKey(public_key),
         Box::new(signer),
     );
 
+    if let Some(policy) = &config.policy {
+        signer = signer.with_policy(KeyPolicy::new(&config.chain_ids, policy));
+    }
+
     for chain_id in &config.chain_ids {
         chain_registry.add_account_key(chain_id, signer.clone())?;
     }
@@ -75,28 +108,233 @@ fn add_account_key(
 fn add_consensus_key(
     chain_registry: &mut chain::Registry,
     config: &SigningKeyConfig,
+    on_pubkey_change: PubkeyChangeAction,
 ) -> Result<(), Error> {
-    let signer = yubihsm::ed25519::Signer::create(crate::yubihsm::client().clone(), config.key)
-        .map_err(|_| {
-            format_err!(
-                InvalidKey,
-                "YubiHSM key ID 0x{:04x} is not a valid Ed25519 signing key",
-                config.key
-            )
-        })?;
+    let client = crate::yubihsm::client().clone();
+    let signer = yubihsm::ed25519::Signer::create(client.clone(), config.key).map_err(|_| {
+        format_err!(
+            InvalidKey,
+            "YubiHSM key ID 0x{:04x} is not a valid Ed25519 signing key",
+            config.key
+        )
+    })?;
 
     let public_key = tendermint::PublicKey::from_raw_ed25519(signer.public_key().as_bytes())
         .expect("invalid Ed25519 key");
 
-    let signer = keyring::ed25519::Signer::new(
+    let watch = Arc::new(PubkeyWatch::new(
+        config.key,
+        *signer.public_key().as_bytes(),
+        on_pubkey_change,
+    ));
+    register_pubkey_watch(watch.clone());
+
+    let mut signer = keyring::ed25519::Signer::new(
         SigningProvider::Yubihsm,
         TendermintKey::ConsensusKey(public_key),
-        Box::new(signer),
+        Box::new(CheckedSigner {
+            signer,
+            client,
+            watch,
+            last_used: Mutex::new(Some(Instant::now())),
+        }),
     );
 
+    if let Some(policy) = &config.policy {
+        signer = signer.with_policy(KeyPolicy::new(&config.chain_ids, policy));
+    }
+
     for chain_id in &config.chain_ids {
         chain_registry.add_consensus_key(chain_id, signer.clone())?;
     }
 
     Ok(())
 }
+
+/// YubiHSM Ed25519 signer which checks the key's public key is unchanged
+/// before signing after a reconnect
+struct CheckedSigner {
+    /// Signer for the key
+    signer: yubihsm::ed25519::Signer,
+
+    /// Client the signer uses
+    client: yubihsm::Client,
+
+    /// Public key loaded at startup, and what to do if it changes
+    watch: Arc<PubkeyWatch>,
+
+    /// When the key was last used successfully, or `None` if its most recent
+    /// use failed (so the client will reconnect)
+    last_used: Mutex<Option<Instant>>,
+}
+
+impl CheckedSigner {
+    /// Might the client have reconnected since the key was last used?
+    fn may_have_reconnected(&self) -> bool {
+        self.last_used
+            .lock()
+            .unwrap()
+            .map(|last_used| last_used.elapsed() >= SESSION_TIMEOUT)
+            .unwrap_or(true)
+    }
+
+    /// Fetch the key's public key from the YubiHSM and record it
+    fn check_pubkey(&self) -> Result<(), signature::Error> {
+        let public_key = self
+            .client
+            .get_public_key(self.watch.key_id)
+            .map_err(signature::Error::from_source)?
+            .ed25519()
+            .ok_or_else(|| {
+                signature::Error::from_source(format!(
+                    "YubiHSM key ID 0x{:04x} is no longer an Ed25519 key",
+                    self.watch.key_id
+                ))
+            })?;
+
+        self.watch.record(*public_key.as_bytes());
+        Ok(())
+    }
+}
+
+impl signature::Signer<keyring::ed25519::Signature> for CheckedSigner {
+    fn try_sign(&self, msg: &[u8]) -> Result<keyring::ed25519::Signature, signature::Error> {
+        let result = if self.may_have_reconnected() {
+            self.check_pubkey()
+        } else {
+            Ok(())
+        }
+        .and_then(|()| {
+            if self.watch.is_changed() {
+                return Err(signature::Error::from_source(format!(
+                    "YubiHSM key ID 0x{:04x} has a different public key than at startup: \
+                     refusing to sign until tmkms is restarted",
+                    self.watch.key_id
+                )));
+            }
+
+            self.signer.try_sign(msg)
+        });
+
+        *self.last_used.lock().unwrap() = result.as_ref().ok().map(|_| Instant::now());
+        result
+    }
+}
+
+/// Public key of a consensus key loaded at startup, compared against the
+/// one the YubiHSM reports after reconnecting.
+///
+/// A different public key means tmkms is now talking to a different device
+/// (or the key was replaced), and signing would silently change the
+/// validator's identity. `refuse` is the safe default: tmkms keeps running
+/// (and reporting the change via logs and metrics) without ever signing with
+/// the new key, whereas exiting under a supervisor which restarts tmkms would
+/// load the new key and start signing with it.
+pub struct PubkeyWatch {
+    /// ID of the key in the YubiHSM
+    key_id: u16,
+
+    /// What to do once a change is detected
+    on_change: PubkeyChangeAction,
+
+    /// Public key when the key was loaded
+    loaded: [u8; 32],
+
+    /// Public key at the most recent check
+    detected: Mutex<[u8; 32]>,
+
+    /// Has the public key changed since it was loaded? Stays set until
+    /// restart, even if the original key comes back.
+    changed: AtomicBool,
+}
+
+impl PubkeyWatch {
+    fn new(key_id: u16, loaded: [u8; 32], on_change: PubkeyChangeAction) -> Self {
+        Self {
+            key_id,
+            on_change,
+            loaded,
+            detected: Mutex::new(loaded),
+            changed: AtomicBool::new(false),
+        }
+    }
+
+    /// Record the public key reported by the YubiHSM
+    fn record(&self, detected: [u8; 32]) {
+        *self.detected.lock().unwrap() = detected;
+
+        if detected == self.loaded || self.changed.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        error!(
+            "[keyring:yubihsm] *** key ID 0x{:04x} changed after reconnecting \
+             (public key {} -> {}): {} ***",
+            self.key_id,
+            hex_upper(&self.loaded),
+            hex_upper(&detected),
+            match self.on_change {
+                PubkeyChangeAction::Refuse => "refusing to sign until tmkms is restarted",
+                PubkeyChangeAction::WarnAndStop => "shutting down",
+            }
+        );
+
+        if self.on_change == PubkeyChangeAction::WarnAndStop {
+            process::exit(EXIT_CODE);
+        }
+    }
+
+    /// Has the public key changed since it was loaded?
+    pub fn is_changed(&self) -> bool {
+        self.changed.load(Ordering::SeqCst)
+    }
+
+    /// ID of the key in the YubiHSM, e.g. `0x0001`
+    pub fn key_id(&self) -> String {
+        format!("0x{:04x}", self.key_id)
+    }
+
+    /// Hex-encoded public key at the most recent check
+    pub fn detected_pubkey(&self) -> String {
+        hex_upper(&self.detected.lock().unwrap())
+    }
+}
+
+/// Uppercase hex encoding of a public key
+fn hex_upper(public_key: &[u8; 32]) -> String {
+    String::from_utf8(hex::encode_upper(public_key)).unwrap()
+}
+
+/// Register a public key watch, replacing any from an earlier attempt to
+/// load the same key
+fn register_pubkey_watch(watch: Arc<PubkeyWatch>) {
+    let mut watches = PUBKEY_WATCHES.lock().unwrap();
+    watches.retain(|other| other.key_id != watch.key_id);
+    watches.push(watch);
+}
+
+/// Public key watches of all loaded consensus keys
+pub fn pubkey_watches() -> Vec<Arc<PubkeyWatch>> {
+    PUBKEY_WATCHES.lock().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuse_after_pubkey_change() {
+        let watch = PubkeyWatch::new(1, [1; 32], PubkeyChangeAction::Refuse);
+
+        watch.record([1; 32]);
+        assert!(!watch.is_changed());
+
+        watch.record([2; 32]);
+        assert!(watch.is_changed());
+        assert_eq!(watch.detected_pubkey(), "02".repeat(32));
+
+        // Stays changed even if the original key comes back
+        watch.record([1; 32]);
+        assert!(watch.is_changed());
+    }
+}
@@ -3,6 +3,24 @@
 pub use super::ed25519;
 pub use k256::ecdsa;
 
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+
+/// Order of the Ed25519 base point (`2^252 + 27742317777372353535851937790883648493`),
+/// little endian
+const ED25519_ORDER: [u8; 32] = [
+    0xed, 0xd3, 0xf5, 0x5c, 0x1a, 0x63, 0x12, 0x58, 0xd6, 0x9c, 0xf7, 0xa2, 0xde, 0xf9, 0xde, 0x14,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10,
+];
+
+/// Field modulus of Curve25519 (`2^255 - 19`), little endian
+const CURVE25519_MODULUS: [u8; 32] = [
+    0xed, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f,
+];
+
 /// Cryptographic signature used for block signing
 pub enum Signature {
     /// ECDSA signature (e.g secp256k1)
@@ -20,6 +38,53 @@ impl Signature {
             Self::Ed25519(sig) => sig.to_vec(),
         }
     }
+
+    /// Ensure this signature is in canonical form, i.e. that it's the only
+    /// encoding of itself a verifier would accept.
+    ///
+    /// Signatures are checked after they're returned by a signing provider
+    /// (and post-processed) so a malleable signature is never released,
+    /// regardless of which provider produced it:
+    ///
+    /// - ECDSA: `s` must be in the lower half of the curve order ("low S").
+    ///   Out-of-range `r` and `s` values are already unrepresentable.
+    /// - Ed25519: `S` must be reduced modulo the group order, and `R` must be
+    ///   a canonical point encoding (i.e. its y-coordinate reduced modulo the
+    ///   field prime).
+    pub fn check_canonical(&self) -> Result<(), Error> {
+        match self {
+            Self::Ecdsa(sig) => {
+                ensure!(
+                    sig.normalize_s().is_none(),
+                    SigningError,
+                    "provider returned a malleable (high S) ECDSA signature"
+                );
+            }
+            Self::Ed25519(sig) => {
+                let mut r = *sig.r_bytes();
+                r[31] &= 0x7f;
+
+                ensure!(
+                    less_than_le(sig.s_bytes(), &ED25519_ORDER),
+                    SigningError,
+                    "provider returned a non-canonical Ed25519 signature (unreduced S)"
+                );
+
+                ensure!(
+                    less_than_le(&r, &CURVE25519_MODULUS),
+                    SigningError,
+                    "provider returned a non-canonical Ed25519 signature (unreduced R)"
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Is the little endian integer `a` less than `b`?
+fn less_than_le(a: &[u8; 32], b: &[u8; 32]) -> bool {
+    a.iter().rev().lt(b.iter().rev())
 }
 
 impl From<ecdsa::Signature> for Signature {
@@ -39,3 +104,68 @@ impl From<Signature> for tendermint::Signature {
         sig.to_vec().try_into().expect("signature should be valid")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k256::elliptic_curve::ops::Neg;
+    use signature::Signer;
+
+    /// Add the Ed25519 group order to a little endian scalar
+    fn add_order(s: &[u8; 32]) -> [u8; 32] {
+        let mut sum = [0u8; 32];
+        let mut carry = 0u16;
+
+        for i in 0..32 {
+            let digit = s[i] as u16 + ED25519_ORDER[i] as u16 + carry;
+            sum[i] = digit as u8;
+            carry = digit >> 8;
+        }
+
+        sum
+    }
+
+    #[test]
+    fn accept_canonical_signatures() {
+        let ecdsa_key = ecdsa::SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let ecdsa_sig: ecdsa::Signature = ecdsa_key.sign(b"test message");
+        Signature::Ecdsa(ecdsa_sig).check_canonical().unwrap();
+
+        let ed25519_key = ed25519::SigningKey::from([0x42; 32]);
+        let ed25519_sig: ed25519::Signature = ed25519_key.sign(b"test message");
+        Signature::Ed25519(ed25519_sig).check_canonical().unwrap();
+    }
+
+    #[test]
+    fn reject_high_s_ecdsa() {
+        let signing_key = ecdsa::SigningKey::from_slice(&[0x42; 32]).unwrap();
+        let sig: ecdsa::Signature = signing_key.sign(b"test message");
+        let (r, s) = sig.split_scalars();
+        let high_s = ecdsa::Signature::from_scalars(r, s.neg()).unwrap();
+
+        let err = Signature::Ecdsa(high_s).check_canonical().unwrap_err();
+        assert_eq!(*err.kind(), SigningError);
+    }
+
+    #[test]
+    fn reject_non_canonical_ed25519() {
+        let signing_key = ed25519::SigningKey::from([0x42; 32]);
+        let sig: ed25519::Signature = signing_key.sign(b"test message");
+
+        // `S + L` verifies under lax implementations but isn't canonical
+        let unreduced_s =
+            ed25519::Signature::from_components(*sig.r_bytes(), add_order(sig.s_bytes()));
+        assert!(Signature::Ed25519(unreduced_s).check_canonical().is_err());
+
+        // `y = p` is a non-canonical encoding of `y = 0`
+        let unreduced_r = ed25519::Signature::from_components(CURVE25519_MODULUS, *sig.s_bytes());
+        assert!(Signature::Ed25519(unreduced_r).check_canonical().is_err());
+
+        // ...as is `y = p + 1` with the sign bit set
+        let mut r = CURVE25519_MODULUS;
+        r[0] += 1;
+        r[31] |= 0x80;
+        let unreduced_r = ed25519::Signature::from_components(r, *sig.s_bytes());
+        assert!(Signature::Ed25519(unreduced_r).check_canonical().is_err());
+    }
+}
@@ -1,4 +1,33 @@
 //! Tendermint Key Management System
+//!
+//! ## Embedding
+//!
+//! Besides the `tmkms` binary, this crate can be used as a library to run the
+//! KMS inside another process (e.g. a custom supervisor). The supported
+//! entry points are:
+//!
+//! - [`config::KmsConfig`]: the parsed `tmkms.toml`, which can be loaded with
+//!   [`abscissa_core::Config::load_toml`] or constructed programmatically.
+//! - [`client::start`]: loads chains and signing providers into the global
+//!   [`chain::REGISTRY`] and spawns a [`client::Client`] per validator.
+//! - [`client::Client::join`]: waits for a client to exit.
+//! - [`session::Session`]: a single validator connection, for callers which
+//!   want to manage reconnects themselves.
+//!
+//! ```no_run
+//! use abscissa_core::Config;
+//! use tmkms::{client, config::KmsConfig};
+//!
+//! let toml = std::fs::read_to_string("tmkms.toml").unwrap();
+//! let config = KmsConfig::load_toml(toml).unwrap();
+//!
+//! for client in client::start(&config).unwrap() {
+//!     client.join().unwrap();
+//! }
+//! ```
+//!
+//! These entry points follow semantic versioning. Other public items are
+//! exposed for the `tmkms` binary and may change between minor releases.
 
 #![deny(unsafe_code)]
 #![warn(missing_docs, rust_2018_idioms, unused_qualifications)]
@@ -15,18 +44,27 @@ compile_error!(
 );
 
 pub mod application;
+pub mod backoff;
 pub mod chain;
 pub mod client;
+pub mod clock;
 pub mod commands;
 pub mod config;
 pub mod connection;
+pub mod control;
 pub mod error;
+pub mod events;
 pub mod key_utils;
 pub mod keyring;
+pub mod maintenance;
+pub mod metrics;
 pub mod prelude;
 pub mod privval;
+pub mod resources;
 pub mod rpc;
 pub mod session;
+pub mod status;
+pub mod watchdog;
 
 #[cfg(feature = "yubihsm")]
 pub mod yubihsm;
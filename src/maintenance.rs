@@ -0,0 +1,203 @@
+//! Maintenance mode: pausing signing on demand
+//!
+//! Maintenance mode is entered and left with the `pause` and `resume`
+//! commands on the control socket (see [`crate::control`]), e.g. as sent by
+//! `tmkms maintenance pause`. While paused, every sign request is refused
+//! with a [`MAINTENANCE_ERROR`] response before it reaches the double-signing
+//! guard, but the process, its validator connections, and the state files
+//! are left intact. Signing only resumes on an explicit `resume`.
+//!
+//! Maintenance mode survives restarts: pausing writes a marker file next to
+//! each chain's state file (see [`marker_path`]), and a KMS which finds one at
+//! startup comes up paused. `resume` removes them.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Error code reported to validators for requests refused in maintenance mode
+pub const MAINTENANCE_ERROR: i32 = 4;
+
+/// When maintenance mode was entered, if signing is paused
+static PAUSED_SINCE: Mutex<Option<DateTime<Utc>>> = Mutex::new(None);
+
+/// Marker files recording that signing is paused
+static MARKER_PATHS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Path of the marker file recording maintenance mode next to the given
+/// state file, e.g. `cosmoshub-4_priv_validator_state.maintenance`
+pub fn marker_path(state_file: &Path) -> PathBuf {
+    state_file.with_extension("maintenance")
+}
+
+/// Record maintenance mode next to the given state files, entering it if any
+/// of them already records it (i.e. signing was paused before a restart)
+pub fn init(state_files: impl IntoIterator<Item = PathBuf>) -> Result<(), Error> {
+    let marker_paths = state_files
+        .into_iter()
+        .map(|state_file| marker_path(&state_file))
+        .collect::<Vec<_>>();
+
+    let mut restored = None;
+
+    for path in &marker_paths {
+        match fs::read_to_string(path) {
+            Ok(contents) => {
+                let since = DateTime::parse_from_rfc3339(contents.trim())
+                    .map(|since| since.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now());
+
+                restored = restored.or(Some((since, path)));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => fail!(IoError, "{}: {}", path.display(), e),
+        }
+    }
+
+    if let Some((since, path)) = restored {
+        warn!(
+            "*** maintenance mode entered at {} is still in effect ({}): refusing all sign requests until resumed ***",
+            since.to_rfc3339_opts(SecondsFormat::Secs, true),
+            path.display()
+        );
+
+        *PAUSED_SINCE.lock().unwrap() = Some(since);
+    }
+
+    *MARKER_PATHS.lock().unwrap() = marker_paths;
+    Ok(())
+}
+
+/// Is signing paused for maintenance?
+pub fn is_paused() -> bool {
+    paused_since().is_some()
+}
+
+/// When maintenance mode was entered, if signing is paused
+pub fn paused_since() -> Option<DateTime<Utc>> {
+    *PAUSED_SINCE.lock().unwrap()
+}
+
+/// Enter maintenance mode (if not already in it), returning the status line.
+///
+/// Signing is paused even if recording it fails, in which case an error says
+/// a restart would resume signing.
+pub fn pause() -> Result<String, Error> {
+    let mut paused_since = PAUSED_SINCE.lock().unwrap();
+
+    if paused_since.is_none() {
+        warn!("*** maintenance mode entered: refusing all sign requests until resumed ***");
+        *paused_since = Some(Utc::now());
+    }
+
+    let since = paused_since.expect("just paused");
+
+    for path in MARKER_PATHS.lock().unwrap().iter() {
+        write_marker(path, since).map_err(|e| {
+            format_err!(
+                IoError,
+                "paused, but couldn't record it in {} (a restart would resume signing): {}",
+                path.display(),
+                e
+            )
+        })?;
+    }
+
+    Ok(describe(Some(since)))
+}
+
+/// Leave maintenance mode (if in it), returning the status line.
+///
+/// Signing stays paused if a marker file can't be removed, so a restart
+/// never pauses it again unexpectedly.
+pub fn resume() -> Result<String, Error> {
+    let mut paused_since = PAUSED_SINCE.lock().unwrap();
+
+    for path in MARKER_PATHS.lock().unwrap().iter() {
+        match fs::remove_file(path) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => fail!(
+                IoError,
+                "still paused: couldn't remove {}: {}",
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    if paused_since.take().is_some() {
+        info!("maintenance mode left: signing resumed");
+    }
+
+    Ok(describe(None))
+}
+
+/// Durably record when maintenance mode was entered in the given marker file
+fn write_marker(path: &Path, since: DateTime<Utc>) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", since.to_rfc3339_opts(SecondsFormat::Secs, true))?;
+    file.sync_all()
+}
+
+/// Status line describing the current mode
+pub fn status() -> String {
+    describe(paused_since())
+}
+
+/// Describe the mode given when it was entered (if paused)
+fn describe(paused_since: Option<DateTime<Utc>>) -> String {
+    match paused_since {
+        Some(since) => format!(
+            "paused since {}",
+            since.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ),
+        None => "signing".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_resume() {
+        assert!(!is_paused());
+        assert_eq!(status(), "signing");
+
+        assert!(pause().unwrap().starts_with("paused since "));
+        assert!(is_paused());
+
+        // Pausing again keeps the original time
+        let since = paused_since();
+        pause().unwrap();
+        assert_eq!(paused_since(), since);
+
+        assert_eq!(resume().unwrap(), "signing");
+        assert!(!is_paused());
+
+        // Paused across a restart
+        let dir = tempfile::tempdir().unwrap();
+        let state_file = dir.path().join("test_priv_validator_state.json");
+        init([state_file.clone()]).unwrap();
+        pause().unwrap();
+        assert!(marker_path(&state_file).exists());
+
+        *PAUSED_SINCE.lock().unwrap() = None;
+        init([state_file.clone()]).unwrap();
+        assert!(is_paused());
+
+        resume().unwrap();
+        assert!(!marker_path(&state_file).exists());
+        init([state_file]).unwrap();
+        assert!(!is_paused());
+    }
+}
@@ -0,0 +1,560 @@
+//! Prometheus metrics exporter
+//!
+//! When `metrics_addr` is set in `tmkms.toml`, the KMS serves metrics in the
+//! Prometheus text exposition format at `http://<metrics_addr>/metrics`:
+//!
+//! - `tmkms_last_signed_height`, `tmkms_last_signed_round`, and
+//!   `tmkms_last_signed_step`: the height/round/step recorded in each chain's
+//!   state file
+//! - `tmkms_last_sign_timestamp_seconds`: Unix time a signature for the chain
+//!   was last sent to a validator (absent until one has been since startup)
+//! - `tmkms_signer_quiet`: 1 if the chain's sign watchdog considers this
+//!   signer quiet, otherwise 0 (only for chains with `sign_watchdog`
+//!   configured, see [`crate::chain::quiet`])
+//! - `tmkms_consecutive_guard_rejections`: number of consecutive requests for
+//!   the same height/round/step the double-signing guard has rejected (see
+//!   [`crate::chain::rejections`])
+//! - `tmkms_maintenance_mode`: 1 while signing is paused for maintenance,
+//!   otherwise 0 (unlabeled, see [`crate::maintenance`])
+//! - `tmkms_policy_violations_total`: sign requests refused by a signing key's
+//!   usage policy, also labeled with the violated `policy` (`chain_id`,
+//!   `message_type`, or `rate_limit`, see [`crate::keyring::policy`])
+//! - `tmkms_height_bound_rejections_total`: sign requests refused for being
+//!   below the chain's `min_height` (or a height since signed) or above its
+//!   `max_height`, also labeled with the `bound` (`min` or `max`, see
+//!   [`crate::chain::bounds`])
+//! - `tmkms_approval_hook_total`: runs of the chain's `approval_hook`, also
+//!   labeled with the `outcome` (`approved`, `denied`, `timed_out`, or
+//!   `failed`, see [`crate::chain::approval`])
+//! - `tmkms_signing_errors_total`: sign requests failed by a signing key's
+//!   usage policy or its provider, also labeled with the error `class`
+//!   (`auth`, `connectivity`, `timeout`, `backend_sealed`, `bad_key`,
+//!   `malformed_response`, `policy`, or `other`, see
+//!   [`crate::error::ErrorClass`])
+//! - `tmkms_validator_connected`: 1 while a session with the validator is
+//!   open, otherwise 0, and `tmkms_validator_reconnects_total`: sessions
+//!   opened after the first
+//! - `tmkms_validator_received_bytes_total` and
+//!   `tmkms_validator_sent_bytes_total`: privval traffic with the validator
+//!   (within the secret connection, i.e. excluding its framing and overhead)
+//! - `tmkms_validator_handshake_seconds`: time the most recent session took
+//!   to connect and complete its handshake
+//! - `tmkms_validator_last_received_seconds`: time since data was last
+//!   received from the validator (absent until some has been)
+//!
+//!   The `tmkms_validator_*` metrics are also labeled with the validator's
+//!   `addr` and the connection `mode` (`tcp`, `tls`, or `unix`, see
+//!   [`crate::connection::stats`])
+//! - `tmkms_fortanixdsm_key_changed`: 1 once a Fortanix DSM key with
+//!   `key_check_interval_secs` configured has been found to differ from the
+//!   one loaded at startup, otherwise 0 (labeled with the configured `key`
+//!   and the `kid` seen at the most recent check, rather than `chain_id`)
+//! - `tmkms_yubihsm_pubkey_changed`: 1 once a YubiHSM consensus key has
+//!   reported a different public key after reconnecting than the one loaded
+//!   at startup, otherwise 0 (labeled with the `key_id` and the hex `pubkey`
+//!   seen at the most recent check, rather than `chain_id`)
+//! - `tmkms_monitor_heights_total`: heights `tmkms monitor` signed a
+//!   precommit for and checked against the chain, also labeled with the
+//!   `outcome` (`included`, or `missing` from the block's commit), see
+//!   [`crate::chain::monitor`] (only served by `tmkms monitor --metrics-addr`)
+//!
+//! All other metrics are labeled with `chain_id`. Values are read from the
+//! double-signing guard's live state when scraped, under the same lock which
+//! serializes sign requests, so a scrape never observes a partially applied
+//! update.
+
+use crate::{
+    chain::{self, approval, bounds, monitor, Chain},
+    connection::stats,
+    error::{Error, ErrorKind::*},
+    keyring::{self, policy},
+    maintenance,
+    prelude::*,
+};
+use std::{
+    fmt::Write as _,
+    io::{BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::PoisonError,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Maximum time spent reading a scrape request or writing its response
+pub const IO_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Start serving metrics on the given address
+pub fn init(addr: &SocketAddr) -> Result<(), Error> {
+    let listener = TcpListener::bind(addr)
+        .map_err(|e| format_err!(ConfigError, "couldn't bind metrics address {}: {}", addr, e))?;
+
+    thread::Builder::new()
+        .name("metrics-exporter".to_owned())
+        .spawn(move || {
+            for stream in listener.incoming() {
+                if let Err(e) = stream.and_then(serve) {
+                    debug!("error serving metrics: {}", e);
+                }
+            }
+        })?;
+
+    info!("serving metrics on http://{}/metrics", addr);
+    Ok(())
+}
+
+/// Answer a single HTTP request
+fn serve(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let response = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => http_response(
+            "200 OK",
+            "text/plain; version=0.0.4",
+            &render(chain::REGISTRY.get().chains()),
+        ),
+        _ => http_response("404 Not Found", "text/plain", "not found\n"),
+    };
+
+    stream.write_all(response.as_bytes())
+}
+
+/// Build an HTTP response with the given status and body
+fn http_response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// Render metrics for the given chains
+pub fn render<'a>(chains: impl Iterator<Item = &'a Chain>) -> String {
+    let mut states = Vec::new();
+    let mut quiet = Vec::new();
+    let mut rejections = Vec::new();
+
+    for chain in chains {
+        let chain_id = chain.id.as_str();
+
+        let consecutive = chain
+            .rejections
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .consecutive();
+        rejections.push((chain_id, consecutive as f64));
+
+        if let Some(sign_watchdog) = &chain.sign_watchdog {
+            quiet.push((chain_id, f64::from(u8::from(sign_watchdog.is_quiet()))));
+        }
+
+        // A poisoned state is unrecoverable and shuts its chain down anyway
+        if let Ok(state) = chain.state.lock() {
+            let released_at = *chain
+                .last_released_at
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            states.push((chain_id, state, released_at));
+        }
+    }
+
+    let mut exposition = state_exposition(
+        states
+            .iter()
+            .map(|(id, state, released_at)| (*id, &**state, *released_at)),
+    );
+
+    exposition.gauges(
+        "tmkms_signer_quiet",
+        "Whether no signature has been sent within the sign watchdog's threshold",
+        quiet,
+    );
+
+    exposition.gauges(
+        "tmkms_consecutive_guard_rejections",
+        "Consecutive same height/round/step requests rejected by the double-signing guard",
+        rejections,
+    );
+
+    exposition.family(
+        "tmkms_maintenance_mode",
+        "Whether signing is paused for maintenance",
+        "gauge",
+    );
+    exposition.sample(
+        "tmkms_maintenance_mode",
+        &[],
+        f64::from(u8::from(maintenance::is_paused())),
+    );
+
+    exposition.family(
+        "tmkms_policy_violations_total",
+        "Sign requests refused by a signing key's usage policy",
+        "counter",
+    );
+
+    for (chain_id, violation, count) in policy::violations() {
+        exposition.sample(
+            "tmkms_policy_violations_total",
+            &[("chain_id", &chain_id), ("policy", &violation.to_string())],
+            count as f64,
+        );
+    }
+
+    exposition.family(
+        "tmkms_height_bound_rejections_total",
+        "Sign requests refused for being outside the chain's min_height/max_height",
+        "counter",
+    );
+
+    for (chain_id, bound, count) in bounds::rejections() {
+        exposition.sample(
+            "tmkms_height_bound_rejections_total",
+            &[("chain_id", &chain_id), ("bound", &bound.to_string())],
+            count as f64,
+        );
+    }
+
+    exposition.family(
+        "tmkms_approval_hook_total",
+        "Runs of the chain's approval hook, by outcome",
+        "counter",
+    );
+
+    for (chain_id, outcome, count) in approval::outcomes() {
+        exposition.sample(
+            "tmkms_approval_hook_total",
+            &[("chain_id", &chain_id), ("outcome", &outcome.to_string())],
+            count as f64,
+        );
+    }
+
+    exposition.family(
+        "tmkms_signing_errors_total",
+        "Sign requests failed by a signing key's usage policy or its provider",
+        "counter",
+    );
+
+    for (chain_id, class, count) in keyring::signing_errors() {
+        exposition.sample(
+            "tmkms_signing_errors_total",
+            &[("chain_id", &chain_id), ("class", &class.to_string())],
+            count as f64,
+        );
+    }
+
+    connection_exposition(&mut exposition);
+
+    let outcomes = monitor::outcomes();
+
+    if !outcomes.is_empty() {
+        exposition.family(
+            "tmkms_monitor_heights_total",
+            "Signed precommit heights checked against the chain by `tmkms monitor`",
+            "counter",
+        );
+    }
+
+    for (chain_id, outcome, count) in outcomes {
+        exposition.sample(
+            "tmkms_monitor_heights_total",
+            &[("chain_id", &chain_id), ("outcome", &outcome.to_string())],
+            count as f64,
+        );
+    }
+
+    #[cfg(feature = "fortanixdsm")]
+    {
+        use crate::keyring::providers::fortanixdsm;
+
+        let watches = fortanixdsm::key_watches();
+
+        if !watches.is_empty() {
+            exposition.family(
+                "tmkms_fortanixdsm_key_changed",
+                "Whether the key in Fortanix DSM differs from the one loaded at startup",
+                "gauge",
+            );
+        }
+
+        for watch in watches {
+            exposition.sample(
+                "tmkms_fortanixdsm_key_changed",
+                &[("key", watch.key()), ("kid", &watch.detected_kid())],
+                f64::from(u8::from(watch.is_changed())),
+            );
+        }
+    }
+
+    #[cfg(feature = "yubihsm")]
+    {
+        use crate::keyring::providers::yubihsm;
+
+        let watches = yubihsm::pubkey_watches();
+
+        if !watches.is_empty() {
+            exposition.family(
+                "tmkms_yubihsm_pubkey_changed",
+                "Whether a YubiHSM key's public key differs from the one loaded at startup",
+                "gauge",
+            );
+        }
+
+        for watch in watches {
+            exposition.sample(
+                "tmkms_yubihsm_pubkey_changed",
+                &[
+                    ("key_id", &watch.key_id()),
+                    ("pubkey", &watch.detected_pubkey()),
+                ],
+                f64::from(u8::from(watch.is_changed())),
+            );
+        }
+    }
+
+    exposition.0
+}
+
+/// Name, help text, type and value (if any) of a validator connection metric
+type ConnectionMetric = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&stats::Stats) -> Option<f64>,
+);
+
+/// Add the validator connection metrics to the given exposition
+fn connection_exposition(exposition: &mut Exposition) {
+    let connections = stats::connections();
+
+    let metrics: [ConnectionMetric; 6] = [
+        (
+            "tmkms_validator_connected",
+            "Whether a session with the validator is open",
+            "gauge",
+            |stats| Some(f64::from(u8::from(stats.is_connected()))),
+        ),
+        (
+            "tmkms_validator_reconnects_total",
+            "Sessions opened with the validator after the first",
+            "counter",
+            |stats| Some(stats.reconnects() as f64),
+        ),
+        (
+            "tmkms_validator_received_bytes_total",
+            "Bytes received from the validator",
+            "counter",
+            |stats| Some(stats.received_bytes() as f64),
+        ),
+        (
+            "tmkms_validator_sent_bytes_total",
+            "Bytes sent to the validator",
+            "counter",
+            |stats| Some(stats.sent_bytes() as f64),
+        ),
+        (
+            "tmkms_validator_handshake_seconds",
+            "Time the most recent session took to connect and complete its handshake",
+            "gauge",
+            |stats| stats.handshake().map(|handshake| handshake.as_secs_f64()),
+        ),
+        (
+            "tmkms_validator_last_received_seconds",
+            "Time since data was last received from the validator",
+            "gauge",
+            |stats| stats.since_last_received().map(|since| since.as_secs_f64()),
+        ),
+    ];
+
+    for (name, help, metric_type, value) in metrics {
+        exposition.family(name, help, metric_type);
+
+        for (chain_id, addr, mode, stats) in &connections {
+            if let Some(value) = value(stats) {
+                exposition.sample(
+                    name,
+                    &[
+                        ("chain_id", chain_id),
+                        ("addr", addr),
+                        ("mode", &mode.to_string()),
+                    ],
+                    value,
+                );
+            }
+        }
+    }
+}
+
+/// Render the double-signing guard metrics for the given chain states, e.g.
+/// to show what a simulated sequence of sign requests would export (nothing
+/// simulated is ever sent, so there's no last sign timestamp)
+pub fn render_states<'a>(states: impl Iterator<Item = (&'a str, &'a chain::State)>) -> String {
+    state_exposition(states.map(|(chain_id, state)| (chain_id, state, None))).0
+}
+
+/// Exposition of the double-signing guard metrics for the given chain states,
+/// along with when a signature for each chain was last sent (if ever)
+fn state_exposition<'a>(
+    states: impl Iterator<Item = (&'a str, &'a chain::State, Option<SystemTime>)>,
+) -> Exposition {
+    let mut exposition = Exposition::default();
+    let mut height = Vec::new();
+    let mut round = Vec::new();
+    let mut step = Vec::new();
+    let mut timestamp = Vec::new();
+
+    for (chain_id, state, released_at) in states {
+        let consensus_state = state.consensus_state();
+
+        height.push((chain_id, consensus_state.height.value() as f64));
+        round.push((chain_id, consensus_state.round.value() as f64));
+        step.push((chain_id, consensus_state.step as f64));
+
+        if let Some(released_at) = released_at {
+            let secs = released_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+
+            timestamp.push((chain_id, secs));
+        }
+    }
+
+    for (name, help, samples) in [
+        (
+            "tmkms_last_signed_height",
+            "Height recorded in the chain's state file",
+            height,
+        ),
+        (
+            "tmkms_last_signed_round",
+            "Round recorded in the chain's state file",
+            round,
+        ),
+        (
+            "tmkms_last_signed_step",
+            "Step recorded in the chain's state file (0 = proposal, 1 = prevote, 2 = precommit)",
+            step,
+        ),
+        (
+            "tmkms_last_sign_timestamp_seconds",
+            "Unix time a signature was last sent to a validator",
+            timestamp,
+        ),
+    ] {
+        exposition.gauges(name, help, samples);
+    }
+
+    exposition
+}
+
+/// Metrics in the Prometheus text exposition format
+#[derive(Default)]
+pub struct Exposition(String);
+
+impl Exposition {
+    /// Begin a metric family with the given name, help text, and type
+    pub fn family(&mut self, name: &str, help: &str, metric_type: &str) {
+        writeln!(self.0, "# HELP {} {}", name, help).unwrap();
+        writeln!(self.0, "# TYPE {} {}", name, metric_type).unwrap();
+    }
+
+    /// Begin a gauge family with one sample per chain
+    pub fn gauges(&mut self, name: &str, help: &str, samples: Vec<(&str, f64)>) {
+        self.family(name, help, "gauge");
+
+        for (chain_id, value) in samples {
+            self.sample(name, &[("chain_id", chain_id)], value);
+        }
+    }
+
+    /// Add a sample with the given labels
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.0.push_str(name);
+
+        if !labels.is_empty() {
+            let labels = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                .collect::<Vec<_>>();
+
+            write!(self.0, "{{{}}}", labels.join(",")).unwrap();
+        }
+
+        writeln!(self.0, " {}", value).unwrap();
+    }
+}
+
+/// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_guard_rejections() {
+        let dir = tempfile::tempdir().unwrap();
+        let config: crate::config::chain::ChainConfig = toml::from_str(&format!(
+            r#"
+            id = "rejections-chain"
+            key_format = {{ type = "hex" }}
+            protocol_version = "v0.34"
+            on_missing_state = "init_zero"
+            state_file = "{}"
+            "#,
+            dir.path().join("state.json").display()
+        ))
+        .unwrap();
+        let chain = Chain::from_config(&config).unwrap();
+
+        let request_state = tendermint::consensus::State {
+            height: 5u32.into(),
+            round: 0u16.into(),
+            step: 1,
+            block_id: None,
+        };
+
+        for _ in 0..3 {
+            chain
+                .rejections
+                .lock()
+                .unwrap()
+                .record_rejection(&request_state, std::time::Instant::now());
+        }
+
+        let metrics = render([&chain].into_iter());
+        assert!(
+            metrics.contains("tmkms_consecutive_guard_rejections{chain_id=\"rejections-chain\"} 3"),
+            "{}",
+            metrics
+        );
+    }
+
+    #[test]
+    fn exposition_format() {
+        let mut exposition = Exposition::default();
+        exposition.family("tmkms_test", "A test metric", "gauge");
+        exposition.sample("tmkms_test", &[("chain_id", "test\"chain")], 42.0);
+        exposition.sample("tmkms_test", &[], 0.5);
+
+        assert_eq!(
+            exposition.0,
+            "# HELP tmkms_test A test metric\n\
+             # TYPE tmkms_test gauge\n\
+             tmkms_test{chain_id=\"test\\\"chain\"} 42\n\
+             tmkms_test 0.5\n"
+        );
+    }
+}
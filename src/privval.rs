@@ -2,6 +2,7 @@
 
 use bytes::{Bytes, BytesMut};
 use prost::{EncodeError, Message as _};
+use serde::{Deserialize, Serialize};
 use tendermint::{block, chain, consensus, vote, Error, Proposal, Vote};
 use tendermint_proto as proto;
 
@@ -20,6 +21,9 @@ const PRECOMMIT_CODE: SignedMsgCode = 0x02;
 /// Code for precommits.
 const PROPOSAL_CODE: SignedMsgCode = 0x20;
 
+/// Maximum length of a vote extension we'll sign.
+pub const MAX_EXTENSION_LEN: usize = 1024 * 1024;
+
 /// Trait for signed messages.
 #[derive(Debug)]
 pub enum SignableMsg {
@@ -47,6 +51,14 @@ impl SignableMsg {
         }
     }
 
+    /// Get the round.
+    pub fn round(&self) -> block::Round {
+        match self {
+            Self::Proposal(proposal) => proposal.round,
+            Self::Vote(vote) => vote.round,
+        }
+    }
+
     /// Get the bytes representing a canonically encoded message over which a
     /// signature is computed over.
     pub fn canonical_bytes(&self, chain_id: chain::Id) -> Result<Bytes, EncodeError> {
@@ -85,6 +97,65 @@ impl SignableMsg {
         Ok(bytes.into())
     }
 
+    /// Ensure this request is well-formed before anything is done with it, in
+    /// particular before the double-signing guard records its height/round/step
+    /// (the request's chain ID is checked when it's decoded, see
+    /// [`Request::decode`](crate::rpc::Request::decode)):
+    ///
+    /// - the height must be positive
+    /// - a block ID, if present, must be complete (i.e. have a part set header)
+    /// - a proposal's POL round must be before its round
+    /// - only precommits for a block may carry a vote extension, of at most
+    ///   [`MAX_EXTENSION_LEN`] bytes
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.height().value() == 0 {
+            return Err(Error::protocol("height must be positive".to_owned()));
+        }
+
+        let block_id = match self {
+            Self::Proposal(proposal) => proposal.block_id,
+            Self::Vote(vote) => vote.block_id,
+        };
+
+        if block_id.is_some_and(|block_id| block_id.part_set_header.total == 0) {
+            return Err(Error::protocol(
+                "block ID has no part set header".to_owned(),
+            ));
+        }
+
+        match self {
+            Self::Proposal(proposal) => {
+                if proposal
+                    .pol_round
+                    .is_some_and(|pol_round| pol_round >= proposal.round)
+                {
+                    return Err(Error::protocol(format!(
+                        "POL round must be before round {}",
+                        proposal.round
+                    )));
+                }
+            }
+            Self::Vote(vote) => {
+                let extendable = vote.vote_type == vote::Type::Precommit && vote.block_id.is_some();
+
+                if !vote.extension.is_empty() && !extendable {
+                    return Err(Error::protocol(
+                        "only precommits for a block may have a vote extension".to_owned(),
+                    ));
+                }
+
+                if vote.extension.len() > MAX_EXTENSION_LEN {
+                    return Err(Error::protocol(format!(
+                        "vote extension is {} bytes (max {MAX_EXTENSION_LEN})",
+                        vote.extension.len()
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     /// Get the bytes representing a vote extension if applicable.
     pub fn extension_bytes(&self, chain_id: chain::Id) -> Result<Option<Bytes>, EncodeError> {
         match self {
@@ -191,10 +262,12 @@ impl TryFrom<proto::types::Vote> for SignableMsg {
 ///
 /// Adapted from:
 /// <https://github.com/cometbft/cometbft/blob/27d2a18/proto/tendermint/types/types.proto#L13>
-#[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
 #[repr(i32)]
 pub enum SignedMsgType {
     /// Unknown message types.
+    #[serde(skip)]
     Unknown = UNKNOWN_CODE,
 
     /// Votes.
@@ -268,7 +341,7 @@ impl TryFrom<SignedMsgCode> for SignedMsgType {
 
 #[cfg(test)]
 mod tests {
-    use super::{chain, proto, SignableMsg, SignedMsgType};
+    use super::{chain, proto, vote, SignableMsg, SignedMsgType};
     use tendermint::{Proposal, Time, Vote};
 
     fn example_chain_id() -> chain::Id {
@@ -339,6 +412,43 @@ mod tests {
         );
     }
 
+    #[test]
+    fn validate() {
+        SignableMsg::from(example_proposal()).validate().unwrap();
+        SignableMsg::from(example_vote()).validate().unwrap();
+    }
+
+    #[test]
+    fn reject_malformed_requests() {
+        // Zero height
+        let mut vote = example_vote();
+        vote.height = 0u32.into();
+        assert!(SignableMsg::from(vote).validate().is_err());
+
+        // Incomplete block ID
+        let mut vote = example_vote();
+        let mut block_id = vote.block_id.unwrap();
+        block_id.part_set_header = Default::default();
+        vote.block_id = Some(block_id);
+        assert!(SignableMsg::from(vote).validate().is_err());
+
+        // Vote extension on a prevote
+        let mut vote = example_vote();
+        vote.extension = b"extension".to_vec();
+        assert!(SignableMsg::from(vote).validate().is_err());
+
+        // Oversized vote extension
+        let mut vote = example_vote();
+        vote.vote_type = vote::Type::Precommit;
+        vote.extension = vec![0; super::MAX_EXTENSION_LEN + 1];
+        assert!(SignableMsg::from(vote).validate().is_err());
+
+        // POL round not before the round
+        let mut proposal = example_proposal();
+        proposal.pol_round = Some(proposal.round);
+        assert!(SignableMsg::from(proposal).validate().is_err());
+    }
+
     #[test]
     fn serialize_canonical_vote() {
         let signable_msg = SignableMsg::from(example_vote());
@@ -358,4 +468,43 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn serialize_canonical_vote_extension() {
+        let mut vote = example_vote();
+        vote.vote_type = vote::Type::Precommit;
+        vote.extension = b"extension".to_vec();
+
+        let signable_msg = SignableMsg::from(vote);
+        let extension_bytes = signable_msg
+            .extension_bytes(example_chain_id())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            extension_bytes.as_ref(),
+            &[
+                0x2c, 0xa, 0x9, 0x65, 0x78, 0x74, 0x65, 0x6e, 0x73, 0x69, 0x6f, 0x6e, 0x11, 0x21,
+                0xa1, 0x7, 0x0, 0x0, 0x0, 0x0, 0x0, 0x19, 0x2, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0, 0x0,
+                0x22, 0xd, 0x74, 0x65, 0x73, 0x74, 0x5f, 0x63, 0x68, 0x61, 0x69, 0x6e, 0x5f, 0x69,
+                0x64
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_msg_types() {
+        let msg_types: Vec<SignedMsgType> =
+            serde_json::from_str(r#"["proposal", "prevote", "precommit"]"#).unwrap();
+
+        assert_eq!(
+            msg_types,
+            [
+                SignedMsgType::Proposal,
+                SignedMsgType::Prevote,
+                SignedMsgType::Precommit
+            ]
+        );
+
+        assert!(serde_json::from_str::<SignedMsgType>(r#""unknown""#).is_err());
+    }
 }
@@ -0,0 +1,174 @@
+//! Startup check of the process's resource limits
+//!
+//! Every validator connection holds a socket (TCP connections hold a second
+//! descriptor for their idle timeout) and every chain opens its state file on
+//! each signature, on top of a fixed number of descriptors and threads for
+//! the rest of the KMS. A configuration serving more chains than the process's
+//! limits allow fails in the middle of operation, e.g. when a state file can't
+//! be opened, so `tmkms start` estimates what it needs up front: it refuses to
+//! start if the configuration clearly can't fit in the limits, and warns when
+//! it's close to them.
+//!
+//! The limits are read from `/proc/self/limits`, so the check is skipped on
+//! platforms other than Linux.
+
+use crate::{
+    config::KmsConfig,
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::fs;
+
+/// Default maximum number of `[[chain]]` entries (see `max_chains`)
+pub const DEFAULT_MAX_CHAINS: usize = 256;
+
+/// File descriptors reserved for everything other than validator connections
+/// and state files (stdio, logging, signing providers, the event socket,
+/// metrics scrapes, node RPC requests)
+pub const RESERVED_FILE_DESCRIPTORS: u64 = 64;
+
+/// Threads reserved for everything other than validator connections and halt
+/// detectors (main thread, event socket, metrics exporter, watchdog)
+pub const RESERVED_THREADS: u64 = 8;
+
+/// Estimated usage of a resource compared to its limit
+#[derive(Debug, Eq, PartialEq)]
+pub enum Usage {
+    /// Comfortably within the limit
+    Ok,
+
+    /// Over half of the limit
+    Close,
+
+    /// Over the limit
+    Exceeded,
+}
+
+impl Usage {
+    /// Compare an estimate to a soft limit (`None` if unlimited)
+    pub fn of(estimate: u64, limit: Option<u64>) -> Self {
+        match limit {
+            Some(limit) if estimate > limit => Usage::Exceeded,
+            Some(limit) if estimate > limit / 2 => Usage::Close,
+            _ => Usage::Ok,
+        }
+    }
+}
+
+/// Check the given configuration against `max_chains` and the process's
+/// resource limits
+pub fn check(config: &KmsConfig) -> Result<(), Error> {
+    let max_chains = config.max_chains.unwrap_or(DEFAULT_MAX_CHAINS);
+
+    ensure!(
+        config.chain.len() <= max_chains,
+        ConfigError,
+        "{} chains configured, more than max_chains = {} (raise it if this is intentional)",
+        config.chain.len(),
+        max_chains
+    );
+
+    let limits = match fs::read_to_string("/proc/self/limits") {
+        Ok(limits) => limits,
+        Err(e) => {
+            debug!("skipping resource limit check: /proc/self/limits: {}", e);
+            return Ok(());
+        }
+    };
+
+    check_limit(
+        "open files",
+        "ulimit -n",
+        estimated_file_descriptors(config),
+        parse_soft_limit(&limits, "Max open files"),
+    )?;
+
+    // Threads count against the per-user process limit
+    check_limit(
+        "processes",
+        "ulimit -u",
+        estimated_threads(config),
+        parse_soft_limit(&limits, "Max processes"),
+    )
+}
+
+/// Estimate the number of file descriptors the given configuration needs
+pub fn estimated_file_descriptors(config: &KmsConfig) -> u64 {
+    RESERVED_FILE_DESCRIPTORS + 2 * config.validator.len() as u64 + 2 * config.chain.len() as u64
+}
+
+/// Estimate the number of threads the given configuration needs
+pub fn estimated_threads(config: &KmsConfig) -> u64 {
+    let halt_detectors = config
+        .chain
+        .iter()
+        .filter(|chain| chain.halt_detection.is_some())
+        .count();
+
+    RESERVED_THREADS + config.validator.len() as u64 + halt_detectors as u64
+}
+
+/// Fail if an estimate exceeds its limit, and warn if it's close
+fn check_limit(
+    resource: &str,
+    command: &str,
+    estimate: u64,
+    limit: Option<u64>,
+) -> Result<(), Error> {
+    match Usage::of(estimate, limit) {
+        Usage::Ok => Ok(()),
+        Usage::Close => {
+            warn!(
+                "configuration needs an estimated {} {}, close to the limit of {} (raise it with `{}`)",
+                estimate,
+                resource,
+                limit.unwrap(),
+                command
+            );
+            Ok(())
+        }
+        Usage::Exceeded => fail!(
+            ConfigError,
+            "configuration needs an estimated {} {}, but the limit is {}: raise it with `{}` \
+             or serve fewer chains from this instance",
+            estimate,
+            resource,
+            limit.unwrap(),
+            command
+        ),
+    }
+}
+
+/// Parse the soft limit on the given line of `/proc/<pid>/limits`, returning
+/// `None` if it's unlimited or missing
+fn parse_soft_limit(limits: &str, name: &str) -> Option<u64> {
+    let line = limits.lines().find(|line| line.starts_with(name))?;
+    line[name.len()..].split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: &str = "\
+Limit                     Soft Limit           Hard Limit           Units
+Max cpu time              unlimited            unlimited            seconds
+Max processes             unlimited            unlimited            processes
+Max open files            1024                 524288               files
+";
+
+    #[test]
+    fn parse_limits() {
+        assert_eq!(parse_soft_limit(LIMITS, "Max open files"), Some(1024));
+        assert_eq!(parse_soft_limit(LIMITS, "Max processes"), None);
+        assert_eq!(parse_soft_limit(LIMITS, "Max locked memory"), None);
+    }
+
+    #[test]
+    fn usage_thresholds() {
+        assert_eq!(Usage::of(100, Some(1024)), Usage::Ok);
+        assert_eq!(Usage::of(600, Some(1024)), Usage::Close);
+        assert_eq!(Usage::of(1025, Some(1024)), Usage::Exceeded);
+        assert_eq!(Usage::of(u64::MAX, None), Usage::Ok);
+    }
+}
@@ -11,6 +11,7 @@ use tendermint_p2p::secret_connection::DATA_MAX_SIZE;
 use tendermint_proto as proto;
 
 use crate::{
+    config::chain::ReplyEncoding,
     error::{Error, ErrorKind},
     prelude::*,
 };
@@ -28,37 +29,33 @@ pub enum Request {
 impl Request {
     /// Read a request from the given readable.
     pub fn read(conn: &mut impl Read, expected_chain_id: &chain::Id) -> Result<Self, Error> {
-        let mut msg_bytes: Vec<u8> = vec![];
-        let msg;
-
-        // fix for Sei: collect incoming bytes of Protobuf from incoming msg
-        loop {
-            let mut msg_chunk = read_msg(conn)?;
-            let chunk_len = msg_chunk.len();
-            msg_bytes.append(&mut msg_chunk);
-
-            // if we can decode it, great, break the loop
-            match proto::privval::Message::decode_length_delimited(msg_bytes.as_ref()) {
-                Ok(m) => {
-                    msg = m.sum;
-                    break;
-                }
-                Err(e) => {
-                    // if chunk_len < DATA_MAX_SIZE (1024) we assume it was the end of the message and it is malformed
-                    if chunk_len < DATA_MAX_SIZE {
-                        return Err(format_err!(
-                            ErrorKind::ProtocolError,
-                            "malformed message packet: {}",
-                            e
-                        )
-                        .into());
-                    }
-                    // otherwise, we go to start of the loop assuming next chunk(s)
-                    // will fill the message
-                }
-            }
+        Self::decode(&read_request_bytes(conn)?, expected_chain_id)
+    }
+
+    /// Decode a request from the raw bytes of a length-delimited Protobuf message
+    pub fn decode(msg_bytes: &[u8], expected_chain_id: &chain::Id) -> Result<Self, Error> {
+        let (req, chain_id) = Self::decode_unchecked(msg_bytes)?;
+
+        if let Some(chain_id) = chain_id {
+            ensure!(
+                expected_chain_id == &chain_id,
+                ErrorKind::ChainIdError,
+                "got unexpected chain ID: {} (expecting: {})",
+                &chain_id,
+                expected_chain_id
+            );
         }
 
+        Ok(req)
+    }
+
+    /// Decode a request along with the chain ID it's for (none for pings),
+    /// without checking which chain that is
+    pub fn decode_unchecked(msg_bytes: &[u8]) -> Result<(Self, Option<chain::Id>), Error> {
+        let msg = proto::privval::Message::decode_length_delimited(msg_bytes)
+            .map_err(|e| format_err!(ErrorKind::ProtocolError, "malformed message packet: {}", e))?
+            .sum;
+
         let (req, chain_id) = match msg {
             Some(proto::privval::message::Sum::SignVoteRequest(
                 proto::privval::SignVoteRequest {
@@ -76,20 +73,32 @@ impl Request {
                 (Request::ShowPublicKey, req.chain_id)
             }
             Some(proto::privval::message::Sum::PingRequest(_)) => {
-                return Ok(Request::PingRequest);
+                return Ok((Request::PingRequest, None));
             }
             _ => fail!(ErrorKind::ProtocolError, "invalid RPC message: {:?}", msg),
         };
 
-        ensure!(
-            expected_chain_id == &chain::Id::try_from(chain_id.as_str())?,
-            ErrorKind::ChainIdError,
-            "got unexpected chain ID: {} (expecting: {})",
-            &chain_id,
-            expected_chain_id
-        );
+        Ok((req, Some(chain::Id::try_from(chain_id.as_str())?)))
+    }
 
-        Ok(req)
+    /// Decode a request with the Protobuf definitions of the given encoding,
+    /// dropping any fields they don't have (e.g. vote extensions before
+    /// v0.38), along with the chain ID it's for
+    pub fn decode_as(
+        msg_bytes: &[u8],
+        encoding: ReplyEncoding,
+    ) -> Result<(Self, Option<chain::Id>), Error> {
+        let msg_bytes = match encoding {
+            ReplyEncoding::ProtobufV0_38 => msg_bytes.to_vec(),
+            ReplyEncoding::ProtobufV0_37 => transcode::<proto::v0_37::privval::Message>(msg_bytes)?,
+            ReplyEncoding::ProtobufV0_34 => transcode::<proto::v0_34::privval::Message>(msg_bytes)?,
+            ReplyEncoding::Amino => fail!(
+                ErrorKind::ProtocolError,
+                "Amino-encoded requests are unsupported"
+            ),
+        };
+
+        Self::decode_unchecked(&msg_bytes)
     }
 
     /// Convert this request into a [`SignableMsg`].
@@ -109,7 +118,7 @@ impl Request {
 }
 
 /// RPC responses from the KMS
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Response {
     /// Signature response
     SignedVote(proto::privval::SignedVoteResponse),
@@ -134,6 +143,35 @@ impl Response {
         Ok(buf)
     }
 
+    /// Encode response to bytes using the given wire encoding.
+    ///
+    /// Responses are built with the newest Protobuf definitions. They're
+    /// transcoded for older encodings, which drops any fields the older
+    /// definitions don't have (e.g. vote extensions).
+    pub fn encode_as(self, encoding: ReplyEncoding) -> Result<Vec<u8>, Error> {
+        let buf = self.encode()?;
+
+        match encoding {
+            ReplyEncoding::ProtobufV0_38 => Ok(buf),
+            ReplyEncoding::ProtobufV0_37 => transcode::<proto::v0_37::privval::Message>(&buf),
+            ReplyEncoding::ProtobufV0_34 => transcode::<proto::v0_34::privval::Message>(&buf),
+            ReplyEncoding::Amino => fail!(
+                ErrorKind::ProtocolError,
+                "Amino-encoded replies are unsupported"
+            ),
+        }
+    }
+
+    /// Get the error carried by this response, if any
+    pub fn remote_error(&self) -> Option<&proto::privval::RemoteSignerError> {
+        match self {
+            Response::SignedVote(resp) => resp.error.as_ref(),
+            Response::SignedProposal(resp) => resp.error.as_ref(),
+            Response::Ping(_) => None,
+            Response::PublicKey(resp) => resp.error.as_ref(),
+        }
+    }
+
     /// Construct an error response for a given [`SignableMsg`].
     pub fn error(msg: SignableMsg, error: proto::privval::RemoteSignerError) -> Response {
         match msg {
@@ -168,6 +206,44 @@ impl From<SignableMsg> for Response {
     }
 }
 
+/// Read the raw bytes of a request, which may span several Secret Connection
+/// messages. The bytes are returned even if they fail to decode, so callers
+/// can inspect malformed requests.
+pub fn read_request_bytes(conn: &mut impl Read) -> Result<Vec<u8>, Error> {
+    let mut msg_bytes: Vec<u8> = vec![];
+
+    // fix for Sei: collect incoming bytes of Protobuf from incoming msg
+    loop {
+        let mut msg_chunk = read_msg(conn)?;
+        let chunk_len = msg_chunk.len();
+        msg_bytes.append(&mut msg_chunk);
+
+        // if we can decode it, great, we're done. If chunk_len < DATA_MAX_SIZE (1024) we assume
+        // it was the end of the message and it is malformed. Otherwise, we go to start of the loop
+        // assuming next chunk(s) will fill the message
+        if chunk_len < DATA_MAX_SIZE
+            || proto::privval::Message::decode_length_delimited(msg_bytes.as_ref()).is_ok()
+        {
+            return Ok(msg_bytes);
+        }
+    }
+}
+
+/// Re-encode a length-delimited Protobuf message as the given message type
+fn transcode<M: prost::Message + Default>(buf: &[u8]) -> Result<Vec<u8>, Error> {
+    let msg = M::decode_length_delimited(buf).map_err(|e| {
+        format_err!(
+            ErrorKind::ProtocolError,
+            "couldn't transcode message: {}",
+            e
+        )
+    })?;
+
+    let mut transcoded = Vec::new();
+    msg.encode_length_delimited(&mut transcoded)?;
+    Ok(transcoded)
+}
+
 /// Read a message from a Secret Connection
 // TODO(tarcieri): extract this into Secret Connection
 fn read_msg(conn: &mut impl Read) -> Result<Vec<u8>, Error> {
@@ -176,3 +252,107 @@ fn read_msg(conn: &mut impl Read) -> Result<Vec<u8>, Error> {
     buf.truncate(buf_read);
     Ok(buf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_vote_response() -> Response {
+        Response::SignedVote(proto::privval::SignedVoteResponse {
+            vote: Some(proto::types::Vote {
+                r#type: 2,
+                height: 12345,
+                round: 1,
+                validator_address: vec![0xa3; 20],
+                validator_index: 7,
+                signature: vec![0x42; 64],
+                extension: b"extension".to_vec(),
+                extension_signature: vec![0x24; 64],
+                ..Default::default()
+            }),
+            error: None,
+        })
+    }
+
+    #[test]
+    fn encode_v0_38() {
+        let bytes = signed_vote_response()
+            .encode_as(ReplyEncoding::ProtobufV0_38)
+            .unwrap();
+
+        match proto::v0_38::privval::Message::decode_length_delimited(bytes.as_slice())
+            .unwrap()
+            .sum
+        {
+            Some(proto::v0_38::privval::message::Sum::SignedVoteResponse(resp)) => {
+                let vote = resp.vote.unwrap();
+                assert_eq!(vote.height, 12345);
+                assert_eq!(vote.signature, vec![0x42; 64]);
+                assert_eq!(vote.extension_signature, vec![0x24; 64]);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn encode_v0_37() {
+        let bytes = signed_vote_response()
+            .encode_as(ReplyEncoding::ProtobufV0_37)
+            .unwrap();
+
+        match proto::v0_37::privval::Message::decode_length_delimited(bytes.as_slice())
+            .unwrap()
+            .sum
+        {
+            Some(proto::v0_37::privval::message::Sum::SignedVoteResponse(resp)) => {
+                let vote = resp.vote.unwrap();
+                assert_eq!(vote.height, 12345);
+                assert_eq!(vote.signature, vec![0x42; 64]);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        // Vote extensions are dropped
+        assert!(bytes.len() < signed_vote_response().encode().unwrap().len());
+    }
+
+    #[test]
+    fn encode_v0_34() {
+        let bytes = signed_vote_response()
+            .encode_as(ReplyEncoding::ProtobufV0_34)
+            .unwrap();
+
+        match proto::v0_34::privval::Message::decode_length_delimited(bytes.as_slice())
+            .unwrap()
+            .sum
+        {
+            Some(proto::v0_34::privval::message::Sum::SignedVoteResponse(resp)) => {
+                let vote = resp.vote.unwrap();
+                assert_eq!(vote.height, 12345);
+                assert_eq!(vote.validator_index, 7);
+                assert_eq!(vote.signature, vec![0x42; 64]);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+
+        // Decoding with the newest definitions shows nothing else survived
+        match proto::privval::Message::decode_length_delimited(bytes.as_slice())
+            .unwrap()
+            .sum
+        {
+            Some(proto::privval::message::Sum::SignedVoteResponse(resp)) => {
+                let vote = resp.vote.unwrap();
+                assert!(vote.extension.is_empty());
+                assert!(vote.extension_signature.is_empty());
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reject_amino() {
+        assert!(signed_vote_response()
+            .encode_as(ReplyEncoding::Amino)
+            .is_err());
+    }
+}
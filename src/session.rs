@@ -1,15 +1,26 @@
 //! A session with a validator node
 
 use crate::{
-    chain::{self, state::StateErrorKind, Chain},
-    config::ValidatorConfig,
-    connection::{tcp, unix::UnixConnection, Connection},
+    chain::{self, state::StateErrorKind, Chain, DuplicateRequestPolicy, LastSigned},
+    config::{chain::ReplyEncoding, ValidatorConfig, MAX_PAYLOAD_PREVIEW_LEN},
+    connection::{stats, tcp, unix::UnixConnection, Connection},
     error::{Error, ErrorKind::*},
+    events::{self, Decision, SignEvent},
+    keyring, maintenance,
     prelude::*,
-    privval::SignableMsg,
-    rpc::{Request, Response},
+    privval::{SignableMsg, SignedMsgType},
+    rpc::{self, Request, Response},
+    watchdog,
 };
-use std::{os::unix::net::UnixStream, time::Instant};
+use std::{
+    fmt::Display,
+    io,
+    os::unix::net::UnixStream,
+    sync::PoisonError,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+use subtle_encoding::hex;
 use tendermint::{consensus, TendermintKey};
 use tendermint_config::net;
 use tendermint_proto as proto;
@@ -21,11 +32,18 @@ pub struct Session {
 
     /// TCP connection to a validator node
     connection: Box<dyn Connection>,
+
+    /// Time the double-signing guard took to check and persist the most
+    /// recent request's state
+    guard_latency: Option<Duration>,
 }
 
 impl Session {
     /// Open a session using the given validator configuration
     pub fn open(config: ValidatorConfig) -> Result<Self, Error> {
+        let stats = stats::register(&config);
+        let started_at = Instant::now();
+
         let connection: Box<dyn Connection> = match &config.addr {
             net::Address::Tcp {
                 peer_id,
@@ -44,6 +62,8 @@ impl Session {
                     peer_id,
                     config.timeout,
                     config.protocol_version.into(),
+                    tcp::SocketOptions::from(&config),
+                    config.tls.as_ref(),
                 )?;
 
                 info!(
@@ -74,6 +94,7 @@ impl Session {
                 );
 
                 let socket = UnixStream::connect(path)?;
+                socket.set_read_timeout(config.idle_timeout())?;
                 let conn = UnixConnection::new(socket);
 
                 info!(
@@ -85,18 +106,87 @@ impl Session {
             }
         };
 
-        Ok(Self { config, connection })
+        stats.connected(started_at.elapsed());
+        let connection = Box::new(stats::Metered::new(connection, stats));
+
+        Ok(Self {
+            config,
+            connection,
+            guard_latency: None,
+        })
     }
 
     /// Main request loop
     pub fn request_loop(&mut self) -> Result<(), Error> {
-        while self.handle_request()? {}
-        Ok(())
+        self.wait_for_grace_period();
+
+        loop {
+            self.handle_request()?;
+        }
     }
 
-    /// Handle an incoming request from the validator
-    fn handle_request(&mut self) -> Result<bool, Error> {
-        let request = Request::read(&mut self.connection, &self.config.chain_id)?;
+    /// Handle requests until one sign request has been answered, returning
+    /// the response sent for it (which may carry an error, e.g. if the
+    /// double-signing guard rejected the request)
+    pub fn handle_sign_request(&mut self) -> Result<Response, Error> {
+        self.wait_for_grace_period();
+
+        loop {
+            if let Some(response) = self.handle_request()? {
+                return Ok(response);
+            }
+        }
+    }
+
+    /// If the double-signing guard recently rejected a request for this
+    /// chain, wait out the rest of the configured grace period before
+    /// processing this connection's requests
+    fn wait_for_grace_period(&self) {
+        let remaining = {
+            let registry = chain::REGISTRY.get();
+
+            registry.get_chain(&self.config.chain_id).and_then(|chain| {
+                chain
+                    .rejections
+                    .lock()
+                    .unwrap()
+                    .grace_period_remaining(chain.rejection_grace_period, Instant::now())
+            })
+        };
+
+        if let Some(remaining) = remaining {
+            warn!(
+                "[{}@{}] double-signing guard recently rejected a request: waiting {} ms \
+                 before processing requests on this connection",
+                &self.config.chain_id,
+                &self.config.addr,
+                remaining.as_millis()
+            );
+
+            thread::sleep(remaining);
+        }
+    }
+
+    /// Handle an incoming request from the validator, returning the response
+    /// if it was a sign request
+    fn handle_request(&mut self) -> Result<Option<Response>, Error> {
+        let request_bytes = rpc::read_request_bytes(&mut self.connection).map_err(|e| {
+            if is_timeout(&e) {
+                format_err!(
+                    IoError,
+                    "no requests from validator for {}s; closing idle connection",
+                    self.config.idle_timeout().unwrap_or_default().as_secs()
+                )
+                .into()
+            } else {
+                e
+            }
+        })?;
+        let request = Request::decode(&request_bytes, &self.config.chain_id).map_err(|e| {
+            self.log_rejected_payload(&request_bytes, None, &e);
+            e
+        })?;
+
         debug!(
             "[{}@{}] received request: {:?}",
             &self.config.chain_id, &self.config.addr, &request
@@ -104,7 +194,23 @@ impl Session {
 
         let response = match request {
             Request::SignProposal(_) | Request::SignVote(_) => {
-                self.sign(request.into_signable_msg()?)?
+                let signable_msg = request.into_signable_msg()?;
+                let msg_type = signable_msg.msg_type();
+                let request_state = signable_msg.consensus_state();
+                let started_at = Instant::now();
+                self.guard_latency = None;
+                let result = self.sign(signable_msg, &request_bytes);
+
+                if events::is_enabled() {
+                    self.emit_sign_event(msg_type, &request_state, started_at, &result);
+                }
+
+                result.map_err(|e| {
+                    if is_rejection(&e) {
+                        self.log_rejected_payload(&request_bytes, Some(&request_state), &e);
+                    }
+                    e
+                })?
             }
             // non-signable requests:
             Request::PingRequest => Response::Ping(proto::privval::PingResponse {}),
@@ -116,14 +222,56 @@ impl Session {
             &self.config.chain_id, &self.config.addr, &response
         );
 
-        let response_bytes = response.encode()?;
+        let response_bytes = response.clone().encode_as(self.reply_encoding()?)?;
         self.connection.write_all(&response_bytes)?;
 
-        Ok(true)
+        // Only now has a signature actually been released
+        if matches!(
+            response,
+            Response::SignedVote(_) | Response::SignedProposal(_)
+        ) && response.remote_error().is_none()
+        {
+            if let Some(chain) = chain::REGISTRY.get().get_chain(&self.config.chain_id) {
+                *chain
+                    .last_released_at
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(SystemTime::now());
+            }
+        }
+
+        Ok(match response {
+            Response::SignedVote(_) | Response::SignedProposal(_) => Some(response),
+            Response::Ping(_) | Response::PublicKey(_) => None,
+        })
     }
 
     /// Perform a digital signature operation
-    fn sign(&mut self, mut signable_msg: SignableMsg) -> Result<Response, Error> {
+    fn sign(
+        &mut self,
+        mut signable_msg: SignableMsg,
+        request_bytes: &[u8],
+    ) -> Result<Response, Error> {
+        let _sign_guard = watchdog::SignGuard::acquire()?;
+
+        // Checked before the double-signing guard, so the state file isn't
+        // advanced for requests we don't sign
+        if maintenance::is_paused() {
+            let request_state = signable_msg.consensus_state();
+
+            warn!(
+                "[{}@{}] maintenance mode: refusing to sign {:?} at h/r/s {}",
+                &self.config.chain_id,
+                &self.config.addr,
+                signable_msg.msg_type(),
+                request_state
+            );
+
+            return Ok(Response::error(
+                signable_msg,
+                maintenance_mode(request_state),
+            ));
+        }
+
         self.check_max_height(&signable_msg)?;
 
         let registry = chain::REGISTRY.get();
@@ -134,37 +282,248 @@ impl Session {
                 panic!("chain '{}' missing from registry!", &self.config.chain_id);
             });
 
-        if let Some(remote_err) = self.update_consensus_state(chain, &signable_msg)? {
-            // In the event of double signing we send a response to notify the validator
-            return Ok(Response::error(signable_msg, remote_err));
+        let msg_type = signable_msg.msg_type();
+
+        ensure!(
+            chain.allowed_message_types.contains(&msg_type),
+            InvalidMessageError,
+            "refusing to sign {:?}: message type not allowed for chain {}",
+            msg_type,
+            chain.id
+        );
+
+        // Like maintenance mode, checked before the double-signing guard, so a
+        // malformed request never advances the state file
+        if let Err(e) = signable_msg.validate() {
+            let request_state = signable_msg.consensus_state();
+
+            warn!(
+                "[{}@{}] refusing to sign malformed {:?} at h/r/s {}: {}",
+                &self.config.chain_id,
+                &self.config.addr,
+                msg_type,
+                request_state,
+                e.detail()
+            );
+
+            return Ok(Response::error(
+                signable_msg,
+                invalid_request(request_state, e.detail()),
+            ));
         }
 
+        // Requests for a chain are handled one at a time, so the second of two
+        // identical requests sees the outcome of the first rather than racing
+        // it. A panic while signing never records a signature, so the last one
+        // recorded is still valid.
+        let mut last_signed = chain
+            .last_signed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
         // TODO(tarcieri): support for non-default public keys
         let public_key = None;
+
+        // Like maintenance mode, checked before the double-signing guard
+        if let Err(e) = chain.keyring.check_policy(public_key, &chain.id, msg_type) {
+            let request_state = signable_msg.consensus_state();
+
+            warn!(
+                class = %keyring::record_signing_error(&chain.id, &e),
+                "[{}@{}] {} at h/r/s {}",
+                &self.config.chain_id, &self.config.addr, e, request_state
+            );
+
+            return Ok(Response::error(
+                signable_msg,
+                policy_violation(request_state, &e),
+            ));
+        }
+
+        if let Some(height_bounds) = &chain.height_bounds {
+            if let Err(e) = height_bounds.check(&chain.id, signable_msg.height()) {
+                let request_state = signable_msg.consensus_state();
+
+                warn!(
+                    "[{}@{}] {} (h/r/s {})",
+                    &self.config.chain_id, &self.config.addr, e, request_state
+                );
+
+                return Ok(Response::error(
+                    signable_msg,
+                    height_out_of_bounds(request_state, &e),
+                ));
+            }
+        }
+
+        if let Some(standby_lock) = &chain.standby_lock {
+            standby_lock.acquire()?;
+        }
+
         let chain_id = self.config.chain_id.clone();
         let canonical_msg = signable_msg.canonical_bytes(chain_id.clone())?;
+        let extension_msg = if chain.sign_extensions {
+            signable_msg.extension_bytes(chain_id.clone())?
+        } else {
+            None
+        };
 
-        let started_at = Instant::now();
-        let consensus_sig = chain.keyring.sign(public_key, &canonical_msg)?;
-        signable_msg.add_consensus_signature(consensus_sig);
-        self.log_signing_request(&signable_msg, started_at).unwrap();
+        if let Some(last) = last_signed
+            .as_ref()
+            .filter(|last| last.is_duplicate(&canonical_msg, extension_msg.as_deref()))
+        {
+            let request_state = signable_msg.consensus_state();
+
+            match chain.on_duplicate_request {
+                DuplicateRequestPolicy::Resign => debug!(
+                    "[{}@{}] duplicate {:?} at h/r/s {}: signing it again",
+                    &self.config.chain_id, &self.config.addr, msg_type, request_state
+                ),
+                DuplicateRequestPolicy::Cached => {
+                    info!(
+                        "[{}@{}] duplicate {:?} at h/r/s {}: answering with the signature already produced",
+                        &self.config.chain_id, &self.config.addr, msg_type, request_state
+                    );
 
-        // Add extension signature if the message is a precommit for a non-empty block ID.
-        if chain.sign_extensions {
-            if let Some(extension_msg) = signable_msg.extension_bytes(chain_id)? {
-                let started_at = Instant::now();
-                let extension_sig = chain.keyring.sign(public_key, &extension_msg)?;
-                signable_msg.add_extension_signature(extension_sig)?;
+                    signable_msg.add_consensus_signature(last.signature.clone());
 
-                info!(
-                    "[{}@{}] signed vote extension ({} ms)",
-                    &self.config.chain_id,
-                    &self.config.addr,
-                    started_at.elapsed().as_millis(),
-                );
+                    if let Some(extension_sig) = &last.extension_signature {
+                        signable_msg.add_extension_signature(extension_sig.clone())?;
+                    }
+
+                    return Ok(signable_msg.into());
+                }
+                DuplicateRequestPolicy::Reject => {
+                    warn!(
+                        "[{}@{}] duplicate {:?} at h/r/s {}: refusing to sign it again",
+                        &self.config.chain_id, &self.config.addr, msg_type, request_state
+                    );
+
+                    return Ok(Response::error(
+                        signable_msg,
+                        duplicate_request(request_state),
+                    ));
+                }
+            }
+        }
+
+        if let Some(remote_err) = self.update_consensus_state(chain, &signable_msg)? {
+            self.log_rejected_payload(
+                request_bytes,
+                Some(&signable_msg.consensus_state()),
+                &remote_err.description,
+            );
+
+            // In the event of double signing we send a response to notify the validator
+            return Ok(Response::error(signable_msg, remote_err));
+        }
+
+        if let Some(height_bounds) = &chain.height_bounds {
+            height_bounds.advance(signable_msg.height());
+        }
+
+        // Nothing past this point may run for observe-only chains
+        if chain.observe_only {
+            let request_state = signable_msg.consensus_state();
+
+            info!(
+                "[{}@{}] observe-only: would have signed {:?}:{} at h/r/s {}",
+                &self.config.chain_id,
+                &self.config.addr,
+                msg_type,
+                request_state.block_id_prefix(),
+                request_state
+            );
+
+            return Ok(Response::error(signable_msg, observe_only(request_state)));
+        }
+
+        // The double-signing guard has already accepted the request, so one
+        // which isn't approved is never signed at this h/r/s, even if retried
+        if let Some(approval_hook) = &chain.approval_hook {
+            let request_state = signable_msg.consensus_state();
+
+            if approval_hook.applies_to(msg_type, request_state.height) {
+                if let Err(e) = approval_hook.approve(&chain.id, msg_type, &request_state) {
+                    warn!(
+                        "[{}@{}] refusing to sign: {}",
+                        &self.config.chain_id, &self.config.addr, e
+                    );
+
+                    return Ok(Response::error(
+                        signable_msg,
+                        not_approved(request_state, &e),
+                    ));
+                }
             }
         }
 
+        // Only requests which are about to be signed use up the key's rate
+        // limit, so ones refused above can't run it down
+        if let Err(e) = chain.keyring.record_policy(public_key, &chain.id, msg_type) {
+            let request_state = signable_msg.consensus_state();
+
+            warn!(
+                class = %keyring::record_signing_error(&chain.id, &e),
+                "[{}@{}] {} at h/r/s {}",
+                &self.config.chain_id, &self.config.addr, e, request_state
+            );
+
+            return Ok(Response::error(
+                signable_msg,
+                policy_violation(request_state, &e),
+            ));
+        }
+
+        // Provider failures are counted by class, for alerting
+        let record_error = |e: Error| {
+            keyring::record_signing_error(&chain.id, &e);
+            e
+        };
+
+        // A signature the validator has stopped waiting for is useless, so
+        // rather than send it late, give up (which never risks double signing)
+        let budget = chain.sign_timeout.for_msg_type(msg_type);
+        let started_at = Instant::now();
+        let consensus_sig: tendermint::Signature = chain
+            .keyring
+            .sign_within(public_key, &canonical_msg, budget)
+            .map_err(record_error)?
+            .into();
+        signable_msg.add_consensus_signature(consensus_sig.clone());
+        self.log_signing_request(&signable_msg, started_at).unwrap();
+
+        // Add extension signature if the message is a precommit for a non-empty
+        // block ID (and extensions are signed for this chain).
+        let mut extension_sig = None;
+
+        if let Some(extension_msg) = &extension_msg {
+            // The precommit's budget covers its vote extension too
+            let remaining = budget.map(|budget| budget.saturating_sub(started_at.elapsed()));
+            let started_at = Instant::now();
+            let sig: tendermint::Signature = chain
+                .keyring
+                .sign_within(public_key, extension_msg, remaining)
+                .map_err(record_error)?
+                .into();
+            signable_msg.add_extension_signature(sig.clone())?;
+            extension_sig = Some(sig);
+
+            info!(
+                "[{}@{}] signed vote extension ({} ms)",
+                &self.config.chain_id,
+                &self.config.addr,
+                started_at.elapsed().as_millis(),
+            );
+        }
+
+        *last_signed = Some(LastSigned {
+            sign_bytes: canonical_msg,
+            extension_bytes: extension_msg,
+            signature: consensus_sig,
+            extension_signature: extension_sig,
+        });
+
         Ok(signable_msg.into())
     }
 
@@ -198,9 +557,26 @@ impl Session {
         let request_state = signable_msg.consensus_state();
         let mut chain_state = chain.state.lock().unwrap();
 
-        match chain_state.update_consensus_state(request_state.clone()) {
-            Ok(()) => Ok(None),
+        let started_at = Instant::now();
+        let result = chain_state.update_consensus_state(request_state.clone());
+        let guard_latency = started_at.elapsed();
+        self.guard_latency = Some(guard_latency);
+
+        debug!(
+            "[{}@{}] double-signing guard took {} µs",
+            &self.config.chain_id,
+            &self.config.addr,
+            guard_latency.as_micros()
+        );
+
+        match result {
+            Ok(()) => {
+                chain.rejections.lock().unwrap().record_success();
+                Ok(None)
+            }
             Err(e) if e.kind() == StateErrorKind::DoubleSign => {
+                self.record_rejection(chain, &request_state);
+
                 // Report double signing error back to the validator
                 let original_block_id = chain_state.consensus_state().block_id_prefix();
 
@@ -217,10 +593,57 @@ impl Session {
                 let remote_err = double_sign(request_state);
                 Ok(Some(remote_err))
             }
-            Err(e) => Err(e.into()),
+            Err(e) if e.kind() == StateErrorKind::SyncError => {
+                // Fail closed: never release a signature we couldn't record
+                error!(
+                    "[{}@{}] FAILING CLOSED: couldn't persist consensus state for {:?} at h/r/s {}, refusing to sign: {}",
+                    &self.config.chain_id,
+                    &self.config.addr,
+                    msg_type,
+                    request_state,
+                    e
+                );
+
+                Err(e.into())
+            }
+            Err(e) => {
+                self.record_rejection(chain, &request_state);
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Count a double-signing guard rejection, warning (at most once per
+    /// `rejections::WARNING_INTERVAL`) if the same height/round/step keeps
+    /// being rejected
+    fn record_rejection(&self, chain: &Chain, request_state: &consensus::State) {
+        let mut rejections = chain.rejections.lock().unwrap();
+
+        if let Some(consecutive) = rejections.record_rejection(request_state, Instant::now()) {
+            warn!(
+                "[{}@{}] {} consecutive rejected sign requests at h/r/s {}: \
+                 validator may be stuck, looping, or flapping between instances",
+                &self.config.chain_id, &self.config.addr, consecutive, request_state
+            );
         }
     }
 
+    /// Wire encoding of replies for this session's chain
+    fn reply_encoding(&self) -> Result<ReplyEncoding, Error> {
+        chain::REGISTRY
+            .get()
+            .get_chain(&self.config.chain_id)
+            .map(|chain| chain.reply_encoding)
+            .ok_or_else(|| {
+                format_err!(
+                    ChainIdError,
+                    "chain '{}' missing from registry",
+                    &self.config.chain_id
+                )
+                .into()
+            })
+    }
+
     /// Get the public key for (the only) public key in the keyring
     fn get_public_key(&mut self) -> Result<Response, Error> {
         let registry = chain::REGISTRY.get();
@@ -242,6 +665,83 @@ impl Session {
         }))
     }
 
+    /// If configured, log a bounded hex preview of a rejected request along
+    /// with its height/round/step (when it could be parsed)
+    fn log_rejected_payload(
+        &self,
+        payload: &[u8],
+        request_state: Option<&consensus::State>,
+        reason: impl Display,
+    ) {
+        let preview_len = match self.config.rejected_payload_preview {
+            Some(len) => len.min(MAX_PAYLOAD_PREVIEW_LEN).min(payload.len()),
+            None => return,
+        };
+
+        let preview = String::from_utf8(hex::encode_upper(&payload[..preview_len])).unwrap();
+
+        let hrs = request_state
+            .map(ToString::to_string)
+            .unwrap_or_else(|| "unknown".to_owned());
+
+        warn!(
+            "[{}@{}] rejected request at h/r/s {} ({}): {}{} ({} bytes total)",
+            &self.config.chain_id,
+            &self.config.addr,
+            hrs,
+            reason,
+            preview,
+            if preview_len < payload.len() {
+                "..."
+            } else {
+                ""
+            },
+            payload.len()
+        );
+    }
+
+    /// Emit an event describing the outcome of a sign request to the event
+    /// stream
+    fn emit_sign_event(
+        &self,
+        msg_type: SignedMsgType,
+        request_state: &consensus::State,
+        started_at: Instant,
+        result: &Result<Response, Error>,
+    ) {
+        let (decision, reason) = match result {
+            Ok(response) => match response.remote_error() {
+                Some(e) if e.code == OBSERVE_ONLY_ERROR => {
+                    (Decision::Observe, Some(e.description.clone()))
+                }
+                Some(e) => (Decision::Reject, Some(e.description.clone())),
+                None => (Decision::Accept, None),
+            },
+            Err(e) if is_rejection(e) => (Decision::Reject, Some(e.to_string())),
+            Err(e) => (Decision::Error, Some(e.to_string())),
+        };
+
+        let provider = chain::REGISTRY
+            .get()
+            .get_chain(&self.config.chain_id)
+            .and_then(|chain| chain.keyring.default_provider())
+            .map(|provider| provider.to_string());
+
+        events::emit(&SignEvent {
+            chain_id: self.config.chain_id.to_string(),
+            validator: self.config.addr.to_string(),
+            decision,
+            msg_type: format!("{:?}", msg_type).to_lowercase(),
+            height: request_state.height.value(),
+            round: request_state.round.value(),
+            step: request_state.step,
+            latency_ms: started_at.elapsed().as_millis() as u64,
+            guard_latency_us: self.guard_latency.map(|d| d.as_micros() as u64),
+            provider,
+            reason,
+        });
+    }
+
     /// Write an INFO logline about a signing request
     fn log_signing_request(
         &self,
@@ -265,6 +765,126 @@ impl Session {
     }
 }
 
+/// Does the given error indicate we rejected a request (as opposed to e.g. a
+/// provider or I/O failure)?
+fn is_rejection(err: &Error) -> bool {
+    matches!(
+        err.kind(),
+        ChainIdError | DoubleSign | ExceedMaxHeight | InvalidMessageError | ProtocolError
+    )
+}
+
+/// Error code reported to validators for requests to observe-only chains
+const OBSERVE_ONLY_ERROR: i32 = 3;
+
+/// Error for requests which would have been signed if the chain weren't
+/// observe-only
+fn observe_only(consensus_state: consensus::State) -> proto::privval::RemoteSignerError {
+    proto::privval::RemoteSignerError {
+        code: OBSERVE_ONLY_ERROR,
+        description: format!("observe-only KMS: not signing at h/r/s {}", consensus_state),
+    }
+}
+
+/// Error for requests refused while in maintenance mode
+fn maintenance_mode(consensus_state: consensus::State) -> proto::privval::RemoteSignerError {
+    proto::privval::RemoteSignerError {
+        code: maintenance::MAINTENANCE_ERROR,
+        description: format!(
+            "KMS in maintenance mode: not signing at h/r/s {}",
+            consensus_state
+        ),
+    }
+}
+
+/// Error code reported to validators for requests refused by a key policy
+const POLICY_ERROR: i32 = 5;
+
+/// Error for requests refused by the signing key's usage policy
+fn policy_violation(
+    consensus_state: consensus::State,
+    err: &Error,
+) -> proto::privval::RemoteSignerError {
+    proto::privval::RemoteSignerError {
+        code: POLICY_ERROR,
+        description: format!("{}: not signing at h/r/s {}", err, consensus_state),
+    }
+}
+
+/// Error code reported to validators for requests outside the chain's
+/// configured height bounds
+const HEIGHT_BOUND_ERROR: i32 = 6;
+
+/// Error for requests outside the chain's configured height bounds
+fn height_out_of_bounds(
+    consensus_state: consensus::State,
+    err: &Error,
+) -> proto::privval::RemoteSignerError {
+    proto::privval::RemoteSignerError {
+        code: HEIGHT_BOUND_ERROR,
+        description: format!("{}: not signing at h/r/s {}", err, consensus_state),
+    }
+}
+
+/// Error code reported to validators for requests the chain's approval hook
+/// didn't approve
+const APPROVAL_ERROR: i32 = 7;
+
+/// Error for requests the chain's approval hook didn't approve
+fn not_approved(
+    consensus_state: consensus::State,
+    err: &Error,
+) -> proto::privval::RemoteSignerError {
+    proto::privval::RemoteSignerError {
+        code: APPROVAL_ERROR,
+        description: format!("{}: not signing at h/r/s {}", err, consensus_state),
+    }
+}
+
+/// Error code reported to validators for duplicate requests refused by the
+/// chain's `on_duplicate_request = "reject"` policy
+const DUPLICATE_ERROR: i32 = 8;
+
+/// Error for duplicates of the request last signed for the chain
+fn duplicate_request(consensus_state: consensus::State) -> proto::privval::RemoteSignerError {
+    proto::privval::RemoteSignerError {
+        code: DUPLICATE_ERROR,
+        description: format!(
+            "duplicate of the request last signed: not signing again at h/r/s {}",
+            consensus_state
+        ),
+    }
+}
+
+/// Error code reported to validators for malformed sign requests
+const INVALID_REQUEST_ERROR: i32 = 9;
+
+/// Error for sign requests which aren't well-formed
+fn invalid_request(
+    consensus_state: consensus::State,
+    err: impl Display,
+) -> proto::privval::RemoteSignerError {
+    proto::privval::RemoteSignerError {
+        code: INVALID_REQUEST_ERROR,
+        description: format!(
+            "malformed request ({}): not signing at h/r/s {}",
+            err, consensus_state
+        ),
+    }
+}
+
+/// Did the given error occur because a read timed out?
+fn is_timeout(err: &Error) -> bool {
+    std::error::Error::source(err)
+        .and_then(|source| source.downcast_ref::<io::Error>())
+        .is_some_and(|e| {
+            matches!(
+                e.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            )
+        })
+}
+
 /// Double signing handler.
 fn double_sign(consensus_state: consensus::State) -> proto::privval::RemoteSignerError {
     /// Double signing error code.
@@ -0,0 +1,238 @@
+//! Live runtime state of a running KMS
+//!
+//! Reported as JSON by the `status` command on the control socket (see
+//! [`crate::control`]), e.g. for `tmkms status`. Validator connection states
+//! are tracked here by the client threads; everything else is read from the
+//! chain registry when the report is generated.
+
+use crate::{
+    chain::{self, Chain},
+    config::ValidatorConfig,
+    error::Error,
+    maintenance,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    sync::{Mutex, PoisonError},
+    time::SystemTime,
+};
+
+/// State of every validator connection
+static CONNECTIONS: Mutex<Vec<Connection>> = Mutex::new(Vec::new());
+
+/// Runtime state of the KMS
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+    /// When maintenance mode was entered (`null` if signing)
+    pub maintenance_since: Option<String>,
+
+    /// Per-chain state
+    pub chains: Vec<ChainStatus>,
+}
+
+/// Runtime state of a chain
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ChainStatus {
+    /// Chain ID
+    pub chain_id: String,
+
+    /// Last signed height
+    pub height: u64,
+
+    /// Last signed round
+    pub round: u32,
+
+    /// Last signed step
+    pub step: i8,
+
+    /// When a signature was last sent to a validator (`null` if none has been
+    /// since startup)
+    pub last_signed_at: Option<String>,
+
+    /// Signing provider of the chain's key
+    pub provider: Option<String>,
+
+    /// Whether the chain is `observe_only`
+    pub observe_only: bool,
+
+    /// Whether the chain appears halted (`null` without halt detection)
+    pub halted: Option<bool>,
+
+    /// Whether the sign watchdog considers this signer quiet (`null` without
+    /// a sign watchdog)
+    pub signer_quiet: Option<bool>,
+
+    /// Consecutive rejections of the same height/round/step
+    pub consecutive_rejections: u64,
+
+    /// Connections to the chain's validators
+    pub validators: Vec<ConnectionStatus>,
+}
+
+/// Runtime state of a validator connection
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ConnectionStatus {
+    /// Address of the validator
+    pub addr: String,
+
+    /// State of the connection
+    pub state: ConnectionState,
+
+    /// When the connection entered its current state
+    pub since: String,
+
+    /// Number of errors (each of which ended a session) since startup
+    pub errors: u64,
+
+    /// The most recent error
+    pub last_error: Option<String>,
+}
+
+/// State of a validator connection
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    /// Connecting (or waiting to reconnect)
+    Connecting,
+
+    /// Connected and handling requests
+    Connected,
+
+    /// Stopped, e.g. after an error with `reconnect = false`
+    Stopped,
+}
+
+impl fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Stopped => "stopped",
+        })
+    }
+}
+
+/// Tracked state of a validator connection
+struct Connection {
+    /// Chain the validator belongs to
+    chain_id: chain::Id,
+
+    /// Reported status
+    status: ConnectionStatus,
+}
+
+/// Record the state of the given validator's connection
+pub fn set_connection_state(config: &ValidatorConfig, state: ConnectionState) {
+    update_connection(config, |status| {
+        if status.state != state {
+            status.state = state;
+            status.since = timestamp(SystemTime::now());
+        }
+    });
+}
+
+/// Record an error which ended a session with the given validator
+pub fn record_connection_error(config: &ValidatorConfig, error: &Error) {
+    update_connection(config, |status| {
+        status.errors += 1;
+        status.last_error = Some(error.to_string());
+    });
+}
+
+/// Generate a report of the current runtime state
+pub fn report() -> Report {
+    let registry = chain::REGISTRY.get();
+    let connections = CONNECTIONS.lock().unwrap();
+
+    let chains = registry
+        .chains()
+        .map(|chain| {
+            let validators = connections
+                .iter()
+                .filter(|connection| connection.chain_id == chain.id)
+                .map(|connection| connection.status.clone())
+                .collect();
+
+            chain_status(chain, validators)
+        })
+        .collect();
+
+    Report {
+        maintenance_since: maintenance::paused_since()
+            .map(|since| since.to_rfc3339_opts(SecondsFormat::Secs, true)),
+        chains,
+    }
+}
+
+/// Runtime state of the given chain
+fn chain_status(chain: &Chain, validators: Vec<ConnectionStatus>) -> ChainStatus {
+    // A poisoned state is unrecoverable and shuts its chain down anyway
+    let consensus_state = match chain.state.lock() {
+        Ok(state) => state.consensus_state().clone(),
+        Err(_) => Default::default(),
+    };
+    let released_at = *chain
+        .last_released_at
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+
+    ChainStatus {
+        chain_id: chain.id.to_string(),
+        height: consensus_state.height.value(),
+        round: consensus_state.round.value(),
+        step: consensus_state.step,
+        last_signed_at: released_at.map(timestamp),
+        provider: chain
+            .keyring
+            .default_provider()
+            .map(|provider| provider.to_string()),
+        observe_only: chain.observe_only,
+        halted: chain.halt_detector.as_ref().map(|_| chain.is_halted()),
+        signer_quiet: chain
+            .sign_watchdog
+            .as_ref()
+            .map(|sign_watchdog| sign_watchdog.is_quiet()),
+        consecutive_rejections: chain
+            .rejections
+            .lock()
+            .map(|rejections| rejections.consecutive())
+            .unwrap_or_default(),
+        validators,
+    }
+}
+
+/// Update the tracked status of the given validator's connection, creating
+/// it if this is the first update
+fn update_connection(config: &ValidatorConfig, f: impl FnOnce(&mut ConnectionStatus)) {
+    let addr = config.addr.to_string();
+    let mut connections = CONNECTIONS.lock().unwrap();
+
+    let index = match connections
+        .iter()
+        .position(|c| c.chain_id == config.chain_id && c.status.addr == addr)
+    {
+        Some(index) => index,
+        None => {
+            connections.push(Connection {
+                chain_id: config.chain_id.clone(),
+                status: ConnectionStatus {
+                    addr,
+                    state: ConnectionState::Connecting,
+                    since: timestamp(SystemTime::now()),
+                    errors: 0,
+                    last_error: None,
+                },
+            });
+            connections.len() - 1
+        }
+    };
+
+    f(&mut connections[index].status);
+}
+
+/// Format a time as an RFC 3339 timestamp
+fn timestamp(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
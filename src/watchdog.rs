@@ -0,0 +1,145 @@
+//! Memory usage watchdog
+//!
+//! When `max_rss_mb` is set in `tmkms.toml`, the KMS periodically checks its
+//! resident set size (RSS) and shuts down once it exceeds the ceiling, so a
+//! slow leak results in a clean restart by the process supervisor rather than
+//! an OOM kill in the middle of signing. New sign requests are refused once
+//! the ceiling is hit, and in-flight ones are given [`SHUTDOWN_GRACE`] to
+//! complete before the process exits.
+//!
+//! RSS is read from `/proc/self/status`, so the watchdog is only available on
+//! Linux.
+
+use crate::{
+    error::{Error, ErrorKind::*},
+    prelude::*,
+};
+use std::{
+    fs, process,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Interval at which the RSS is checked
+pub const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximum time to wait for in-flight sign requests before exiting
+pub const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Exit code used when shutting down due to excessive memory usage
+pub const EXIT_CODE: i32 = 3;
+
+/// Have we exceeded the ceiling and begun shutting down?
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Number of sign requests currently being processed
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Marks a sign request as in flight until dropped
+pub struct SignGuard(());
+
+impl SignGuard {
+    /// Mark a sign request as in flight, failing if we're shutting down
+    pub fn acquire() -> Result<Self, Error> {
+        IN_FLIGHT.fetch_add(1, Ordering::SeqCst);
+
+        if SHUTTING_DOWN.load(Ordering::SeqCst) {
+            IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+            fail!(
+                SigningError,
+                "refusing to sign: shutting down (memory ceiling exceeded)"
+            );
+        }
+
+        Ok(SignGuard(()))
+    }
+}
+
+impl Drop for SignGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Spawn the watchdog, shutting down once the RSS exceeds the given number of
+/// megabytes
+pub fn spawn(max_rss_mb: u64) -> Result<(), Error> {
+    let initial_rss = resident_set_size()?;
+    let max_rss = max_rss_mb.saturating_mul(1024 * 1024);
+
+    ensure!(
+        initial_rss < max_rss,
+        ConfigError,
+        "max_rss_mb = {} is below the current RSS ({} MB)",
+        max_rss_mb,
+        initial_rss / (1024 * 1024)
+    );
+
+    thread::Builder::new()
+        .name("memory-watchdog".to_owned())
+        .spawn(move || loop {
+            thread::sleep(CHECK_INTERVAL);
+
+            match resident_set_size() {
+                Ok(rss) if rss > max_rss => shutdown(rss, max_rss_mb),
+                Ok(_) => (),
+                Err(e) => warn!("memory watchdog couldn't read RSS: {}", e),
+            }
+        })?;
+
+    info!("memory watchdog enabled (max RSS: {} MB)", max_rss_mb);
+    Ok(())
+}
+
+/// Stop accepting sign requests, wait for in-flight ones, then exit
+fn shutdown(rss: u64, max_rss_mb: u64) -> ! {
+    SHUTTING_DOWN.store(true, Ordering::SeqCst);
+
+    error!(
+        "*** RSS of {} MB exceeds max_rss_mb = {}; shutting down (possible memory leak) ***",
+        rss / (1024 * 1024),
+        max_rss_mb
+    );
+
+    let started_at = Instant::now();
+
+    while IN_FLIGHT.load(Ordering::SeqCst) > 0 && started_at.elapsed() < SHUTDOWN_GRACE {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    process::exit(EXIT_CODE);
+}
+
+/// Get the resident set size of this process in bytes
+fn resident_set_size() -> Result<u64, Error> {
+    let status = fs::read_to_string("/proc/self/status").map_err(|e| {
+        format_err!(
+            ConfigError,
+            "memory watchdog requires /proc/self/status: {}",
+            e
+        )
+    })?;
+
+    parse_vm_rss(&status)
+        .ok_or_else(|| format_err!(ParseError, "no VmRSS in /proc/self/status").into())
+}
+
+/// Parse the `VmRSS` line of `/proc/<pid>/status` (in kB) into bytes
+fn parse_vm_rss(status: &str) -> Option<u64> {
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kb = line["VmRSS:".len()..].trim().strip_suffix("kB")?.trim();
+    kb.parse::<u64>().ok().map(|kb| kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_status() {
+        let status = "Name:\ttmkms\nVmPeak:\t  20000 kB\nVmRSS:\t    1234 kB\nThreads:\t4\n";
+        assert_eq!(parse_vm_rss(status), Some(1234 * 1024));
+        assert_eq!(parse_vm_rss("Name:\ttmkms\n"), None);
+    }
+}
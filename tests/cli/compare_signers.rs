@@ -0,0 +1,62 @@
+//! Integration tests for the `compare-signers` subcommand
+
+use crate::cli;
+use std::{ffi::OsStr, fs, path::Path};
+
+/// Write a config using the given softsign key, returning its path
+fn write_config(dir: &Path, name: &str, key_path: &str) -> std::path::PathBuf {
+    let config_path = dir.join(name);
+    let key_path = fs::canonicalize(key_path).unwrap();
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+state_file = "{}/state.json"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "{}"
+"#,
+            dir.display(),
+            key_path.display()
+        ),
+    )
+    .unwrap();
+
+    config_path
+}
+
+#[test]
+fn test_compare_signers() {
+    let dir = tempfile::tempdir().unwrap();
+    let ours = write_config(dir.path(), "ours.toml", "tests/support/signing_ed25519.key");
+    let same = write_config(dir.path(), "same.toml", "tests/support/signing_ed25519.key");
+    let other = write_config(
+        dir.path(),
+        "other.toml",
+        "tests/support/secret_connection.key",
+    );
+
+    let compare = |other: &Path| {
+        cli::run([
+            OsStr::new("compare-signers"),
+            OsStr::new("-c"),
+            ours.as_os_str(),
+            OsStr::new("--chain-id"),
+            OsStr::new("test_chain_id"),
+            OsStr::new("--other"),
+            other.as_os_str(),
+        ])
+    };
+
+    assert!(compare(&same).status.success());
+    assert!(!compare(&other).status.success());
+
+    // The double-signing state must never be touched
+    assert!(!dir.path().join("state.json").exists());
+}
@@ -0,0 +1,141 @@
+//! Integration tests for the `config` subcommand
+
+use crate::cli;
+use std::{ffi::OsStr, fs, str};
+
+const CONFIG: &str = r#"
+[[chain]]
+id = "test_chain_id"
+key_format = { type = "hex" }
+
+[[validator]]
+addr = "unix:///tmp/validator.sock"
+chain_id = "test_chain_id"
+protocol_version = "v0.34"
+
+[[providers.yubihsm]]
+adapter = { type = "usb" }
+auth = { key = 1, password = "super secret password" }
+keys = [{ chain_ids = ["test_chain_id"], key = 1 }]
+"#;
+
+#[test]
+fn test_dump() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+    fs::write(&config_path, CONFIG).unwrap();
+
+    let result = cli::run_successfully([
+        OsStr::new("config"),
+        OsStr::new("dump"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+        OsStr::new("--set"),
+        OsStr::new("validator.0.reconnect=false"),
+        OsStr::new("--format"),
+        OsStr::new("json"),
+    ]);
+
+    let stdout = str::from_utf8(&result.stdout).unwrap();
+    assert!(!stdout.contains("super secret password"));
+
+    let dump: serde_json::Value = serde_json::from_str(stdout).unwrap();
+    assert_eq!(dump["validator"][0]["reconnect"], false);
+    assert_eq!(
+        dump["providers"]["yubihsm"][0]["auth"]["password"],
+        "REDACTED"
+    );
+
+    // Defaults are filled in
+    assert_eq!(dump["chain"][0]["sign_extensions"], false);
+    assert_eq!(
+        dump["providers"]["yubihsm"][0]["adapter"]["timeout_ms"],
+        1000
+    );
+
+    // TOML output round-trips through the config parser
+    let result = cli::run_successfully([
+        OsStr::new("config"),
+        OsStr::new("dump"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+    ]);
+
+    let dumped_path = dir.path().join("dumped.toml");
+    fs::write(&dumped_path, &result.stdout).unwrap();
+
+    cli::run_successfully([
+        OsStr::new("config"),
+        OsStr::new("dump"),
+        OsStr::new("-c"),
+        dumped_path.as_os_str(),
+    ]);
+}
+
+#[test]
+fn test_include_dir() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+    let include_dir = dir.path().join("tmkms.d");
+    fs::create_dir(&include_dir).unwrap();
+    fs::write(
+        &config_path,
+        format!("include_dir = \"tmkms.d\"\n{}", CONFIG),
+    )
+    .unwrap();
+
+    fs::write(
+        include_dir.join("other_chain.toml"),
+        r#"
+[[chain]]
+id = "other_chain_id"
+key_format = { type = "hex" }
+
+[[validator]]
+addr = "unix:///tmp/other-validator.sock"
+chain_id = "other_chain_id"
+protocol_version = "v0.34"
+"#,
+    )
+    .unwrap();
+
+    let dump = |config_path: &OsStr| {
+        cli::run([
+            OsStr::new("config"),
+            OsStr::new("dump"),
+            OsStr::new("-c"),
+            config_path,
+            OsStr::new("--format"),
+            OsStr::new("json"),
+        ])
+    };
+
+    let result = dump(config_path.as_os_str());
+    let stdout = str::from_utf8(&result.stdout).unwrap();
+    assert!(result.status.success(), "{}", stdout);
+
+    let dump_json: serde_json::Value = serde_json::from_str(stdout).unwrap();
+    assert_eq!(dump_json["chain"][1]["id"], "other_chain_id");
+    assert_eq!(dump_json["validator"][1]["chain_id"], "other_chain_id");
+    assert!(dump_json.get("include_dir").is_none());
+
+    // The same chain ID in two files is refused
+    fs::write(
+        include_dir.join("duplicate.toml"),
+        "[[chain]]\nid = \"test_chain_id\"\nkey_format = { type = \"hex\" }\n",
+    )
+    .unwrap();
+
+    let result = dump(config_path.as_os_str());
+    assert!(!result.status.success());
+    let output = format!(
+        "{}{}",
+        str::from_utf8(&result.stdout).unwrap(),
+        str::from_utf8(&result.stderr).unwrap()
+    );
+    assert!(
+        output.contains("duplicate chain ID test_chain_id"),
+        "{}",
+        output
+    );
+}
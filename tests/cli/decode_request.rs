@@ -0,0 +1,77 @@
+//! Integration tests for the `decode-request` subcommand
+
+use crate::cli;
+use prost::Message;
+use subtle_encoding::hex;
+use tendermint_proto as proto;
+use tmkms::privval::SignableMsg;
+
+/// Hex-encoded request to sign a precommit with a vote extension
+fn precommit_request() -> (proto::types::Vote, String) {
+    let vote = proto::types::Vote {
+        r#type: proto::types::SignedMsgType::Precommit as i32,
+        height: 42,
+        round: 1,
+        block_id: Some(proto::types::BlockId {
+            hash: vec![0xab; 32],
+            part_set_header: Some(proto::types::PartSetHeader {
+                total: 1,
+                hash: vec![0xcd; 32],
+            }),
+        }),
+        timestamp: Some(proto::google::protobuf::Timestamp {
+            seconds: 1_700_000_000,
+            nanos: 0,
+        }),
+        validator_address: vec![0xa3; 20],
+        validator_index: 0,
+        extension: b"extension".to_vec(),
+        ..Default::default()
+    };
+
+    let msg = proto::privval::Message {
+        sum: Some(proto::privval::message::Sum::SignVoteRequest(
+            proto::privval::SignVoteRequest {
+                vote: Some(vote.clone()),
+                chain_id: "test_chain_id".to_owned(),
+            },
+        )),
+    };
+
+    let bytes = msg.encode_length_delimited_to_vec();
+    (vote, String::from_utf8(hex::encode(bytes)).unwrap())
+}
+
+#[test]
+fn test_decode_request() {
+    let (vote, request) = precommit_request();
+    let signable_msg = SignableMsg::try_from(vote).unwrap();
+    let chain_id = "test_chain_id".parse().unwrap();
+    let sign_bytes = format!(
+        "sign_bytes: {}",
+        String::from_utf8(hex::encode_upper(
+            signable_msg.canonical_bytes(chain_id).unwrap()
+        ))
+        .unwrap()
+    );
+
+    let decode = |args: &[&str]| {
+        let output = cli::run_successfully([&["decode-request", "--hex", &request], args].concat());
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let stdout = decode(&[]);
+    assert!(stdout.contains("type: Precommit"), "{}", stdout);
+    assert!(stdout.contains("chain_id: test_chain_id"), "{}", stdout);
+    assert!(stdout.contains("height/round/step: 42/1/2"), "{}", stdout);
+    assert!(stdout.contains(&sign_bytes), "{}", stdout);
+    assert!(stdout.contains("extension_sign_bytes: "), "{}", stdout);
+
+    // Vote extensions don't exist before v0.38
+    let stdout = decode(&["--protocol-version", "v0.34"]);
+    assert!(stdout.contains(&sign_bytes), "{}", stdout);
+    assert!(!stdout.contains("extension_sign_bytes"), "{}", stdout);
+
+    let result = cli::run(["decode-request", "--hex", "not hex"]);
+    assert!(!result.status.success());
+}
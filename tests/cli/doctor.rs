@@ -0,0 +1,55 @@
+//! Integration tests for the `doctor` subcommand
+
+use crate::cli;
+use std::{ffi::OsStr, fs, os::unix::fs::PermissionsExt, str};
+
+#[test]
+fn test_offline_checks() {
+    let dir = tempfile::tempdir().unwrap();
+    let key_path = dir.path().join("signing.key");
+    fs::copy("tests/support/signing_ed25519.key", &key_path).unwrap();
+    fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600)).unwrap();
+
+    let config_path = dir.path().join("tmkms.toml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+on_missing_state = "init_zero"
+
+[[validator]]
+addr = "unix://{}/validator.sock"
+chain_id = "test_chain_id"
+protocol_version = "v0.34"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "{}"
+"#,
+            dir.path().display(),
+            key_path.display()
+        ),
+    )
+    .unwrap();
+
+    let args = [
+        OsStr::new("doctor"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+        OsStr::new("--state-dir"),
+        dir.path().as_os_str(),
+    ];
+
+    // The validator socket doesn't exist, but that's only checked `--online`
+    let result = cli::run_successfully(args);
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("SKIPPED"));
+
+    // World-readable keys are flagged
+    fs::set_permissions(&key_path, fs::Permissions::from_mode(0o644)).unwrap();
+    assert!(!cli::run(args).status.success());
+}
@@ -0,0 +1,158 @@
+//! Integration tests for the `maintenance` subcommand
+
+use crate::{cli, KMS_EXE_PATH};
+use prost::Message;
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    process::{Command, Stdio},
+    str, thread,
+    time::Duration,
+};
+use tendermint_proto as proto;
+
+/// Send a prevote at the given height, returning the response
+fn sign_vote(socket: &mut UnixStream, height: i64) -> proto::privval::SignedVoteResponse {
+    let request = proto::privval::message::Sum::SignVoteRequest(proto::privval::SignVoteRequest {
+        vote: Some(proto::types::Vote {
+            r#type: 0x01,
+            height,
+            round: 0,
+            timestamp: Some(proto::google::protobuf::Timestamp {
+                seconds: 1518332962,
+                nanos: 0,
+            }),
+            validator_address: vec![0xa3; 20],
+            validator_index: 56789,
+            ..Default::default()
+        }),
+        chain_id: "test_chain_id".to_owned(),
+    });
+
+    let mut buf = vec![];
+    proto::privval::Message { sum: Some(request) }
+        .encode_length_delimited(&mut buf)
+        .unwrap();
+    socket.write_all(&buf).unwrap();
+
+    let mut response = [0u8; 4096];
+    let len = socket.read(&mut response).unwrap();
+
+    match proto::privval::Message::decode_length_delimited(&response[..len])
+        .unwrap()
+        .sum
+    {
+        Some(proto::privval::message::Sum::SignedVoteResponse(response)) => response,
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
+
+#[test]
+fn test_pause_and_resume() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("validator.sock");
+    let control_path = dir.path().join("control.sock");
+    let config_path = dir.path().join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+control_socket = "{}"
+
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+on_missing_state = "init_zero"
+
+[[validator]]
+addr = "unix://{}"
+chain_id = "test_chain_id"
+protocol_version = "v0.34"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "tests/support/signing_ed25519.key"
+"#,
+            control_path.display(),
+            socket_path.display()
+        ),
+    )
+    .unwrap();
+
+    let maintenance = |action: &str| {
+        cli::run([
+            OsStr::new("maintenance"),
+            OsStr::new("-c"),
+            config_path.as_os_str(),
+            OsStr::new(action),
+        ])
+    };
+
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let mut process = Command::new(KMS_EXE_PATH)
+        .args([
+            OsStr::new("start"),
+            OsStr::new("-c"),
+            config_path.as_os_str(),
+            OsStr::new("--state-dir"),
+            dir.path().as_os_str(),
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let (mut socket, _) = listener.accept().unwrap();
+
+    while !control_path.exists() {
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let paused = maintenance("pause");
+    assert!(paused.status.success());
+    assert!(str::from_utf8(&paused.stderr)
+        .unwrap()
+        .contains("paused since"));
+
+    let status = cli::run([
+        OsStr::new("status"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+    ]);
+    let stdout = str::from_utf8(&status.stdout).unwrap();
+    assert!(stdout.contains("maintenance mode: PAUSED since"));
+    assert!(stdout.contains("connected since"));
+
+    // Refused without touching the state file, so the same height can be
+    // signed once resumed
+    let response = sign_vote(&mut socket, 100);
+    assert_eq!(response.error.unwrap().code, 4);
+
+    let resumed = maintenance("resume");
+    assert!(str::from_utf8(&resumed.stderr).unwrap().contains("signing"));
+
+    let response = sign_vote(&mut socket, 100);
+    assert!(response.error.is_none());
+    assert!(!response.vote.unwrap().signature.is_empty());
+
+    let status = cli::run([
+        OsStr::new("status"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+        OsStr::new("--format"),
+        OsStr::new("json"),
+    ]);
+    let status: serde_json::Value = serde_json::from_slice(&status.stdout).unwrap();
+    assert!(status["maintenance_since"].is_null());
+    assert_eq!(status["chains"][0]["height"], 100);
+    assert_eq!(status["chains"][0]["provider"], "softsign");
+    assert_eq!(status["chains"][0]["validators"][0]["state"], "connected");
+
+    process.kill().unwrap();
+    process.wait().unwrap();
+}
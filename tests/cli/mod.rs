@@ -8,7 +8,20 @@ use std::{
 
 use super::KMS_EXE_PATH;
 
+mod compare_signers;
+#[cfg(feature = "yubihsm")]
+mod config;
+mod decode_request;
+mod doctor;
 mod init;
+mod maintenance;
+mod monitor;
+mod pubkey;
+#[cfg(feature = "softsign")]
+mod softsign;
+mod start;
+mod state;
+mod verify_signature;
 mod version;
 
 #[cfg(feature = "yubihsm")]
@@ -0,0 +1,128 @@
+//! Integration tests for the `monitor` subcommand
+
+use crate::cli;
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    str, thread,
+};
+
+/// Address of the validator in the fake node's responses
+const VALIDATOR_ADDRESS: &str = "A3A3A3A3A3A3A3A3A3A3A3A3A3A3A3A3A3A3A3A3";
+
+/// Serve a fake node RPC endpoint at height 43 whose canonical commit for
+/// height 42 is signed by the given validators, returning its address
+fn spawn_node(signers: &'static [&'static str]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+
+            // Read the rest of the request before responding
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+
+            let result = if request_line.starts_with("GET /status") {
+                format!(
+                    r#"{{"validator_info":{{"address":"{}"}},"sync_info":{{"latest_block_height":"43"}}}}"#,
+                    VALIDATOR_ADDRESS
+                )
+            } else if request_line.starts_with("GET /commit?height=42") {
+                let signatures = signers
+                    .iter()
+                    .map(|address| {
+                        format!(
+                            r#"{{"block_id_flag":2,"validator_address":"{}","signature":"c2ln"}}"#,
+                            address
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",");
+
+                format!(
+                    r#"{{"signed_header":{{"commit":{{"signatures":[{}]}}}},"canonical":true}}"#,
+                    signatures
+                )
+            } else {
+                panic!("unexpected request: {}", request_line);
+            };
+
+            let body = format!(r#"{{"jsonrpc":"2.0","id":-1,"result":{}}}"#, result);
+            write!(
+                stream,
+                "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{}",
+                body
+            )
+            .unwrap();
+        }
+    });
+
+    format!("tcp://{}", addr)
+}
+
+#[test]
+fn test_monitor_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+    let state_path = dir.path().join("state.json");
+
+    fs::write(
+        &state_path,
+        r#"{"height":"42","round":"0","step":2,"block_id":null}"#,
+    )
+    .unwrap();
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+state_file = "{}"
+
+[providers]
+"#,
+            state_path.display()
+        ),
+    )
+    .unwrap();
+
+    let monitor = |rpc: &str| {
+        cli::run([
+            "monitor",
+            "-c",
+            config_path.to_str().unwrap(),
+            "--chain-id",
+            "test_chain_id",
+            "--rpc",
+            rpc,
+            "--once",
+        ])
+    };
+
+    let result = monitor(&spawn_node(&[
+        "B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4",
+        VALIDATOR_ADDRESS,
+    ]));
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(result.status.success(), "{}", stderr);
+
+    let result = monitor(&spawn_node(&["B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4B4"]));
+    assert!(!result.status.success());
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("1 signed precommit(s) missing on chain"));
+
+    // Read-only: the state file is left as it was
+    assert!(fs::read_to_string(&state_path)
+        .unwrap()
+        .contains(r#""height":"42""#));
+}
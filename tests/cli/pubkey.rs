@@ -0,0 +1,103 @@
+//! Integration tests for the `pubkey` subcommand
+
+use crate::cli;
+use std::{fs, path::Path, process::Output, str};
+
+/// Run `tmkms pubkey` for a chain whose softsign key of the given type is at
+/// the given path
+fn pubkey(key_type: &str, key_path: &str, args: &[&str]) -> Output {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+state_file = "{}/state.json"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_type = "{}"
+key_format = "base64"
+path = "{}"
+"#,
+            dir.path().display(),
+            key_type,
+            fs::canonicalize(Path::new(key_path)).unwrap().display()
+        ),
+    )
+    .unwrap();
+
+    let mut all_args = vec![
+        "pubkey",
+        "-c",
+        config_path.to_str().unwrap(),
+        "--chain-id",
+        "test_chain_id",
+    ];
+    all_args.extend_from_slice(args);
+    cli::run_successfully(all_args)
+}
+
+#[test]
+fn test_pubkey() {
+    let consensus = |args: &[&str]| pubkey("consensus", "tests/support/signing_ed25519.key", args);
+
+    let text = consensus(&[]);
+    let stdout = str::from_utf8(&text.stdout).unwrap();
+    assert!(
+        stdout.contains("key type:       consensus (ed25519)"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("valcons pubkey: cosmosvalconspub1"),
+        "{}",
+        stdout
+    );
+    assert!(
+        stdout.contains("valcons addr:   cosmosvalcons1"),
+        "{}",
+        stdout
+    );
+
+    let json = consensus(&["--bech32-prefix", "osmo", "--format", "json"]);
+    let json: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+    assert_eq!(json["algorithm"], "ed25519");
+    assert_eq!(json["hex"].as_str().unwrap().len(), 64);
+    assert_eq!(json["address"].as_str().unwrap().len(), 40);
+    assert!(json["bech32_address"]
+        .as_str()
+        .unwrap()
+        .starts_with("osmovalcons1"));
+}
+
+#[test]
+fn test_pubkey_account() {
+    let account = |args: &[&str]| pubkey("account", "tests/support/signing_secp256k1.key", args);
+
+    let text = account(&[]);
+    let stdout = str::from_utf8(&text.stdout).unwrap();
+    assert!(
+        stdout.contains("key type:       account (secp256k1)"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("account pubkey: cosmospub1"), "{}", stdout);
+    assert!(stdout.contains("account addr:   cosmos1"), "{}", stdout);
+    assert!(!stdout.contains("valcons"), "{}", stdout);
+
+    let json = account(&["--bech32-prefix", "osmo", "--format", "json"]);
+    let json: serde_json::Value = serde_json::from_slice(&json.stdout).unwrap();
+    assert!(json["bech32_pubkey"]
+        .as_str()
+        .unwrap()
+        .starts_with("osmopub1"));
+    assert!(json["bech32_address"]
+        .as_str()
+        .unwrap()
+        .starts_with("osmo1"));
+}
@@ -0,0 +1,117 @@
+//! Integration tests for the `softsign` subcommand
+
+use crate::KMS_EXE_PATH;
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    process::{Command, Output},
+    str,
+};
+
+/// Environment variable the test passphrase is passed in
+const PASSPHRASE_ENV: &str = "TMKMS_TEST_KEY_PASSPHRASE";
+
+/// Run `tmkms` with a throwaway GnuPG home directory and the test passphrase
+fn run_with_gpg<I, S>(gnupg_home: &Path, passphrase: &str, args: I) -> Output
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    Command::new(KMS_EXE_PATH)
+        .args(args)
+        .env("GNUPGHOME", gnupg_home)
+        .env(PASSPHRASE_ENV, passphrase)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_gpg_encrypted_key() {
+    if Command::new("gpg").arg("--version").output().is_err() {
+        eprintln!("gpg not found; skipping");
+        return;
+    }
+
+    let dir = tempfile::tempdir().unwrap();
+    let gnupg_home = dir.path().join("gnupg");
+    fs::create_dir(&gnupg_home).unwrap();
+    fs::set_permissions(&gnupg_home, fs::Permissions::from_mode(0o700)).unwrap();
+
+    let encrypted_key_path = dir.path().join("signing.key.gpg");
+    let encrypt = run_with_gpg(
+        &gnupg_home,
+        "correct horse battery staple",
+        [
+            "softsign",
+            "encrypt",
+            "--gpg-passphrase-env",
+            PASSPHRASE_ENV,
+            "tests/support/signing_ed25519.key",
+            encrypted_key_path.to_str().unwrap(),
+        ],
+    );
+    assert!(
+        encrypt.status.success(),
+        "{}",
+        str::from_utf8(&encrypt.stderr).unwrap()
+    );
+    assert_ne!(
+        fs::read(&encrypted_key_path).unwrap(),
+        fs::read("tests/support/signing_ed25519.key").unwrap()
+    );
+
+    let config_path = dir.path().join("tmkms.toml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+on_missing_state = "init_zero"
+
+[[validator]]
+addr = "unix://{}/validator.sock"
+chain_id = "test_chain_id"
+protocol_version = "v0.34"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "{}"
+encryption = {{ type = "gpg", passphrase_env = "{}" }}
+"#,
+            dir.path().display(),
+            encrypted_key_path.display(),
+            PASSPHRASE_ENV
+        ),
+    )
+    .unwrap();
+
+    let doctor_args = [
+        "doctor",
+        "-c",
+        config_path.to_str().unwrap(),
+        "--state-dir",
+        dir.path().to_str().unwrap(),
+    ];
+
+    let doctor = run_with_gpg(&gnupg_home, "correct horse battery staple", doctor_args);
+    assert!(
+        doctor.status.success(),
+        "{}",
+        str::from_utf8(&doctor.stderr).unwrap()
+    );
+
+    // The wrong passphrase can't decrypt the key
+    let doctor = run_with_gpg(&gnupg_home, "wrong passphrase", doctor_args);
+    assert!(!doctor.status.success());
+
+    Command::new("gpgconf")
+        .args(["--kill", "gpg-agent"])
+        .env("GNUPGHOME", &gnupg_home)
+        .output()
+        .ok();
+}
@@ -0,0 +1,353 @@
+//! Integration tests for the `start` subcommand
+
+use crate::{cli, KMS_EXE_PATH};
+use prost::Message;
+use std::{
+    ffi::OsStr,
+    fs,
+    io::{Read, Write},
+    os::unix::net::UnixListener,
+    process::{Command, Stdio},
+    str,
+};
+use tendermint_proto as proto;
+
+const CONFIG: &str = r#"
+[[chain]]
+id = "chain-a"
+key_format = { type = "hex" }
+on_missing_state = "init_zero"
+
+[[chain]]
+id = "chain-b"
+key_format = { type = "hex" }
+on_missing_state = "init_zero"
+
+[[validator]]
+addr = "unix:///nonexistent/validator.sock"
+chain_id = "chain-b"
+protocol_version = "v0.34"
+reconnect = false
+
+[providers]
+"#;
+
+#[test]
+fn test_chain_id_filter() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+    fs::write(&config_path, CONFIG).unwrap();
+
+    let start = |chain_id: &str| {
+        cli::run([
+            OsStr::new("start"),
+            OsStr::new("-c"),
+            config_path.as_os_str(),
+            OsStr::new("--state-dir"),
+            dir.path().as_os_str(),
+            OsStr::new("--chain-id"),
+            OsStr::new(chain_id),
+        ])
+    };
+
+    // chain-b's validator (which would fail to connect) is never started
+    let result = start("chain-a");
+    assert!(result.status.success());
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("chain-b (not selected with --chain-id)"));
+
+    let result = start("chain-c");
+    assert!(!result.status.success());
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("none of the selected chains are configured"));
+}
+
+#[test]
+fn test_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("validator.sock");
+    let config_path = dir.path().join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+on_missing_state = "init_zero"
+
+[[validator]]
+addr = "unix://{}"
+chain_id = "test_chain_id"
+protocol_version = "v0.34"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "tests/support/signing_ed25519.key"
+"#,
+            socket_path.display()
+        ),
+    )
+    .unwrap();
+
+    let args = [
+        OsStr::new("start"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+        OsStr::new("--state-dir"),
+        dir.path().as_os_str(),
+        OsStr::new("--once"),
+        OsStr::new("--once-timeout"),
+        OsStr::new("2"),
+    ];
+
+    // Nothing ever sends a sign request
+    let listener = UnixListener::bind(&socket_path).unwrap();
+    let result = cli::run(args);
+    assert!(!result.status.success());
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("no sign request received within 2s"));
+
+    // A ping is answered, then the process exits after the first sign request
+    drop(listener);
+    fs::remove_file(&socket_path).unwrap();
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let process = Command::new(KMS_EXE_PATH)
+        .args(args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (mut socket, _) = listener.accept().unwrap();
+
+    for request in [
+        proto::privval::message::Sum::PingRequest(proto::privval::PingRequest {}),
+        proto::privval::message::Sum::SignVoteRequest(proto::privval::SignVoteRequest {
+            vote: Some(proto::types::Vote {
+                r#type: 0x01,
+                height: 12345,
+                round: 2,
+                timestamp: Some(proto::google::protobuf::Timestamp {
+                    seconds: 1518332962,
+                    nanos: 0,
+                }),
+                validator_address: vec![0xa3; 20],
+                validator_index: 56789,
+                ..Default::default()
+            }),
+            chain_id: "test_chain_id".to_owned(),
+        }),
+    ] {
+        let mut buf = vec![];
+        proto::privval::Message { sum: Some(request) }
+            .encode_length_delimited(&mut buf)
+            .unwrap();
+        socket.write_all(&buf).unwrap();
+
+        // Wait for the response before sending the next request
+        let mut response = [0u8; 4096];
+        assert_ne!(socket.read(&mut response).unwrap(), 0);
+    }
+
+    let result = process.wait_with_output().unwrap();
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(result.status.success(), "{}", stderr);
+    assert!(str::from_utf8(&result.stdout)
+        .unwrap()
+        .contains("SignedVote"));
+}
+
+#[test]
+fn test_shared_chain_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+
+    let start = |chain_b: &str, state_dir: Option<&str>| {
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[[chain]]
+id = "chain-a"
+key_format = {{ type = "hex" }}
+state_file = "{dir}/a/state.json"
+on_missing_state = "init_zero"
+standby_lock = {{ path = "{dir}/a.lock", holder = "kms-a" }}
+
+[[chain]]
+id = "chain-b"
+key_format = {{ type = "hex" }}
+on_missing_state = "init_zero"
+{chain_b}
+
+[providers]
+"#,
+                dir = dir.path().display(),
+            ),
+        )
+        .unwrap();
+
+        let mut args = vec!["start", "-c", config_path.to_str().unwrap()];
+        args.extend(
+            state_dir
+                .iter()
+                .flat_map(|state_dir| ["--state-dir", *state_dir]),
+        );
+        cli::run(args)
+    };
+
+    for subdir in ["a", "b", "states"] {
+        fs::create_dir(dir.path().join(subdir)).unwrap();
+    }
+
+    let state_dir = dir.path().join("states");
+    let state_dir = state_dir.to_str().unwrap();
+    let b_state = format!("state_file = \"{}/b/state.json\"", dir.path().display());
+
+    let result = start(&b_state, None);
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(result.status.success(), "{}", stderr);
+
+    for (chain_b, state_dir, error) in [
+        (
+            format!("state_file = \"{}/a/state.json\"", dir.path().display()),
+            None,
+            "state file",
+        ),
+        // Both state files are named `state.json`
+        (b_state.clone(), Some(state_dir), "state file"),
+        (
+            format!(
+                "{}\nstandby_lock = {{ path = \"{}/a.lock\", holder = \"kms-a\" }}",
+                b_state,
+                dir.path().display()
+            ),
+            None,
+            "standby lock file",
+        ),
+    ] {
+        let result = start(&chain_b, state_dir);
+        assert!(!result.status.success());
+        let stderr = str::from_utf8(&result.stderr).unwrap();
+        assert!(
+            stderr.contains(&format!("[chain-b] {}", error)) && stderr.contains("of chain chain-a"),
+            "{}",
+            stderr
+        );
+    }
+}
+
+#[test]
+fn test_state_write_failure() {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_path = dir.path().join("validator.sock");
+    let config_path = dir.path().join("tmkms.toml");
+    let state_path = dir.path().join("test_chain_id_priv_validator_state.json");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+on_missing_state = "init_zero"
+
+[[validator]]
+addr = "unix://{}"
+chain_id = "test_chain_id"
+protocol_version = "v0.34"
+reconnect = false
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "tests/support/signing_ed25519.key"
+"#,
+            socket_path.display()
+        ),
+    )
+    .unwrap();
+
+    let listener = UnixListener::bind(&socket_path).unwrap();
+
+    let mut process = Command::new(KMS_EXE_PATH)
+        .args([
+            OsStr::new("start"),
+            OsStr::new("-c"),
+            config_path.as_os_str(),
+            OsStr::new("--state-dir"),
+            dir.path().as_os_str(),
+        ])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let (mut socket, _) = listener.accept().unwrap();
+
+    // Send a prevote at the given height, returning the raw response (empty
+    // if the KMS closed the connection instead)
+    let mut sign_vote = |height: i64| {
+        let request =
+            proto::privval::message::Sum::SignVoteRequest(proto::privval::SignVoteRequest {
+                vote: Some(proto::types::Vote {
+                    r#type: 0x01,
+                    height,
+                    round: 0,
+                    timestamp: Some(proto::google::protobuf::Timestamp {
+                        seconds: 1518332962,
+                        nanos: 0,
+                    }),
+                    validator_address: vec![0xa3; 20],
+                    validator_index: 56789,
+                    ..Default::default()
+                }),
+                chain_id: "test_chain_id".to_owned(),
+            });
+
+        let mut buf = vec![];
+        proto::privval::Message { sum: Some(request) }
+            .encode_length_delimited(&mut buf)
+            .unwrap();
+        socket.write_all(&buf).unwrap();
+
+        let mut response = [0u8; 4096];
+        let len = socket.read(&mut response).unwrap_or(0);
+        response[..len].to_vec()
+    };
+
+    assert!(!signature(&sign_vote(100)).is_empty());
+
+    // Replace the state file with a directory, which it can't be renamed over
+    // (unlike read-only permissions, this also stops root)
+    fs::remove_file(&state_path).unwrap();
+    fs::create_dir(&state_path).unwrap();
+
+    // The signature for a state which couldn't be recorded is never released
+    assert!(signature(&sign_vote(101)).is_empty());
+
+    process.kill().unwrap();
+    process.wait().unwrap();
+}
+
+/// Signature in the given raw vote response (empty if there's none)
+fn signature(response: &[u8]) -> Vec<u8> {
+    if response.is_empty() {
+        return vec![];
+    }
+
+    match proto::privval::Message::decode_length_delimited(response)
+        .unwrap()
+        .sum
+    {
+        Some(proto::privval::message::Sum::SignedVoteResponse(response)) => {
+            response.vote.map(|vote| vote.signature).unwrap_or_default()
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+}
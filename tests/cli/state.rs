@@ -0,0 +1,187 @@
+//! Integration tests for the `state` subcommand
+
+use crate::cli;
+use std::{ffi::OsStr, fs, os::unix::fs::PermissionsExt, path::Path, str};
+use tendermint::consensus;
+use tmkms::chain::State;
+
+const CONFIG: &str = r#"
+[[chain]]
+id = "test_chain_id"
+key_format = { type = "hex" }
+
+[providers]
+"#;
+
+#[test]
+fn test_inspect() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+    let state_path = dir.path().join("test_chain_id_priv_validator_state.json");
+    fs::write(&config_path, CONFIG).unwrap();
+    fs::write(
+        &state_path,
+        r#"{"height":"42","round":"1","step":2,"block_id":null}"#,
+    )
+    .unwrap();
+
+    let args = [
+        OsStr::new("state"),
+        OsStr::new("inspect"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+        OsStr::new("--state-dir"),
+        dir.path().as_os_str(),
+    ];
+
+    let result = cli::run_successfully(args);
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("height=42 round=1 step=2"));
+
+    // Inspecting must not modify the state file
+    assert_eq!(
+        fs::read_to_string(&state_path).unwrap(),
+        r#"{"height":"42","round":"1","step":2,"block_id":null}"#
+    );
+
+    // World-writable state files are refused
+    fs::set_permissions(&state_path, fs::Permissions::from_mode(0o666)).unwrap();
+    assert!(!cli::run(args).status.success());
+}
+
+#[test]
+fn test_import() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+    let state_path = dir.path().join("test_chain_id_priv_validator_state.json");
+    fs::write(&config_path, CONFIG).unwrap();
+
+    let import = |height: &str| {
+        cli::run([
+            OsStr::new("state"),
+            OsStr::new("import"),
+            OsStr::new("-c"),
+            config_path.as_os_str(),
+            OsStr::new("--state-dir"),
+            dir.path().as_os_str(),
+            OsStr::new("--chain-id"),
+            OsStr::new("test_chain_id"),
+            OsStr::new("--height"),
+            OsStr::new(height),
+        ])
+    };
+
+    // Starting without a state file is refused by default
+    let result = cli::run([
+        OsStr::new("start"),
+        OsStr::new("-c"),
+        config_path.as_os_str(),
+        OsStr::new("--state-dir"),
+        dir.path().as_os_str(),
+    ]);
+    assert!(!result.status.success());
+    assert!(str::from_utf8(&result.stderr)
+        .unwrap()
+        .contains("tmkms state import"));
+    assert!(!state_path.exists());
+
+    assert!(import("100").status.success());
+    let state: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&state_path).unwrap()).unwrap();
+    assert_eq!(state["height"], "100");
+
+    // Nothing at the imported height can be signed, only above it
+    let mut guard = State::load_state(&state_path).unwrap();
+    let request = |height: u32, step: i8| consensus::State {
+        height: height.into(),
+        round: 0u16.into(),
+        step,
+        block_id: None,
+    };
+    assert!(guard.update_consensus_state(request(100, 0)).is_err());
+    assert!(guard.update_consensus_state(request(100, 1)).is_err());
+    assert!(guard.update_consensus_state(request(101, 0)).is_ok());
+
+    // Importing a lower height than the existing state is refused
+    assert!(!import("50").status.success());
+    assert!(import("150").status.success());
+}
+
+#[test]
+fn test_benchmark_guard() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let result = cli::run_successfully([
+        OsStr::new("state"),
+        OsStr::new("benchmark-guard"),
+        OsStr::new("-n"),
+        OsStr::new("10"),
+        OsStr::new("--dir"),
+        dir.path().as_os_str(),
+    ]);
+
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("10 guarded state updates"));
+    assert!(stderr.contains("p99="));
+
+    // The scratch state file is cleaned up afterwards
+    assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn test_simulate_guard() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let result = cli::run_successfully(["state", "simulate-guard"]);
+    let stdout = str::from_utf8(&result.stdout).unwrap();
+    assert!(stdout.contains("REJECTED precommit at h/r/s 10/0/2"));
+    assert!(stdout.contains("double sign detected"));
+    assert!(stdout.contains("tmkms_last_signed_height{chain_id=\"simulated-chain\"} 11"));
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("8 sign requests: 6 accepted, 2 rejected"));
+
+    // A configured chain's state file is refused, even if it doesn't exist yet
+    let config_path = dir.path().join("tmkms.toml");
+    let state_path = dir.path().join("test_chain_id_priv_validator_state.json");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+state_file = "{}"
+
+[providers]
+"#,
+            state_path.display()
+        ),
+    )
+    .unwrap();
+
+    let simulate = |state_path: &Path| {
+        cli::run([
+            OsStr::new("state"),
+            OsStr::new("simulate-guard"),
+            OsStr::new("-c"),
+            config_path.as_os_str(),
+            OsStr::new("--state-file"),
+            state_path.as_os_str(),
+            OsStr::new("--sequence"),
+            OsStr::new("1/0/0,1/0/1"),
+        ])
+    };
+
+    let result = simulate(&state_path);
+    assert!(!result.status.success());
+    let stderr = str::from_utf8(&result.stderr).unwrap();
+    assert!(stderr.contains("is the state file of chain test_chain_id"));
+
+    // Existing files are refused too
+    let existing_path = dir.path().join("existing.json");
+    fs::write(&existing_path, "{}").unwrap();
+    assert!(!simulate(&existing_path).status.success());
+
+    let throwaway_path = dir.path().join("throwaway.json");
+    assert!(simulate(&throwaway_path).status.success());
+}
@@ -0,0 +1,93 @@
+//! Integration tests for the `verify-signature` subcommand
+
+use crate::cli;
+use signature::Signer;
+use std::{fs, str};
+use subtle_encoding::hex;
+use tmkms::{key_utils, keyring::ed25519};
+
+const SIGNING_KEY_PATH: &str = "tests/support/signing_ed25519.key";
+const ACCOUNT_KEY_PATH: &str = "tests/support/signing_secp256k1.key";
+
+#[test]
+fn test_verify_signature() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("tmkms.toml");
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+state_file = "{}/state.json"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "{}"
+
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_type = "account"
+key_format = "base64"
+path = "{}"
+"#,
+            dir.path().display(),
+            fs::canonicalize(SIGNING_KEY_PATH).unwrap().display(),
+            fs::canonicalize(ACCOUNT_KEY_PATH).unwrap().display()
+        ),
+    )
+    .unwrap();
+
+    let message = b"hello world";
+    let signing_key = key_utils::load_base64_ed25519_key(SIGNING_KEY_PATH).unwrap();
+    let signature: ed25519::Signature = signing_key.sign(message);
+    let signature = signature.to_bytes();
+
+    let verify = |message: &[u8], signature: &[u8]| {
+        let message = String::from_utf8(hex::encode(message)).unwrap();
+        let signature = String::from_utf8(hex::encode(signature)).unwrap();
+
+        cli::run([
+            "verify-signature",
+            "-c",
+            config_path.to_str().unwrap(),
+            "--chain-id",
+            "test_chain_id",
+            "--message",
+            &message,
+            "--signature",
+            &signature,
+        ])
+    };
+
+    let valid = verify(message, &signature);
+    assert!(valid.status.success());
+    assert!(str::from_utf8(&valid.stderr).unwrap().contains("VALID"));
+
+    let stderr = str::from_utf8(&valid.stderr).unwrap();
+    assert!(stderr.contains("consensus key"), "{}", stderr);
+
+    // Keys other than the consensus key are checked too
+    let (account_key, _) = key_utils::load_base64_secp256k1_key(ACCOUNT_KEY_PATH).unwrap();
+    let account_signature: k256::ecdsa::Signature = account_key.sign(message);
+    let valid = verify(message, &account_signature.to_bytes());
+    assert!(valid.status.success());
+    let stderr = str::from_utf8(&valid.stderr).unwrap();
+    assert!(stderr.contains("account key"), "{}", stderr);
+
+    let invalid = verify(b"goodbye world", &signature);
+    assert_eq!(invalid.status.code(), Some(1));
+    let stderr = str::from_utf8(&invalid.stderr).unwrap();
+    assert!(stderr.contains("INVALID"), "{}", stderr);
+    assert!(stderr.contains("consensus key"), "{}", stderr);
+    assert!(stderr.contains("account key"), "{}", stderr);
+
+    // Malformed signatures are an error rather than INVALID
+    assert_eq!(verify(message, &signature[..10]).status.code(), Some(2));
+
+    // The double-signing state must never be touched
+    assert!(!dir.path().join("state.json").exists());
+}
@@ -12,6 +12,7 @@ use std::{
     net::{TcpListener, TcpStream},
     os::unix::net::{UnixListener, UnixStream},
     process::{Child, Command},
+    time::{Duration, Instant},
 };
 use tempfile::NamedTempFile;
 use tendermint_p2p::secret_connection::{self, SecretConnection};
@@ -138,6 +139,7 @@ impl KmsProcess {
             [[chain]]
             id = "test_chain_id"
             key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+            on_missing_state = "init_zero"
 
             [[validator]]
             addr = "tcp://{}@127.0.0.1:{}"
@@ -170,6 +172,7 @@ impl KmsProcess {
             [[chain]]
             id = "test_chain_id"
             key_format = {{ type = "bech32", account_key_prefix = "cosmospub", consensus_key_prefix = "cosmosvalconspub" }}
+            on_missing_state = "init_zero"
 
             [[validator]]
             addr = "unix://{socket_path}"
@@ -478,6 +481,63 @@ fn handle_and_sign_vote(key_type: KeyType) {
     });
 }
 
+#[test]
+fn test_malformed_vote_not_recorded() {
+    let vote_msg = |part_set_header| proto::types::Vote {
+        r#type: 0x01,
+        height: 12345,
+        round: 2,
+        timestamp: Some(proto::google::protobuf::Timestamp {
+            seconds: 1_518_333_000,
+            nanos: 0,
+        }),
+        block_id: Some(proto::types::BlockId {
+            hash: b"some hash00000000000000000000000".to_vec(),
+            part_set_header: Some(part_set_header),
+        }),
+        validator_address: vec![0xa3; 20],
+        validator_index: 56789,
+        signature: vec![],
+        extension: vec![],
+        extension_signature: vec![],
+    };
+
+    let sign_request = |vote| {
+        proto::privval::message::Sum::SignVoteRequest(proto::privval::SignVoteRequest {
+            vote: Some(vote),
+            chain_id: "test_chain_id".into(),
+        })
+    };
+
+    ProtocolTester::apply(&KeyType::Consensus, |mut pt| {
+        // A block ID without a part set header is refused...
+        send_request(sign_request(vote_msg(Default::default())), &mut pt);
+
+        match read_response(&mut pt) {
+            proto::privval::message::Sum::SignedVoteResponse(resp) => {
+                assert_eq!(resp.error.unwrap().code, 9);
+                assert!(resp.vote.is_none());
+            }
+            other => panic!("unexpected message type in response: {other:?}"),
+        }
+
+        // ...without recording its h/r/s, so a valid vote there is still signed
+        let part_set_header = proto::types::PartSetHeader {
+            total: 1000000,
+            hash: b"parts_hash0000000000000000000000".to_vec(),
+        };
+        send_request(sign_request(vote_msg(part_set_header)), &mut pt);
+
+        match read_response(&mut pt) {
+            proto::privval::message::Sum::SignedVoteResponse(resp) => {
+                assert!(resp.error.is_none());
+                assert!(!resp.vote.unwrap().signature.is_empty());
+            }
+            other => panic!("unexpected message type in response: {other:?}"),
+        }
+    });
+}
+
 #[test]
 #[should_panic]
 fn test_exceed_max_height_account() {
@@ -632,6 +692,270 @@ fn test_buffer_underflow_sign_proposal() {
     });
 }
 
+#[test]
+fn test_duplicate_concurrent_requests() {
+    for policy in ["cached", "reject"] {
+        duplicate_concurrent_requests(policy);
+    }
+}
+
+/// Send the same vote sign request over two validator connections at once,
+/// checking the second request is handled according to the given
+/// `on_duplicate_request` policy
+fn duplicate_concurrent_requests(policy: &str) {
+    let dir = tempfile::tempdir().unwrap();
+    let socket_paths = [dir.path().join("a.sock"), dir.path().join("b.sock")];
+    let listeners = socket_paths
+        .clone()
+        .map(|path| UnixListener::bind(path).unwrap());
+
+    let config_path = dir.path().join("tmkms.toml");
+    let validators = socket_paths
+        .iter()
+        .map(|path| {
+            format!(
+                "[[validator]]\naddr = \"unix://{}\"\nchain_id = \"test_chain_id\"\nreconnect = false\nprotocol_version = \"v0.34\"\n",
+                path.display()
+            )
+        })
+        .collect::<String>();
+
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[chain]]
+id = "test_chain_id"
+key_format = {{ type = "hex" }}
+state_file = "{}"
+on_missing_state = "init_zero"
+on_duplicate_request = "{}"
+
+{}
+[[providers.softsign]]
+chain_ids = ["test_chain_id"]
+key_format = "base64"
+path = "{}"
+"#,
+            dir.path().join("state.json").display(),
+            policy,
+            validators,
+            SIGNING_ED25519_KEY_PATH
+        ),
+    )
+    .unwrap();
+
+    let mut process = Command::new(KMS_EXE_PATH)
+        .args(["start", "-c", config_path.to_str().unwrap()])
+        .spawn()
+        .unwrap();
+
+    let vote_msg = proto::types::Vote {
+        r#type: 0x01,
+        height: 42,
+        round: 0,
+        timestamp: Some(proto::google::protobuf::Timestamp {
+            seconds: 1518332962,
+            nanos: 0,
+        }),
+        block_id: None,
+        validator_address: vec![0xa3; 20],
+        validator_index: 0,
+        signature: vec![],
+        extension: vec![],
+        extension_signature: vec![],
+    };
+
+    let mut request = vec![];
+    proto::privval::Message {
+        sum: Some(proto::privval::message::Sum::SignVoteRequest(
+            proto::privval::SignVoteRequest {
+                vote: Some(vote_msg),
+                chain_id: "test_chain_id".into(),
+            },
+        )),
+    }
+    .encode_length_delimited(&mut request)
+    .unwrap();
+
+    let connections = listeners.map(|listener| listener.accept().unwrap().0);
+    let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+
+    let responses = connections
+        .map(|mut socket| {
+            let barrier = barrier.clone();
+            let request = request.clone();
+
+            std::thread::spawn(move || {
+                barrier.wait();
+                socket.write_all(&request).unwrap();
+
+                let mut response = vec![0u8; 4096];
+                let len = socket.read(&mut response).unwrap();
+
+                match proto::privval::Message::decode_length_delimited(&response[..len])
+                    .unwrap()
+                    .sum
+                {
+                    Some(proto::privval::message::Sum::SignedVoteResponse(resp)) => resp,
+                    other => panic!("unexpected message type in response: {other:?}"),
+                }
+            })
+        })
+        .map(|handle| handle.join().unwrap());
+
+    process.kill().unwrap();
+    process.wait().unwrap();
+
+    let signatures = responses
+        .iter()
+        .filter_map(|response| Some(response.vote.as_ref()?.signature.clone()))
+        .collect::<Vec<_>>();
+
+    match policy {
+        // Both requests are answered with the same signature
+        "cached" => {
+            assert_eq!(signatures.len(), 2, "{responses:?}");
+            assert_eq!(signatures[0], signatures[1]);
+        }
+        // Exactly one request is signed, whichever was handled first
+        "reject" => {
+            assert_eq!(signatures.len(), 1, "{responses:?}");
+            let error = responses
+                .iter()
+                .find_map(|response| response.error.as_ref())
+                .unwrap();
+            assert!(error.description.contains("duplicate"), "{error:?}");
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// Environment variable containing the duration of the soak test in seconds
+const SOAK_SECS_ENV_VAR: &str = "TMKMS_SOAK_SECS";
+
+/// Maximum growth in resident memory (in kB) tolerated during the soak test
+const SOAK_MAX_RSS_GROWTH_KB: u64 = 16 * 1024;
+
+/// Long-running soak test which signs votes at increasing rounds for
+/// `TMKMS_SOAK_SECS` seconds (default 60), asserting every request succeeds
+/// and KMS memory usage stays bounded, then reports the latency distribution.
+///
+/// Run with: `cargo test --features=softsign test_soak -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn test_soak() {
+    let chain_id = "test_chain_id";
+    let duration = Duration::from_secs(
+        std::env::var(SOAK_SECS_ENV_VAR)
+            .map(|secs| secs.parse().expect("invalid TMKMS_SOAK_SECS"))
+            .unwrap_or(60),
+    );
+
+    let dt = "2018-02-11T07:09:22.765Z".parse::<DateTime<Utc>>().unwrap();
+    let t = proto::google::protobuf::Timestamp {
+        seconds: dt.timestamp(),
+        nanos: dt.timestamp_subsec_nanos() as i32,
+    };
+
+    ProtocolTester::apply(&KeyType::Consensus, |mut pt| {
+        let pids = [pt.tcp_device.process.id(), pt.unix_device.process.id()];
+        let started_at = Instant::now();
+        let mut baseline_rss = None;
+        let mut latencies = vec![];
+        let mut round = 0;
+
+        while started_at.elapsed() < duration {
+            let vote_msg = proto::types::Vote {
+                r#type: 0x01,
+                height: 1,
+                round,
+                timestamp: Some(t),
+                block_id: None,
+                validator_address: vec![0xa3; 20],
+                validator_index: 0,
+                signature: vec![],
+                extension: vec![],
+                extension_signature: vec![],
+            };
+
+            let signable_msg = SignableMsg::try_from(vote_msg.clone()).unwrap();
+
+            let request = proto::privval::SignVoteRequest {
+                vote: Some(vote_msg),
+                chain_id: chain_id.into(),
+            };
+
+            let request_started_at = Instant::now();
+            send_request(
+                proto::privval::message::Sum::SignVoteRequest(request),
+                &mut pt,
+            );
+
+            let response = match read_response(&mut pt) {
+                proto::privval::message::Sum::SignedVoteResponse(resp) => resp,
+                other => panic!("unexpected message type in response: {other:?}"),
+            };
+
+            latencies.push(request_started_at.elapsed());
+
+            let vote = response
+                .vote
+                .unwrap_or_else(|| panic!("no vote at round {round}: {:?}", response.error));
+
+            let signable_bytes = signable_msg
+                .canonical_bytes(chain_id.parse().unwrap())
+                .unwrap();
+
+            let signature = ed25519::Signature::try_from(vote.signature.as_slice()).unwrap();
+            assert!(test_ed25519_keypair()
+                .verifying_key()
+                .verify(&signable_bytes, &signature)
+                .is_ok());
+
+            // Take the memory baseline once the KMS has warmed up
+            if baseline_rss.is_none() && started_at.elapsed() > duration / 10 {
+                baseline_rss = Some(pids.map(resident_memory_kb));
+            }
+
+            round += 1;
+        }
+
+        if let Some(baseline_rss) = baseline_rss {
+            for (pid, baseline) in pids.into_iter().zip(baseline_rss) {
+                let rss = resident_memory_kb(pid);
+                assert!(
+                    rss <= baseline + SOAK_MAX_RSS_GROWTH_KB,
+                    "KMS process {pid} grew from {baseline} kB to {rss} kB"
+                );
+            }
+        }
+
+        latencies.sort();
+        let percentile = |p: usize| latencies[(latencies.len() - 1) * p / 100];
+
+        println!(
+            "signed {} votes in {:?}: p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+            latencies.len(),
+            started_at.elapsed(),
+            percentile(50),
+            percentile(90),
+            percentile(99),
+            percentile(100)
+        );
+    });
+}
+
+/// Get the resident set size (in kB) of the process with the given PID
+fn resident_memory_kb(pid: u32) -> u64 {
+    fs::read_to_string(format!("/proc/{pid}/status"))
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rss| rss.trim().trim_end_matches("kB").trim().parse().ok())
+        .expect("couldn't determine resident memory usage")
+}
+
 /// Encode request as a Protobuf message
 fn send_request(request: proto::privval::message::Sum, pt: &mut ProtocolTester) {
     let mut buf = vec![];
@@ -0,0 +1,356 @@
+//! Replayable test vectors for the signing path
+//!
+//! Each file in `tests/support/vectors` describes a chain and a sequence of
+//! privval requests, each with the double-signing guard's expected decision
+//! and, for accepted requests, the expected sign bytes and signatures.
+//! Conflicting signatures at the same height/round/step are rejected with an
+//! error response, while height and round regressions close the connection,
+//! so a `disconnect` step can only be the last one of a vector. The
+//! KMS signs with the deterministic `tests/support/signing_ed25519.key`, so
+//! signatures are fixed.
+//!
+//! Requests are raw length-delimited privval messages as sent by a validator,
+//! so vectors can be added from captured traffic: add a step with just a
+//! `description` and the hex-encoded `request`, then run
+//!
+//! ```text
+//! TMKMS_BLESS_VECTORS=1 cargo test --test vectors
+//! ```
+//!
+//! to fill in the expected values from the current tmkms, and review the diff.
+
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use signature::Verifier;
+use std::{
+    fs,
+    io::{Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::Path,
+    process::{Command, Stdio},
+};
+use subtle_encoding::hex;
+use tendermint_proto as proto;
+use tmkms::{keyring::ed25519, privval::SignableMsg};
+
+/// Path to the KMS executable
+const KMS_EXE_PATH: &str = "target/debug/tmkms";
+
+/// Directory containing the test vectors
+const VECTORS_DIR: &str = "tests/support/vectors";
+
+/// Environment variable which makes the test record actual results as the
+/// expected ones, rather than comparing them
+const BLESS_ENV_VAR: &str = "TMKMS_BLESS_VECTORS";
+
+/// Signing key the vectors' signatures were made with
+const SIGNING_KEY_PATH: &str = "tests/support/signing_ed25519.key";
+
+/// A chain and a sequence of requests to replay against it
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Vector {
+    /// What the vector covers
+    description: String,
+
+    /// Chain ID to configure (requests for other chains are refused)
+    chain_id: String,
+
+    /// Protocol version of the chain and its validator
+    protocol_version: String,
+
+    /// Whether the chain signs vote extensions
+    #[serde(default)]
+    sign_extensions: bool,
+
+    /// Requests, replayed in order over a single connection
+    steps: Vec<Step>,
+}
+
+/// A single request and its expected result
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Step {
+    /// What the step covers
+    description: String,
+
+    /// Hex-encoded length-delimited privval request
+    request: String,
+
+    /// Expected decision: `accept` (signed), `reject` (error response), or
+    /// `disconnect` (connection closed without a response)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decision: Option<String>,
+
+    /// Expected hex-encoded sign bytes of accepted requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sign_bytes: Option<String>,
+
+    /// Expected hex-encoded signature of accepted requests
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+
+    /// Expected hex-encoded vote extension sign bytes, if signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extension_sign_bytes: Option<String>,
+
+    /// Expected hex-encoded vote extension signature, if signed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extension_signature: Option<String>,
+}
+
+/// Running KMS connected to a validator socket
+struct Kms {
+    process: std::process::Child,
+    socket: UnixStream,
+    _dir: tempfile::TempDir,
+}
+
+impl Kms {
+    /// Start a KMS for the given vector's chain
+    fn start(vector: &Vector) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let socket_path = dir.path().join("validator.sock");
+        let config_path = dir.path().join("tmkms.toml");
+
+        fs::write(
+            &config_path,
+            format!(
+                r#"
+[[chain]]
+id = "{chain_id}"
+key_format = {{ type = "hex" }}
+state_file = "{state_file}"
+on_missing_state = "init_zero"
+protocol_version = "{protocol_version}"
+sign_extensions = {sign_extensions}
+
+[[validator]]
+addr = "unix://{socket}"
+chain_id = "{chain_id}"
+protocol_version = "{protocol_version}"
+reconnect = false
+
+[[providers.softsign]]
+chain_ids = ["{chain_id}"]
+key_format = "base64"
+path = "{SIGNING_KEY_PATH}"
+"#,
+                chain_id = vector.chain_id,
+                state_file = dir.path().join("state.json").display(),
+                protocol_version = vector.protocol_version,
+                sign_extensions = vector.sign_extensions,
+                socket = socket_path.display(),
+            ),
+        )
+        .unwrap();
+
+        let listener = UnixListener::bind(&socket_path).unwrap();
+
+        let process = Command::new(KMS_EXE_PATH)
+            .args(["start", "-c", config_path.to_str().unwrap()])
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let (socket, _) = listener.accept().unwrap();
+
+        Self {
+            process,
+            socket,
+            _dir: dir,
+        }
+    }
+
+    /// Send a raw request, returning the decoded response, or `None` if the
+    /// KMS closed the connection instead
+    fn request(&mut self, request: &[u8]) -> Option<proto::privval::message::Sum> {
+        if self.socket.write_all(request).is_err() {
+            return None;
+        }
+
+        let mut response = Vec::new();
+        let mut buf = [0u8; 4096];
+
+        loop {
+            if let Ok(message) = proto::privval::Message::decode_length_delimited(&response[..]) {
+                return Some(message.sum.expect("no sum field in response"));
+            }
+
+            match self.socket.read(&mut buf) {
+                Ok(0) | Err(_) => return None,
+                Ok(len) => response.extend_from_slice(&buf[..len]),
+            }
+        }
+    }
+}
+
+impl Drop for Kms {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// Replay a step, returning it with the actual results as the expected ones
+fn replay(kms: &mut Kms, chain_id: &str, step: &Step) -> Step {
+    let request = hex::decode(&step.request).expect("invalid hex in `request`");
+    let chain_id: tmkms::chain::Id = chain_id.parse().unwrap();
+
+    let signable_msg = match proto::privval::Message::decode_length_delimited(&request[..])
+        .expect("`request` isn't a length-delimited privval message")
+        .sum
+    {
+        Some(proto::privval::message::Sum::SignVoteRequest(req)) => {
+            SignableMsg::try_from(req.vote.unwrap()).unwrap()
+        }
+        Some(proto::privval::message::Sum::SignProposalRequest(req)) => {
+            SignableMsg::try_from(req.proposal.unwrap()).unwrap()
+        }
+        other => panic!("not a sign request: {:?}", other),
+    };
+
+    let mut actual = Step {
+        description: step.description.clone(),
+        request: step.request.clone(),
+        decision: None,
+        sign_bytes: None,
+        signature: None,
+        extension_sign_bytes: None,
+        extension_signature: None,
+    };
+
+    let response = match kms.request(&request) {
+        Some(response) => response,
+        None => {
+            actual.decision = Some("disconnect".to_owned());
+            return actual;
+        }
+    };
+
+    let (error, signature, extension_signature) = match response {
+        proto::privval::message::Sum::SignedVoteResponse(resp) => {
+            let vote = resp.vote.unwrap_or_default();
+            (resp.error, vote.signature, vote.extension_signature)
+        }
+        proto::privval::message::Sum::SignedProposalResponse(resp) => (
+            resp.error,
+            resp.proposal.unwrap_or_default().signature,
+            vec![],
+        ),
+        other => panic!("unexpected response: {:?}", other),
+    };
+
+    if error.is_some() {
+        actual.decision = Some("reject".to_owned());
+        return actual;
+    }
+
+    actual.decision = Some("accept".to_owned());
+
+    let verifying_key = tmkms::key_utils::load_base64_ed25519_key(SIGNING_KEY_PATH)
+        .unwrap()
+        .verifying_key();
+
+    let sign_bytes = signable_msg.canonical_bytes(chain_id.clone()).unwrap();
+    verify(&verifying_key, &sign_bytes, &signature, &step.description);
+    actual.sign_bytes = Some(encode(&sign_bytes));
+    actual.signature = Some(encode(&signature));
+
+    if !extension_signature.is_empty() {
+        let extension_bytes = signable_msg
+            .extension_bytes(chain_id)
+            .unwrap()
+            .expect("extension signed for a message without one");
+
+        verify(
+            &verifying_key,
+            &extension_bytes,
+            &extension_signature,
+            &step.description,
+        );
+        actual.extension_sign_bytes = Some(encode(&extension_bytes));
+        actual.extension_signature = Some(encode(&extension_signature));
+    }
+
+    actual
+}
+
+/// Ensure a signature returned by the KMS is valid for the given bytes
+fn verify(key: &ed25519::VerifyingKey, msg: &[u8], signature: &[u8], description: &str) {
+    let signature = ed25519::Signature::try_from(signature).unwrap();
+
+    assert!(
+        key.verify(msg, &signature).is_ok(),
+        "invalid signature: {}",
+        description
+    );
+}
+
+/// Hex-encode bytes
+fn encode(bytes: &[u8]) -> String {
+    String::from_utf8(hex::encode(bytes)).unwrap()
+}
+
+/// Replay all steps of the vector in the given file
+fn run(path: &Path, bless: bool) {
+    let mut vector: Vector =
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).expect("invalid test vector");
+
+    let mut kms = Kms::start(&vector);
+
+    let mut actual = Vec::with_capacity(vector.steps.len());
+
+    for step in &vector.steps {
+        let result = replay(&mut kms, &vector.chain_id, step);
+        let disconnected = result.decision.as_deref() == Some("disconnect");
+        actual.push(result);
+
+        if disconnected {
+            break;
+        }
+    }
+
+    if bless {
+        vector.steps = actual;
+        let mut json = serde_json::to_string_pretty(&vector).unwrap();
+        json.push('\n');
+        fs::write(path, json).unwrap();
+        return;
+    }
+
+    for (expected, actual) in vector.steps.iter().zip(&actual) {
+        assert_eq!(
+            expected,
+            actual,
+            "{}: {}",
+            path.display(),
+            expected.description
+        );
+    }
+
+    assert_eq!(
+        vector.steps.len(),
+        actual.len(),
+        "{}: KMS disconnected before the last step",
+        path.display()
+    );
+}
+
+#[test]
+fn test_vectors() {
+    let bless = std::env::var_os(BLESS_ENV_VAR).is_some();
+
+    let mut paths = fs::read_dir(VECTORS_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect::<Vec<_>>();
+
+    paths.sort();
+    assert!(!paths.is_empty(), "no test vectors in {}", VECTORS_DIR);
+
+    for path in paths {
+        run(&path, bless);
+    }
+}